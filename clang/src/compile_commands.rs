@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use zoltan::args;
+
+use crate::error::{Error, Result};
+
+/// Flags followed by a path that is relative to the directory of the command.
+const PATH_FLAGS: &[&str] = &["-I", "-isystem", "-iquote", "-idirafter", "-include", "-imacros"];
+/// Flags that only affect the output of the compiler, along with the number of values they take.
+const OUTPUT_FLAGS: &[(&str, usize)] = &[
+    ("-c", 0),
+    ("-o", 1),
+    ("-M", 0),
+    ("-MM", 0),
+    ("-MD", 0),
+    ("-MMD", 0),
+    ("-MP", 0),
+    ("-MF", 1),
+    ("-MT", 1),
+    ("-MQ", 1),
+];
+
+/// An entry of a `compile_commands.json` file.
+#[derive(Debug, Deserialize)]
+struct CompileCommand {
+    directory: PathBuf,
+    file: PathBuf,
+    #[serde(default)]
+    arguments: Vec<String>,
+    #[serde(default)]
+    command: Option<String>,
+}
+
+impl CompileCommand {
+    fn path(&self) -> PathBuf {
+        let path = self.directory.join(&self.file);
+        path.canonicalize().unwrap_or(path)
+    }
+
+    fn arguments(&self) -> Result<Vec<String>> {
+        match &self.command {
+            Some(command) if self.arguments.is_empty() => {
+                args::split(command).map_err(Error::CompileCommandsError)
+            }
+            _ => Ok(self.arguments.clone()),
+        }
+    }
+}
+
+/// Returns the flags used to compile the source file according to a compilation database.
+/// Headers usually don't have entries of their own, so they get the flags of the file closest
+/// to them in the directory tree.
+pub fn flags_for(database: &Path, source: &Path) -> Result<Vec<String>> {
+    let contents = std::fs::read(database).map_err(zoltan::error::Error::from)?;
+    let commands: Vec<CompileCommand> =
+        serde_json::from_slice(&contents).map_err(|err| Error::CompileCommandsError(err.to_string()))?;
+    let source = source.canonicalize().unwrap_or_else(|_| source.to_owned());
+
+    let command = commands
+        .iter()
+        .max_by_key(|cmd| {
+            let path = cmd.path();
+            (path == source, common_prefix_len(&path, &source))
+        })
+        .ok_or_else(|| Error::CompileCommandsError("the compilation database is empty".to_owned()))?;
+    if command.path() != source {
+        log::info!(
+            "Using the flags of {} from the compilation database",
+            command.file.display()
+        );
+    }
+    Ok(filter_flags(command, command.arguments()?))
+}
+
+/// Drops the compiler, the input file and the flags related to outputs, and makes relative
+/// include paths absolute.
+fn filter_flags(command: &CompileCommand, arguments: Vec<String>) -> Vec<String> {
+    let mut flags = vec![];
+    let mut args = arguments.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if let Some((_, count)) = OUTPUT_FLAGS.iter().find(|(flag, _)| *flag == arg) {
+            args.by_ref().take(*count).for_each(drop);
+        } else if arg.starts_with("-o") || Path::new(&arg) == command.file {
+            // the input is replaced with the source file and the output is discarded
+            continue;
+        } else if PATH_FLAGS.contains(&arg.as_str()) {
+            flags.push(arg);
+            flags.extend(args.next().map(|path| absolute_path(command, &path)));
+        } else if let Some(path) = arg.strip_prefix("-I") {
+            flags.push(format!("-I{}", absolute_path(command, path)));
+        } else {
+            flags.push(arg);
+        }
+    }
+    flags
+}
+
+fn absolute_path(command: &CompileCommand, path: &str) -> String {
+    command.directory.join(path).to_string_lossy().into_owned()
+}
+
+fn common_prefix_len(a: &Path, b: &Path) -> usize {
+    a.components()
+        .zip(b.components())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
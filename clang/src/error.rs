@@ -14,6 +14,8 @@ pub enum Error {
     UnresolvedType(Ustr),
     #[error("parse error: {0}")]
     ParseFailure(#[from] clang::SourceError),
+    #[error("compilation database error: {0}")]
+    CompileCommandsError(String),
     #[error("compilation errors: \n{0}")]
     CompilerErrors(String),
     #[error("{0}")]
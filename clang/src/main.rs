@@ -1,20 +1,20 @@
 use clang::diagnostic::Severity;
 use clang::{Clang, EntityKind, EntityVisitResult, Index};
 use error::{Error, Result};
-use flexi_logger::{LogSpecification, Logger};
+use zoltan::containers::{ContainerMap, StdLib};
 use zoltan::opts::Opts;
-use zoltan::spec::FunctionSpec;
-use zoltan::types::Type;
+use zoltan::spec::{FunctionSpec, SourceLocation};
+use zoltan::types::{Type, TypeInfo};
 
 use crate::resolver::TypeResolver;
 
+mod compile_commands;
 mod error;
 mod resolver;
 
 fn main() {
-    Logger::with(LogSpecification::info()).start().unwrap();
-
     let opts = Opts::load("Zoltan Clang frontend for C/C++");
+    zoltan::logging::init(&opts).unwrap();
     match run(&opts) {
         Ok(()) => log::info!("Finished!"),
         Err(err) => {
@@ -28,11 +28,33 @@ fn run(opts: &Opts) -> Result<()> {
     let clang = Clang::new().unwrap();
     let index = Index::new(&clang, true, false);
 
+    if opts.watch {
+        zoltan::watch(opts, || parse(&index, opts))?;
+    } else {
+        let (specs, types) = parse(&index, opts)?;
+        zoltan::process_specs(specs, types, opts)?;
+    }
+
+    Ok(())
+}
+
+fn parse(index: &Index, opts: &Opts) -> Result<(Vec<FunctionSpec>, TypeInfo)> {
     log::info!("Parsing sources...");
 
+    let mut flags = vec![];
+    if let Some(triple) = zoltan::target_triple(opts)? {
+        log::info!("Compiling for {triple}");
+        flags.push(format!("--target={triple}"));
+    }
+    if let Some(path) = &opts.compile_commands_path {
+        flags.extend(compile_commands::flags_for(path, &opts.source_path)?);
+    }
+    flags.extend_from_slice(&opts.compiler_flags);
+    flags.extend_from_slice(&opts.cflags);
+
     let unit = index
         .parser(&opts.source_path)
-        .arguments(&opts.compiler_flags)
+        .arguments(&flags)
         .skip_function_bodies(true)
         .parse()?;
 
@@ -46,16 +68,30 @@ fn run(opts: &Opts) -> Result<()> {
 
     log::info!("Searching for typedefs...");
 
-    let mut resolver = TypeResolver::new(opts.strip_namespaces);
+    let mut resolver =
+        TypeResolver::new(opts.strip_namespaces, opts.strict_types).with_type_filter(opts.type_filter());
+    if opts.map_containers || opts.container_map_path.is_some() {
+        let target = unit.get_target();
+        let std_lib = if target.triple.contains("msvc") {
+            StdLib::Msvc
+        } else {
+            StdLib::Gnu
+        };
+        let mut containers = ContainerMap::builtin(std_lib);
+        if let Some(path) = &opts.container_map_path {
+            containers.extend(ContainerMap::load(path)?);
+        }
+        resolver = resolver.with_containers(containers, target.pointer_width / 8);
+    }
     let mut entities = vec![];
 
     unit.get_entity().visit_children(|ent, _| {
-        let is_project_file = ent
+        let path = ent
             .get_location()
             .and_then(|loc| loc.get_file_location().file)
-            .map(|file| file.get_path())
-            .as_deref()
-            == Some(&opts.source_path);
+            .map(|file| file.get_path());
+        let is_project_file = path.as_deref() == Some(&opts.source_path);
+        let is_eager_export_file = path.map_or(false, |path| opts.is_eager_export_path(&path));
 
         match ent.get_kind() {
             EntityKind::Namespace if is_project_file => EntityVisitResult::Recurse,
@@ -67,7 +103,7 @@ fn run(opts: &Opts) -> Result<()> {
             | EntityKind::ClassDecl
             | EntityKind::UnionDecl
             | EntityKind::EnumDecl
-                if opts.eager_type_export =>
+                if opts.eager_type_export && is_eager_export_file =>
             {
                 resolver.resolve_decl(ent).ok();
                 EntityVisitResult::Continue
@@ -77,18 +113,46 @@ fn run(opts: &Opts) -> Result<()> {
     });
 
     let mut specs = vec![];
+    let mut skipped = vec![];
     for ent in entities {
-        if let Some(comment) = ent.get_comment_raw() {
-            if let Type::Function(typ) = resolver.resolve_type(ent.get_type().unwrap())? {
-                let name = ent.get_name_raw().unwrap().as_str().into();
-                if let Some(spec) = FunctionSpec::new(name, typ, comment.as_str().lines()) {
-                    specs.push(spec?);
-                }
-            }
+        match parse_spec(&mut resolver, ent) {
+            Ok(Some(spec)) => specs.push(spec),
+            Ok(None) => {}
+            Err(err) if opts.strict_types => return Err(err),
+            Err(err) => skipped.push(format!("{}: {err}", ent.get_name_raw().unwrap().as_str())),
         }
     }
+    if !skipped.is_empty() {
+        log::warn!(
+            "Skipped {} typedef(s) that could not be processed:\n{}",
+            skipped.len(),
+            skipped.join("\n")
+        );
+    }
 
-    zoltan::process_specs(specs, &resolver.into_types(), opts)?;
+    Ok((specs, resolver.into_types()))
+}
 
-    Ok(())
+fn parse_spec(resolver: &mut TypeResolver, ent: clang::Entity) -> Result<Option<FunctionSpec>> {
+    let comment = match ent.get_comment_raw() {
+        Some(comment) => comment,
+        None => return Ok(None),
+    };
+    let name = ent.get_name_raw().unwrap().as_str().into();
+    let spec = match resolver.resolve_type(ent.get_type().unwrap())? {
+        Type::Function(typ) => FunctionSpec::new(name, typ, comment.as_str().lines(), source_location(ent)),
+        typ => FunctionSpec::new_data(name, typ, comment.as_str().lines(), source_location(ent)),
+    };
+    match spec {
+        Some(spec) => Ok(Some(spec?)),
+        None => Ok(None),
+    }
+}
+
+fn source_location(ent: clang::Entity) -> Option<SourceLocation> {
+    let location = ent.get_location()?.get_spelling_location();
+    Some(SourceLocation {
+        file: location.file?.get_path(),
+        line: location.line.into(),
+    })
 }
@@ -1,6 +1,10 @@
+use std::collections::{HashMap, HashSet};
 use std::hash::BuildHasherDefault;
+use std::path::PathBuf;
 
 use quickscope::ScopeMap;
+use zoltan::containers::{ContainerLayout, ContainerMap};
+use zoltan::filter::TypeFilter;
 use zoltan::types::*;
 use zoltan::ustr::{IdentityHasher, Ustr};
 
@@ -11,34 +15,74 @@ pub struct TypeResolver {
     unions: TypeMap<UnionId, UnionType>,
     enums: TypeMap<EnumId, EnumType>,
     local_types: ScopeMap<Ustr, Type, BuildHasherDefault<IdentityHasher>>,
+    aliases: HashMap<Ustr, Ustr>,
+    anonymous_names: HashMap<(Option<PathBuf>, u32), Ustr>,
+    opaque_types: HashSet<Ustr>,
     name_allocator: NameAllocator,
     strip_namespaces: bool,
+    strict_types: bool,
+    containers: ContainerMap,
+    pointer_size: usize,
+    type_filter: TypeFilter,
 }
 
 impl TypeResolver {
-    pub fn new(strip_namespaces: bool) -> Self {
+    pub fn new(strip_namespaces: bool, strict_types: bool) -> Self {
         Self {
             structs: TypeMap::default(),
             unions: TypeMap::default(),
             enums: TypeMap::default(),
             local_types: ScopeMap::default(),
+            aliases: HashMap::new(),
+            anonymous_names: HashMap::new(),
+            opaque_types: HashSet::new(),
             name_allocator: NameAllocator::default(),
             strip_namespaces,
+            strict_types,
+            containers: ContainerMap::default(),
+            pointer_size: 8,
+            type_filter: TypeFilter::default(),
         }
     }
 
+    /// Replaces instances of the templates in the map with synthetic structs, the pointer size
+    /// of the target is needed to lay them out.
+    pub fn with_containers(self, containers: ContainerMap, pointer_size: usize) -> Self {
+        Self {
+            containers,
+            pointer_size,
+            ..self
+        }
+    }
+
+    /// Leaves out the members of the types rejected by the filter, they only keep their size,
+    /// so that the layouts of the types containing them don't change.
+    pub fn with_type_filter(self, type_filter: TypeFilter) -> Self {
+        Self { type_filter, ..self }
+    }
+
     pub fn into_types(self) -> TypeInfo {
         TypeInfo {
             structs: self.structs,
             unions: self.unions,
             enums: self.enums,
+            ..TypeInfo::default()
         }
     }
 
     pub fn resolve_decl(&mut self, entity: clang::Entity) -> Result<Type> {
         let name: Ustr = self.generate_type_name(entity);
+        let definition = entity.get_definition();
+        let entity = definition.unwrap_or(entity);
 
         match entity.get_kind() {
+            // forward declarations without a definition are incomplete types, they're left out of the type info
+            clang::EntityKind::StructDecl | clang::EntityKind::ClassDecl
+                if definition.is_none() && entity.get_template().is_none() =>
+            {
+                Ok(Type::Struct(name.into()))
+            }
+            clang::EntityKind::UnionDecl if definition.is_none() => Ok(Type::Union(name.into())),
             clang::EntityKind::StructDecl
             | clang::EntityKind::ClassDecl
             | clang::EntityKind::ClassTemplate => {
@@ -46,8 +90,17 @@ impl TypeResolver {
                     self.structs.insert(name.into(), StructType::stub(name));
 
                     let size = entity.get_type().and_then(|t| t.get_sizeof().ok());
-                    let res = if let Some(template) = entity.get_template() {
-                        self.resolve_struct(name, template, size)?
+                    let res = if !self.type_filter.is_exported(&name) {
+                        StructType {
+                            size,
+                            ..StructType::stub(name)
+                        }
+                    } else if let Some(template) = entity.get_template() {
+                        let template_name = self.template_name(template);
+                        match self.containers.get(&template_name).cloned() {
+                            Some(layout) => self.resolve_container(name, entity, &layout, size)?,
+                            None => self.resolve_struct(name, template, size)?,
+                        }
                     } else {
                         self.resolve_struct(name, entity, size)?
                     };
@@ -57,14 +110,27 @@ impl TypeResolver {
             }
             clang::EntityKind::EnumDecl => {
                 if !self.enums.contains_key(&name.into()) {
-                    let res = self.resolve_enum(name, entity)?;
+                    let mut res = self.resolve_enum(name, entity)?;
+                    if !self.type_filter.is_exported(&name) {
+                        res.members.clear();
+                    }
                     self.enums.insert(name.into(), res);
                 }
                 Ok(Type::Enum(name.into()))
             }
             clang::EntityKind::UnionDecl => {
                 if !self.unions.contains_key(&name.into()) {
-                    let res = self.resolve_union(name, entity)?;
+                    self.unions.insert(name.into(), UnionType::stub(name));
+
+                    let res = if self.type_filter.is_exported(&name) {
+                        self.resolve_union(name, entity)?
+                    } else {
+                        let size = entity.get_type().and_then(|t| t.get_sizeof().ok());
+                        UnionType {
+                            size,
+                            ..UnionType::stub(name)
+                        }
+                    };
                     self.unions.insert(name.into(), res);
                 }
 
@@ -110,10 +176,15 @@ impl TypeResolver {
             clang::TypeKind::UShort => Type::Short(false),
             clang::TypeKind::Int => Type::Int(true),
             clang::TypeKind::UInt => Type::Int(false),
-            clang::TypeKind::Long | clang::TypeKind::LongLong => Type::Long(true),
-            clang::TypeKind::ULong | clang::TypeKind::ULongLong => Type::Long(false),
+            clang::TypeKind::Long => Type::Long(true),
+            clang::TypeKind::ULong => Type::Long(false),
+            clang::TypeKind::LongLong => Type::LongLong(true),
+            clang::TypeKind::ULongLong => Type::LongLong(false),
+            clang::TypeKind::Int128 => Type::Int128(true),
+            clang::TypeKind::UInt128 => Type::Int128(false),
             clang::TypeKind::Float => Type::Float,
             clang::TypeKind::Double => Type::Double,
+            clang::TypeKind::LongDouble => Type::LongDouble,
             clang::TypeKind::Pointer => {
                 let inner = self.resolve_type(typ.get_pointee_type().unwrap())?;
                 Type::Pointer(inner.into())
@@ -122,9 +193,23 @@ impl TypeResolver {
                 let inner = self.resolve_type(typ.get_pointee_type().unwrap())?;
                 Type::Reference(inner.into())
             }
+            clang::TypeKind::MemberPointer => {
+                let inner = self.resolve_type(typ.get_pointee_type().unwrap())?;
+                let class = self
+                    .resolve_type(typ.get_class_type().unwrap())?
+                    .into_struct()
+                    .map_err(|_| Error::UnexpectedType(clang::TypeKind::MemberPointer))?;
+                Type::MemberPointer(inner.into(), class)
+            }
             clang::TypeKind::Enum => self.resolve_decl(typ.get_declaration().unwrap())?,
             clang::TypeKind::Record => self.resolve_decl(typ.get_declaration().unwrap())?,
-            clang::TypeKind::Typedef => self.resolve_type(typ.get_canonical_type())?,
+            clang::TypeKind::Typedef => {
+                let canonical = typ.get_canonical_type();
+                if let Some(alias) = typ.get_declaration() {
+                    self.register_alias(alias, canonical);
+                }
+                self.resolve_type(canonical)?
+            }
             clang::TypeKind::FunctionPrototype => {
                 let fun = self.resolve_function(typ)?;
                 Type::Function(fun.into())
@@ -134,6 +219,10 @@ impl TypeResolver {
                 let inner = self.resolve_type(typ.get_element_type().unwrap())?;
                 Type::FixedArray(inner.into(), typ.get_size().unwrap())
             }
+            clang::TypeKind::Vector | clang::TypeKind::ExtVector => {
+                let inner = self.resolve_type(typ.get_element_type().unwrap())?;
+                Type::Vector(inner.into(), typ.get_size().unwrap())
+            }
             clang::TypeKind::DependentSizedArray => {
                 let inner = self.resolve_type(typ.get_element_type().unwrap())?;
                 Type::FixedArray(inner.into(), typ.get_size().unwrap())
@@ -146,12 +235,13 @@ impl TypeResolver {
                 } else {
                     // template argument
                     let name = typ.get_display_name().into();
-                    self.local_types
-                        .get(&name)
-                        .ok_or(Error::UnresolvedType(name))?
-                        .clone()
+                    match self.local_types.get(&name) {
+                        Some(typ) => typ.clone(),
+                        None => self.opaque_type(name)?,
+                    }
                 }
             }
+            clang::TypeKind::Dependent => self.opaque_type(typ.get_display_name().into())?,
             other => return Err(Error::UnexpectedType(other)),
         };
 
@@ -161,6 +251,18 @@ impl TypeResolver {
         Ok(res)
     }
 
+    /// Substitutes a type that can't be resolved, like a dependent template type, with an incomplete
+    /// struct, unless strict types are requested.
+    fn opaque_type(&mut self, name: Ustr) -> Result<Type> {
+        if self.strict_types {
+            return Err(Error::UnresolvedType(name));
+        }
+        if self.opaque_types.insert(name) {
+            log::warn!("Could not resolve type {name}, it will be opaque");
+        }
+        Ok(Type::Struct(name.into()))
+    }
+
     fn resolve_struct(
         &mut self,
         name: Ustr,
@@ -181,17 +283,6 @@ impl TypeResolver {
 
         for child in children {
             match child.get_kind() {
-                clang::EntityKind::FieldDecl => {
-                    let name = self.get_entity_name(child);
-                    let typ = self.resolve_type(child.get_type().unwrap())?;
-                    let bit_offset = child.get_offset_of_field().ok();
-                    members.push(DataMember {
-                        name,
-                        typ,
-                        bit_offset,
-                        is_bitfield: child.is_bit_field(),
-                    })
-                }
                 clang::EntityKind::Method | clang::EntityKind::Destructor if child.is_virtual_method() => {
                     let name = self.get_entity_name(child);
                     if let Type::Function(typ) = self.resolve_type(child.get_type().unwrap())? {
@@ -201,7 +292,7 @@ impl TypeResolver {
                         });
                     }
                 }
-                _ => {}
+                _ => members.extend(self.resolve_member(entity, child)?),
             }
         }
         Ok(StructType {
@@ -213,20 +304,56 @@ impl TypeResolver {
         })
     }
 
+    fn resolve_container(
+        &mut self,
+        name: Ustr,
+        entity: clang::Entity,
+        layout: &ContainerLayout,
+        size: Option<usize>,
+    ) -> Result<StructType> {
+        let mut args = vec![];
+        for arg in entity
+            .get_type()
+            .and_then(|typ| typ.get_template_argument_types())
+            .unwrap_or_default()
+            .into_iter()
+            .take(layout.arg_count())
+        {
+            args.push(match arg {
+                Some(typ) => self.resolve_type(typ)?,
+                None => Type::Void,
+            });
+        }
+        Ok(layout.instantiate(name, &args, self.pointer_size, size))
+    }
+
     fn resolve_enum(&mut self, name: Ustr, entity: clang::Entity) -> Result<EnumType> {
         let children = entity.get_children();
-        let mut members = vec![];
+        let underlying_type = match entity.get_enum_underlying_type() {
+            Some(typ) => self.resolve_type(typ.get_canonical_type())?,
+            None => Type::Int(true),
+        };
+        let mut enum_ = EnumType {
+            name,
+            members: vec![],
+            size: entity.get_type().unwrap().get_sizeof().ok(),
+            underlying_type,
+            is_scoped: entity.is_scoped(),
+        };
 
         for child in children {
             if child.get_kind() == clang::EntityKind::EnumConstantDecl {
                 let name = self.get_entity_name(child);
-                let (value, _) = child.get_enum_constant_value().unwrap();
-                members.push(EnumMember { name, value });
+                let (signed, unsigned) = child.get_enum_constant_value().unwrap();
+                let value = if enum_.is_signed() {
+                    EnumValue::Signed(signed)
+                } else {
+                    EnumValue::Unsigned(unsigned)
+                };
+                enum_.members.push(EnumMember { name, value });
             }
         }
-
-        let size = entity.get_type().unwrap().get_sizeof().ok();
-        Ok(EnumType { name, members, size })
+        Ok(enum_)
     }
 
     fn resolve_union(&mut self, name: Ustr, entity: clang::Entity) -> Result<UnionType> {
@@ -234,23 +361,42 @@ impl TypeResolver {
         let mut members = vec![];
 
         for child in children {
-            if child.get_kind() == clang::EntityKind::FieldDecl {
-                let name = self.get_entity_name(child);
-                let typ = self.resolve_type(child.get_type().unwrap())?;
-                let bit_offset = child.get_offset_of_field().ok();
-                members.push(DataMember {
-                    name,
-                    typ,
-                    bit_offset,
-                    is_bitfield: false,
-                })
-            }
+            members.extend(self.resolve_member(entity, child)?);
         }
 
         let size = entity.get_type().unwrap().get_sizeof().ok();
         Ok(UnionType { name, members, size })
     }
 
+    /// Resolves a field or an anonymous struct or union declared in the parent record.
+    fn resolve_member(
+        &mut self,
+        parent: clang::Entity,
+        child: clang::Entity,
+    ) -> Result<Option<DataMember>> {
+        match child.get_kind() {
+            clang::EntityKind::FieldDecl => Ok(Some(DataMember {
+                name: self.get_entity_name(child),
+                typ: self.resolve_type(child.get_type().unwrap())?,
+                bit_offset: child.get_offset_of_field().ok(),
+                bit_width: child.get_bit_field_width(),
+                is_anonymous: false,
+            })),
+            clang::EntityKind::StructDecl | clang::EntityKind::UnionDecl
+                if child.is_anonymous_record_decl() =>
+            {
+                Ok(Some(DataMember {
+                    name: self.get_entity_name(child),
+                    typ: self.resolve_decl(child)?,
+                    bit_offset: anonymous_member_offset(parent, child),
+                    bit_width: None,
+                    is_anonymous: true,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
     fn resolve_function(&mut self, typ: clang::Type) -> Result<FunctionType> {
         let return_type = self.resolve_type(typ.get_result_type().unwrap())?;
         let mut params = vec![];
@@ -261,16 +407,38 @@ impl TypeResolver {
         Ok(FunctionType { return_type, params })
     }
 
+    /// Remembers the name of an alias of a template specialization, so that it can be used instead
+    /// of the template name. Only the outermost alias in a chain is kept, since that's the one used in the sources.
+    fn register_alias(&mut self, alias: clang::Entity, target: clang::Type) {
+        if target.get_kind() != clang::TypeKind::Record || target.get_template_argument_types().is_none() {
+            return;
+        }
+        if let Some(decl) = target.get_declaration() {
+            let name = self.qualified_name(decl);
+            if !self.structs.contains_key(&name.into()) && !self.aliases.contains_key(&name) {
+                let alias = self.qualified_name(alias);
+                self.aliases.insert(name, alias);
+            }
+        }
+    }
+
     fn generate_type_name(&mut self, entity: clang::Entity) -> Ustr {
+        let name = self.qualified_name(entity);
+        self.aliases.get(&name).copied().unwrap_or(name)
+    }
+
+    fn qualified_name(&mut self, entity: clang::Entity) -> Ustr {
         let mut cur = entity;
         let mut full_name = entity
             .get_display_name()
-            .unwrap_or_else(|| self.name_allocator.allocate());
+            .unwrap_or_else(|| self.anonymous_name(entity).to_string());
 
         while let Some(parent) = cur.get_semantic_parent() {
             match parent.get_kind() {
                 clang::EntityKind::TranslationUnit => {}
                 clang::EntityKind::Namespace if self.strip_namespaces => {}
+                // inline namespaces are transparent, they're never written out in the sources
+                clang::EntityKind::Namespace if parent.is_inline_namespace() => {}
                 _ => {
                     let parent_name = parent.get_name();
                     let prefix = parent_name.as_deref().unwrap_or("__unnamed");
@@ -283,6 +451,41 @@ impl TypeResolver {
         full_name.into()
     }
 
+    /// Names anonymous declarations once, so that the generated names only depend on the order
+    /// of the declarations and not on how many times they're referenced.
+    fn anonymous_name(&mut self, entity: clang::Entity) -> Ustr {
+        let location = entity.get_location().map(|loc| {
+            let loc = loc.get_file_location();
+            (loc.file.map(|file| file.get_path()), loc.offset)
+        });
+        match location {
+            Some(key) => *self
+                .anonymous_names
+                .entry(key)
+                .or_insert_with(|| self.name_allocator.allocate().into()),
+            None => self.name_allocator.allocate().into(),
+        }
+    }
+
+    /// Returns the fully qualified name of a template, regardless of whether namespaces are stripped.
+    fn template_name(&self, template: clang::Entity) -> String {
+        let mut cur = template;
+        let mut full_name = template.get_name().unwrap_or_default();
+        while let Some(parent) = cur.get_semantic_parent() {
+            match parent.get_kind() {
+                clang::EntityKind::TranslationUnit => {}
+                clang::EntityKind::Namespace if parent.is_inline_namespace() => {}
+                _ => {
+                    let parent_name = parent.get_name();
+                    let prefix = parent_name.as_deref().unwrap_or("__unnamed");
+                    full_name = format!("{}::{}", prefix, full_name);
+                }
+            }
+            cur = parent;
+        }
+        full_name
+    }
+
     fn get_entity_name(&mut self, entity: clang::Entity) -> Ustr {
         entity
             .get_name_raw()
@@ -290,3 +493,14 @@ impl TypeResolver {
             .unwrap_or_else(|| self.name_allocator.allocate().into())
     }
 }
+
+/// Anonymous records are not fields in libclang, so their offset is derived from the offset of their
+/// first field, which the parent record can look up like one of its own.
+fn anonymous_member_offset(parent: clang::Entity, record: clang::Entity) -> Option<usize> {
+    let field = record
+        .get_children()
+        .into_iter()
+        .find(|child| child.get_kind() == clang::EntityKind::FieldDecl && child.get_name().is_some())?;
+    let offset = parent.get_type()?.get_offsetof(field.get_name()?).ok()?;
+    offset.checked_sub(field.get_offset_of_field().ok()?)
+}
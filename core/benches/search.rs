@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use zoltan::patterns::{self, Pattern};
+
+/// Size of the haystack, roughly the size of the code of a large game.
+const HAYSTACK_SIZE: usize = 32 << 20;
+
+/// Generates pseudo-random bytes interspersed with common x86-64 instructions, so that patterns
+/// anchored on them get plenty of false candidates like they would in a real executable.
+fn haystack() -> Vec<u8> {
+    let mut state = 0x2545_F491_4F6C_DD1D_u64;
+    let mut bytes = Vec::with_capacity(HAYSTACK_SIZE + 8);
+    while bytes.len() < HAYSTACK_SIZE {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        match state % 16 {
+            0 => bytes.extend_from_slice(&[0x48, 0x89, 0x5C, 0x24, 0x08]),
+            1 => bytes.extend_from_slice(&[0x48, 0x8B, 0x05]),
+            2 => bytes.extend_from_slice(&[0x48, 0x83, 0xEC, 0x20]),
+            3 => bytes.push(0xE8),
+            _ => bytes.push((state >> 32) as u8),
+        }
+    }
+    bytes.truncate(HAYSTACK_SIZE);
+    bytes
+}
+
+fn parse_all(patterns: impl Iterator<Item = String>) -> Vec<Pattern> {
+    patterns.map(|str| Pattern::parse(&str).unwrap()).collect()
+}
+
+fn search(c: &mut Criterion) {
+    let haystack = haystack();
+    // prologues shared by many functions, with the distinctive bytes behind wildcards
+    let generic =
+        parse_all((0..100).map(|i| format!("48 89 5C 24 08 ? ? ? ? E8 {i:02X} ? ? ? 48 83 EC 20")));
+    // long runs of bytes that are unlikely to be found by chance
+    let distinct = parse_all((0..5000).map(|i| {
+        let (lo, hi) = (i & 0xFF, i >> 8);
+        format!(
+            "48 8B 05 ? ? ? ? {lo:02X} {hi:02X} {:02X} 0F B6 {lo:02X} C3",
+            (i * 7) & 0xFF
+        )
+    }));
+    let single = parse_all(std::iter::once(
+        "48 89 5C 24 08 57 48 83 EC 20 8B 05 ? ? ? ?".to_owned(),
+    ));
+
+    let mut group = c.benchmark_group("multi_search");
+    group.sample_size(10);
+    group.bench_function("generic anchors", |b| {
+        b.iter(|| patterns::multi_search(&generic, black_box(&haystack)))
+    });
+    group.bench_function("distinct anchors", |b| {
+        b.iter(|| patterns::multi_search(&distinct, black_box(&haystack)))
+    });
+    group.bench_function("single pattern", |b| {
+        b.iter(|| patterns::multi_search(&single, black_box(&haystack)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
@@ -0,0 +1,130 @@
+//! Splitting of argument strings and expansion of response files, for passing large sets
+//! of compiler flags.
+use std::ffi::OsString;
+
+/// Maximum depth of response files referencing other response files.
+const MAX_RESPONSE_FILE_DEPTH: usize = 16;
+
+/// Splits a string into arguments separated by whitespace, nothing gets expanded. Quotes group
+/// characters into a single argument and a backslash escapes whitespace, quotes or another
+/// backslash, any other backslash is kept so that Windows paths don't have to be escaped.
+pub fn split(str: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = str.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            char if char.is_whitespace() => args.extend(current.take()),
+            '\'' | '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(next) if next == char => break,
+                        Some('\\') if char == '"' && matches!(chars.peek(), Some('"' | '\\')) => {
+                            arg.extend(chars.next())
+                        }
+                        Some(next) => arg.push(next),
+                        None => return Err(format!("unterminated {char} quote")),
+                    }
+                }
+            }
+            '\\' => {
+                let arg = current.get_or_insert_with(String::new);
+                match chars.peek() {
+                    Some(next) if next.is_whitespace() || matches!(next, '\'' | '"' | '\\') => {
+                        arg.extend(chars.next())
+                    }
+                    _ => arg.push(char),
+                }
+            }
+            char => current.get_or_insert_with(String::new).push(char),
+        }
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+/// Replaces the arguments of the form `@file` with the arguments read from the files, which can
+/// reference other response files. Like in GCC and Clang, arguments naming files that cannot be
+/// read are left as they are.
+pub fn expand_response_files<I>(args: I) -> Result<Vec<OsString>, String>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut res = vec![];
+    for arg in args {
+        expand_arg(arg, 0, &mut res)?;
+    }
+    Ok(res)
+}
+
+fn expand_arg(arg: OsString, depth: usize, res: &mut Vec<OsString>) -> Result<(), String> {
+    let path = match arg.to_str().and_then(|str| str.strip_prefix('@')) {
+        Some(path) => path,
+        None => {
+            res.push(arg);
+            return Ok(());
+        }
+    };
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => {
+            res.push(arg);
+            return Ok(());
+        }
+    };
+    if depth == MAX_RESPONSE_FILE_DEPTH {
+        return Err(format!("response files nested too deeply in {path}"));
+    }
+    for arg in split(&contents).map_err(|err| format!("invalid response file {path}: {err}"))? {
+        expand_arg(arg.into(), depth + 1, res)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_arguments() {
+        assert_eq!(split("  -I include  -DDEBUG=1\n-std=c++20 ").unwrap(), [
+            "-I",
+            "include",
+            "-DDEBUG=1",
+            "-std=c++20"
+        ]);
+        assert_eq!(split(r#"-I "Program Files/sdk" '-DNAME="x"'"#).unwrap(), [
+            "-I",
+            "Program Files/sdk",
+            r#"-DNAME="x""#
+        ]);
+        assert_eq!(split(r#"-IC:\sdk\include my\ dir "a\"b""#).unwrap(), [
+            r"-IC:\sdk\include",
+            "my dir",
+            r#"a"b"#
+        ]);
+        assert_eq!(split("\"\" x").unwrap(), ["", "x"]);
+        assert!(split("-I \"include").is_err());
+    }
+
+    #[test]
+    fn expand_nested_response_files() {
+        let dir = std::env::temp_dir().join(format!("zoltan-args-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let inner = dir.join("inner.rsp");
+        let outer = dir.join("outer.rsp");
+        std::fs::write(&inner, "-DINNER").unwrap();
+        std::fs::write(&outer, format!("-I include\n@{}", inner.display())).unwrap();
+
+        let args = [format!("@{}", outer.display()), "@missing.rsp".to_owned()];
+        let expanded = expand_response_files(args.into_iter().map(OsString::from)).unwrap();
+        assert_eq!(expanded, ["-I", "include", "-DINNER", "@missing.rsp"]);
+
+        std::fs::write(&inner, format!("@{}", inner.display())).unwrap();
+        let args = [OsString::from(format!("@{}", inner.display()))];
+        assert!(expand_response_files(args).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
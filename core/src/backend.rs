@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::dwarf::DwarfOptions;
+use crate::error::Result;
+use crate::exe::{BinaryIdentity, ExeProperties};
+use crate::report::ReportFormat;
+use crate::symbols::FunctionSymbol;
+use crate::template::Template;
+use crate::types::TypeInfo;
+use crate::{codegen, dwarf, report, template};
+
+/// A format the resolved symbols can be written in. The built-in outputs are backends too,
+/// additional formats can be added with [`ExternalBackend`] without changes to zoltan.
+pub trait OutputBackend {
+    /// A short name used in messages.
+    fn name(&self) -> &str;
+
+    fn write(
+        &self,
+        symbols: &[FunctionSymbol],
+        types: &TypeInfo,
+        props: &ExeProperties,
+        writer: &mut dyn Write,
+    ) -> Result<()>;
+}
+
+/// A C header with the addresses of the symbols, written with `--c-output`.
+#[derive(Debug, Clone, Copy)]
+pub struct CBackend {
+    /// Virtual addresses are written instead of RVAs when it's set.
+    pub image_base: Option<u64>,
+    pub struct_definitions: bool,
+}
+
+impl OutputBackend for CBackend {
+    fn name(&self) -> &str {
+        "c"
+    }
+
+    fn write(
+        &self,
+        symbols: &[FunctionSymbol],
+        types: &TypeInfo,
+        _props: &ExeProperties,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        codegen::write_c_header(&mut *writer, symbols, self.image_base)?;
+        if self.struct_definitions {
+            codegen::write_c_structs(writer, types)?;
+        }
+        Ok(())
+    }
+}
+
+/// A Rust module with the addresses of the symbols, written with `--rust-output`.
+#[derive(Debug, Clone, Copy)]
+pub struct RustBackend {
+    /// Virtual addresses are written instead of RVAs when it's set.
+    pub image_base: Option<u64>,
+    pub struct_definitions: bool,
+}
+
+impl OutputBackend for RustBackend {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn write(
+        &self,
+        symbols: &[FunctionSymbol],
+        types: &TypeInfo,
+        _props: &ExeProperties,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        codegen::write_rust_header(&mut *writer, symbols, self.image_base)?;
+        if self.struct_definitions {
+            codegen::write_rust_structs(writer, types)?;
+        }
+        Ok(())
+    }
+}
+
+/// The JSON symbol map, written with `--json-output`.
+#[derive(Debug, Clone)]
+pub struct JsonBackend<'a> {
+    /// Virtual addresses are written instead of RVAs when it's set.
+    pub image_base: Option<u64>,
+    /// The disassembly of the first instructions of the symbols, see [`codegen::write_json`].
+    pub previews: HashMap<&'a str, Vec<String>>,
+    pub identity: BinaryIdentity,
+}
+
+impl OutputBackend for JsonBackend<'_> {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn write(
+        &self,
+        symbols: &[FunctionSymbol],
+        types: &TypeInfo,
+        _props: &ExeProperties,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        codegen::write_json(
+            writer,
+            symbols,
+            types,
+            self.image_base,
+            &self.previews,
+            &self.identity,
+        )
+    }
+}
+
+/// A single file with the DWARF sections, written with `--dwarf-output`.
+#[derive(Debug, Clone)]
+pub struct DwarfBackend {
+    pub options: DwarfOptions,
+}
+
+impl OutputBackend for DwarfBackend {
+    fn name(&self) -> &str {
+        "dwarf"
+    }
+
+    fn write(
+        &self,
+        symbols: &[FunctionSymbol],
+        types: &TypeInfo,
+        props: &ExeProperties,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        dwarf::write_symbol_file(writer, symbols, types, props.clone(), self.options.clone())
+    }
+}
+
+/// A Markdown or HTML report of the symbols and types, written with `--report`.
+#[derive(Debug, Clone, Copy)]
+pub struct ReportBackend {
+    pub format: ReportFormat,
+    /// Virtual addresses are written instead of RVAs when it's set.
+    pub image_base: Option<u64>,
+}
+
+impl OutputBackend for ReportBackend {
+    fn name(&self) -> &str {
+        "report"
+    }
+
+    fn write(
+        &self,
+        symbols: &[FunctionSymbol],
+        types: &TypeInfo,
+        _props: &ExeProperties,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        report::write_report(writer, symbols, types, self.image_base, self.format)
+    }
+}
+
+/// A user-supplied template rendered with the symbols and the types, written with `--template`.
+#[derive(Debug)]
+pub struct TemplateBackend {
+    pub template: Template,
+    /// Virtual addresses are written instead of RVAs when it's set.
+    pub image_base: Option<u64>,
+}
+
+impl OutputBackend for TemplateBackend {
+    fn name(&self) -> &str {
+        "template"
+    }
+
+    fn write(
+        &self,
+        symbols: &[FunctionSymbol],
+        types: &TypeInfo,
+        props: &ExeProperties,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        let context = template::template_context(symbols, types, props, self.image_base);
+        self.template.render(&context, writer)
+    }
+}
+
+#[cfg(feature = "native")]
+pub use external::ExternalBackend;
+
+#[cfg(feature = "native")]
+mod external {
+    use std::io::{self, Write};
+    use std::process::{Command, Stdio};
+
+    use super::OutputBackend;
+    use crate::args;
+    use crate::error::{Error, Result};
+    use crate::exe::ExeProperties;
+    use crate::schema::{BackendInput, SymbolRecord, SCHEMA_VERSION};
+    use crate::symbols::FunctionSymbol;
+    use crate::types::TypeInfo;
+
+    /// A command that gets the symbols as JSON on stdin and prints the output to stdout.
+    /// The input is described by [`BackendInput`], the types are not included.
+    #[derive(Debug, Clone)]
+    pub struct ExternalBackend {
+        command: Vec<String>,
+    }
+
+    impl ExternalBackend {
+        /// Takes a command line, split into arguments like in a shell.
+        pub fn new(command: &str) -> Result<Self> {
+            let command = args::split(command).map_err(|err| Error::OtherError(err.into()))?;
+            if command.is_empty() {
+                return Err(Error::InvalidOptions("the command of a backend cannot be empty"));
+            }
+            Ok(Self { command })
+        }
+    }
+
+    impl OutputBackend for ExternalBackend {
+        fn name(&self) -> &str {
+            &self.command[0]
+        }
+
+        fn write(
+            &self,
+            symbols: &[FunctionSymbol],
+            _types: &TypeInfo,
+            props: &ExeProperties,
+            writer: &mut dyn Write,
+        ) -> Result<()> {
+            let input = BackendInput {
+                schema_version: SCHEMA_VERSION,
+                architecture: format!("{:?}", props.architecture()).to_lowercase(),
+                format: format!("{:?}", props.format()).to_lowercase(),
+                image_base: props.image_base(),
+                symbols: symbols
+                    .iter()
+                    .map(|sym| SymbolRecord {
+                        name: sym.name().to_owned(),
+                        confidence: sym.confidence().as_str().to_owned(),
+                        rva: Some(sym.rva()),
+                        va: None,
+                        preview: None,
+                    })
+                    .collect(),
+            };
+            let input = serde_json::to_vec(&input)?;
+
+            let mut child = Command::new(&self.command[0])
+                .args(&self.command[1..])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?;
+            // the input is written from another thread, so that neither of the processes blocks
+            // on a full pipe when the command starts printing before it reads all of the input
+            let mut stdin = child.stdin.take().unwrap();
+            let feeder = std::thread::spawn(move || stdin.write_all(&input));
+            io::copy(child.stdout.as_mut().unwrap(), writer)?;
+            let status = child.wait()?;
+            match feeder.join() {
+                // the command is free to stop reading once it has what it needs
+                Ok(Err(err)) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+                _ => {}
+            }
+            if !status.success() {
+                return Err(Error::BackendError(self.name().to_owned(), status.to_string()));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "native", unix))]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::error::Error;
+    use crate::layout::Layout;
+    use crate::schema::BackendInput;
+    use crate::symbols::Confidence;
+    use crate::types::{FunctionType, Type};
+
+    fn properties() -> ExeProperties {
+        let layout = Layout::parse(
+            "image_base = 0x140000000\n[text]\nfile_offset = 0\naddress = 0x140001000\nsize = 0x40",
+        )
+        .unwrap();
+        ExeProperties::from_layout(&layout)
+    }
+
+    #[test]
+    fn pass_symbols_to_external_backend() {
+        let typ = FunctionType::new(vec![], Type::Void).into();
+        let symbols = [FunctionSymbol::new(
+            "get_player".into(),
+            typ,
+            0x1000,
+            Confidence::Unique,
+            None,
+        )];
+
+        let mut output = vec![];
+        let backend = ExternalBackend::new("cat").unwrap();
+        backend
+            .write(&symbols, &TypeInfo::default(), &properties(), &mut output)
+            .unwrap();
+
+        let input: BackendInput = serde_json::from_slice(&output).unwrap();
+        assert_eq!(input.format, "pe");
+        assert_eq!(input.image_base, 0x140000000);
+        assert_eq!(input.symbols[0].name, "get_player");
+        assert_eq!(input.symbols[0].rva, Some(0x1000));
+    }
+
+    #[test]
+    fn report_failed_external_backend() {
+        let backend = ExternalBackend::new("sh -c 'exit 3'").unwrap();
+        let res = backend.write(&[], &TypeInfo::default(), &properties(), &mut io::sink());
+        assert!(matches!(res, Err(Error::BackendError(..))));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::exe::BinaryIdentity;
+use crate::patterns::{HexRegex, Pattern, RegexMatch};
+
+/// Results of searches done in previous runs, only valid for the exact same executable. Each
+/// pattern, regex and string is cached on its own, so only the ones that have been added or
+/// changed since have to be searched for.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PatternCache {
+    /// The hash of the executable, only set when it has no [`BinaryIdentity`] key.
+    exe_hash: u64,
+    /// The key of the [`BinaryIdentity`] of the executable, executables are compared by it instead
+    /// of the hash when they have one.
+    #[serde(default)]
+    identity: Option<String>,
+    entries: HashMap<u64, Vec<u64>>,
+    #[serde(default)]
+    regex_entries: HashMap<u64, Vec<RegexMatch>>,
+    /// Offsets of the functions referencing each string.
+    #[serde(default)]
+    string_entries: HashMap<u64, Vec<usize>>,
+    /// The number of matches of the specs that pick one of them by its index with `@nth`, by the name
+    /// of the spec. They're kept when the executable changes, so that a different count can be reported.
+    match_counts: HashMap<String, usize>,
+    #[serde(skip)]
+    used: HashSet<u64>,
+}
+
+impl PatternCache {
+    /// Loads the cache from a file, it starts out empty when the file is missing,
+    /// invalid or was created for a different executable.
+    #[cfg(feature = "native")]
+    pub fn load(path: &std::path::Path, exe: &[u8]) -> Self {
+        let cache = Self::new(exe);
+        if let Ok(bytes) = std::fs::read(path) {
+            match serde_json::from_slice::<Self>(&bytes) {
+                Ok(loaded) if loaded.is_for_same_exe(&cache) => return loaded,
+                Ok(loaded) => {
+                    log::info!("The executable has changed, the pattern cache will be rebuilt");
+                    return Self {
+                        match_counts: loaded.match_counts,
+                        ..cache
+                    };
+                }
+                Err(err) => log::warn!("Ignoring an invalid pattern cache: {err}"),
+            }
+        }
+        cache
+    }
+
+    /// Creates an empty cache for an executable. Large executables take a while to hash,
+    /// so they're only hashed when they can't be identified by their PDB or build ID.
+    pub fn new(exe: &[u8]) -> Self {
+        let identity = object::read::File::parse(exe)
+            .ok()
+            .and_then(|exe| BinaryIdentity::from_object(&exe).key());
+        Self {
+            exe_hash: if identity.is_none() { stable_hash(exe) } else { 0 },
+            identity,
+            ..Self::default()
+        }
+    }
+
+    fn is_for_same_exe(&self, other: &Self) -> bool {
+        match (&self.identity, &other.identity) {
+            (Some(identity), Some(other)) => identity == other,
+            (None, None) => self.exe_hash == other.exe_hash,
+            _ => false,
+        }
+    }
+
+    /// Saves the cache, entries of patterns that haven't been used since the last save are dropped.
+    #[cfg(feature = "native")]
+    pub fn save(&mut self, path: &std::path::Path) -> crate::error::Result<()> {
+        let used = std::mem::take(&mut self.used);
+        self.entries.retain(|key, _| used.contains(key));
+        self.regex_entries.retain(|key, _| used.contains(key));
+        self.string_entries.retain(|key, _| used.contains(key));
+        serde_json::to_writer(std::fs::File::create(path)?, &self)?;
+        Ok(())
+    }
+
+    pub fn get(&mut self, pattern: &Pattern) -> Option<&[u64]> {
+        let key = stable_hash(pattern);
+        self.used.insert(key);
+        self.entries.get(&key).map(Vec::as_slice)
+    }
+
+    pub fn insert(&mut self, pattern: &Pattern, rvas: Vec<u64>) {
+        let key = stable_hash(pattern);
+        self.used.insert(key);
+        self.entries.insert(key, rvas);
+    }
+
+    pub fn get_regex(&mut self, regex: &HexRegex) -> Option<&[RegexMatch]> {
+        let key = stable_hash(&("regex", regex.as_str()));
+        self.used.insert(key);
+        self.regex_entries.get(&key).map(Vec::as_slice)
+    }
+
+    pub fn insert_regex(&mut self, regex: &HexRegex, matches: Vec<RegexMatch>) {
+        let key = stable_hash(&("regex", regex.as_str()));
+        self.used.insert(key);
+        self.regex_entries.insert(key, matches);
+    }
+
+    pub fn get_string(&mut self, str: &str) -> Option<&[usize]> {
+        let key = stable_hash(&("string", str));
+        self.used.insert(key);
+        self.string_entries.get(&key).map(Vec::as_slice)
+    }
+
+    pub fn insert_string(&mut self, str: &str, functions: Vec<usize>) {
+        let key = stable_hash(&("string", str));
+        self.used.insert(key);
+        self.string_entries.insert(key, functions);
+    }
+
+    /// Records the number of matches of a spec that picks one of them by its index, returns the number
+    /// recorded in an earlier run.
+    pub fn replace_match_count(&mut self, name: &str, count: usize) -> Option<usize> {
+        self.match_counts.insert(name.to_owned(), count)
+    }
+}
+
+pub(crate) fn stable_hash<A: Hash + ?Sized>(value: &A) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the FNV-1a hash of the bytes, without the length prefix [`Hash`] adds to slices.
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// FNV-1a, unlike the std hasher its output is guaranteed to be the same between runs.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn keep_match_counts_of_other_executables() {
+        let path = std::env::temp_dir().join(format!("zoltan-cache-{}.json", std::process::id()));
+        let mut cache = PatternCache::new(b"raw code");
+        assert_eq!(cache.replace_match_count("remove_item", 24), None);
+        cache.save(&path).unwrap();
+
+        let mut cache = PatternCache::load(&path, b"other code");
+        assert_eq!(cache.exe_hash, stable_hash(&b"other code"[..]));
+        assert_eq!(cache.replace_match_count("remove_item", 25), Some(24));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn match_executables_by_identity() {
+        let cache = PatternCache::new(b"raw code");
+        assert_eq!(cache.identity, None);
+        assert!(cache.is_for_same_exe(&PatternCache::new(b"raw code")));
+        assert!(!cache.is_for_same_exe(&PatternCache::new(b"other code")));
+
+        let identified = |identity: &str| PatternCache {
+            identity: Some(identity.to_owned()),
+            ..PatternCache::default()
+        };
+        assert!(identified("build-id:8f3c2a").is_for_same_exe(&identified("build-id:8f3c2a")));
+        assert!(!identified("build-id:8f3c2a").is_for_same_exe(&identified("build-id:8f3c2b")));
+        assert!(!identified("build-id:8f3c2a").is_for_same_exe(&cache));
+    }
+}
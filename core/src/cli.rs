@@ -0,0 +1,821 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::{fmt, io};
+
+use object::BinaryFormat;
+use ustr::Ustr;
+
+use crate::backend::{
+    CBackend, DwarfBackend, ExternalBackend, JsonBackend, OutputBackend, ReportBackend, RustBackend, TemplateBackend
+};
+use crate::cache::PatternCache;
+use crate::dwarf::{DwarfOptions, DwarfVersion, UnitInfo};
+use crate::error::{Error, Result, SymbolError};
+use crate::exe::{BinaryIdentity, ExeProperties, ObjectConfig};
+use crate::layout::Layout;
+use crate::metrics::{Metrics, MetricsFormat};
+use crate::opts::Opts;
+use crate::report::ReportFormat;
+use crate::session::Session;
+use crate::spec::{FunctionSpec, SourceLocation, Warning};
+use crate::symbols::{FunctionSymbol, ResolutionContext, SymbolHook};
+use crate::template::Template;
+use crate::types::{EnumId, StructId, Type, TypeInfo, UnionId};
+use crate::version::Version;
+use crate::{cache, codegen, dwarf, explain, import, inject, process, symbols, x86};
+
+/// How often the source file is checked for changes in the watch mode.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+/// The path that stands for stdin when given as the executable and for stdout when given as an output.
+const STDIO_PATH: &str = "-";
+
+pub fn process_specs(specs: Vec<FunctionSpec>, type_info: TypeInfo, opts: &Opts) -> Result<()> {
+    process_specs_with_hooks(specs, type_info, opts, &[])
+}
+
+/// Same as [`process_specs`], but runs each of the hooks on every resolved symbol before
+/// the outputs are written.
+pub fn process_specs_with_hooks(
+    specs: Vec<FunctionSpec>,
+    type_info: TypeInfo,
+    opts: &Opts,
+    hooks: &[&SymbolHook],
+) -> Result<()> {
+    check_stdio_outputs(opts)?;
+    let exe_bytes = read_exe(&opts.exe_path)?;
+    let mut cache = opts
+        .pattern_cache_path
+        .as_ref()
+        .map(|path| PatternCache::load(path, &exe_bytes));
+    process_loaded_specs(specs, type_info, opts, hooks, &exe_bytes, cache.as_mut())?;
+    if let (Some(cache), Some(path)) = (&mut cache, &opts.pattern_cache_path) {
+        cache.save(path)?;
+    }
+    Ok(())
+}
+
+/// Returns the target triple to compile the sources for, either the one passed with `--target` or
+/// one derived from the executable. The executable cannot be inspected before the sources are
+/// compiled when it's read from stdin, so the compiler's default is used then.
+pub fn target_triple(opts: &Opts) -> Result<Option<String>> {
+    if let Some(target) = &opts.target {
+        return Ok(Some(target.clone()));
+    }
+    if is_stdio(&opts.exe_path) {
+        log::info!("The executable is read from stdin, compiling for the default target");
+        return Ok(None);
+    }
+    let props = match &opts.layout_path {
+        Some(path) => ExeProperties::from_layout(&Layout::load(path)?),
+        None => ExeProperties::from_object(&object::read::File::parse(&*std::fs::read(&opts.exe_path)?)?),
+    };
+    let triple = props.target_triple();
+    if triple.is_none() {
+        log::warn!(
+            "No known target triple for {:?} {:?}, compiling for the default target",
+            props.architecture(),
+            props.format()
+        );
+    }
+    Ok(triple.map(str::to_owned))
+}
+
+/// Invokes `load` to get the specs and processes them every time the source file changes.
+/// The executable is only read once and the matches of patterns that didn't change are reused.
+/// Errors caused by the sources are logged and the function only returns on other errors.
+pub fn watch<F, E>(opts: &Opts, mut load: F) -> Result<()>
+where
+    F: FnMut() -> Result<(Vec<FunctionSpec>, TypeInfo), E>,
+    E: fmt::Display,
+{
+    check_stdio_outputs(opts)?;
+    let exe_bytes = read_exe(&opts.exe_path)?;
+    let mut cache = match &opts.pattern_cache_path {
+        Some(path) => PatternCache::load(path, &exe_bytes),
+        None => PatternCache::new(&exe_bytes),
+    };
+    let mut last_modified = None;
+
+    loop {
+        // the file can be briefly missing while an editor is saving it
+        let modified = std::fs::metadata(&opts.source_path).and_then(|meta| meta.modified());
+        if let Ok(modified) = modified {
+            if last_modified != Some(modified) {
+                last_modified = Some(modified);
+                match load() {
+                    Ok((specs, type_info)) => {
+                        process_watched_specs(specs, type_info, opts, &exe_bytes, &mut cache)?
+                    }
+                    Err(err) => log::error!("{err}"),
+                }
+                log::info!("Waiting for changes in {}...", opts.source_path.display());
+            }
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Processes the specs after a change in watch mode, errors are only logged. The pattern cache isn't
+/// saved after a failed run, since the entries it didn't get to would be dropped from the file.
+fn process_watched_specs(
+    specs: Vec<FunctionSpec>,
+    type_info: TypeInfo,
+    opts: &Opts,
+    exe_bytes: &[u8],
+    cache: &mut PatternCache,
+) -> Result<()> {
+    match process_loaded_specs(specs, type_info, opts, &[], exe_bytes, Some(cache)) {
+        Ok(()) => {
+            if let Some(path) = &opts.pattern_cache_path {
+                cache.save(path)?;
+            }
+        }
+        Err(err) => log::error!("{err}"),
+    }
+    Ok(())
+}
+
+fn process_loaded_specs(
+    specs: Vec<FunctionSpec>,
+    mut type_info: TypeInfo,
+    opts: &Opts,
+    hooks: &[&SymbolHook],
+    exe_bytes: &[u8],
+    mut cache: Option<&mut PatternCache>,
+) -> Result<()> {
+    let mut metrics = Metrics::default();
+    let start = Instant::now();
+    let layout = opts.layout_path.as_deref().map(Layout::load).transpose()?;
+    let mut session =
+        Session::new(exe_bytes, layout.as_ref())?.with_ambiguity_strategy(opts.ambiguity_strategy);
+    let module = opts.exe_path.file_name().unwrap_or_default().to_string_lossy();
+    // an executable without a section table has no headers or resources to identify it by
+    let identity = match &layout {
+        Some(_) => BinaryIdentity::default(),
+        None => BinaryIdentity::from_object(&object::read::File::parse(exe_bytes)?),
+    };
+    log::info!("Loaded {module}: {identity}");
+    metrics.add_phase("load", start);
+
+    type_info.data_model = opts
+        .data_model
+        .unwrap_or_else(|| session.properties().data_model());
+    for path in &opts.import_types_paths {
+        let count = type_info.merge(import::import_dwarf_types(&std::fs::read(path)?)?);
+        log::info!("Imported {count} type(s) from {}", path.display());
+    }
+    for mismatch in type_info.verify_layouts() {
+        log::warn!("Layout mismatch: {mismatch}");
+    }
+
+    let version = match opts.binary_version.clone() {
+        Some(version) => Some((version, "--binary-version")),
+        None => identity
+            .file_version
+            .clone()
+            .map(|version| (version, "the version resource")),
+    };
+    let specs = select_versions(specs, version);
+    let (specs, selected) = select_groups(specs, opts);
+    log::info!("Searching for symbols...");
+    let start = Instant::now();
+    let spec_count = specs.len();
+    let expected: Vec<_> = specs
+        .iter()
+        .filter_map(|spec| Some((spec.name, spec.expect?)))
+        .collect();
+    let locations: HashMap<_, _> = specs
+        .iter()
+        .filter_map(|spec| Some((spec.name, spec.location.clone()?)))
+        .collect();
+    let aliases: HashMap<_, _> = specs
+        .iter()
+        .filter(|spec| !spec.aliases.is_empty())
+        .map(|spec| (spec.name, spec.aliases.clone()))
+        .collect();
+    let allowed: HashMap<_, _> = specs
+        .iter()
+        .filter(|spec| !spec.allow.is_empty())
+        .map(|spec| (spec.name, spec.allow.clone()))
+        .collect();
+    // the patterns are only kept when they're needed to explain the failures
+    let patterns: HashMap<_, _> = specs
+        .iter()
+        .filter(|_| opts.explain_failures)
+        .map(|spec| (spec.name, (spec.patterns.clone(), spec.section.clone())))
+        .collect();
+    if let Some(cache) = cache.as_deref_mut() {
+        session = session.with_cache(std::mem::take(cache));
+    }
+    let errors = session.resolve(specs);
+    // the cache is given back before the error is returned, watch mode keeps using it
+    if let Some(cache) = cache {
+        *cache = session.take_cache();
+    }
+    let errors = errors?;
+    if opts.stats {
+        let ambiguous = errors
+            .iter()
+            .filter(|err| err.warning() == Some(Warning::Ambiguous))
+            .count();
+        metrics.set_stats(ambiguous, session.pattern_stats());
+    }
+    let data = session.data();
+    let mut syms = session.symbols().to_vec();
+    if let Some(selected) = &selected {
+        syms.retain(|sym| selected.contains(&Ustr::from(sym.name())));
+    }
+
+    if !hooks.is_empty() {
+        let ctx = ResolutionContext {
+            data,
+            errors: &errors,
+        };
+        syms.retain_mut(|sym| hooks.iter().all(|hook| hook(sym, &ctx)));
+    }
+    log::info!("Found {} symbol(s)", syms.len());
+    metrics.set_resolution(spec_count, syms.len(), errors.len());
+    metrics.add_phase("search", start);
+
+    let is_allowed = |name: &Ustr, warning: Option<Warning>| matches!((warning, allowed.get(name)), (Some(warning), Some(allowed)) if allowed.contains(&warning));
+    let (suppressed, reported): (Vec<_>, Vec<_>) = errors
+        .iter()
+        .partition(|err| is_allowed(&err.name(), err.warning()));
+    metrics.set_suppressed(suppressed.len());
+    if !suppressed.is_empty() {
+        log::info!("Suppressed {} failure(s) with @allow", suppressed.len());
+    }
+    if !reported.is_empty() {
+        let message = reported
+            .iter()
+            .map(|err| located_error(err, &locations))
+            .collect::<Vec<_>>()
+            .join("\n");
+        log::warn!("Some of the patterns have failed:\n{message}",);
+    }
+    for err in reported.iter().filter(|_| opts.explain_failures) {
+        let explanation = patterns.get(&err.name()).and_then(|(patterns, section)| {
+            explain::explain_failure(data, patterns, section.as_deref(), err)
+        });
+        if let Some(explanation) = explanation {
+            log::warn!("{}:\n{explanation}", err.name());
+        }
+    }
+
+    let collisions: Vec<_> = symbols::find_collisions(&syms)
+        .into_iter()
+        .filter(|err| match err {
+            SymbolError::AddressCollision(name, other, _) => {
+                !is_allowed(name, err.warning()) && !is_allowed(other, err.warning())
+            }
+            _ => true,
+        })
+        .collect();
+    if !collisions.is_empty() {
+        let message = collisions
+            .iter()
+            .map(|err| located_error(err, &locations))
+            .collect::<Vec<_>>()
+            .join("\n");
+        log::warn!("Some of the symbols share an address:\n{message}");
+    }
+    symbols::add_aliases(&mut syms, &aliases);
+    if opts.export_all_functions {
+        if !data.has_function_table() {
+            log::warn!("The executable has no unwind tables, only the resolved functions will be exported");
+        }
+        let count = symbols::add_unnamed_functions(&mut syms, data);
+        log::info!("Added {count} unnamed function(s) from the unwind tables");
+    }
+    if opts.reachable_types {
+        retain_reachable_types(&mut type_info, &syms, opts);
+    } else if !opts.keep_types.is_empty() {
+        log::warn!("--keep-type only applies along with --reachable-types");
+    }
+
+    let mut verification_errors = vec![];
+    if opts.verify {
+        verification_errors = symbols::verify(&syms, &expected);
+        if verification_errors.is_empty() {
+            log::info!("Verified {} expected address(es) for {identity}", expected.len());
+        } else {
+            let message = verification_errors
+                .iter()
+                .map(|err| located_error(err, &locations))
+                .collect::<Vec<_>>()
+                .join("\n");
+            log::error!("Some of the symbols have unexpected addresses:\n{message}");
+        }
+    }
+
+    if opts.c_output_path.is_none()
+        && opts.rust_output_path.is_none()
+        && opts.x64dbg_output_path.is_none()
+        && opts.sdk_output_path.is_none()
+        && opts.breakpad_output_path.is_none()
+        && opts.json_output_path.is_none()
+        && opts.backend_outputs.is_empty()
+        && opts.template_output_path.is_none()
+        && opts.report_path.is_none()
+        && !opts.list
+        && opts.dwarf_output_path.is_none()
+        && opts.inject_output_path.is_none()
+        && !opts.verify
+    {
+        log::error!("No output option specified, nothing to do")
+    }
+
+    if opts.reproducible {
+        make_reproducible(&mut syms)?;
+    }
+
+    let start = Instant::now();
+    let mut props = session.properties().clone().with_object_config(ObjectConfig {
+        elf_machine: opts.elf_machine,
+        elf_os_abi: opts.elf_os_abi,
+        section_names: opts.section_names.clone(),
+    });
+    if (opts.elf_machine.is_some() || opts.elf_os_abi.is_some()) && props.format() == BinaryFormat::MachO {
+        log::warn!("The ELF header fields only apply to ELF files, they're ignored for Mach-O executables");
+    }
+    if let Some(process) = &opts.base_process {
+        let base = process::find_module_base(process, &module)?;
+        log::info!("Using the image base of {module} in the running process: 0x{base:X}");
+        props = props.with_image_base(base);
+    }
+    let image_base = opts.absolute_addresses.then(|| props.image_base());
+
+    let mut previews = HashMap::new();
+    if let Some(count) = opts.preview {
+        for sym in syms.iter().filter(|sym| sym.data_type().is_none()) {
+            let code = sym
+                .rva()
+                .checked_sub(data.text_offset_from_base())
+                .and_then(|offset| data.text().get(offset as usize..));
+            if let Some(code) = code {
+                let lines = x86::disassemble(code, data.rva_to_va(sym.rva()), count);
+                log::info!("{}:\n  {}", sym.name(), lines.join("\n  "));
+                previews.insert(sym.name(), lines);
+            }
+        }
+    }
+
+    if opts.list {
+        let text_end = data.text_end_from_base();
+        codegen::write_symbol_list(io::stdout().lock(), &syms, text_end, opts.list_order, image_base)?;
+    }
+    if let Some(path) = &opts.c_output_path {
+        let backend = CBackend {
+            image_base,
+            struct_definitions: opts.struct_definitions,
+        };
+        backend.write(&syms, &type_info, &props, &mut create_output(path)?)?;
+    }
+    if let Some(path) = &opts.rust_output_path {
+        let backend = RustBackend {
+            image_base,
+            struct_definitions: opts.struct_definitions,
+        };
+        backend.write(&syms, &type_info, &props, &mut create_output(path)?)?;
+    }
+    if let Some(path) = &opts.sdk_output_path {
+        codegen::write_sdk_header(create_output(path)?, &syms, &opts.sdk_namespace, image_base)?;
+    }
+    if let Some(path) = &opts.breakpad_output_path {
+        let text_end = data.text_end_from_base();
+        codegen::write_breakpad_symbols(create_output(path)?, &syms, &module, &props, text_end)?;
+    }
+    if let Some(path) = &opts.json_output_path {
+        let backend = JsonBackend {
+            image_base,
+            previews,
+            identity,
+        };
+        backend.write(&syms, &type_info, &props, &mut create_output(path)?)?;
+    }
+    if let (Some(template_path), Some(path)) = (&opts.template_path, &opts.template_output_path) {
+        let template = Template::parse(&std::fs::read_to_string(template_path)?)?;
+        let backend = TemplateBackend { template, image_base };
+        backend.write(&syms, &type_info, &props, &mut create_output(path)?)?;
+    }
+    if let Some(path) = &opts.report_path {
+        let backend = ReportBackend {
+            format: ReportFormat::from_path(path),
+            image_base,
+        };
+        backend.write(&syms, &type_info, &props, &mut create_output(path)?)?;
+    }
+    for (command, path) in &opts.backend_outputs {
+        let backend = ExternalBackend::new(command)?;
+        backend.write(&syms, &type_info, &props, &mut create_output(path)?)?;
+        log::info!("Written the output of {} to {}", backend.name(), path.display());
+    }
+    if let Some(path) = &opts.x64dbg_output_path {
+        codegen::write_x64dbg_database(create_output(path)?, &syms, &module)?;
+    }
+    let mut syms: Vec<_> = syms
+        .into_iter()
+        .filter(|sym| sym.confidence() >= opts.min_confidence)
+        .collect();
+    if let Some(path) = &opts.merge_with_path {
+        if opts.dwarf_output_path.is_none() && opts.inject_output_path.is_none() {
+            log::warn!("--merge-with only applies to the DWARF outputs (--dwarf-output, --inject-output)");
+        }
+        // the file is read before the outputs are created, so it can be the DWARF output itself
+        let previous = import::import_dwarf_symbols(&std::fs::read(path)?, &props)?;
+        let count = symbols::merge_symbols(&mut syms, previous.symbols);
+        let types = type_info.merge(previous.types);
+        log::info!(
+            "Kept {count} symbol(s) and {types} type(s) from {}",
+            path.display()
+        );
+        if opts.reproducible {
+            make_reproducible(&mut syms)?;
+        }
+        if opts.reachable_types {
+            retain_reachable_types(&mut type_info, &syms, opts);
+        }
+    }
+    let dwarf_options = DwarfOptions {
+        eager_type_export: opts.eager_type_export,
+        type_filter: opts.type_filter(),
+        name_style: opts.name_style,
+        compression: opts.compress_debug_sections,
+        version: opts.dwarf_version,
+        unit: unit_info(opts)?,
+    };
+    if opts.compress_debug_sections.is_some() && props.format() == BinaryFormat::MachO {
+        log::warn!("Debug sections are only compressed in ELF files, writing uncompressed sections");
+    }
+    if let Some(path) = &opts.dwarf_output_path {
+        let split = opts.dwarf_split && props.format() != BinaryFormat::MachO;
+        if opts.dwarf_split && !split {
+            log::warn!("Split DWARF is not supported for Mach-O executables, writing a single file");
+        }
+        let split = split && opts.dwarf_version == DwarfVersion::V5;
+        if opts.dwarf_split && opts.dwarf_version != DwarfVersion::V5 {
+            log::warn!("Split DWARF requires DWARF 5, writing a single file");
+        }
+        if split {
+            dwarf::write_split_symbol_files(
+                create_output(path)?,
+                &path.with_extension("dwo"),
+                &syms,
+                &type_info,
+                props.clone(),
+                dwarf_options.clone(),
+            )?;
+        } else {
+            let backend = DwarfBackend {
+                options: dwarf_options.clone(),
+            };
+            backend.write(&syms, &type_info, &props, &mut create_output(path)?)?;
+        }
+
+        if let Some(link_path) = &opts.debuglink_output_path {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let link = inject::debuglink(&file_name, &std::fs::read(path)?, props.endianness());
+            let exe = inject::append_sections(exe_bytes, &[(inject::DEBUGLINK_SECTION, link)])?;
+            create_output(link_path)?.write_all(&exe)?;
+        }
+    } else if opts.debuglink_output_path.is_some() {
+        log::error!("A debuglink can only be written along with a DWARF file (--dwarf-output)");
+    }
+    if let Some(path) = &opts.inject_output_path {
+        dwarf::write_injected_symbol_file(
+            create_output(path)?,
+            exe_bytes,
+            &syms,
+            &type_info,
+            props,
+            dwarf_options,
+        )?;
+    }
+    metrics.add_phase("output", start);
+
+    if let Some(summary) = metrics.summary() {
+        log::info!("{summary}");
+    }
+
+    if let Some(path) = &opts.metrics_path {
+        let outputs = [
+            ("c", &opts.c_output_path),
+            ("rust", &opts.rust_output_path),
+            ("sdk", &opts.sdk_output_path),
+            ("breakpad", &opts.breakpad_output_path),
+            ("json", &opts.json_output_path),
+            ("template", &opts.template_output_path),
+            ("report", &opts.report_path),
+            ("x64dbg", &opts.x64dbg_output_path),
+            ("dwarf", &opts.dwarf_output_path),
+            ("inject", &opts.inject_output_path),
+            ("debuglink", &opts.debuglink_output_path),
+        ];
+        for (name, output) in outputs {
+            if let Some(output) = output.as_deref().filter(|path| !is_stdio(path)) {
+                metrics.add_output(name, output)?;
+            }
+        }
+        for (_, path) in &opts.backend_outputs {
+            if !is_stdio(path) {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                metrics.add_output(format!("backend:{file_name}"), path)?;
+            }
+        }
+        metrics.write(create_output(path)?, MetricsFormat::from_path(path))?;
+    }
+
+    if !verification_errors.is_empty() {
+        return Err(Error::VerificationFailed(verification_errors.len()));
+    }
+    Ok(())
+}
+
+/// Makes sure that the outputs written to stdout don't get mixed up. Only one of them can go to
+/// stdout and the DWARF file has to be a regular file when other files are derived from it.
+fn check_stdio_outputs(opts: &Opts) -> Result<()> {
+    let outputs = [
+        &opts.c_output_path,
+        &opts.rust_output_path,
+        &opts.sdk_output_path,
+        &opts.breakpad_output_path,
+        &opts.json_output_path,
+        &opts.template_output_path,
+        &opts.report_path,
+        &opts.x64dbg_output_path,
+        &opts.dwarf_output_path,
+        &opts.inject_output_path,
+        &opts.debuglink_output_path,
+        &opts.metrics_path,
+    ];
+    let count = outputs
+        .iter()
+        .filter(|path| path.as_deref().map_or(false, is_stdio))
+        .count()
+        + opts
+            .backend_outputs
+            .iter()
+            .filter(|(_, path)| is_stdio(path))
+            .count()
+        + usize::from(opts.list);
+    if count > 1 {
+        return Err(Error::InvalidOptions("only one output can be written to stdout"));
+    }
+    if opts.template_path.is_some() != opts.template_output_path.is_some() {
+        return Err(Error::InvalidOptions(
+            "--template and --template-output have to be given together",
+        ));
+    }
+    let dwarf_to_stdout = opts.dwarf_output_path.as_deref().map_or(false, is_stdio);
+    if dwarf_to_stdout && (opts.dwarf_split || opts.debuglink_output_path.is_some()) {
+        return Err(Error::InvalidOptions(
+            "the DWARF output cannot be written to stdout with --dwarf-split or --debuglink-output",
+        ));
+    }
+    Ok(())
+}
+
+/// Leaves out the specs whose `@version` doesn't match the version of the executable and reports
+/// which of the versions the specs were selected for. Specs with a `@version` are all left out when
+/// the version isn't known.
+fn select_versions(specs: Vec<FunctionSpec>, version: Option<(Version, &str)>) -> Vec<FunctionSpec> {
+    let mut buckets: BTreeMap<String, (bool, usize)> = BTreeMap::new();
+    let specs: Vec<_> = specs
+        .into_iter()
+        .filter(|spec| match &spec.version {
+            Some(req) => {
+                let matches = version
+                    .as_ref()
+                    .map_or(false, |(version, _)| req.matches(version));
+                buckets.entry(req.to_string()).or_insert((matches, 0)).1 += 1;
+                matches
+            }
+            None => true,
+        })
+        .collect();
+    if buckets.is_empty() {
+        return specs;
+    }
+
+    match version {
+        Some((version, source)) => log::info!("Selecting the specs for version {version} from {source}"),
+        None => {
+            log::warn!("The version of the executable is unknown, it can be given with --binary-version")
+        }
+    }
+    for (req, (matches, count)) in &buckets {
+        let action = if *matches { "Using" } else { "Skipping" };
+        log::info!("{action} {count} spec(s) for version {req}");
+    }
+    specs
+}
+
+/// Leaves out the specs that don't pass the `--only-group` and `--exclude-group` filters. The specs
+/// the others depend on through `@xref-to` are kept to be resolved, but they're not written, so
+/// the names of the specs that were selected are returned along with them.
+fn select_groups(specs: Vec<FunctionSpec>, opts: &Opts) -> (Vec<FunctionSpec>, Option<HashSet<Ustr>>) {
+    if opts.only_groups.is_empty() && opts.exclude_groups.is_empty() {
+        return (specs, None);
+    }
+    let selected: HashSet<_> = specs
+        .iter()
+        .filter(|spec| spec.in_groups(&opts.only_groups, &opts.exclude_groups))
+        .map(|spec| spec.name)
+        .collect();
+    let dependencies: HashMap<_, _> = specs
+        .iter()
+        .filter_map(|spec| Some((spec.name, spec.xref_to?)))
+        .collect();
+    let mut required = selected.clone();
+    let mut pending: Vec<_> = selected.iter().copied().collect();
+    while let Some(name) = pending.pop() {
+        if let Some(&dep) = dependencies.get(&name) {
+            if required.insert(dep) {
+                pending.push(dep);
+            }
+        }
+    }
+
+    let total = specs.len();
+    let specs: Vec<_> = specs
+        .into_iter()
+        .filter(|spec| required.contains(&spec.name))
+        .collect();
+    log::info!(
+        "Selected {} of {total} spec(s) by group, {} more are resolved for @xref-to",
+        selected.len(),
+        specs.len() - selected.len()
+    );
+    (specs, Some(selected))
+}
+
+/// Describes the source file for the DWARF outputs. When the outputs have to be reproducible,
+/// the path is relative to the current directory and the directory itself is left out.
+fn unit_info(opts: &Opts) -> Result<UnitInfo> {
+    let dir = std::env::current_dir()?;
+    let source_hash = if opts.source_hash {
+        Some(cache::hash_bytes(&std::fs::read(&opts.source_path)?))
+    } else {
+        None
+    };
+    let unit = if opts.reproducible {
+        let name = opts.source_path.strip_prefix(&dir).unwrap_or(&opts.source_path);
+        UnitInfo {
+            name: Some(name.to_owned()),
+            comp_dir: None,
+            source_hash,
+        }
+    } else {
+        UnitInfo {
+            name: Some(opts.source_path.clone()),
+            comp_dir: Some(dir),
+            source_hash,
+        }
+    };
+    Ok(unit)
+}
+
+/// Removes the types that can't be reached from the signatures of the symbols or the `--keep-type` types.
+fn retain_reachable_types(type_info: &mut TypeInfo, syms: &[FunctionSymbol], opts: &Opts) {
+    let mut roots = vec![];
+    for sym in syms {
+        match sym.data_type() {
+            Some(typ) => roots.push(typ.clone()),
+            None => {
+                let typ = sym.function_type();
+                roots.extend(typ.params.iter().cloned());
+                roots.push(typ.return_type.clone());
+            }
+        }
+    }
+    for name in &opts.keep_types {
+        let id = Ustr::from(name);
+        if type_info.structs.contains_key(&StructId::from(id)) {
+            roots.push(Type::Struct(id.into()));
+        } else if type_info.unions.contains_key(&UnionId::from(id)) {
+            roots.push(Type::Union(id.into()));
+        } else if type_info.enums.contains_key(&EnumId::from(id)) {
+            roots.push(Type::Enum(id.into()));
+        } else {
+            log::warn!("Type {name} given in --keep-type was not found in the sources");
+        }
+    }
+    let count = type_info.retain_reachable(roots);
+    log::info!("Removed {count} type(s) that are not reachable from the symbols");
+}
+
+/// Sorts the symbols by address and name, so that their order doesn't depend on the order of the specs
+/// or on the earlier runs in the watch mode, and makes the paths of their specs relative to the current
+/// directory, so that the outputs don't depend on where the sources are checked out.
+fn make_reproducible(syms: &mut Vec<FunctionSymbol>) -> Result<()> {
+    let dir = std::env::current_dir()?;
+    *syms = std::mem::take(syms)
+        .into_iter()
+        .map(|sym| {
+            let location = sym.location().map(|location| SourceLocation {
+                file: location
+                    .file
+                    .strip_prefix(&dir)
+                    .unwrap_or(&location.file)
+                    .to_owned(),
+                line: location.line,
+            });
+            sym.with_location(location)
+        })
+        .collect();
+    syms.sort_by(|a, b| a.rva().cmp(&b.rva()).then_with(|| a.name().cmp(b.name())));
+    Ok(())
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
+/// Reads the executable from a file, or from stdin when the path is `-`.
+fn read_exe(path: &Path) -> Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut bytes = vec![];
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        Ok(std::fs::read(path)?)
+    }
+}
+
+/// Creates an output file, or writes to stdout when the path is `-`.
+fn create_output(path: &Path) -> Result<Box<dyn Write>> {
+    if is_stdio(path) {
+        Ok(Box::new(io::stdout().lock()))
+    } else {
+        Ok(Box::new(File::create(path)?))
+    }
+}
+
+/// Formats an error with the location of the spec it's about, when it's known.
+fn located_error(err: &SymbolError, locations: &HashMap<Ustr, SourceLocation>) -> String {
+    match locations.get(&err.name()) {
+        Some(location) => format!("{location}: {err}"),
+        None => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+    use crate::patterns::Pattern;
+    use crate::types::{FunctionType, Type};
+
+    fn spec(name: &str, comment: &[&str]) -> FunctionSpec {
+        let typ = FunctionType::new(vec![], Type::Void).into();
+        FunctionSpec::new(name.into(), typ, comment.iter().copied(), None)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn keep_pattern_cache_after_failed_watch_run() {
+        let dir = std::env::temp_dir().join(format!("zoltan-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let layout_path = dir.join("layout.toml");
+        let cache_path = dir.join("patterns.json");
+        std::fs::write(
+            &layout_path,
+            "image_base = 0x140000000\n[text]\nfile_offset = 0\naddress = 0x140001000\nsize = 0x10",
+        )
+        .unwrap();
+        let exe_bytes: Vec<u8> = (0..0x10).collect();
+        let args = [
+            OsStr::new("types.hpp"),
+            OsStr::new("game.bin"),
+            OsStr::new("--layout"),
+            layout_path.as_os_str(),
+            OsStr::new("--pattern-cache"),
+            cache_path.as_os_str(),
+        ];
+        let opts = bpaf::Info::default()
+            .for_parser(Opts::parser())
+            .run_inner(bpaf::Args::from(&args[..]))
+            .unwrap();
+
+        let mut cache = PatternCache::new(&exe_bytes);
+        let found = spec("found", &["/// @pattern 04 05 06"]);
+        process_watched_specs(vec![found], TypeInfo::default(), &opts, &exe_bytes, &mut cache).unwrap();
+        let saved = std::fs::read(&cache_path).unwrap();
+
+        // the name in the expression is only looked up once the pattern has matched
+        let specs = vec![
+            spec("found", &["/// @pattern 04 05 06"]),
+            spec("broken", &["/// @pattern 08 09", "/// @eval missing"]),
+        ];
+        process_watched_specs(specs, TypeInfo::default(), &opts, &exe_bytes, &mut cache).unwrap();
+        assert_eq!(std::fs::read(&cache_path).unwrap(), saved);
+        let pattern = Pattern::parse("04 05 06").unwrap();
+        assert_eq!(cache.get(&pattern), Some(&[4][..]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,36 +1,714 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
+use std::str::FromStr;
+
+use object::{Architecture, BinaryFormat};
+use serde_json::json;
 
 use crate::error::Result;
+use crate::exe::{BinaryIdentity, ExeProperties};
+use crate::names::sanitize_identifier;
+use crate::schema::{IdentityRecord, MemberRecord, SymbolRecord, TypeRecord, SCHEMA_VERSION};
 use crate::symbols::FunctionSymbol;
+use crate::types::{
+    DataMember, EnumId, EnumType, EnumValue, FunctionType, StructId, StructType, Type, TypeInfo
+};
 
 const HEADER: &str = "\
 // This file has been generated by zoltan (https://github.com/jac3km4/zoltan)
 ";
 
-pub fn write_c_header<W: Write>(mut output: W, symbols: &[FunctionSymbol]) -> Result<()> {
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+
+pub fn write_c_header<W: Write>(
+    mut output: W,
+    symbols: &[FunctionSymbol],
+    image_base: Option<u64>,
+) -> Result<()> {
+    writeln!(output, "{}", HEADER)?;
+    for symbol in symbols {
+        writeln!(
+            output,
+            "#define {}_ADDR 0x{:X} // {}",
+            sanitize_identifier(symbol.name()).to_uppercase(),
+            address(symbol, image_base),
+            symbol.confidence()
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn write_rust_header<W: Write>(
+    mut output: W,
+    symbols: &[FunctionSymbol],
+    image_base: Option<u64>,
+) -> Result<()> {
     writeln!(output, "{}", HEADER)?;
     for symbol in symbols {
         writeln!(
             output,
-            "#define {}_ADDR 0x{:X}",
-            symbol.name().to_uppercase(),
-            symbol.rva()
+            "const {}_ADDR: usize = 0x{:X}; // {}",
+            sanitize_identifier(symbol.name()).to_uppercase(),
+            address(symbol, image_base),
+            symbol.confidence()
         )?;
     }
 
     Ok(())
 }
 
-pub fn write_rust_header<W: Write>(mut output: W, symbols: &[FunctionSymbol]) -> Result<()> {
+pub fn write_sdk_header<W: Write>(
+    mut output: W,
+    symbols: &[FunctionSymbol],
+    namespace: &str,
+    image_base: Option<u64>,
+) -> Result<()> {
     writeln!(output, "{}", HEADER)?;
+    writeln!(output, "#pragma once")?;
+    writeln!(output, "#include <cstdint>")?;
+    writeln!(output)?;
+    writeln!(output, "namespace {}", namespace)?;
+    writeln!(output, "{{")?;
+    for symbol in symbols {
+        writeln!(
+            output,
+            "constexpr uintptr_t {}_ADDR = 0x{:X}; // {}",
+            sanitize_identifier(symbol.name()).to_uppercase(),
+            address(symbol, image_base),
+            symbol.confidence()
+        )?;
+    }
+    writeln!(output)?;
+    writeln!(output, "struct AddressEntry")?;
+    writeln!(output, "{{")?;
+    writeln!(output, "    const char* name;")?;
+    writeln!(output, "    uintptr_t {};", address_kind(image_base))?;
+    writeln!(output, "}};")?;
+    writeln!(output)?;
+    writeln!(output, "constexpr AddressEntry ADDRESSES[] = {{")?;
     for symbol in symbols {
         writeln!(
             output,
-            "const {}_ADDR: usize = 0x{:X};",
-            symbol.name().to_uppercase(),
-            symbol.rva()
+            "    {{\"{}\", {}_ADDR}},",
+            symbol.name(),
+            sanitize_identifier(symbol.name()).to_uppercase()
         )?;
     }
+    writeln!(output, "}};")?;
+    writeln!(output, "}} // namespace {}", namespace)?;
+
+    Ok(())
+}
+
+/// Writes a Breakpad symbol file. Function sizes are approximated by the distance to the next
+/// symbol (or the end of the text section) and the debug identifier is left zeroed.
+pub fn write_breakpad_symbols<W: Write>(
+    mut output: W,
+    symbols: &[FunctionSymbol],
+    module: &str,
+    props: &ExeProperties,
+    text_end: u64,
+) -> Result<()> {
+    const DEBUG_ID: &str = "000000000000000000000000000000000";
+
+    let os = match props.format() {
+        BinaryFormat::Pe | BinaryFormat::Coff => "windows",
+        BinaryFormat::MachO => "mac",
+        _ => "Linux",
+    };
+    let arch = match props.architecture() {
+        Architecture::X86_64 => "x86_64",
+        Architecture::I386 | Architecture::X86_64_X32 => "x86",
+        Architecture::Aarch64 => "arm64",
+        Architecture::Arm => "arm",
+        _ => "unknown",
+    };
+    writeln!(output, "MODULE {} {} {} {}", os, arch, DEBUG_ID, module)?;
+
+    // the records describe code, so the data symbols are left out
+    let functions: Vec<_> = symbols
+        .iter()
+        .filter(|symbol| symbol.data_type().is_none())
+        .cloned()
+        .collect();
+    for (symbol, size) in with_approximate_sizes(&functions, text_end) {
+        writeln!(output, "FUNC {:x} {:x} 0 {}", symbol.rva(), size, symbol.name())?;
+    }
 
     Ok(())
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListOrder {
+    Address,
+    Name,
+}
+
+impl FromStr for ListOrder {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "address" => Ok(Self::Address),
+            "name" => Ok(Self::Name),
+            other => Err(format!("unknown sort order '{other}'")),
+        }
+    }
+}
+
+/// Writes an `nm`-style listing of the symbols with their approximate sizes and signatures.
+pub fn write_symbol_list<W: Write>(
+    mut output: W,
+    symbols: &[FunctionSymbol],
+    text_end: u64,
+    order: ListOrder,
+    image_base: Option<u64>,
+) -> Result<()> {
+    let mut entries = with_approximate_sizes(symbols, text_end);
+    if order == ListOrder::Name {
+        entries.sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+    }
+    for (symbol, size) in entries {
+        writeln!(
+            output,
+            "{:016X} {:8X} {} {}",
+            address(symbol, image_base),
+            size,
+            symbol.name(),
+            match symbol.data_type() {
+                Some(typ) => typ.name().into_owned(),
+                None => signature(symbol.function_type()),
+            }
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the symbols sorted by address, with sizes approximated by the distance to the next
+/// symbol or to `end` for the last one.
+pub(crate) fn with_approximate_sizes(symbols: &[FunctionSymbol], end: u64) -> Vec<(&FunctionSymbol, u64)> {
+    let mut sorted: Vec<_> = symbols.iter().collect();
+    sorted.sort_by_key(|symbol| symbol.rva());
+    let ends: Vec<_> = sorted
+        .iter()
+        .skip(1)
+        .map(|next| next.rva())
+        .chain([end])
+        .collect();
+    sorted
+        .into_iter()
+        .zip(ends)
+        .map(|(symbol, end)| (symbol, end.max(symbol.rva()) - symbol.rva()))
+        .collect()
+}
+
+/// Returns the address of a symbol to write to the outputs, which is a virtual address
+/// when an image base is provided and an RVA otherwise.
+fn address(symbol: &FunctionSymbol, image_base: Option<u64>) -> u64 {
+    image_base.unwrap_or_default() + symbol.rva()
+}
+
+fn address_kind(image_base: Option<u64>) -> &'static str {
+    if image_base.is_some() {
+        "va"
+    } else {
+        "rva"
+    }
+}
+
+pub(crate) fn signature(typ: &FunctionType) -> String {
+    let params: Vec<_> = typ.params.iter().map(|param| param.name()).collect();
+    format!("{} ({})", typ.return_type.name(), params.join(", "))
+}
+
+/// Writes the symbols and the layouts of the types as JSON, followed by an index of the byte ranges
+/// of each symbol record in the file, so that individual symbols can be read without parsing
+/// the whole file. The symbols in `previews` get the disassembly of their first instructions.
+pub fn write_json<W: Write>(
+    mut output: W,
+    symbols: &[FunctionSymbol],
+    types: &TypeInfo,
+    image_base: Option<u64>,
+    previews: &HashMap<&str, Vec<String>>,
+    identity: &BinaryIdentity,
+) -> Result<()> {
+    let mut buffer = format!("{{\n\"schema_version\": {SCHEMA_VERSION},\n").into_bytes();
+    if let Some(identity) = identity_record(identity) {
+        buffer.extend_from_slice(b"\"identity\": ");
+        serde_json::to_writer(&mut buffer, &identity)?;
+        buffer.extend_from_slice(b",\n");
+    }
+    buffer.extend_from_slice(b"\"symbols\": [\n");
+    let mut index = BTreeMap::new();
+    for (i, symbol) in symbols.iter().enumerate() {
+        if i > 0 {
+            buffer.extend_from_slice(b",\n");
+        }
+        let address = address(symbol, image_base);
+        let record = SymbolRecord {
+            name: symbol.name().to_owned(),
+            confidence: symbol.confidence().as_str().to_owned(),
+            rva: image_base.is_none().then(|| address),
+            va: image_base.map(|_| address),
+            preview: previews.get(symbol.name()).cloned(),
+        };
+        let record = serde_json::to_vec(&record)?;
+        index.insert(symbol.name(), (buffer.len(), record.len()));
+        buffer.extend(record);
+    }
+    buffer.extend_from_slice(b"\n],\n\"types\": ");
+    serde_json::to_writer(&mut buffer, &type_records(types))?;
+    buffer.extend_from_slice(b",\n\"index\": ");
+    serde_json::to_writer(&mut buffer, &index)?;
+    buffer.extend_from_slice(b"\n}\n");
+    output.write_all(&buffer)?;
+
+    Ok(())
+}
+
+fn identity_record(identity: &BinaryIdentity) -> Option<IdentityRecord> {
+    let record = IdentityRecord {
+        file_version: identity.file_version.as_ref().map(ToString::to_string),
+        pdb: identity.pdb_id(),
+        build_id: identity.build_id_hex(),
+    };
+    (record.file_version.is_some() || record.pdb.is_some() || record.build_id.is_some()).then(|| record)
+}
+
+/// Returns the layouts of the structs and unions, sorted by name.
+pub(crate) fn type_records(types: &TypeInfo) -> Vec<TypeRecord> {
+    let members = |members: &[DataMember]| {
+        members
+            .iter()
+            .map(|member| MemberRecord {
+                name: member.name.to_string(),
+                typ: member.typ.name().into_owned(),
+                offset: member.bit_offset.map(|offset| offset / 8),
+            })
+            .collect()
+    };
+    types
+        .sorted_types()
+        .into_iter()
+        .filter_map(|typ| match typ {
+            Type::Struct(id) => {
+                let struct_ = &types.structs[&id];
+                Some(TypeRecord {
+                    name: struct_.name.to_string(),
+                    kind: "struct".to_owned(),
+                    size: struct_.size,
+                    members: members(&struct_.members),
+                })
+            }
+            Type::Union(id) => {
+                let union = &types.unions[&id];
+                Some(TypeRecord {
+                    name: union.name.to_string(),
+                    kind: "union".to_owned(),
+                    size: union.size,
+                    members: members(&union.members),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn write_x64dbg_database<W: Write>(output: W, symbols: &[FunctionSymbol], module: &str) -> Result<()> {
+    let labels: Vec<_> = symbols
+        .iter()
+        .map(|symbol| {
+            json!({
+                "module": module,
+                "address": format!("0x{:X}", symbol.rva()),
+                "manual": true,
+                "text": symbol.name(),
+            })
+        })
+        .collect();
+    serde_json::to_writer_pretty(output, &json!({ "labels": labels }))?;
+
+    Ok(())
+}
+
+/// Writes packed C definitions of the structs with explicit padding members, so that they have
+/// the same layout as in the executable regardless of the compiler.
+pub fn write_c_structs<W: Write>(mut output: W, types: &TypeInfo) -> Result<()> {
+    let defs = StructDefinitions::new(types);
+    writeln!(output)?;
+    writeln!(output, "#include <stdint.h>")?;
+    for enum_ in defined_enums(types) {
+        let name = sanitize_identifier(&enum_.name);
+        let (typ, _) = c_type(&enum_field_type(enum_));
+        writeln!(output)?;
+        writeln!(output, "typedef {typ} {name};")?;
+        for member in &enum_.members {
+            let value = enum_value_literal(member.value);
+            let member = sanitize_identifier(&member.name);
+            // C has no scoped enums, the enumerators of an enum class are prefixed with its name instead
+            if enum_.is_scoped {
+                writeln!(output, "static const {name} {name}_{member} = {value};")?;
+            } else {
+                writeln!(output, "static const {name} {member} = {value};")?;
+            }
+        }
+    }
+    writeln!(output)?;
+    writeln!(output, "#pragma pack(push, 1)")?;
+    for id in defs.ordered() {
+        let items = &defs.layouts[&id];
+        let struct_ = &types.structs[&id];
+        writeln!(output)?;
+        writeln!(output, "// size: 0x{:X}", struct_.size.unwrap_or_default())?;
+        writeln!(output, "struct {}", sanitize_identifier(&struct_.name))?;
+        writeln!(output, "{{")?;
+        for (item, name) in items.iter().zip(item_names(items)) {
+            let (typ, suffix) = c_type(&defs.field_type(item));
+            writeln!(output, "    {typ} {name}{suffix};")?;
+        }
+        writeln!(output, "}};")?;
+    }
+    writeln!(output)?;
+    writeln!(output, "#pragma pack(pop)")?;
+
+    Ok(())
+}
+
+/// Writes packed Rust definitions of the structs with explicit padding members, along with
+/// compile-time checks of their sizes.
+pub fn write_rust_structs<W: Write>(mut output: W, types: &TypeInfo) -> Result<()> {
+    let defs = StructDefinitions::new(types);
+    for enum_ in defined_enums(types) {
+        let name = rust_identifier(sanitize_identifier(&enum_.name));
+        let typ = rust_type(&enum_field_type(enum_));
+        writeln!(output)?;
+        // an enum class gets a newtype with associated constants, so that its enumerators stay
+        // in its own scope, while the enumerators of a plain enum are constants next to it
+        if enum_.is_scoped {
+            writeln!(output, "#[repr(transparent)]")?;
+            writeln!(output, "#[derive(Debug, Clone, Copy, PartialEq, Eq)]")?;
+            writeln!(output, "pub struct {name}(pub {typ});")?;
+            writeln!(output, "impl {name} {{")?;
+            for member in &enum_.members {
+                let value = enum_value_literal(member.value);
+                let member = rust_identifier(sanitize_identifier(&member.name));
+                writeln!(output, "    pub const {member}: Self = Self({value});")?;
+            }
+            writeln!(output, "}}")?;
+        } else {
+            writeln!(output, "pub type {name} = {typ};")?;
+            for member in &enum_.members {
+                let value = enum_value_literal(member.value);
+                let member = rust_identifier(sanitize_identifier(&member.name));
+                writeln!(output, "pub const {member}: {name} = {value};")?;
+            }
+        }
+    }
+    for id in defs.ordered() {
+        let items = &defs.layouts[&id];
+        let struct_ = &types.structs[&id];
+        let name = rust_identifier(sanitize_identifier(&struct_.name));
+        writeln!(output)?;
+        writeln!(output, "#[repr(C, packed)]")?;
+        writeln!(output, "pub struct {name} {{")?;
+        for (item, member) in items.iter().zip(item_names(items)) {
+            let typ = rust_type(&defs.field_type(item));
+            writeln!(output, "    pub {}: {typ},", rust_identifier(member.into()))?;
+        }
+        writeln!(output, "}}")?;
+        writeln!(
+            output,
+            "const _: () = assert!(std::mem::size_of::<{name}>() == 0x{:X});",
+            struct_.size.unwrap_or_default()
+        )?;
+    }
+
+    Ok(())
+}
+
+/// An entry of a struct definition written to the C and Rust outputs.
+enum StructItem<'a> {
+    VirtualTable,
+    Member(&'a DataMember),
+    Padding(usize),
+}
+
+/// A representation of a member type that can be written in both C and Rust.
+enum FieldType {
+    Int(bool, usize),
+    Float,
+    Double,
+    Pointer(Option<StructId>),
+    Struct(StructId),
+    Enum(EnumId),
+    Array(Box<FieldType>, usize),
+    Bytes(usize),
+}
+
+struct StructDefinitions<'a> {
+    types: &'a TypeInfo,
+    layouts: HashMap<StructId, Vec<StructItem<'a>>>,
+}
+
+impl<'a> StructDefinitions<'a> {
+    fn new(types: &'a TypeInfo) -> Self {
+        let layouts = types
+            .structs
+            .iter()
+            .filter_map(|(id, struct_)| Some((*id, padded_members(struct_, types)?)))
+            .collect();
+        Self { types, layouts }
+    }
+
+    /// Returns the structs sorted by name, with the structs that others contain by value placed before them.
+    fn ordered(&self) -> Vec<StructId> {
+        let mut ids: Vec<_> = self.layouts.keys().copied().collect();
+        ids.sort_by(|a, b| a.as_ref().as_str().cmp(b.as_ref().as_str()));
+
+        let mut visited = HashSet::new();
+        let mut res = Vec::with_capacity(ids.len());
+        for id in ids {
+            self.visit(id, &mut visited, &mut res);
+        }
+        res
+    }
+
+    fn visit(&self, id: StructId, visited: &mut HashSet<StructId>, res: &mut Vec<StructId>) {
+        if !visited.insert(id) {
+            return;
+        }
+        for item in &self.layouts[&id] {
+            if let StructItem::Member(member) = item {
+                if let Some(dep) = self.contained_struct(&member.typ) {
+                    self.visit(dep, visited, res);
+                }
+            }
+        }
+        res.push(id);
+    }
+
+    fn contained_struct(&self, typ: &Type) -> Option<StructId> {
+        match typ {
+            Type::Struct(id) if self.layouts.contains_key(id) => Some(*id),
+            Type::FixedArray(inner, _) => self.contained_struct(inner),
+            _ => None,
+        }
+    }
+
+    fn field_type(&self, item: &StructItem) -> FieldType {
+        match item {
+            StructItem::VirtualTable => FieldType::Pointer(None),
+            StructItem::Member(member) => self.convert_type(&member.typ),
+            StructItem::Padding(size) => FieldType::Bytes(*size),
+        }
+    }
+
+    fn convert_type(&self, typ: &Type) -> FieldType {
+        let size = typ.size(self.types).unwrap_or_default();
+        match typ {
+            Type::Bool => FieldType::Int(false, 1),
+            Type::Char(signed)
+            | Type::Short(signed)
+            | Type::Int(signed)
+            | Type::Long(signed)
+            | Type::LongLong(signed)
+            | Type::Int128(signed) => FieldType::Int(*signed, size),
+            Type::WChar => FieldType::Int(false, size),
+            Type::Enum(id) if matches!(size, 1 | 2 | 4 | 8) => FieldType::Enum(*id),
+            Type::Float => FieldType::Float,
+            Type::Double => FieldType::Double,
+            // MSVC's long double is a double, the x87 one has no equivalent and stays opaque
+            Type::LongDouble if size == 8 => FieldType::Double,
+            // pointers to incomplete structs and to the ones that can't be laid out are opaque
+            Type::Pointer(inner) | Type::Reference(inner) => match inner.as_ref() {
+                Type::Struct(id) if self.layouts.contains_key(id) => FieldType::Pointer(Some(*id)),
+                _ => FieldType::Pointer(None),
+            },
+            Type::Function(_) => FieldType::Pointer(None),
+            // member pointers have no C equivalent, they're written as bytes by the fallback below
+            Type::Struct(id) if self.layouts.contains_key(id) => FieldType::Struct(*id),
+            Type::FixedArray(inner, count) => FieldType::Array(self.convert_type(inner).into(), *count),
+            _ => FieldType::Bytes(size),
+        }
+    }
+}
+
+/// Places the members of a struct at the offsets reported by the frontend (or at the computed ones
+/// when they're not available) with padding in between them. Bitfields end up covered by padding
+/// and the members of anonymous structs and unions are inlined.
+fn padded_members<'a>(struct_: &'a StructType, types: &'a TypeInfo) -> Option<Vec<StructItem<'a>>> {
+    let size = struct_
+        .size
+        .or_else(|| struct_.compute_layout(types).map(|(_, size)| size))?;
+
+    let mut items = vec![];
+    let mut end = 0;
+    if struct_.has_virtual_methods(types) {
+        items.push(StructItem::VirtualTable);
+        end = types.data_model.pointer_size();
+    }
+    for (member, offset) in member_offsets(struct_, types)? {
+        if member.is_bitfield() {
+            continue;
+        }
+        // overlapping members can't be represented in a struct
+        if offset < end {
+            continue;
+        }
+        if offset > end {
+            items.push(StructItem::Padding(offset - end));
+        }
+        items.push(StructItem::Member(member));
+        end = offset + member.typ.size(types)?;
+    }
+    if end > size {
+        return None;
+    }
+    if size > end {
+        items.push(StructItem::Padding(size - end));
+    }
+    Some(items)
+}
+
+/// Returns the members of a struct with their offsets, the members of anonymous structs and unions
+/// are returned in place of the anonymous member.
+fn member_offsets<'a>(
+    struct_: &'a StructType,
+    types: &'a TypeInfo,
+) -> Option<Vec<(&'a DataMember, usize)>> {
+    let computed = struct_.compute_layout(types);
+    let mut res = vec![];
+    for (i, member) in struct_.all_members(types).enumerate() {
+        let offset = match member.bit_offset {
+            Some(bits) => bits / u8::BITS as usize,
+            None => computed.as_ref()?.0[i],
+        };
+        match &member.typ {
+            Type::Struct(id) if member.is_anonymous => {
+                let inner = member_offsets(types.structs.get(id)?, types)?;
+                res.extend(inner.into_iter().map(|(member, inner)| (member, offset + inner)));
+            }
+            Type::Union(id) if member.is_anonymous => {
+                for member in &types.unions.get(id)?.members {
+                    let inner = member.bit_offset.map_or(0, |bits| bits / u8::BITS as usize);
+                    res.push((member, offset + inner));
+                }
+            }
+            _ => res.push((member, offset)),
+        }
+    }
+    Some(res)
+}
+
+/// Returns the enums with a size the C and Rust outputs have an integer type for, sorted by name.
+fn defined_enums(types: &TypeInfo) -> Vec<&EnumType> {
+    types
+        .sorted_types()
+        .into_iter()
+        .filter_map(|typ| match typ {
+            Type::Enum(id) => types.enums.get(&id),
+            _ => None,
+        })
+        .filter(|enum_| matches!(enum_.size, Some(1 | 2 | 4 | 8)))
+        .collect()
+}
+
+fn enum_field_type(enum_: &EnumType) -> FieldType {
+    FieldType::Int(enum_.is_signed(), enum_.size.unwrap_or_default())
+}
+
+/// Unsigned values are written in hex, so that the large ones aren't taken for signed integers.
+fn enum_value_literal(value: EnumValue) -> String {
+    match value {
+        EnumValue::Signed(value) => value.to_string(),
+        EnumValue::Unsigned(value) => format!("0x{value:X}"),
+    }
+}
+
+fn item_names(items: &[StructItem]) -> Vec<String> {
+    let mut used = HashSet::new();
+    let mut paddings = 0;
+    items
+        .iter()
+        .map(|item| {
+            let name = match item {
+                StructItem::VirtualTable => "_vftable".to_owned(),
+                StructItem::Member(member) => sanitize_identifier(&member.name).into_owned(),
+                StructItem::Padding(_) => {
+                    paddings += 1;
+                    format!("_pad_{}", paddings - 1)
+                }
+            };
+            let mut unique = name.clone();
+            let mut i = 1;
+            while !used.insert(unique.clone()) {
+                unique = format!("{name}_{i}");
+                i += 1;
+            }
+            unique
+        })
+        .collect()
+}
+
+/// Returns the name of a C type along with a declarator suffix for arrays.
+fn c_type(typ: &FieldType) -> (Cow<'static, str>, String) {
+    match typ {
+        FieldType::Int(true, 16) => ("__int128".into(), String::new()),
+        FieldType::Int(false, 16) => ("unsigned __int128".into(), String::new()),
+        FieldType::Int(true, size) => (format!("int{}_t", size * 8).into(), String::new()),
+        FieldType::Int(false, size) => (format!("uint{}_t", size * 8).into(), String::new()),
+        FieldType::Float => ("float".into(), String::new()),
+        FieldType::Double => ("double".into(), String::new()),
+        FieldType::Pointer(Some(id)) => (
+            format!("struct {}*", sanitize_identifier(id.as_ref())).into(),
+            String::new(),
+        ),
+        FieldType::Pointer(None) => ("void*".into(), String::new()),
+        FieldType::Struct(id) => (
+            format!("struct {}", sanitize_identifier(id.as_ref())).into(),
+            String::new(),
+        ),
+        FieldType::Enum(id) => (
+            sanitize_identifier(id.as_ref()).into_owned().into(),
+            String::new(),
+        ),
+        FieldType::Array(inner, count) => {
+            let (name, suffix) = c_type(inner);
+            (name, format!("[{count}]{suffix}"))
+        }
+        FieldType::Bytes(size) => ("uint8_t".into(), format!("[{size}]")),
+    }
+}
+
+fn rust_type(typ: &FieldType) -> String {
+    match typ {
+        FieldType::Int(true, size) => format!("i{}", size * 8),
+        FieldType::Int(false, size) => format!("u{}", size * 8),
+        FieldType::Float => "f32".to_owned(),
+        FieldType::Double => "f64".to_owned(),
+        FieldType::Pointer(Some(id)) => {
+            format!("*mut {}", rust_identifier(sanitize_identifier(id.as_ref())))
+        }
+        FieldType::Pointer(None) => "*mut std::ffi::c_void".to_owned(),
+        FieldType::Struct(id) => rust_identifier(sanitize_identifier(id.as_ref())).into_owned(),
+        FieldType::Enum(id) => rust_identifier(sanitize_identifier(id.as_ref())).into_owned(),
+        FieldType::Array(inner, count) => format!("[{}; {count}]", rust_type(inner)),
+        FieldType::Bytes(size) => format!("[u8; {size}]"),
+    }
+}
+
+fn rust_identifier(name: Cow<str>) -> Cow<str> {
+    if RUST_KEYWORDS.contains(&name.as_ref()) {
+        format!("{name}_").into()
+    } else {
+        name
+    }
+}
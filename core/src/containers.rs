@@ -0,0 +1,261 @@
+use serde::Deserialize;
+use ustr::Ustr;
+
+use crate::error::{Error, Result};
+use crate::types::{align_up, DataMember, StructType, Type};
+
+/// Templates from the standard library and user-defined ones that are replaced with compact
+/// synthetic structs, instead of exposing the internals of their implementation.
+///
+/// ```toml
+/// [[containers]]
+/// template = "RED4ext::DynArray"
+/// members = [
+///     { name = "entries", type = "pointer", arg = 0 },
+///     { name = "capacity", type = "u32" },
+///     { name = "size", type = "u32" },
+/// ]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct ContainerMap {
+    containers: Vec<ContainerLayout>,
+}
+
+impl ContainerMap {
+    /// The common templates of the standard library, the layout of strings depends on the implementation.
+    pub fn builtin(std_lib: StdLib) -> Self {
+        let pointer = |name: &str| ContainerMember::new(name, MemberType::Pointer { arg: 0 });
+        let size = |name: &str| ContainerMember::new(name, MemberType::Size);
+        let string = match std_lib {
+            // the buffer for short strings overlaps with the pointer
+            StdLib::Msvc => vec![
+                pointer("data"),
+                ContainerMember::new("size", MemberType::Size).at(16),
+                size("capacity"),
+            ],
+            StdLib::Gnu => vec![pointer("data"), size("size")],
+        };
+        let containers = vec![
+            ContainerLayout::new("std::vector", vec![
+                pointer("begin"),
+                pointer("end"),
+                pointer("capacity_end"),
+            ]),
+            ContainerLayout::new("std::basic_string", string),
+            ContainerLayout::new("std::unique_ptr", vec![pointer("ptr")]),
+            ContainerLayout::new("std::shared_ptr", vec![
+                pointer("ptr"),
+                ContainerMember::new("control_block", MemberType::Opaque),
+            ]),
+            ContainerLayout::new("std::weak_ptr", vec![
+                pointer("ptr"),
+                ContainerMember::new("control_block", MemberType::Opaque),
+            ]),
+        ];
+        Self { containers }
+    }
+
+    #[cfg(feature = "native")]
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(str: &str) -> Result<Self> {
+        toml::from_str(str).map_err(Error::ContainerMapError)
+    }
+
+    /// Adds the layouts of another map, which take precedence over the existing ones.
+    pub fn extend(&mut self, other: ContainerMap) {
+        self.containers.splice(0..0, other.containers);
+    }
+
+    /// Returns the layout for a fully qualified template name like `std::vector`.
+    pub fn get(&self, template: &str) -> Option<&ContainerLayout> {
+        self.containers.iter().find(|layout| layout.template == template)
+    }
+}
+
+/// The standard library implementation, which determines the layout of some of the templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdLib {
+    Msvc,
+    /// libstdc++, also used for libc++ which has the same layout for everything but strings.
+    Gnu,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContainerLayout {
+    pub template: String,
+    pub members: Vec<ContainerMember>,
+}
+
+impl ContainerLayout {
+    fn new(template: &str, members: Vec<ContainerMember>) -> Self {
+        Self {
+            template: template.to_owned(),
+            members,
+        }
+    }
+
+    /// Returns the number of leading template arguments referred to by the members.
+    pub fn arg_count(&self) -> usize {
+        self.members
+            .iter()
+            .filter_map(|member| match member.typ {
+                MemberType::Pointer { arg } => Some(arg + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Creates a synthetic struct for an instance of the template with the given arguments. Members are
+    /// laid out one after another unless they have an explicit offset, the size is left to the frontend.
+    pub fn instantiate(
+        &self,
+        name: Ustr,
+        args: &[Type],
+        pointer_size: usize,
+        size: Option<usize>,
+    ) -> StructType {
+        let mut offset = 0;
+        let mut members = vec![];
+        for member in &self.members {
+            let member_size = member.typ.size(pointer_size);
+            offset = member.offset.unwrap_or_else(|| align_up(offset, member_size));
+            members.push(DataMember {
+                bit_offset: Some(offset * u8::BITS as usize),
+                ..DataMember::basic(
+                    member.name.as_str().into(),
+                    member.typ.resolve(args, pointer_size),
+                )
+            });
+            offset += member_size;
+        }
+        StructType {
+            name,
+            base: None,
+            members,
+            virtual_methods: vec![],
+            size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContainerMember {
+    pub name: String,
+    #[serde(flatten)]
+    pub typ: MemberType,
+    /// Offset in bytes, by default the member follows the previous one.
+    #[serde(default)]
+    pub offset: Option<usize>,
+}
+
+impl ContainerMember {
+    fn new(name: &str, typ: MemberType) -> Self {
+        Self {
+            name: name.to_owned(),
+            typ,
+            offset: None,
+        }
+    }
+
+    fn at(self, offset: usize) -> Self {
+        Self {
+            offset: Some(offset),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MemberType {
+    /// A pointer to one of the template arguments.
+    Pointer {
+        #[serde(default)]
+        arg: usize,
+    },
+    /// A pointer to something that isn't exposed.
+    Opaque,
+    /// An unsigned integer with the size of a pointer, like `size_t`.
+    Size,
+    U32,
+    I32,
+}
+
+impl MemberType {
+    fn size(self, pointer_size: usize) -> usize {
+        match self {
+            MemberType::Pointer { .. } | MemberType::Opaque | MemberType::Size => pointer_size,
+            MemberType::U32 | MemberType::I32 => 4,
+        }
+    }
+
+    fn resolve(self, args: &[Type], pointer_size: usize) -> Type {
+        match self {
+            MemberType::Pointer { arg } => {
+                Type::Pointer(args.get(arg).cloned().unwrap_or(Type::Void).into())
+            }
+            MemberType::Opaque => Type::Pointer(Type::Void.into()),
+            MemberType::Size if pointer_size == 8 => Type::LongLong(false),
+            MemberType::Size => Type::Int(false),
+            MemberType::U32 => Type::Int(false),
+            MemberType::I32 => Type::Int(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instantiate_builtin_string() {
+        let map = ContainerMap::builtin(StdLib::Msvc);
+        let layout = map.get("std::basic_string").unwrap();
+        let string = layout.instantiate("std::string".into(), &[Type::Char(true)], 8, Some(32));
+
+        let members: Vec<_> = string
+            .members
+            .iter()
+            .map(|member| (member.name.as_str(), member.bit_offset.unwrap() / 8))
+            .collect();
+        assert_eq!(members, [("data", 0), ("size", 16), ("capacity", 24)]);
+        assert_eq!(string.members[0].typ, Type::Pointer(Type::Char(true).into()));
+        assert_eq!(string.size, Some(32));
+    }
+
+    #[test]
+    fn load_custom_containers() {
+        let custom: ContainerMap = toml::from_str(
+            r#"
+            [[containers]]
+            template = "std::vector"
+            members = [
+                { name = "entries", type = "pointer", arg = 0 },
+                { name = "capacity", type = "u32" },
+                { name = "size", type = "u32" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let mut map = ContainerMap::builtin(StdLib::Gnu);
+        map.extend(custom);
+
+        let array = map.get("std::vector").unwrap().instantiate(
+            "std::vector<int>".into(),
+            &[Type::Int(true)],
+            8,
+            Some(16),
+        );
+        let members: Vec<_> = array
+            .members
+            .iter()
+            .map(|member| (member.name.as_str(), member.bit_offset.unwrap() / 8))
+            .collect();
+        assert_eq!(members, [("entries", 0), ("capacity", 8), ("size", 12)]);
+        assert_eq!(array.members[2].typ, Type::Int(false));
+    }
+}
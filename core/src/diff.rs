@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::error::Result;
+use crate::schema::{DiffReport, MovedSymbolRecord, SymbolMap, TypeChangeRecord, TypeRecord, SCHEMA_VERSION};
+
+/// Compares the symbols and the type layouts of two symbol maps written with `--json-output`.
+/// Symbols are matched by name, so renamed symbols are reported as removed and added.
+pub fn diff_symbol_maps(old: &SymbolMap, new: &SymbolMap) -> DiffReport {
+    let old_symbols: BTreeMap<_, _> = old.symbols.iter().map(|sym| (sym.name.as_str(), sym)).collect();
+    let new_symbols: BTreeMap<_, _> = new.symbols.iter().map(|sym| (sym.name.as_str(), sym)).collect();
+
+    let added = new_symbols
+        .iter()
+        .filter(|(name, _)| !old_symbols.contains_key(*name))
+        .map(|(_, sym)| (*sym).clone())
+        .collect();
+    let removed = old_symbols
+        .iter()
+        .filter(|(name, _)| !new_symbols.contains_key(*name))
+        .map(|(_, sym)| (*sym).clone())
+        .collect();
+    let moved = old_symbols
+        .iter()
+        .filter_map(|(name, old)| {
+            let old_address = old.address()?;
+            let new_address = new_symbols.get(name)?.address()?;
+            (old_address != new_address).then(|| MovedSymbolRecord {
+                name: name.to_string(),
+                old_address,
+                new_address,
+            })
+        })
+        .collect();
+
+    let old_types: BTreeMap<_, _> = old.types.iter().map(|typ| (typ.name.as_str(), typ)).collect();
+    let new_types: BTreeMap<_, _> = new.types.iter().map(|typ| (typ.name.as_str(), typ)).collect();
+    let mut names: Vec<_> = old_types.keys().chain(new_types.keys()).copied().collect();
+    names.sort_unstable();
+    names.dedup();
+    let type_changes = names
+        .into_iter()
+        .filter_map(|name| {
+            let old = old_types.get(name).copied();
+            let new = new_types.get(name).copied();
+            (old != new).then(|| TypeChangeRecord {
+                name: name.to_owned(),
+                old: old.cloned(),
+                new: new.cloned(),
+            })
+        })
+        .collect();
+
+    DiffReport {
+        schema_version: SCHEMA_VERSION,
+        added,
+        removed,
+        moved,
+        type_changes,
+    }
+}
+
+/// Writes a human-readable summary of the differences, with `+` for additions, `-` for removals
+/// and `~` for changes.
+pub fn write_diff_summary<W: Write>(mut output: W, report: &DiffReport) -> Result<()> {
+    for sym in &report.added {
+        writeln!(output, "+ {} 0x{:X}", sym.name, sym.address().unwrap_or_default())?;
+    }
+    for sym in &report.removed {
+        writeln!(output, "- {} 0x{:X}", sym.name, sym.address().unwrap_or_default())?;
+    }
+    for moved in &report.moved {
+        let delta = moved.new_address as i64 - moved.old_address as i64;
+        let sign = if delta < 0 { '-' } else { '+' };
+        writeln!(
+            output,
+            "~ {} 0x{:X} -> 0x{:X} ({sign}0x{:X})",
+            moved.name,
+            moved.old_address,
+            moved.new_address,
+            delta.unsigned_abs()
+        )?;
+    }
+    for change in &report.type_changes {
+        match (&change.old, &change.new) {
+            (None, Some(new)) => writeln!(output, "+ {} {}{}", new.kind, new.name, size_suffix(new))?,
+            (Some(old), None) => writeln!(output, "- {} {}{}", old.kind, old.name, size_suffix(old))?,
+            (Some(old), Some(new)) => write_layout_changes(&mut output, old, new)?,
+            (None, None) => {}
+        }
+    }
+    writeln!(
+        output,
+        "{} added, {} removed, {} moved, {} type(s) changed",
+        report.added.len(),
+        report.removed.len(),
+        report.moved.len(),
+        report.type_changes.len()
+    )?;
+    Ok(())
+}
+
+fn write_layout_changes<W: Write>(mut output: W, old: &TypeRecord, new: &TypeRecord) -> Result<()> {
+    write!(output, "~ {} {}", new.kind, new.name)?;
+    if old.size != new.size {
+        write!(output, ": size {} -> {}", hex(old.size), hex(new.size))?;
+    }
+    writeln!(output)?;
+
+    let old_members: BTreeMap<_, _> = old.members.iter().map(|member| (&member.name, member)).collect();
+    let new_names: Vec<_> = new.members.iter().map(|member| &member.name).collect();
+    for member in &new.members {
+        match old_members.get(&member.name) {
+            None => writeln!(
+                output,
+                "    + {}: {} at {}",
+                member.name,
+                member.typ,
+                hex(member.offset)
+            )?,
+            Some(prev) if prev.typ != member.typ => {
+                writeln!(output, "    ~ {}: {} -> {}", member.name, prev.typ, member.typ)?
+            }
+            Some(prev) if prev.offset != member.offset => writeln!(
+                output,
+                "    ~ {}: offset {} -> {}",
+                member.name,
+                hex(prev.offset),
+                hex(member.offset)
+            )?,
+            Some(_) => {}
+        }
+    }
+    for member in old
+        .members
+        .iter()
+        .filter(|member| !new_names.contains(&&member.name))
+    {
+        writeln!(output, "    - {}: {}", member.name, member.typ)?;
+    }
+    Ok(())
+}
+
+fn size_suffix(typ: &TypeRecord) -> String {
+    typ.size
+        .map(|size| format!(" (size 0x{size:X})"))
+        .unwrap_or_default()
+}
+
+fn hex(value: Option<usize>) -> String {
+    value.map_or_else(|| "?".to_owned(), |value| format!("0x{value:X}"))
+}
+
+/// Runs the `diff` command, which prints the differences between two symbol maps and optionally
+/// writes them as JSON.
+#[cfg(feature = "native")]
+pub fn run(opts: &crate::opts::DiffOpts) -> Result<()> {
+    let old: SymbolMap = serde_json::from_slice(&std::fs::read(&opts.old_path)?)?;
+    let new: SymbolMap = serde_json::from_slice(&std::fs::read(&opts.new_path)?)?;
+    let report = diff_symbol_maps(&old, &new);
+    write_diff_summary(std::io::stdout().lock(), &report)?;
+    if let Some(path) = &opts.json_output_path {
+        serde_json::to_writer_pretty(std::fs::File::create(path)?, &report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{MemberRecord, SymbolRecord};
+
+    fn symbol(name: &str, rva: u64) -> SymbolRecord {
+        SymbolRecord {
+            name: name.to_owned(),
+            confidence: "unique".to_owned(),
+            rva: Some(rva),
+            va: None,
+            preview: None,
+        }
+    }
+
+    fn player(health_offset: usize) -> TypeRecord {
+        TypeRecord {
+            name: "Player".to_owned(),
+            kind: "struct".to_owned(),
+            size: Some(0x10),
+            members: vec![MemberRecord {
+                name: "health".to_owned(),
+                typ: "int".to_owned(),
+                offset: Some(health_offset),
+            }],
+        }
+    }
+
+    #[test]
+    fn diff_symbols_and_types() {
+        let old = SymbolMap {
+            schema_version: SCHEMA_VERSION,
+            identity: None,
+            symbols: vec![symbol("get_player", 0x1000), symbol("get_world", 0x2000)],
+            types: vec![player(0x8)],
+            index: BTreeMap::new(),
+        };
+        let new = SymbolMap {
+            schema_version: SCHEMA_VERSION,
+            identity: None,
+            symbols: vec![symbol("get_player", 0x1010), symbol("give_item", 0x3000)],
+            types: vec![player(0xC)],
+            index: BTreeMap::new(),
+        };
+        let report = diff_symbol_maps(&old, &new);
+        assert_eq!(report.added[0].name, "give_item");
+        assert_eq!(report.removed[0].name, "get_world");
+        assert_eq!(report.moved[0].new_address, 0x1010);
+        assert_eq!(report.type_changes.len(), 1);
+
+        let mut output = vec![];
+        write_diff_summary(&mut output, &report).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "+ give_item 0x3000\n\
+             - get_world 0x2000\n\
+             ~ get_player 0x1000 -> 0x1010 (+0x10)\n\
+             ~ struct Player\n    ~ health: offset 0x8 -> 0xC\n\
+             1 added, 1 removed, 1 moved, 1 type(s) changed\n"
+        );
+    }
+}
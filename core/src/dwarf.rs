@@ -1,86 +1,620 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use gimli::write::{Address, AttributeValue, DwarfUnit, EndianVec, Sections, Unit, UnitEntryId};
-use gimli::{DwAte, DwTag};
-use object::{BinaryFormat, SectionKind};
+use flate2::write::ZlibEncoder;
+use gimli::write::{
+    Address, AttributeValue, Dwarf, EndianVec, Expression, FileId, LineProgram, LineString, Sections, Unit, UnitEntryId
+};
+use gimli::{DwAte, DwTag, DwUt, Endianity};
+use object::write::StandardSegment;
+use object::{elf, BinaryFormat, Endian, Endianness, SectionFlags, SectionKind};
 
+use crate::cache::stable_hash;
+use crate::codegen::with_approximate_sizes;
 use crate::error::{Error, Result};
 use crate::exe::ExeProperties;
+use crate::filter::TypeFilter;
+use crate::inject;
+use crate::names::NameStyle;
+use crate::spec::SourceLocation;
 use crate::symbols::FunctionSymbol;
 use crate::types::*;
 
+/// Name of the section with an index of function names to their DIE offsets in `.debug_info`.
+pub const INDEX_SECTION: &str = ".zoltan_index";
+const INDEX_MAGIC: &[u8; 4] = b"ZIDX";
+const INDEX_VERSION: u32 = 1;
+/// Name of the section with a hash of the spec file the symbols were resolved from.
+pub const SOURCE_HASH_SECTION: &str = ".zoltan_source";
+const SOURCE_HASH_MAGIC: &[u8; 4] = b"ZSRC";
+const SOURCE_HASH_VERSION: u32 = 1;
+/// The `DW_AT_producer` of the compilation units, it only has the version so that the output stays
+/// the same between machines.
+const PRODUCER: &str = concat!("zoltan ", env!("CARGO_PKG_VERSION"));
+const ELFCOMPRESS_ZSTD: u32 = 2;
+/// gimli can't write the ID that pairs skeleton and split units into the unit headers, so it's written
+/// as the first attribute of the root entry instead and moved into the header later, see [`into_split_unit`].
+const DWO_ID_PLACEHOLDER: gimli::DwAt = gimli::DW_AT_GNU_dwo_id;
+
+/// Options that control the contents of the DWARF output.
+#[derive(Debug, Clone)]
+pub struct DwarfOptions {
+    /// Whether to write all of the types found in the sources, not only the ones used by the symbols.
+    pub eager_type_export: bool,
+    /// Types that are left as declarations, even when they're used by the symbols.
+    pub type_filter: TypeFilter,
+    pub name_style: NameStyle,
+    /// Compression of the debug sections in ELF files.
+    pub compression: Option<DebugCompression>,
+    pub version: DwarfVersion,
+    pub unit: UnitInfo,
+}
+
+/// DWARF 4 is meant for older tools that don't understand the forms and attributes added in DWARF 5,
+/// split units are only written in DWARF 5.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DwarfVersion {
+    V4,
+    V5,
+}
+
+impl DwarfVersion {
+    fn number(self) -> u16 {
+        match self {
+            Self::V4 => 4,
+            Self::V5 => 5,
+        }
+    }
+}
+
+impl FromStr for DwarfVersion {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "4" => Ok(Self::V4),
+            "5" => Ok(Self::V5),
+            other => Err(format!("unsupported DWARF version '{other}'")),
+        }
+    }
+}
+
+/// Describes where the symbols come from, so that consumers of the DWARF output can trace it back
+/// to the sources. It's written to the root entry of the compilation unit.
+#[derive(Debug, Clone, Default)]
+pub struct UnitInfo {
+    /// The spec file, written as `DW_AT_name`.
+    pub name: Option<PathBuf>,
+    /// The directory relative paths are based on, written as `DW_AT_comp_dir`.
+    pub comp_dir: Option<PathBuf>,
+    /// A hash of the contents of the spec file, written to the [`SOURCE_HASH_SECTION`] when it's set.
+    pub source_hash: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCompression {
+    Zlib,
+    Zstd,
+}
+
+impl FromStr for DebugCompression {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "zlib" => Ok(Self::Zlib),
+            "zstd" => Ok(Self::Zstd),
+            other => Err(format!("unknown compression '{other}'")),
+        }
+    }
+}
+
 pub fn write_symbol_file<W>(
     output: W,
-    symbols: Vec<FunctionSymbol>,
+    symbols: &[FunctionSymbol],
     type_info: &TypeInfo,
     props: ExeProperties,
-    eager_type_export: bool,
+    options: DwarfOptions,
 ) -> Result<()>
 where
     W: io::Write,
 {
-    const DWARF_VERSION: u16 = 5;
+    let sections = debug_sections(symbols, type_info, &props, &options, false)?;
+    write_object(output, &props, sections, options.compression)
+}
 
-    let encoding = gimli::Encoding {
-        format: if props.is64bit() {
-            gimli::Format::Dwarf64
+/// Writes a DWARF 5 skeleton unit to the output and the rest of the debug info to a `.dwo` file,
+/// which debuggers only load once they need it. The version in the options is ignored.
+#[cfg(feature = "native")]
+pub fn write_split_symbol_files<W>(
+    output: W,
+    dwo_path: &Path,
+    symbols: &[FunctionSymbol],
+    type_info: &TypeInfo,
+    props: ExeProperties,
+    options: DwarfOptions,
+) -> Result<()>
+where
+    W: io::Write,
+{
+    let options = DwarfOptions {
+        version: DwarfVersion::V5,
+        ..options
+    };
+    let mut sections = debug_sections(symbols, type_info, &props, &options, true)?;
+    let dwo_id = stable_hash(&sections);
+    into_split_unit(
+        &mut sections,
+        &props,
+        gimli::DW_UT_split_compile,
+        gimli::DW_TAG_compile_unit,
+        dwo_id,
+    )?;
+
+    let dwo_name = dwo_path.file_name().unwrap_or_default().to_string_lossy();
+    let mut skeleton = skeleton_sections(&props, &dwo_name, &options.unit)?;
+    into_split_unit(
+        &mut skeleton,
+        &props,
+        gimli::DW_UT_skeleton,
+        gimli::DW_TAG_skeleton_unit,
+        dwo_id,
+    )?;
+    write_object(output, &props, skeleton, options.compression)?;
+
+    let sections =
+        sections
+            .into_iter()
+            .filter(|(_, data)| !data.is_empty())
+            .map(|(name, data)| match name {
+                INDEX_SECTION | SOURCE_HASH_SECTION => (name.to_owned(), data),
+                _ => (format!("{name}.dwo"), data),
+            });
+    write_object(
+        std::fs::File::create(dwo_path)?,
+        &props,
+        sections,
+        options.compression,
+    )
+}
+
+/// Writes a copy of the executable with the debug sections appended to it.
+pub fn write_injected_symbol_file<W>(
+    mut output: W,
+    exe: &[u8],
+    symbols: &[FunctionSymbol],
+    type_info: &TypeInfo,
+    props: ExeProperties,
+    options: DwarfOptions,
+) -> Result<()>
+where
+    W: io::Write,
+{
+    let sections = debug_sections(symbols, type_info, &props, &options, false)?;
+    output.write_all(&inject::append_sections(exe, &sections)?)?;
+
+    Ok(())
+}
+
+fn write_object<W, S>(
+    output: W,
+    props: &ExeProperties,
+    sections: impl IntoIterator<Item = (S, Vec<u8>)>,
+    compression: Option<DebugCompression>,
+) -> Result<()>
+where
+    W: io::Write,
+    S: AsRef<str>,
+{
+    // Mach-O debug info is expected in a Mach-O file like in a dSYM bundle, everything else gets ELF
+    let format = match props.format() {
+        BinaryFormat::MachO => BinaryFormat::MachO,
+        _ => BinaryFormat::Elf,
+    };
+    let mut obj = props.replicate_object(format);
+    let segment = obj.segment_name(StandardSegment::Debug).to_vec();
+    for (name, data) in sections {
+        let kind = if matches!(name.as_ref(), INDEX_SECTION | SOURCE_HASH_SECTION) {
+            SectionKind::Metadata
         } else {
-            gimli::Format::Dwarf32
-        },
-        version: DWARF_VERSION,
-        address_size: props.address_size(),
+            SectionKind::Debug
+        };
+        let name = section_name(format, props.object_section_name(name.as_ref()));
+        let id = obj.add_section(segment.clone(), name, kind);
+        match compression {
+            Some(compression)
+                if format == BinaryFormat::Elf && kind == SectionKind::Debug && !data.is_empty() =>
+            {
+                obj.set_section_data(id, compress_section(&data, compression, props)?, 8);
+                let sh_flags = elf::SHF_COMPRESSED.into();
+                obj.section_mut(id).flags = SectionFlags::Elf { sh_flags };
+            }
+            _ => obj.set_section_data(id, data, 8),
+        }
+    }
+    props.write_object(&obj, output)
+}
+
+/// Returns the names and the contents of the debug sections, including the [`INDEX_SECTION`] and
+/// the [`SOURCE_HASH_SECTION`] when there's a hash. Units meant to be split get a placeholder for the unit ID.
+fn debug_sections(
+    symbols: &[FunctionSymbol],
+    type_info: &TypeInfo,
+    props: &ExeProperties,
+    options: &DwarfOptions,
+    split: bool,
+) -> Result<Vec<(&'static str, Vec<u8>)>> {
+    let mut dwarf = Dwarf::new();
+    let encoding = encoding(props, options.version)?;
+    // the line program of a split unit would belong to the skeleton, so it's only written for regular units
+    let line_program = match symbols.iter().find_map(FunctionSymbol::location) {
+        Some(location) if !split => line_program(location, &options.unit, encoding),
+        _ => LineProgram::none(),
     };
-    let mut dwarf = DwarfUnit::new(encoding);
-    let mut writer = DwarfWriter::new(&mut dwarf.unit, type_info);
+    let has_lines = !line_program.is_none();
+    let unit_id = dwarf.units.add(Unit::new(encoding, line_program));
+    let unit = dwarf.units.get_mut(unit_id);
+    let root = unit.root();
+    let entry = unit.get_mut(root);
+    if split {
+        entry.set(DWO_ID_PLACEHOLDER, AttributeValue::Data8(0));
+    }
+    entry.set(
+        gimli::DW_AT_producer,
+        AttributeValue::String(PRODUCER.as_bytes().to_vec()),
+    );
+    if let Some(name) = &options.unit.name {
+        entry.set(gimli::DW_AT_name, path_attribute(name));
+    }
+    // the compilation directory of a split unit is written to the skeleton
+    if let Some(dir) = options.unit.comp_dir.as_ref().filter(|_| !split) {
+        entry.set(gimli::DW_AT_comp_dir, path_attribute(dir));
+    }
+    if has_lines {
+        entry.set(gimli::DW_AT_stmt_list, AttributeValue::LineProgramRef);
+    }
+    let mut writer = DwarfWriter::new(
+        dwarf.units.get_mut(unit_id),
+        type_info,
+        options.name_style,
+        options.version,
+    )
+    .with_type_filter(options.type_filter.clone());
+    // all of the structs get defined in an eager export, so their layouts are worth computing upfront
+    if options.eager_type_export {
+        writer = writer.with_layouts(StructLayout::compute_all(type_info));
+    }
+    let mut functions = Vec::with_capacity(symbols.len());
     for sym in symbols {
-        writer.define_function_symbol(sym, props.image_base());
+        let name = sym.name().to_owned();
+        let id = match sym.data_type() {
+            Some(typ) => writer.define_data_symbol(sym, typ, props),
+            None => writer.define_function_symbol(sym, props),
+        };
+        functions.push((name, id));
     }
 
-    if eager_type_export {
-        for id in type_info.structs.keys() {
-            writer.get_or_define_type(&Type::Struct(*id));
-        }
-        for id in type_info.unions.keys() {
-            writer.get_or_define_type(&Type::Union(*id));
+    if options.eager_type_export {
+        for typ in type_info.sorted_types() {
+            if options.type_filter.is_exported(&typ.name()) {
+                writer.get_or_define_type(&typ);
+            }
         }
-        for id in type_info.enums.keys() {
-            writer.get_or_define_type(&Type::Enum(*id));
+    }
+    writer.define_pending_types();
+    let line_program = &mut dwarf.units.get_mut(unit_id).line_program;
+    if !line_program.is_none() {
+        write_line_sequences(line_program, symbols, props);
+    }
+
+    let mut sections = Sections::new(EndianVec::new(runtime_endian(props)));
+    let line_strings = dwarf.line_strings.write(&mut sections.debug_line_str)?;
+    let strings = dwarf.strings.write(&mut sections.debug_str)?;
+    let offsets = dwarf.units.write(&mut sections, &line_strings, &strings)?;
+    let index = functions
+        .into_iter()
+        .map(|(name, id)| (name, offsets.entry(unit_id, id).0 as u64))
+        .collect();
+
+    let mut res = take_sections(sections)?;
+    res.push((INDEX_SECTION, encode_index(index)));
+    if let Some(hash) = options.unit.source_hash {
+        res.push((SOURCE_HASH_SECTION, encode_source_hash(hash)));
+    }
+    Ok(res)
+}
+
+/// Creates a line program with the compilation directory and the spec file of the unit. When they're
+/// not known, the directory and the file of a spec are used instead.
+fn line_program(location: &SourceLocation, unit: &UnitInfo, encoding: gimli::Encoding) -> LineProgram {
+    let file = unit.name.as_deref().unwrap_or(&location.file);
+    let (dir, name) = match &unit.comp_dir {
+        Some(dir) => (dir.as_path(), file),
+        None => {
+            let dir = match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            (dir, file.file_name().map_or(file, Path::new))
         }
+    };
+    LineProgram::new(
+        encoding,
+        Default::default(),
+        line_string(dir),
+        line_string(name),
+        None,
+    )
+}
+
+/// Writes a sequence for each of the symbols, which maps the whole function to the line of its typedef.
+/// Unless their size is known, the functions are assumed to end where the next one starts and the last one
+/// is given a size of one byte.
+fn write_line_sequences(program: &mut LineProgram, symbols: &[FunctionSymbol], props: &ExeProperties) {
+    // data has no instructions to map to lines
+    let functions: Vec<_> = symbols
+        .iter()
+        .filter(|sym| sym.data_type().is_none())
+        .cloned()
+        .collect();
+    let end = functions.iter().map(|sym| sym.rva() + 1).max().unwrap_or_default();
+    for (sym, size) in with_approximate_sizes(&functions, end) {
+        // symbols sharing an address with the previous one have no size of their own
+        let size = sym.size().filter(|_| size > 0).unwrap_or(size);
+        let location = match sym.location() {
+            Some(location) if size > 0 => location,
+            _ => continue,
+        };
+        let file = add_source_file(program, location);
+        program.begin_sequence(Some(Address::Constant(props.rva_to_va(sym.rva()))));
+        program.row().file = file;
+        program.row().line = location.line;
+        program.generate_row();
+        program.end_sequence(size);
+    }
+}
+
+fn add_source_file(program: &mut LineProgram, location: &SourceLocation) -> FileId {
+    let dir = match location.file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => program.add_directory(line_string(dir)),
+        _ => program.default_directory(),
+    };
+    let name = location
+        .file
+        .file_name()
+        .map_or(location.file.as_path(), Path::new);
+    program.add_file(line_string(name), dir, None)
+}
+
+fn line_string(path: &Path) -> LineString {
+    LineString::String(path.to_string_lossy().as_bytes().to_vec())
+}
+
+fn path_attribute(path: &Path) -> AttributeValue {
+    AttributeValue::String(path.to_string_lossy().as_bytes().to_vec())
+}
+
+/// Returns the sections of a skeleton unit that points to a `.dwo` file.
+fn skeleton_sections(
+    props: &ExeProperties,
+    dwo_name: &str,
+    info: &UnitInfo,
+) -> Result<Vec<(&'static str, Vec<u8>)>> {
+    let mut dwarf = Dwarf::new();
+    let encoding = encoding(props, DwarfVersion::V5)?;
+    let unit_id = dwarf.units.add(Unit::new(encoding, LineProgram::none()));
+    let unit = dwarf.units.get_mut(unit_id);
+    let root = unit.root();
+    let entry = unit.get_mut(root);
+    entry.set(DWO_ID_PLACEHOLDER, AttributeValue::Data8(0));
+    entry.set(
+        gimli::DW_AT_dwo_name,
+        AttributeValue::String(dwo_name.as_bytes().to_vec()),
+    );
+    if let Some(dir) = &info.comp_dir {
+        entry.set(gimli::DW_AT_comp_dir, path_attribute(dir));
     }
 
-    // TODO: handle endianess here
-    let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
-    dwarf.write(&mut sections)?;
+    let mut sections = Sections::new(EndianVec::new(runtime_endian(props)));
+    let line_strings = dwarf.line_strings.write(&mut sections.debug_line_str)?;
+    let strings = dwarf.strings.write(&mut sections.debug_str)?;
+    dwarf.units.write(&mut sections, &line_strings, &strings)?;
+    take_sections(sections)
+}
 
-    let mut obj = props.replicate_object(BinaryFormat::Elf);
+fn take_sections(
+    mut sections: Sections<EndianVec<gimli::RunTimeEndian>>,
+) -> Result<Vec<(&'static str, Vec<u8>)>> {
+    let mut res = vec![];
     sections.for_each_mut(|id, data| {
-        let name = id.name().as_bytes().to_vec();
-        let id = obj.add_section(b"LOAD".to_vec(), name, SectionKind::Debug);
-        obj.set_section_data(id, Cow::Owned(data.take()), 8);
+        res.push((id.name(), data.take()));
         Ok::<(), Error>(())
     })?;
-    obj.write_stream(output)?;
+    Ok(res)
+}
+
+/// Turns the compile unit in the sections into a skeleton or a split unit with the given ID.
+/// The ID placeholder is the first attribute of the root entry, so it immediately follows
+/// the abbreviation code of the entry. The code is moved in its place and the ID is moved
+/// into the header, which then grows by the same 8 bytes that the entry loses,
+/// so the offsets of all of the other entries stay the same.
+fn into_split_unit(
+    sections: &mut [(&'static str, Vec<u8>)],
+    props: &ExeProperties,
+    unit_type: DwUt,
+    tag: DwTag,
+    dwo_id: u64,
+) -> Result<()> {
+    let position = |name| sections.iter().position(|(section, _)| *section == name).unwrap();
+    let (info, abbrev) = (position(".debug_info"), position(".debug_abbrev"));
+    let mut abbrevs = std::mem::take(&mut sections[abbrev].1);
+    let info = &mut sections[info].1;
 
+    let encoding = encoding(props, DwarfVersion::V5)?;
+    let length_size = match encoding.format {
+        gimli::Format::Dwarf64 => 12,
+        gimli::Format::Dwarf32 => 4,
+    };
+    // the unit length is followed by the version, the unit type, the address size and the abbreviations offset
+    let header_size = length_size + 4 + encoding.format.word_size() as usize;
+    info[length_size + 2] = unit_type.0;
+
+    let mut pos = header_size;
+    let code = read_uleb128(info, &mut pos);
+    let code_bytes = info[header_size..pos].to_vec();
+    info[header_size + 8..header_size + 8 + code_bytes.len()].copy_from_slice(&code_bytes);
+    runtime_endian(props).write_u64(&mut info[header_size..header_size + 8], dwo_id);
+
+    // the abbreviation of the root entry gets the new tag and loses the placeholder
+    let mut pos = 0;
+    loop {
+        let current = read_uleb128(&abbrevs, &mut pos);
+        assert_ne!(current, 0, "missing root abbreviation");
+        let tag_pos = pos;
+        read_uleb128(&abbrevs, &mut pos);
+        // children flag
+        pos += 1;
+
+        let attr_start = pos;
+        let (name, form) = (read_uleb128(&abbrevs, &mut pos), read_uleb128(&abbrevs, &mut pos));
+        if current == code {
+            assert_eq!(
+                (name, form),
+                (DWO_ID_PLACEHOLDER.0 as u64, gimli::DW_FORM_data8.0 as u64)
+            );
+            abbrevs[tag_pos] = tag.0 as u8;
+            abbrevs.drain(attr_start..pos);
+            break;
+        }
+        let (mut name, mut form) = (name, form);
+        while (name, form) != (0, 0) {
+            name = read_uleb128(&abbrevs, &mut pos);
+            form = read_uleb128(&abbrevs, &mut pos);
+        }
+    }
+    sections[abbrev].1 = abbrevs;
     Ok(())
 }
 
+fn read_uleb128(data: &[u8], pos: &mut usize) -> u64 {
+    let mut res = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        res |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return res;
+        }
+        shift += 7;
+    }
+}
+
+fn encoding(props: &ExeProperties, version: DwarfVersion) -> Result<gimli::Encoding> {
+    let encoding = gimli::Encoding {
+        format: if props.is64bit()? {
+            gimli::Format::Dwarf64
+        } else {
+            gimli::Format::Dwarf32
+        },
+        version: version.number(),
+        address_size: props.address_size()?,
+    };
+    Ok(encoding)
+}
+
+/// Compresses the contents of an ELF section and prepends the compression header to them.
+fn compress_section(data: &[u8], compression: DebugCompression, props: &ExeProperties) -> Result<Vec<u8>> {
+    let endian = props.endianness();
+    let ch_type = match compression {
+        DebugCompression::Zlib => elf::ELFCOMPRESS_ZLIB,
+        DebugCompression::Zstd => ELFCOMPRESS_ZSTD,
+    };
+    let mut res = vec![];
+    res.extend(endian.write_u32_bytes(ch_type));
+    if props.is64bit()? {
+        // ch_reserved
+        res.extend(endian.write_u32_bytes(0));
+        res.extend(endian.write_u64_bytes(data.len() as u64));
+        res.extend(endian.write_u64_bytes(1));
+    } else {
+        res.extend(endian.write_u32_bytes(data.len() as u32));
+        res.extend(endian.write_u32_bytes(1));
+    }
+
+    match compression {
+        DebugCompression::Zlib => {
+            let mut encoder = ZlibEncoder::new(res, flate2::Compression::best());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        #[cfg(feature = "native")]
+        DebugCompression::Zstd => {
+            res.extend(zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)?);
+            Ok(res)
+        }
+        #[cfg(not(feature = "native"))]
+        DebugCompression::Zstd => Err(Error::OtherError("zstd compression is not available".into())),
+    }
+}
+
+fn runtime_endian(props: &ExeProperties) -> gimli::RunTimeEndian {
+    match props.endianness() {
+        Endianness::Little => gimli::RunTimeEndian::Little,
+        Endianness::Big => gimli::RunTimeEndian::Big,
+    }
+}
+
+/// Mach-O section names use a `__` prefix instead of a dot and are limited to 16 bytes.
+fn section_name(format: BinaryFormat, name: &str) -> Vec<u8> {
+    match format {
+        BinaryFormat::MachO => {
+            let mut name = format!("__{}", name.trim_start_matches('.')).into_bytes();
+            name.truncate(16);
+            name
+        }
+        _ => name.as_bytes().to_vec(),
+    }
+}
+
 struct DwarfWriter<'a> {
     unit: &'a mut Unit,
     types: &'a TypeInfo,
     cache: HashMap<Cow<'static, str>, UnitEntryId>,
+    name_style: NameStyle,
+    version: DwarfVersion,
+    /// Named types that have been declared, but not defined yet.
+    pending: Vec<(Type, UnitEntryId)>,
+    /// Layouts of structs computed ahead of time, the others are computed when they're defined.
+    layouts: HashMap<StructId, StructLayout>,
+    type_filter: TypeFilter,
 }
 
 impl<'a> DwarfWriter<'a> {
-    fn new(unit: &'a mut Unit, info: &'a TypeInfo) -> Self {
+    fn new(unit: &'a mut Unit, info: &'a TypeInfo, name_style: NameStyle, version: DwarfVersion) -> Self {
         Self {
             unit,
             types: info,
             cache: HashMap::new(),
+            name_style,
+            version,
+            pending: vec![],
+            layouts: HashMap::new(),
+            type_filter: TypeFilter::default(),
         }
     }
 
+    fn with_type_filter(self, type_filter: TypeFilter) -> Self {
+        Self { type_filter, ..self }
+    }
+
+    fn with_layouts(self, layouts: HashMap<StructId, StructLayout>) -> Self {
+        Self { layouts, ..self }
+    }
+
     fn get_or_define_type(&mut self, typ: &Type) -> UnitEntryId {
         let name = typ.name();
         self.cache.get(&name).cloned().unwrap_or_else(|| {
@@ -103,28 +637,65 @@ impl<'a> DwarfWriter<'a> {
             Type::Int(false) => self.define_base_type(typ, gimli::DW_ATE_unsigned),
             Type::Long(true) => self.define_base_type(typ, gimli::DW_ATE_signed),
             Type::Long(false) => self.define_base_type(typ, gimli::DW_ATE_unsigned),
+            Type::LongLong(true) => self.define_base_type(typ, gimli::DW_ATE_signed),
+            Type::LongLong(false) => self.define_base_type(typ, gimli::DW_ATE_unsigned),
+            Type::Int128(true) => self.define_base_type(typ, gimli::DW_ATE_signed),
+            Type::Int128(false) => self.define_base_type(typ, gimli::DW_ATE_unsigned),
             Type::Float => self.define_base_type(typ, gimli::DW_ATE_float),
             Type::Double => self.define_base_type(typ, gimli::DW_ATE_float),
+            Type::LongDouble => self.define_base_type(typ, gimli::DW_ATE_float),
             Type::Reference(inner) => self.define_pointer(inner, gimli::DW_TAG_reference_type),
             Type::Pointer(inner) => self.define_pointer(inner, gimli::DW_TAG_pointer_type),
+            Type::MemberPointer(inner, class) => self.define_member_pointer(typ, inner, *class),
             Type::Array(inner) => self.define_array(inner, typ.size(self.types), None),
             Type::FixedArray(inner, size) => self.define_array(inner, typ.size(self.types), Some(*size)),
-            Type::Struct(id) => {
-                let struct_ty = self.types.structs.get(id).expect("Unresolved struct");
-                self.define_struct(struct_ty)
-            }
-            Type::Enum(id) => {
-                let enum_ty = self.types.enums.get(id).expect("Unresolved enum");
-                self.define_enum(enum_ty)
-            }
-            Type::Union(id) => {
-                let union_ty = self.types.unions.get(id).expect("Unresolved union");
-                self.define_union(union_ty)
-            }
+            Type::Vector(inner, count) => self.define_vector(inner, typ.size(self.types), *count),
+            Type::Struct(id) => self.declare_type(typ, gimli::DW_TAG_structure_type, id.as_ref().as_str()),
+            Type::Enum(id) => self.declare_type(typ, gimli::DW_TAG_enumeration_type, id.as_ref().as_str()),
+            Type::Union(id) => self.declare_type(typ, gimli::DW_TAG_union_type, id.as_ref().as_str()),
             Type::Function(fun) => self.define_function_type(fun),
         }
     }
 
+    /// Adds a declaration of a named type, which is turned into a definition later by
+    /// [`DwarfWriter::define_pending_types`]. This way types that refer to each other
+    /// don't have to be defined recursively.
+    fn declare_type(&mut self, typ: &Type, tag: DwTag, name: &str) -> UnitEntryId {
+        let id = self.unit.add(self.unit.root(), tag);
+        let entry = self.unit.get_mut(id);
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(name.as_bytes().to_vec()),
+        );
+        entry.set(gimli::DW_AT_declaration, AttributeValue::Flag(true));
+        self.pending.push((typ.clone(), id));
+        id
+    }
+
+    /// Defines all of the declared types, including the ones declared while defining others.
+    /// Types without a definition in the type info and the filtered ones are left as declarations.
+    fn define_pending_types(&mut self) {
+        let types = self.types;
+        while let Some((typ, id)) = self.pending.pop() {
+            match &typ {
+                _ if !self.type_filter.is_exported(&typ.name()) => continue,
+                Type::Struct(name) if types.structs.contains_key(name) => self.define_struct(id, *name),
+                Type::Union(name) if types.unions.contains_key(name) => {
+                    self.define_union(id, &types.unions[name])
+                }
+                Type::Enum(name) if types.enums.contains_key(name) => {
+                    self.define_enum(id, &types.enums[name])
+                }
+                _ => continue,
+            }
+            self.unit.get_mut(id).delete(gimli::DW_AT_declaration);
+        }
+    }
+
+    fn pointer_size(&self) -> usize {
+        self.types.data_model.pointer_size()
+    }
+
     fn define_base_type(&mut self, typ: &Type, encoding: DwAte) -> UnitEntryId {
         let id = self.unit.add(self.unit.root(), gimli::DW_TAG_base_type);
         let entry = self.unit.get_mut(id);
@@ -143,9 +714,23 @@ impl<'a> DwarfWriter<'a> {
     fn define_pointer(&mut self, inner: &Type, tag: DwTag) -> UnitEntryId {
         let id = self.unit.add(self.unit.root(), tag);
         let inner = self.get_or_define_type(inner);
+        let pointer_size = self.pointer_size();
+        let entry = self.unit.get_mut(id);
+        entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(inner));
+        entry.set(gimli::DW_AT_byte_size, AttributeValue::Data8(pointer_size as u64));
+        id
+    }
+
+    fn define_member_pointer(&mut self, typ: &Type, inner: &Type, class: StructId) -> UnitEntryId {
+        let id = self.unit.add(self.unit.root(), gimli::DW_TAG_ptr_to_member_type);
+        let inner = self.get_or_define_type(inner);
+        let class = self.get_or_define_type(&Type::Struct(class));
         let entry = self.unit.get_mut(id);
         entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(inner));
-        entry.set(gimli::DW_AT_byte_size, AttributeValue::Data8(POINTER_SIZE as u64));
+        entry.set(gimli::DW_AT_containing_type, AttributeValue::UnitRef(class));
+        if let Some(size) = typ.size(self.types) {
+            entry.set(gimli::DW_AT_byte_size, AttributeValue::Data8(size as u64));
+        }
         id
     }
 
@@ -172,21 +757,30 @@ impl<'a> DwarfWriter<'a> {
         id
     }
 
-    fn define_struct(&mut self, struct_: &StructType) -> UnitEntryId {
-        let id = self.unit.add(self.unit.root(), gimli::DW_TAG_structure_type);
-        self.cache.insert(struct_.name.as_str().into(), id);
-
+    fn define_vector(&mut self, inner: &Type, byte_size: Option<usize>, count: usize) -> UnitEntryId {
+        let id = self.define_array(inner, byte_size, Some(count));
         let entry = self.unit.get_mut(id);
-        let name = AttributeValue::String(struct_.name.as_bytes().to_vec());
-        entry.set(gimli::DW_AT_name, name);
+        entry.set(gimli::DW_AT_GNU_vector, AttributeValue::Flag(true));
+        // vectors are aligned to their size, the alignment can only be written since DWARF 5
+        if let Some(size) = byte_size.filter(|_| self.version == DwarfVersion::V5) {
+            entry.set(gimli::DW_AT_alignment, AttributeValue::Udata(size as u64));
+        }
+        id
+    }
 
+    /// Writes the size and the members of a struct to an existing entry.
+    fn define_struct(&mut self, id: UnitEntryId, struct_id: StructId) {
+        let struct_ = &self.types.structs[&struct_id];
+        let entry = self.unit.get_mut(id);
         if let Some(size) = struct_.size {
             entry.set(gimli::DW_AT_byte_size, AttributeValue::Data8(size as u64));
         }
 
-        let mut offset = 0u64;
-
-        if struct_.has_virtual_methods(self.types) {
+        let layout = match self.layouts.remove(&struct_id) {
+            Some(layout) => layout,
+            None => StructLayout::compute(struct_, self.types),
+        };
+        if layout.has_vtable {
             let vtable_id = self.define_vtable(struct_);
             let this_pointer_id = self.unit.add(id, gimli::DW_TAG_pointer_type);
             let this_pointer = self.unit.get_mut(this_pointer_id);
@@ -198,87 +792,107 @@ impl<'a> DwarfWriter<'a> {
             this_param.set(gimli::DW_AT_name, name);
             this_param.set(gimli::DW_AT_type, AttributeValue::UnitRef(this_pointer_id));
             this_param.set(gimli::DW_AT_artificial, AttributeValue::Data1(1));
-            this_param.set(gimli::DW_AT_data_member_location, AttributeValue::Data8(offset));
-            offset += POINTER_SIZE as u64;
+            this_param.set(gimli::DW_AT_data_member_location, AttributeValue::Data8(0));
         }
 
-        for member in struct_.all_members(self.types) {
-            let type_id = self.get_or_define_type(&member.typ);
-            let member_id = self.unit.add(id, gimli::DW_TAG_member);
-            let member_entry = self.unit.get_mut(member_id);
-            let name = AttributeValue::String(member.name.as_bytes().to_vec());
-            member_entry.set(gimli::DW_AT_name, name);
-
-            if let Some(offset_bits) = member.bit_offset {
-                offset = offset_bits as u64 / u8::BITS as u64;
-                member_entry.set(gimli::DW_AT_data_member_location, AttributeValue::Data8(offset));
-                member_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
-                if member.is_bitfield {
-                    member_entry.set(gimli::DW_AT_bit_offset, AttributeValue::Data8(offset_bits as u64));
-                    member_entry.set(gimli::DW_AT_bit_size, AttributeValue::Data1(1));
-                };
-            } else {
-                member_entry.set(gimli::DW_AT_data_member_location, AttributeValue::Data8(offset));
-                member_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
-
-                if let Some(size) = member.typ.size(self.types) {
-                    let align = size.min(MAX_ALIGN) as u64;
-                    offset += offset % align;
-                    offset += size as u64;
-                }
-            }
+        for (member, offset) in struct_.all_members(self.types).zip(layout.offsets) {
+            self.define_data_member(id, member, offset);
         }
-
-        id
     }
 
-    fn define_union(&mut self, struct_: &UnionType) -> UnitEntryId {
-        let id = self.unit.add(self.unit.root(), gimli::DW_TAG_union_type);
-        self.cache.insert(struct_.name.as_str().into(), id);
-
+    /// Writes the size and the members of a union to an existing entry.
+    fn define_union(&mut self, id: UnitEntryId, union: &UnionType) {
         let entry = self.unit.get_mut(id);
-        let name = AttributeValue::String(struct_.name.as_bytes().to_vec());
-        entry.set(gimli::DW_AT_name, name);
-        if let Some(size) = struct_.size {
+        if let Some(size) = union.size {
             entry.set(gimli::DW_AT_byte_size, AttributeValue::Data8(size as u64));
         }
 
-        for member in &struct_.members {
-            let type_id = self.get_or_define_type(&member.typ);
-            let member_id = self.unit.add(id, gimli::DW_TAG_member);
-            let member_entry = self.unit.get_mut(member_id);
+        for member in &union.members {
+            let offset = member.bit_offset.unwrap_or_default() as u64 / u8::BITS as u64;
+            self.define_data_member(id, member, offset);
+        }
+    }
+
+    fn define_data_member(&mut self, parent: UnitEntryId, member: &DataMember, offset: u64) {
+        let type_id = if member.is_anonymous {
+            self.define_anonymous_type(parent, &member.typ)
+        } else {
+            self.get_or_define_type(&member.typ)
+        };
+        let member_id = self.unit.add(parent, gimli::DW_TAG_member);
+        let entry = self.unit.get_mut(member_id);
+        if !member.is_anonymous {
             let name = AttributeValue::String(member.name.as_bytes().to_vec());
-            member_entry.set(gimli::DW_AT_name, name);
-            member_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
-            if let Some(offset_bits) = member.bit_offset {
-                let location = AttributeValue::Data8(offset_bits as u64 / u8::BITS as u64);
-                member_entry.set(gimli::DW_AT_data_member_location, location);
-            } else {
-                member_entry.set(gimli::DW_AT_data_member_location, AttributeValue::Data8(0));
+            entry.set(gimli::DW_AT_name, name);
+        }
+        entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
+
+        match (member.bit_width, member.bit_offset) {
+            (Some(width), Some(offset_bits)) => {
+                entry.set(gimli::DW_AT_bit_size, AttributeValue::Udata(width as u64));
+                // DWARF 4 consumers expect bit fields to be described relative to their storage unit
+                let legacy = member
+                    .typ
+                    .size(self.types)
+                    .filter(|_| self.version == DwarfVersion::V4)
+                    .and_then(|size| Some((size, legacy_bit_offset(offset_bits, width, size)?)));
+                match legacy {
+                    Some((size, (location, bit_offset))) => {
+                        entry.set(gimli::DW_AT_byte_size, AttributeValue::Udata(size as u64));
+                        entry.set(gimli::DW_AT_bit_offset, AttributeValue::Udata(bit_offset));
+                        entry.set(gimli::DW_AT_data_member_location, AttributeValue::Data8(location));
+                    }
+                    None => entry.set(
+                        gimli::DW_AT_data_bit_offset,
+                        AttributeValue::Udata(offset_bits as u64),
+                    ),
+                }
             }
+            _ => entry.set(gimli::DW_AT_data_member_location, AttributeValue::Data8(offset)),
         }
+    }
 
-        id
+    /// Anonymous structs and unions are defined without a name inside of the type that contains them.
+    fn define_anonymous_type(&mut self, parent: UnitEntryId, typ: &Type) -> UnitEntryId {
+        let types = self.types;
+        match typ {
+            Type::Struct(name) if types.structs.contains_key(name) => {
+                let id = self.unit.add(parent, gimli::DW_TAG_structure_type);
+                self.define_struct(id, *name);
+                id
+            }
+            Type::Union(name) if types.unions.contains_key(name) => {
+                let id = self.unit.add(parent, gimli::DW_TAG_union_type);
+                self.define_union(id, &types.unions[name]);
+                id
+            }
+            other => self.get_or_define_type(other),
+        }
     }
 
-    fn define_enum(&mut self, enum_: &EnumType) -> UnitEntryId {
-        let id = self.unit.add(self.unit.root(), gimli::DW_TAG_enumeration_type);
+    /// Writes the size, the underlying type and the enumerators of an enum to an existing entry.
+    fn define_enum(&mut self, id: UnitEntryId, enum_: &EnumType) {
+        let underlying_type = self.get_or_define_type(&enum_.underlying_type);
         let entry = self.unit.get_mut(id);
-        let name = AttributeValue::String(enum_.name.as_bytes().to_vec());
-        entry.set(gimli::DW_AT_name, name);
         if let Some(size) = enum_.size {
             entry.set(gimli::DW_AT_byte_size, AttributeValue::Data8(size as u64));
         }
+        entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(underlying_type));
+        if enum_.is_scoped {
+            entry.set(gimli::DW_AT_enum_class, AttributeValue::Flag(true));
+        }
 
         for member in &enum_.members {
             let entry = self.unit.add(id, gimli::DW_TAG_enumerator);
             let entry = self.unit.get_mut(entry);
             let name = AttributeValue::String(member.name.as_bytes().to_vec());
             entry.set(gimli::DW_AT_name, name);
-            entry.set(gimli::DW_AT_const_value, AttributeValue::Sdata(member.value));
+            let value = match member.value {
+                EnumValue::Signed(value) => AttributeValue::Sdata(value),
+                EnumValue::Unsigned(value) => AttributeValue::Udata(value),
+            };
+            entry.set(gimli::DW_AT_const_value, value);
         }
-
-        id
     }
 
     fn define_function_type(&mut self, fun: &FunctionType) -> UnitEntryId {
@@ -299,10 +913,11 @@ impl<'a> DwarfWriter<'a> {
 
     fn define_vtable(&mut self, struct_: &StructType) -> UnitEntryId {
         let id = self.unit.add(self.unit.root(), gimli::DW_TAG_structure_type);
+        let pointer_size = self.pointer_size();
         let entry = self.unit.get_mut(id);
         let name = AttributeValue::String(get_vtable_type_name(struct_).as_bytes().to_vec());
         entry.set(gimli::DW_AT_name, name);
-        let size = struct_.all_virtual_methods(self.types).count() * POINTER_SIZE;
+        let size = struct_.all_virtual_methods(self.types).count() * pointer_size;
         entry.set(gimli::DW_AT_byte_size, AttributeValue::Data8(size as u64));
 
         for (i, method) in struct_.all_virtual_methods(self.types).enumerate() {
@@ -313,10 +928,13 @@ impl<'a> DwarfWriter<'a> {
 
             let member_id = self.unit.add(id, gimli::DW_TAG_member);
             let member_entry = self.unit.get_mut(member_id);
-            let name = AttributeValue::String(method.name.as_bytes().to_vec());
-            member_entry.set(gimli::DW_AT_name, name);
+            let name = self.name_style.apply(&method.name);
+            member_entry.set(
+                gimli::DW_AT_name,
+                AttributeValue::String(name.as_bytes().to_vec()),
+            );
             member_entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
-            let location = AttributeValue::Data8(i as u64 * POINTER_SIZE as u64);
+            let location = AttributeValue::Data8((i * pointer_size) as u64);
             member_entry.set(gimli::DW_AT_data_member_location, location);
         }
 
@@ -334,10 +952,11 @@ impl<'a> DwarfWriter<'a> {
         let this_arg_id = self.unit.add(id, gimli::DW_TAG_formal_parameter);
         let this_type_id = self.get_or_define_type(&Type::Pointer(Type::Struct(parent_id).into()));
         let ret_type_id = self.get_or_define_type(&method.typ.return_type);
+        let pointer_size = self.pointer_size();
 
         let entry = self.unit.get_mut(id);
         entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(ret_type_id));
-        let location = AttributeValue::Data8((index * POINTER_SIZE) as u64);
+        let location = AttributeValue::Data8((index * pointer_size) as u64);
         entry.set(gimli::DW_AT_data_member_location, location);
         entry.set(gimli::DW_AT_object_pointer, AttributeValue::UnitRef(this_type_id));
 
@@ -355,26 +974,180 @@ impl<'a> DwarfWriter<'a> {
         id
     }
 
-    fn define_function_symbol(&mut self, fun: FunctionSymbol, image_base: u64) {
+    fn define_function_symbol(&mut self, fun: &FunctionSymbol, props: &ExeProperties) -> UnitEntryId {
         let id = self.unit.add(self.unit.root(), gimli::DW_TAG_subprogram);
         let ret_type_id = self.get_or_define_type(&fun.function_type().return_type);
 
         let entry = self.unit.get_mut(id);
         let name = AttributeValue::String(fun.name().as_bytes().to_vec());
         entry.set(gimli::DW_AT_name, name);
-        let pc = AttributeValue::Address(Address::Constant(image_base + fun.rva()));
+        let pc = AttributeValue::Address(Address::Constant(props.rva_to_va(fun.rva())));
         entry.set(gimli::DW_AT_low_pc, pc);
+        if let Some(size) = fun.size() {
+            // a constant class high_pc is an offset from low_pc
+            entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(size));
+        }
         entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(ret_type_id));
 
+        if let Some(location) = fun.location() {
+            if !self.unit.line_program.is_none() {
+                let file = add_source_file(&mut self.unit.line_program, location);
+                let entry = self.unit.get_mut(id);
+                entry.set(gimli::DW_AT_decl_file, AttributeValue::FileIndex(Some(file)));
+                entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(location.line));
+            }
+        }
+
         for arg in &fun.function_type().params {
             let type_id = self.get_or_define_type(arg);
             let arg_id = self.unit.add(id, gimli::DW_TAG_formal_parameter);
             let param = self.unit.get_mut(arg_id);
             param.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
         }
+
+        id
+    }
+
+    fn define_data_symbol(
+        &mut self,
+        sym: &FunctionSymbol,
+        typ: &Type,
+        props: &ExeProperties,
+    ) -> UnitEntryId {
+        let id = self.unit.add(self.unit.root(), gimli::DW_TAG_variable);
+        let type_id = self.get_or_define_type(typ);
+
+        let entry = self.unit.get_mut(id);
+        let name = AttributeValue::String(sym.name().as_bytes().to_vec());
+        entry.set(gimli::DW_AT_name, name);
+        entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(type_id));
+        entry.set(gimli::DW_AT_external, AttributeValue::Flag(true));
+        let mut location = Expression::new();
+        location.op_addr(Address::Constant(props.rva_to_va(sym.rva())));
+        entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+
+        if let Some(location) = sym.location() {
+            if !self.unit.line_program.is_none() {
+                let file = add_source_file(&mut self.unit.line_program, location);
+                let entry = self.unit.get_mut(id);
+                entry.set(gimli::DW_AT_decl_file, AttributeValue::FileIndex(Some(file)));
+                entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(location.line));
+            }
+        }
+
+        id
+    }
+}
+
+/// Encodes an index of names to offsets, all integers are little-endian. It starts with the `ZIDX` magic,
+/// a `u32` version and a `u32` record count. Then there are fixed-size records sorted by name,
+/// each made of a `u32` name offset, a `u32` name length and a `u64` offset, which makes it possible
+/// to look up a name with a binary search. The names follow the records.
+fn encode_index(mut entries: Vec<(String, u64)>) -> Vec<u8> {
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut records = vec![];
+    let mut names = vec![];
+    for (name, offset) in &entries {
+        records.extend_from_slice(&(names.len() as u32).to_le_bytes());
+        records.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        records.extend_from_slice(&offset.to_le_bytes());
+        names.extend_from_slice(name.as_bytes());
+    }
+
+    let mut index = INDEX_MAGIC.to_vec();
+    index.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+    index.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    index.extend(records);
+    index.extend(names);
+    index
+}
+
+/// The offsets of the members of a struct, which take a while to compute for large types, since
+/// the sizes and the alignments of the members are computed from their own members.
+#[derive(Debug, PartialEq, Eq)]
+struct StructLayout {
+    /// Whether the struct starts with a pointer to a vtable.
+    has_vtable: bool,
+    /// The byte offsets of the members in the order of [`StructType::all_members`].
+    offsets: Vec<u64>,
+}
+
+impl StructLayout {
+    fn compute(struct_: &StructType, types: &TypeInfo) -> Self {
+        let has_vtable = struct_.has_virtual_methods(types);
+        let mut offset = if has_vtable {
+            types.data_model.pointer_size() as u64
+        } else {
+            0
+        };
+        let mut offsets = vec![];
+        for member in struct_.all_members(types) {
+            if let Some(offset_bits) = member.bit_offset {
+                offset = offset_bits as u64 / u8::BITS as u64;
+                offsets.push(offset);
+            } else {
+                if let Some(align) = member.typ.alignment(types) {
+                    offset = align_up(offset as usize, align) as u64;
+                }
+                offsets.push(offset);
+
+                if let Some(size) = member.typ.size(types) {
+                    offset += size as u64;
+                }
+            }
+        }
+        Self { has_vtable, offsets }
+    }
+
+    /// Computes the layouts of all of the structs on worker threads. Only the layouts are computed
+    /// in parallel, the entries have to be added to the unit one by one afterwards.
+    fn compute_all(types: &TypeInfo) -> HashMap<StructId, Self> {
+        let structs: Vec<_> = types.structs.iter().collect();
+        let threads = std::thread::available_parallelism().map_or(1, |count| count.get());
+        let chunk_size = structs.len() / threads + 1;
+        std::thread::scope(|scope| {
+            let mut workers = vec![];
+            for chunk in structs.chunks(chunk_size) {
+                workers.push(scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(id, struct_)| (**id, Self::compute(struct_, types)))
+                        .collect::<Vec<_>>()
+                }));
+            }
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        })
     }
 }
 
+/// Returns the location of the storage unit of a bit field and the offset of the field from its most
+/// significant bit, which is how older producers describe bit fields. The executables are
+/// little-endian, so the fields start at the least significant bit. Fields that cross the storage
+/// unit of their type don't have a location like this.
+fn legacy_bit_offset(offset_bits: usize, width: usize, storage_size: usize) -> Option<(u64, u64)> {
+    let storage_bits = storage_size * u8::BITS as usize;
+    if storage_bits == 0 {
+        return None;
+    }
+    let location = offset_bits / storage_bits * storage_size;
+    let start = offset_bits - location * u8::BITS as usize;
+    let end = start.checked_add(width).filter(|end| *end <= storage_bits)?;
+    Some((location as u64, (storage_bits - end) as u64))
+}
+
+/// Encodes a hash of the spec file, all integers are little-endian. It starts with the `ZSRC` magic
+/// and a `u32` version, followed by the `u64` FNV-1a hash of the contents of the file.
+fn encode_source_hash(hash: u64) -> Vec<u8> {
+    let mut res = SOURCE_HASH_MAGIC.to_vec();
+    res.extend_from_slice(&SOURCE_HASH_VERSION.to_le_bytes());
+    res.extend_from_slice(&hash.to_le_bytes());
+    res
+}
+
 fn get_vtable_type_name(owner: &StructType) -> Cow<'static, str> {
     format!("{}_vft", owner.name).into()
 }
@@ -382,3 +1155,554 @@ fn get_vtable_type_name(owner: &StructType) -> Cow<'static, str> {
 fn get_vtable_field_name(_owner: &StructType) -> Cow<'static, str> {
     "vft".into()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use gimli::read::{AttributeValue, DebugAbbrev, DebugInfo, DebugLine, UnitType};
+    use gimli::{DebugLineOffset, DwoId, LittleEndian};
+    use object::{Object, ObjectSection};
+    use ustr::Ustr;
+
+    use super::*;
+    use crate::exe::{ObjectConfig, EI_OSABI};
+    use crate::filter::Glob;
+    use crate::layout::Layout;
+    use crate::symbols::Confidence;
+
+    const DWO_ID: u64 = 0x1234_5678_9ABC_DEF0;
+
+    fn props() -> ExeProperties {
+        let layout: Layout = toml::from_str(
+            "image_base = 0x140000000\n[text]\nfile_offset = 0x400\naddress = 0x140001000\nsize = 0x1000",
+        )
+        .unwrap();
+        ExeProperties::from_layout(&layout)
+    }
+
+    fn section<'a>(sections: &'a [(&str, Vec<u8>)], name: &str) -> &'a [u8] {
+        &sections.iter().find(|(section, _)| *section == name).unwrap().1
+    }
+
+    #[test]
+    fn write_skeleton_unit() {
+        let props = props();
+        let mut sections = skeleton_sections(&props, "symbols.dwo", &UnitInfo::default()).unwrap();
+        into_split_unit(
+            &mut sections,
+            &props,
+            gimli::DW_UT_skeleton,
+            gimli::DW_TAG_skeleton_unit,
+            DWO_ID,
+        )
+        .unwrap();
+
+        let info = DebugInfo::new(section(&sections, ".debug_info"), LittleEndian);
+        let abbrev = DebugAbbrev::new(section(&sections, ".debug_abbrev"), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        assert_eq!(header.type_(), UnitType::Skeleton(DwoId(DWO_ID)));
+
+        let abbrevs = header.abbreviations(&abbrev).unwrap();
+        let mut entries = header.entries(&abbrevs);
+        let (_, root) = entries.next_dfs().unwrap().unwrap();
+        assert_eq!(root.tag(), gimli::DW_TAG_skeleton_unit);
+        assert!(root.attr(DWO_ID_PLACEHOLDER).unwrap().is_none());
+        assert!(root.attr(gimli::DW_AT_dwo_name).unwrap().is_some());
+    }
+
+    #[test]
+    fn keep_references_in_split_unit() {
+        let mut types = TypeInfo::default();
+        let name = "Object".into();
+        let struct_ = StructType {
+            name,
+            base: None,
+            members: vec![DataMember::basic("id".into(), Type::Int(true))],
+            virtual_methods: vec![],
+            size: Some(4),
+        };
+        types.structs.insert(name.into(), struct_);
+
+        let props = props();
+        let options = DwarfOptions {
+            eager_type_export: true,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let mut sections = debug_sections(&[], &types, &props, &options, true).unwrap();
+        into_split_unit(
+            &mut sections,
+            &props,
+            gimli::DW_UT_split_compile,
+            gimli::DW_TAG_compile_unit,
+            DWO_ID,
+        )
+        .unwrap();
+
+        let info = DebugInfo::new(section(&sections, ".debug_info"), LittleEndian);
+        let abbrev = DebugAbbrev::new(section(&sections, ".debug_abbrev"), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        assert_eq!(header.type_(), UnitType::SplitCompilation(DwoId(DWO_ID)));
+
+        let abbrevs = header.abbreviations(&abbrev).unwrap();
+        let mut entries = header.entries(&abbrevs);
+        let mut member_type = None;
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            if entry.tag() == gimli::DW_TAG_member {
+                member_type = entry.attr_value(gimli::DW_AT_type).unwrap();
+            }
+        }
+        let offset = match member_type {
+            Some(AttributeValue::UnitRef(offset)) => offset,
+            other => panic!("unexpected member type {other:?}"),
+        };
+        let mut entries = header.entries_at_offset(&abbrevs, offset).unwrap();
+        let (_, entry) = entries.next_dfs().unwrap().unwrap();
+        assert_eq!(entry.tag(), gimli::DW_TAG_base_type);
+    }
+
+    #[test]
+    fn declare_cyclic_and_missing_types() {
+        let mut types = TypeInfo::default();
+        let [node, value, missing] = ["Node", "Value", "Missing"].map(Ustr::from);
+        let struct_ = StructType {
+            name: node,
+            base: None,
+            members: vec![
+                DataMember::basic("value".into(), Type::Union(value.into())),
+                DataMember::basic(
+                    "missing".into(),
+                    Type::Pointer(Type::Struct(missing.into()).into()),
+                ),
+            ],
+            virtual_methods: vec![],
+            size: Some(16),
+        };
+        let union = UnionType {
+            name: value,
+            members: vec![DataMember::basic(
+                "node".into(),
+                Type::Pointer(Type::Struct(node.into()).into()),
+            )],
+            size: Some(8),
+        };
+        types.structs.insert(node.into(), struct_);
+        types.unions.insert(value.into(), union);
+
+        let options = DwarfOptions {
+            eager_type_export: true,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let sections = debug_sections(&[], &types, &props(), &options, false).unwrap();
+
+        let info = DebugInfo::new(section(&sections, ".debug_info"), LittleEndian);
+        let abbrev = DebugAbbrev::new(section(&sections, ".debug_abbrev"), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        let abbrevs = header.abbreviations(&abbrev).unwrap();
+        let mut entries = header.entries(&abbrevs);
+        let mut declarations = vec![];
+        let mut definitions = vec![];
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            let name = match entry.attr_value(gimli::DW_AT_name).unwrap() {
+                Some(AttributeValue::String(name)) => name.slice().to_vec(),
+                _ => continue,
+            };
+            if entry.attr(gimli::DW_AT_declaration).unwrap().is_some() {
+                declarations.push(name);
+            } else if entry.tag() != gimli::DW_TAG_member {
+                definitions.push(name);
+            }
+        }
+        definitions.sort();
+        assert_eq!(declarations, [b"Missing".to_vec()]);
+        assert_eq!(definitions, [b"Node".to_vec(), b"Value".to_vec()]);
+    }
+
+    #[test]
+    fn declare_skipped_types() {
+        let mut types = TypeInfo::default();
+        let [node, list] = ["std::_List_node<int>", "List"].map(Ustr::from);
+        let struct_ = StructType {
+            name: list,
+            base: None,
+            members: vec![DataMember::basic(
+                "head".into(),
+                Type::Pointer(Type::Struct(node.into()).into()),
+            )],
+            virtual_methods: vec![],
+            size: Some(8),
+        };
+        types.structs.insert(list.into(), struct_);
+        types.structs.insert(node.into(), StructType::stub(node));
+
+        let options = DwarfOptions {
+            eager_type_export: true,
+            type_filter: TypeFilter::new(vec![], vec![Glob::new("std::*")]),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let sections = debug_sections(&[], &types, &props(), &options, false).unwrap();
+
+        let info = DebugInfo::new(section(&sections, ".debug_info"), LittleEndian);
+        let abbrev = DebugAbbrev::new(section(&sections, ".debug_abbrev"), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        let abbrevs = header.abbreviations(&abbrev).unwrap();
+        let mut entries = header.entries(&abbrevs);
+        let mut declarations = vec![];
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            if let Some(AttributeValue::String(name)) = entry.attr_value(gimli::DW_AT_name).unwrap() {
+                if entry.attr(gimli::DW_AT_declaration).unwrap().is_some() {
+                    declarations.push(name.slice().to_vec());
+                }
+            }
+        }
+        assert_eq!(declarations, [b"std::_List_node<int>".to_vec()]);
+    }
+
+    #[test]
+    fn define_member_pointers() {
+        let mut types = TypeInfo::default();
+        let name = "Object".into();
+        let method = FunctionType::new(vec![], Type::Void);
+        let struct_ = StructType {
+            name,
+            base: None,
+            members: vec![
+                DataMember::basic(
+                    "field".into(),
+                    Type::MemberPointer(Type::Int(true).into(), name.into()),
+                ),
+                DataMember::basic(
+                    "method".into(),
+                    Type::MemberPointer(Type::Function(method.into()).into(), name.into()),
+                ),
+            ],
+            virtual_methods: vec![],
+            size: Some(24),
+        };
+        types.structs.insert(name.into(), struct_);
+
+        let options = DwarfOptions {
+            eager_type_export: true,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let sections = debug_sections(&[], &types, &props(), &options, false).unwrap();
+
+        let info = DebugInfo::new(section(&sections, ".debug_info"), LittleEndian);
+        let abbrev = DebugAbbrev::new(section(&sections, ".debug_abbrev"), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        let abbrevs = header.abbreviations(&abbrev).unwrap();
+        let mut entries = header.entries(&abbrevs);
+        let mut sizes = vec![];
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            if entry.tag() != gimli::DW_TAG_ptr_to_member_type {
+                continue;
+            }
+            let class = match entry.attr_value(gimli::DW_AT_containing_type).unwrap() {
+                Some(AttributeValue::UnitRef(offset)) => offset,
+                other => panic!("unexpected containing type {other:?}"),
+            };
+            let mut classes = header.entries_at_offset(&abbrevs, class).unwrap();
+            let (_, class) = classes.next_dfs().unwrap().unwrap();
+            assert_eq!(class.tag(), gimli::DW_TAG_structure_type);
+            let size = entry.attr_value(gimli::DW_AT_byte_size).unwrap();
+            sizes.push(size.and_then(|size| size.udata_value()));
+        }
+        sizes.sort();
+        assert_eq!(sizes, [Some(8), Some(16)]);
+    }
+
+    #[test]
+    fn map_functions_to_spec_lines() {
+        let types = TypeInfo::default();
+        let symbols = [0x1000, 0x1040].map(|rva| {
+            let location = SourceLocation {
+                file: "specs/types.hpp".into(),
+                line: rva / 0x10,
+            };
+            let typ = FunctionType::new(vec![], Type::Void).into();
+            FunctionSymbol::new("fun".into(), typ, rva, Confidence::Unique, Some(location))
+        });
+        let options = DwarfOptions {
+            eager_type_export: false,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let sections = debug_sections(&symbols, &types, &props(), &options, false).unwrap();
+
+        let line = DebugLine::new(section(&sections, ".debug_line"), LittleEndian);
+        let program = line.program(DebugLineOffset(0), 8, None, None).unwrap();
+        let mut rows = program.rows();
+        let mut lines = vec![];
+        let mut ends = vec![];
+        while let Some((_, row)) = rows.next_row().unwrap() {
+            if row.end_sequence() {
+                ends.push(row.address());
+            } else {
+                lines.push((row.address(), row.line().map(|line| line.get())));
+            }
+        }
+        assert_eq!(lines, [(0x140001000, Some(0x100)), (0x140001040, Some(0x104))]);
+        assert_eq!(ends, [0x140001040, 0x140001041]);
+    }
+
+    #[test]
+    fn write_32bit_symbol_file() {
+        let layout: Layout = toml::from_str(
+            "architecture = \"x86\"\nimage_base = 0x400000\n\
+             [text]\nfile_offset = 0x400\naddress = 0x401000\nsize = 0x1000",
+        )
+        .unwrap();
+        let props = ExeProperties::from_layout(&layout);
+        let options = DwarfOptions {
+            eager_type_export: false,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let mut output = vec![];
+        write_symbol_file(&mut output, &[], &TypeInfo::default(), props.clone(), options).unwrap();
+
+        let obj = object::read::File::parse(&*output).unwrap();
+        assert!(!obj.is_64());
+        assert_eq!(obj.architecture(), object::Architecture::I386);
+        let info = obj.section_by_name(".debug_info").unwrap();
+        let info = DebugInfo::new(info.data().unwrap(), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        assert_eq!(header.format(), gimli::Format::Dwarf32);
+        assert_eq!(header.address_size(), 4);
+
+        let data = b"debug info ".repeat(16);
+        let compressed = compress_section(&data, DebugCompression::Zlib, &props).unwrap();
+        assert_eq!(compressed[4..8], (data.len() as u32).to_le_bytes());
+        assert_eq!(compressed[8..12], 1u32.to_le_bytes());
+    }
+
+    #[test]
+    fn use_known_function_sizes() {
+        let types = TypeInfo::default();
+        let symbols = [(0x1000, 0x20), (0x1040, 0x30)].map(|(rva, size)| {
+            let location = SourceLocation {
+                file: "types.hpp".into(),
+                line: 1,
+            };
+            let typ = FunctionType::new(vec![], Type::Void).into();
+            FunctionSymbol::new("fun".into(), typ, rva, Confidence::Unique, Some(location))
+                .with_size(Some(size))
+        });
+        let options = DwarfOptions {
+            eager_type_export: false,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let sections = debug_sections(&symbols, &types, &props(), &options, false).unwrap();
+
+        let line = DebugLine::new(section(&sections, ".debug_line"), LittleEndian);
+        let program = line.program(DebugLineOffset(0), 8, None, None).unwrap();
+        let mut rows = program.rows();
+        let mut ends = vec![];
+        while let Some((_, row)) = rows.next_row().unwrap() {
+            if row.end_sequence() {
+                ends.push(row.address());
+            }
+        }
+        assert_eq!(ends, [0x140001020, 0x140001070]);
+    }
+
+    #[test]
+    fn describe_compilation_unit() {
+        let location = SourceLocation {
+            file: "specs/types.hpp".into(),
+            line: 1,
+        };
+        let typ = FunctionType::new(vec![], Type::Void).into();
+        let symbols = [FunctionSymbol::new(
+            "fun".into(),
+            typ,
+            0x1000,
+            Confidence::Unique,
+            Some(location),
+        )];
+        let options = DwarfOptions {
+            eager_type_export: false,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo {
+                name: Some("specs/types.hpp".into()),
+                comp_dir: Some("/home/user/game".into()),
+                source_hash: Some(0x0123_4567_89AB_CDEF),
+            },
+        };
+        let sections = debug_sections(&symbols, &TypeInfo::default(), &props(), &options, false).unwrap();
+
+        let info = DebugInfo::new(section(&sections, ".debug_info"), LittleEndian);
+        let abbrev = DebugAbbrev::new(section(&sections, ".debug_abbrev"), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        let abbrevs = header.abbreviations(&abbrev).unwrap();
+        let mut entries = header.entries(&abbrevs);
+        let (_, root) = entries.next_dfs().unwrap().unwrap();
+        let string = |attr| match root.attr_value(attr).unwrap() {
+            Some(AttributeValue::String(str)) => str.to_string_lossy().into_owned(),
+            other => panic!("unexpected attribute value {other:?}"),
+        };
+        assert_eq!(string(gimli::DW_AT_producer), PRODUCER);
+        assert_eq!(string(gimli::DW_AT_name), "specs/types.hpp");
+        assert_eq!(string(gimli::DW_AT_comp_dir), "/home/user/game");
+        assert!(root.attr(gimli::DW_AT_stmt_list).unwrap().is_some());
+
+        let hash = section(&sections, SOURCE_HASH_SECTION);
+        assert_eq!(&hash[0..4], SOURCE_HASH_MAGIC);
+        assert_eq!(hash[8..16], 0x0123_4567_89AB_CDEFu64.to_le_bytes());
+    }
+
+    #[test]
+    fn write_legacy_bit_fields() {
+        let mut types = TypeInfo::default();
+        let name = "Flags".into();
+        let bit_field = |name: &str, offset, width| DataMember {
+            bit_offset: Some(offset),
+            bit_width: Some(width),
+            ..DataMember::basic(name.into(), Type::Int(false))
+        };
+        let struct_ = StructType {
+            name,
+            base: None,
+            members: vec![bit_field("low", 0, 3), bit_field("high", 35, 5)],
+            virtual_methods: vec![],
+            size: Some(8),
+        };
+        types.structs.insert(name.into(), struct_);
+
+        let options = DwarfOptions {
+            eager_type_export: true,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V4,
+            unit: UnitInfo::default(),
+        };
+        let sections = debug_sections(&[], &types, &props(), &options, false).unwrap();
+
+        let info = DebugInfo::new(section(&sections, ".debug_info"), LittleEndian);
+        let abbrev = DebugAbbrev::new(section(&sections, ".debug_abbrev"), LittleEndian);
+        let header = info.units().next().unwrap().unwrap();
+        assert_eq!(header.version(), 4);
+        let abbrevs = header.abbreviations(&abbrev).unwrap();
+        let mut entries = header.entries(&abbrevs);
+        let mut members = vec![];
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            if entry.tag() != gimli::DW_TAG_member {
+                continue;
+            }
+            assert!(entry.attr(gimli::DW_AT_data_bit_offset).unwrap().is_none());
+            let udata = |attr| {
+                entry
+                    .attr_value(attr)
+                    .unwrap()
+                    .and_then(|value| value.udata_value())
+            };
+            members.push((
+                udata(gimli::DW_AT_data_member_location),
+                udata(gimli::DW_AT_bit_offset),
+                udata(gimli::DW_AT_byte_size),
+            ));
+        }
+        assert_eq!(members, [
+            (Some(0), Some(29), Some(4)),
+            (Some(4), Some(24), Some(4))
+        ]);
+    }
+
+    #[test]
+    fn apply_object_config() {
+        use object::Object;
+
+        let props = props().with_object_config(ObjectConfig {
+            elf_machine: Some(elf::EM_386),
+            elf_os_abi: Some(elf::ELFOSABI_GNU),
+            section_names: vec![(".debug_info".to_owned(), ".zoltan_info".to_owned())],
+        });
+        let mut output = vec![];
+        let sections = [(".debug_info", vec![0; 4]), (".debug_abbrev", vec![0; 4])];
+        write_object(&mut output, &props, sections, None).unwrap();
+
+        assert_eq!(output[EI_OSABI], elf::ELFOSABI_GNU);
+        assert_eq!(output[18..20], elf::EM_386.to_le_bytes());
+        let obj = object::read::File::parse(&*output).unwrap();
+        assert!(obj.section_by_name(".zoltan_info").is_some());
+        assert!(obj.section_by_name(".debug_info").is_none());
+        assert!(obj.section_by_name(".debug_abbrev").is_some());
+    }
+
+    #[test]
+    fn compress_section_with_header() {
+        let data = b"debug info ".repeat(16);
+        let compressed = compress_section(&data, DebugCompression::Zlib, &props()).unwrap();
+        assert_eq!(compressed[0..4], elf::ELFCOMPRESS_ZLIB.to_le_bytes());
+        assert_eq!(compressed[8..16], (data.len() as u64).to_le_bytes());
+        assert_eq!(compressed[16..24], 1u64.to_le_bytes());
+
+        let mut decompressed = vec![];
+        flate2::read::ZlibDecoder::new(&compressed[24..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn precompute_struct_layouts() {
+        let [base_name, derived_name] = ["Base", "Derived"].map(Ustr::from);
+        let mut types = TypeInfo::default();
+        let base = StructType {
+            name: "Base".into(),
+            base: None,
+            members: vec![
+                DataMember::basic("flag".into(), Type::Char(true)),
+                DataMember::basic("id".into(), Type::Int(true)),
+            ],
+            virtual_methods: vec![],
+            size: Some(8),
+        };
+        types.structs.insert(base_name.into(), base);
+        let derived = StructType {
+            name: "Derived".into(),
+            base: Some(base_name.into()),
+            members: vec![DataMember::basic("value".into(), Type::Double)],
+            virtual_methods: vec![],
+            size: Some(16),
+        };
+        types.structs.insert(derived_name.into(), derived);
+
+        let layouts = StructLayout::compute_all(&types);
+        assert_eq!(layouts.len(), 2);
+        assert_eq!(layouts[&base_name.into()].offsets, [0, 4]);
+        assert_eq!(layouts[&derived_name.into()].offsets, [0, 4, 8]);
+        for (id, struct_) in &types.structs {
+            assert_eq!(layouts[id], StructLayout::compute(struct_, &types));
+        }
+    }
+}
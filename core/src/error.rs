@@ -4,12 +4,15 @@ use peg::str::LineCol;
 use thiserror::Error;
 use ustr::Ustr;
 
+use crate::spec::{SourceLocation, Warning};
+use crate::symbols::MatchClusters;
+
 pub type Result<A, E = Error> = std::result::Result<A, E>;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("invalid parameter in '{0}': {1}")]
-    TypedefParamError(Ustr, ParamError),
+    #[error("{}invalid parameter in '{0}': {2}", location_prefix(.1))]
+    TypedefParamError(Ustr, Option<SourceLocation>, ParamError),
     #[error("invalid rdata access at {0}")]
     InvalidAccess(usize),
     #[error("unresolved name {0}")]
@@ -20,24 +23,103 @@ pub enum Error {
     ObjectError(#[from] object::Error),
     #[error("DWARF error: {0}")]
     DwarfError(#[from] gimli::write::Error),
+    #[error("unwind table error: {0}")]
+    UnwindTableError(#[from] gimli::read::Error),
     #[error("I/O error: {0}")]
     IoError(#[from] io::Error),
+    #[error("layout error: {0}")]
+    LayoutError(#[from] toml::de::Error),
+    #[error("container map error: {0}")]
+    ContainerMapError(toml::de::Error),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
     #[error("missing {0} section")]
     MissingSection(&'static str),
+    #[error("process {0} not found")]
+    ProcessNotFound(String),
+    #[error("module {0} not found in process {1}")]
+    ModuleNotFound(String, u32),
+    #[error("unsupported architecture: {0:?}")]
+    UnsupportedArchitecture(object::Architecture),
+    #[error("cannot add sections to the executable: {0}")]
+    InjectionError(&'static str),
+    #[error("invalid options: {0}")]
+    InvalidOptions(&'static str),
+    #[error("cannot import types: {0}")]
+    TypeImportError(gimli::read::Error),
+    #[error("template error: {0}")]
+    TemplateError(String),
+    #[error("output backend {0} failed: {1}")]
+    BackendError(String, String),
+    #[error("{0} symbol(s) failed verification")]
+    VerificationFailed(usize),
     #[error("{0}")]
     OtherError(#[from] Box<dyn std::error::Error>),
 }
 
+/// Formats an optional location as a `file:line: ` prefix of a message.
+pub fn location_prefix(location: &Option<SourceLocation>) -> String {
+    location
+        .as_ref()
+        .map(|location| format!("{location}: "))
+        .unwrap_or_default()
+}
+
 #[derive(Debug, Error)]
 pub enum SymbolError {
-    #[error("too many matches for {0} ({1})")]
-    MoreThanOneMatch(Ustr, usize),
+    #[error("too many matches for {0} ({1}: {2})")]
+    MoreThanOneMatch(Ustr, usize, MatchClusters),
+    #[error("no unique match for {0} ({1})")]
+    NoUniqueMatch(Ustr, MatchClusters),
     #[error("no matches for {0}")]
     NoMatches(Ustr),
     #[error("not enough matches for {0} ({1})")]
     NotEnoughMatches(Ustr, usize),
     #[error("count mismatch for {0} ({1})")]
     CountMismatch(Ustr, usize),
+    #[error("{0} was expected at 0x{2:X}, but it was found at 0x{1:X}")]
+    UnexpectedAddress(Ustr, u64, u64),
+    #[error("{0} was expected at 0x{1:X}, but it was not resolved")]
+    MissingExpected(Ustr, u64),
+    #[error("{0} was resolved to 0x{2:X}, the same address as {1}")]
+    AddressCollision(Ustr, Ustr, u64),
+    #[error("{0} is located by references to {1}, which was not resolved")]
+    UnresolvedDependency(Ustr, Ustr),
+    #[error("no {1} section to search for {0} in")]
+    MissingSection(Ustr, String),
+}
+
+impl SymbolError {
+    /// Returns the name of the symbol the error is about.
+    pub fn name(&self) -> Ustr {
+        match self {
+            SymbolError::MoreThanOneMatch(name, _, _)
+            | SymbolError::NoUniqueMatch(name, _)
+            | SymbolError::NoMatches(name)
+            | SymbolError::NotEnoughMatches(name, _)
+            | SymbolError::CountMismatch(name, _)
+            | SymbolError::UnexpectedAddress(name, _, _)
+            | SymbolError::MissingExpected(name, _)
+            | SymbolError::AddressCollision(name, _, _)
+            | SymbolError::UnresolvedDependency(name, _)
+            | SymbolError::MissingSection(name, _) => *name,
+        }
+    }
+
+    /// Returns the kind of warning that can suppress the error, verification errors can't be suppressed.
+    pub fn warning(&self) -> Option<Warning> {
+        match self {
+            SymbolError::MoreThanOneMatch(_, _, _)
+            | SymbolError::NoUniqueMatch(_, _)
+            | SymbolError::NotEnoughMatches(_, _)
+            | SymbolError::CountMismatch(_, _) => Some(Warning::Ambiguous),
+            SymbolError::NoMatches(_)
+            | SymbolError::UnresolvedDependency(_, _)
+            | SymbolError::MissingSection(_, _) => Some(Warning::NoMatch),
+            SymbolError::AddressCollision(_, _, _) => Some(Warning::Collision),
+            SymbolError::UnexpectedAddress(_, _, _) | SymbolError::MissingExpected(_, _) => None,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -46,7 +128,7 @@ pub enum ParamError {
     InvalidParam(&'static str, String),
     #[error("unknown parameter '{0}'")]
     UnknownParam(String),
-    #[error("missing 'pattern' parameter")]
+    #[error("missing 'pattern', 'regex', 'string' or 'xref-to' parameter")]
     MissingPattern,
     #[error("parse error in '{0}': {1}")]
     ParseError(&'static str, peg::error::ParseError<LineCol>),
@@ -2,15 +2,21 @@ use std::collections::HashMap;
 
 use crate::error::{Error, Result};
 use crate::exe::ExecutableData;
-use crate::patterns::{Pattern, VarType};
-use crate::types::POINTER_SIZE;
+use crate::patterns::VarType;
 
 #[derive(Debug)]
 pub enum Expr {
     Deref(Box<Self>),
     Add(Box<Self>, Box<Self>),
     Sub(Box<Self>, Box<Self>),
+    /// Reads the entry at an index of a virtual function table, `vfunc(vft, 3)` is the same as `*(vft + 3)`
+    /// with the default pointer-sized integers, but it doesn't depend on how integers are scaled.
+    /// The pointer size is the one of the executable.
+    VFunc(Box<Self>, u64),
     Ident(String),
+    /// Refers to one of the groups captured more than once under the same name, `name[0]` is
+    /// the same as `name`.
+    Index(String, usize),
     Int(u64),
 }
 
@@ -24,35 +30,66 @@ impl Expr {
             Expr::Deref(expr) => ctx.data.resolve_rel_rdata(expr.eval(ctx)?),
             Expr::Add(lhs, rhs) => Ok(lhs.eval(ctx)? + rhs.eval(ctx)?),
             Expr::Sub(lhs, rhs) => Ok(lhs.eval(ctx)? - rhs.eval(ctx)?),
-            Expr::Ident(name) => ctx.get_var(name),
-            Expr::Int(i) => Ok(*i * POINTER_SIZE as u64),
+            Expr::VFunc(table, index) => {
+                let entry = table.eval(ctx)? + index * ctx.data.pointer_size() as u64;
+                ctx.data.resolve_rel_rdata(entry)
+            }
+            Expr::Ident(name) => ctx.get_var(name, 0),
+            Expr::Index(name, index) => ctx.get_var(name, *index),
+            Expr::Int(i) => Ok(*i * ctx.int_scale),
         }
     }
 }
 
+/// The variables of a single match of a spec, shared by all of the parameters that are evaluated.
+/// They include the captured groups and the built-in variables, `match` for the address of
+/// the match and `symbol` for the address the symbol was resolved to, once it's known.
+/// A group can be captured more than once, the captures are kept in the order of the pattern.
 pub struct EvalContext<'a> {
-    vars: HashMap<&'a str, u64>,
+    vars: HashMap<&'a str, Vec<u64>>,
     data: &'a ExecutableData<'a>,
+    int_scale: u64,
 }
 
 impl<'a> EvalContext<'a> {
-    pub fn new(pattern: &'a Pattern, data: &'a ExecutableData, rva: u64) -> Result<Self> {
-        let mut vars = HashMap::new();
-        for (key, typ, offset) in pattern.groups() {
+    pub fn new<I>(groups: I, data: &'a ExecutableData, rva: u64) -> Result<Self>
+    where
+        I: IntoIterator<Item = (&'a str, VarType, usize)>,
+    {
+        let mut vars: HashMap<&str, Vec<u64>> = HashMap::new();
+        for (key, typ, offset) in groups {
             let abs = match typ {
                 VarType::Rel => data.resolve_rel_text(offset as u64 + rva)?,
             };
-            vars.insert(key, abs);
+            vars.entry(key).or_default().push(abs);
         }
-        let instance = Self { vars, data };
+        let instance = Self {
+            vars,
+            data,
+            int_scale: data.pointer_size() as u64,
+        };
         Ok(instance)
     }
 
-    fn get_var(&self, name: &str) -> Result<u64> {
-        self.vars
-            .get(name)
-            .cloned()
-            .ok_or_else(|| Error::UnresolvedName(name.to_owned()))
+    /// Integer literals are multiplied by the pointer size by default, this makes them
+    /// stand for plain byte counts instead.
+    pub fn with_byte_ints(mut self) -> Self {
+        self.int_scale = 1;
+        self
+    }
+
+    /// Adds a built-in variable, captured groups with the same name take precedence over it.
+    pub fn with_builtin(mut self, name: &'a str, value: u64) -> Self {
+        self.vars.entry(name).or_insert_with(|| vec![value]);
+        self
+    }
+
+    fn get_var(&self, name: &str, index: usize) -> Result<u64> {
+        match self.vars.get(name).and_then(|values| values.get(index)) {
+            Some(value) => Ok(*value),
+            None if index == 0 => Err(Error::UnresolvedName(name.to_owned())),
+            None => Err(Error::UnresolvedName(format!("{name}[{index}]"))),
+        }
     }
 }
 
@@ -61,7 +98,8 @@ peg::parser! {
         rule _() =
             quiet!{[' ' | '\t']*}
         rule number() -> u64
-            = n:$(['0'..='9']+) {? n.parse().or(Err("u64")) }
+            = "0x" n:$(['0'..='9' | 'a'..='f' | 'A'..='F']+) {? u64::from_str_radix(n, 16).or(Err("u64")) }
+            / n:$(['0'..='9']+) {? n.parse().or(Err("u64")) }
 
         pub rule expr() -> Expr = precedence!{
             x:(@) _ "+" _ y:@ { Expr::Add(x.into(), y.into()) }
@@ -70,7 +108,9 @@ peg::parser! {
            "*" e:expr() { Expr::Deref(e.into()) }
            --
             n:number() { Expr::Int(n) }
+            "vfunc" _ "(" _ t:expr() _ "," _ i:number() _ ")" { Expr::VFunc(t.into(), i) }
             "(" e:expr() ")" { e }
+            id:$(['a'..='z' | 'A'..='Z' | '_']+) _ "[" _ i:number() _ "]" { Expr::Index(id.to_owned(), i as usize) }
             id:$(['a'..='z' | 'A'..='Z' | '_']+) { Expr::Ident(id.to_owned()) }
           }
     }
@@ -79,10 +119,76 @@ peg::parser! {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layout::Layout;
+
+    const LAYOUT: &str = "
+        image_base = 0x140000000
+        [text]
+        file_offset = 0
+        address = 0x140001000
+        size = 0
+    ";
 
     #[test]
     fn parse_valid_expr() {
         let res = Expr::parse("*(vft + 2)");
         assert_eq!(format!("{:?}", res), r#"Ok(Deref(Add(Ident("vft"), Int(2))))"#);
     }
+
+    #[test]
+    fn parse_vfunc() {
+        let res = Expr::parse("vfunc(vft + 0x10, 3)");
+        assert_eq!(
+            format!("{:?}", res),
+            r#"Ok(VFunc(Add(Ident("vft"), Int(16)), 3))"#
+        );
+    }
+
+    #[test]
+    fn read_32bit_vtable_entries() {
+        let layout: Layout = toml::from_str(
+            "architecture = \"x86\"\nimage_base = 0x400000\n\
+             [text]\nfile_offset = 0\naddress = 0x401000\nsize = 0\n\
+             [rdata]\nfile_offset = 0\naddress = 0x402000\nsize = 0x10",
+        )
+        .unwrap();
+        let bytes: Vec<u8> = [0x401000u32, 0x401010, 0x401020, 0x401030]
+            .iter()
+            .flat_map(|entry| entry.to_le_bytes())
+            .collect();
+        let data = ExecutableData::from_layout(&bytes, &layout).unwrap();
+        let ctx = EvalContext::new([], &data, 0).unwrap();
+        let eval = |expr: &str, ctx: &EvalContext| Expr::parse(expr).unwrap().eval(ctx).unwrap();
+
+        assert_eq!(eval("*(0x100800 + 1)", &ctx), 0x401010);
+        assert_eq!(eval("vfunc(0x402004, 2)", &ctx.with_byte_ints()), 0x401030);
+    }
+
+    #[test]
+    fn keep_existing_variables() {
+        let data = ExecutableData::from_layout(&[], &Layout::parse(LAYOUT).unwrap()).unwrap();
+        let ctx = EvalContext::new([], &data, 0)
+            .unwrap()
+            .with_byte_ints()
+            .with_builtin("match", 0x1000)
+            .with_builtin("symbol", 0x1010)
+            .with_builtin("match", 0x2000);
+        let expr = Expr::parse("symbol - match + 4").unwrap();
+        assert_eq!(expr.eval(&ctx).unwrap(), 0x14);
+    }
+
+    #[test]
+    fn parse_indexed_groups() {
+        let res = Expr::parse("(*(fn[1] + 2)) - fn [ 0 ]");
+        assert_eq!(
+            format!("{:?}", res),
+            r#"Ok(Sub(Deref(Add(Index("fn", 1), Int(2))), Index("fn", 0)))"#
+        );
+    }
+
+    #[test]
+    fn parse_hex_literals() {
+        let res = Expr::parse("fn + 0x1F");
+        assert_eq!(format!("{:?}", res), r#"Ok(Add(Ident("fn"), Int(31)))"#);
+    }
 }
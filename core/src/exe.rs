@@ -1,9 +1,38 @@
-use object::{Architecture, BinaryFormat, Endianness, Object, ObjectSection};
+use std::{fmt, io};
+
+use gimli::UnwindSection;
+use object::{
+    elf, Architecture, BinaryFormat, Endian, Endianness, LittleEndian, Object, ObjectSection, ObjectSegment
+};
 
 use crate::error::{Error, Result};
+use crate::layout::{Layout, SectionLayout};
+use crate::types::DataModel;
+use crate::version::Version;
+
+/// Sections are identified by an optional segment name and a section name, in the order of preference.
+type SectionNames = &'static [(Option<&'static str>, &'static str)];
 
-const TEXT_SECTION: &str = ".text";
-const RDATA_SECTION: &str = ".rdata";
+const TEXT_SECTIONS: SectionNames = &[(None, ".text")];
+const RDATA_SECTIONS: SectionNames = &[(None, ".rdata")];
+const MACHO_TEXT_SECTIONS: SectionNames = &[(Some("__TEXT"), "__text")];
+const MACHO_RDATA_SECTIONS: SectionNames =
+    &[(Some("__DATA_CONST"), "__const"), (Some("__TEXT"), "__const")];
+const MACHO_TEXT_SEGMENT: &str = "__TEXT";
+const PDATA_SECTION: &str = ".pdata";
+const EH_FRAME_SECTION: &str = ".eh_frame";
+const RSRC_SECTION: &str = ".rsrc";
+/// The `dwSignature` of a `VS_FIXEDFILEINFO` structure.
+const VS_FFI_SIGNATURE: u32 = 0xFEEF04BD;
+const VS_FIXEDFILEINFO_SIZE: usize = 52;
+/// Size of a `RUNTIME_FUNCTION` entry in the exception directory.
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+/// Set in the flags of `UNWIND_INFO` when it's followed by the `RUNTIME_FUNCTION` of the parent.
+const UNW_FLAG_CHAININFO: u8 = 0x4;
+/// Limits how many chained entries are followed to get to the start of a function.
+const MAX_UNWIND_CHAIN: usize = 32;
+/// Index of the OS ABI in the identification bytes that start an ELF header.
+pub(crate) const EI_OSABI: usize = 7;
 
 pub struct ExecutableData<'a> {
     text: &'a [u8],
@@ -11,49 +40,123 @@ pub struct ExecutableData<'a> {
     image_base: u64,
     rdata_offset: u64,
     text_offset: u64,
+    /// The addressing model is only resolved when a relative operand is read, so that executables of
+    /// other architectures can still be searched.
+    architecture: Architecture,
+    endianness: Endianness,
+    /// Sorted by the start of the range, empty when the executable has no unwind tables.
+    functions: Vec<FunctionRange>,
+    pointer_size: usize,
+    /// All of the sections, data specs can be searched for in any of them.
+    sections: Vec<NamedSection<'a>>,
 }
 
 impl<'a> ExecutableData<'a> {
-    pub fn new(exe: &'a object::read::File<'a>) -> Result<Self> {
-        let text = exe
-            .section_by_name(TEXT_SECTION)
-            .ok_or(Error::MissingSection("text"))?;
-        let rdata = exe
-            .section_by_name(RDATA_SECTION)
-            .ok_or(Error::MissingSection("rdata"))?;
+    pub fn new(exe: &object::read::File<'a>) -> Result<Self> {
+        let (text_names, rdata_names) = match exe.format() {
+            BinaryFormat::MachO => (MACHO_TEXT_SECTIONS, MACHO_RDATA_SECTIONS),
+            _ => (TEXT_SECTIONS, RDATA_SECTIONS),
+        };
+        let text = find_section(exe, text_names).ok_or(Error::MissingSection("text"))?;
+        let rdata = find_section(exe, rdata_names).ok_or(Error::MissingSection("rdata"))?;
 
         let res = Self {
             text: text.data()?,
             rdata: rdata.data()?,
-            image_base: exe.relative_address_base(),
+            image_base: image_base(exe),
             rdata_offset: rdata.address(),
             text_offset: text.address(),
+            architecture: exe.architecture(),
+            endianness: exe.endianness(),
+            functions: match exe.format() {
+                BinaryFormat::Pe => read_function_table(exe)?,
+                BinaryFormat::Elf => read_eh_frame(exe)?,
+                _ => vec![],
+            },
+            pointer_size: if exe.is_64() { 8 } else { 4 },
+            sections: exe
+                .sections()
+                .filter_map(|section| {
+                    Some(NamedSection {
+                        name: section.name().ok()?.to_owned(),
+                        address: section.address(),
+                        data: section.data().ok()?,
+                    })
+                })
+                .collect(),
+        };
+        Ok(res)
+    }
+
+    pub fn from_layout(bytes: &'a [u8], layout: &Layout) -> Result<Self> {
+        let section = |layout: &SectionLayout| {
+            let start = layout.file_offset as usize;
+            bytes
+                .get(start..start + layout.size as usize)
+                .ok_or(Error::InvalidAccess(start))
+        };
+
+        let res = Self {
+            text: section(&layout.text)?,
+            rdata: layout
+                .rdata
+                .as_ref()
+                .map(section)
+                .transpose()?
+                .unwrap_or_default(),
+            image_base: layout.image_base,
+            rdata_offset: layout
+                .rdata
+                .as_ref()
+                .map(|rdata| rdata.address)
+                .unwrap_or_default(),
+            text_offset: layout.text.address,
+            architecture: layout.architecture.into(),
+            // all of the architectures supported in layouts are little-endian
+            endianness: Endianness::Little,
+            functions: vec![],
+            pointer_size: ExeProperties::from_layout(layout).address_size()? as usize,
+            sections: layout
+                .rdata
+                .as_ref()
+                .map(|rdata| -> Result<NamedSection> {
+                    Ok(NamedSection {
+                        name: RDATA_SECTIONS[0].1.to_owned(),
+                        address: rdata.address,
+                        data: section(rdata)?,
+                    })
+                })
+                .transpose()?
+                .into_iter()
+                .collect(),
         };
         Ok(res)
     }
 
     pub fn resolve_rel_text(&self, addr: u64) -> Result<u64> {
+        let addressing = self.addressing()?;
         let addr = addr as usize;
         let bytes = self
             .text
-            .get(addr..addr + std::mem::size_of::<i32>())
-            .ok_or(Error::InvalidAccess(addr))?
-            .try_into()
-            .unwrap();
-        let rel = i32::from_ne_bytes(bytes);
-        let abs = self.text_offset as i64 + addr as i64 + std::mem::size_of::<i32>() as i64 + rel as i64;
-        Ok(abs as u64)
+            .get(addr..addr + addressing.rel_size())
+            .ok_or(Error::InvalidAccess(addr))?;
+        let field_addr = self.text_offset + addr as u64;
+        Ok(addressing.resolve_rel(field_addr, bytes, self.endianness))
     }
 
+    /// Reads a pointer from the read-only data at a virtual address.
     pub fn resolve_rel_rdata(&self, addr: u64) -> Result<u64> {
-        let addr = addr as usize - self.rdata_offset as usize;
+        let addr = (addr as usize)
+            .checked_sub(self.rdata_offset as usize)
+            .ok_or(Error::InvalidAccess(addr as usize))?;
         let bytes = self
             .rdata
-            .get(addr..addr + std::mem::size_of::<u64>())
-            .ok_or(Error::InvalidAccess(addr))?
-            .try_into()
-            .unwrap();
-        Ok(u64::from_ne_bytes(bytes))
+            .get(addr..addr + self.pointer_size)
+            .ok_or(Error::InvalidAccess(addr))?;
+        match self.pointer_size {
+            4 => Ok(self.endianness.read_u32_bytes(bytes.try_into().unwrap()).into()),
+            _ => Ok(self.endianness.read_u64_bytes(bytes.try_into().unwrap())),
+        }
     }
 
     pub fn text(&'a self) -> &'a [u8] {
@@ -64,52 +167,584 @@ impl<'a> ExecutableData<'a> {
         self.text_offset
     }
 
+    pub fn rdata(&'a self) -> &'a [u8] {
+        self.rdata
+    }
+
+    pub fn rdata_offset(&'a self) -> u64 {
+        self.rdata_offset
+    }
+
+    /// The preferred load address of the executable, RVAs are relative to it.
     pub fn image_base(&'a self) -> u64 {
         self.image_base
     }
 
+    /// Converts an address relative to the image base into a virtual address at the preferred load address.
+    pub fn rva_to_va(&'a self, rva: u64) -> u64 {
+        self.image_base + rva
+    }
+
+    /// Converts a virtual address at the preferred load address into an address relative to the image base.
+    pub fn va_to_rva(&'a self, va: u64) -> u64 {
+        va - self.image_base
+    }
+
     pub fn text_offset_from_base(&'a self) -> u64 {
         self.text_offset - self.image_base
     }
+
+    pub fn text_end_from_base(&'a self) -> u64 {
+        self.text_offset_from_base() + self.text.len() as u64
+    }
+
+    pub fn addressing(&'a self) -> Result<AddressingModel> {
+        AddressingModel::for_architecture(self.architecture)
+    }
+
+    pub fn endianness(&'a self) -> Endianness {
+        self.endianness
+    }
+
+    /// Size of the pointers in the executable, 4 bytes for 32-bit targets and 8 bytes otherwise.
+    pub fn pointer_size(&'a self) -> usize {
+        self.pointer_size
+    }
+
+    /// Returns the RVA and the contents of a section by its name.
+    pub fn section(&'a self, name: &str) -> Option<(u64, &'a [u8])> {
+        self.sections
+            .iter()
+            .find(|section| section.name == name)
+            .and_then(|section| Some((section.address.checked_sub(self.image_base)?, section.data)))
+    }
+
+    /// Returns whether function bounds are known from the exception directory or `.eh_frame`.
+    pub fn has_function_table(&'a self) -> bool {
+        !self.functions.is_empty()
+    }
+
+    /// Returns the RVAs of the start and the end of each function in the unwind tables.
+    pub fn functions(&'a self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        self.functions
+            .iter()
+            .filter(|range| range.begin == range.function_start)
+            .map(|range| (range.begin, range.end))
+    }
+
+    /// Returns the RVA of the start of the function containing an RVA according to the unwind tables.
+    pub fn function_containing(&'a self, rva: u64) -> Option<u64> {
+        let index = self
+            .functions
+            .partition_point(|range| range.begin <= rva)
+            .checked_sub(1)?;
+        let range = &self.functions[index];
+        (rva < range.end).then_some(range.function_start)
+    }
+
+    /// Returns the RVA of the end of a function starting at an RVA, excluding its chained ranges.
+    pub fn function_end(&'a self, rva: u64) -> Option<u64> {
+        let index = self
+            .functions
+            .binary_search_by_key(&rva, |range| range.begin)
+            .ok()?;
+        let range = &self.functions[index];
+        (range.function_start == rva).then_some(range.end)
+    }
+
+    /// Replaces the function ranges that would be read from the unwind tables.
+    #[cfg(test)]
+    pub(crate) fn with_functions(mut self, functions: &[(u64, u64)]) -> Self {
+        self.functions = functions
+            .iter()
+            .map(|&(begin, end)| FunctionRange {
+                begin,
+                end,
+                function_start: begin,
+            })
+            .collect();
+        self.functions.sort_by_key(|range| range.begin);
+        self
+    }
+}
+
+struct NamedSection<'a> {
+    name: String,
+    address: u64,
+    data: &'a [u8],
+}
+
+/// A range of code described by an entry of the unwind tables. Functions can be split into multiple
+/// chained ranges in the exception directory, all of them point at the start of the function they belong to.
+#[derive(Debug, Clone, Copy)]
+struct FunctionRange {
+    begin: u64,
+    end: u64,
+    function_start: u64,
+}
+
+/// Reads the `RUNTIME_FUNCTION` entries of the `.pdata` section of x86-64 PE files.
+fn read_function_table(exe: &object::read::File) -> Result<Vec<FunctionRange>> {
+    if exe.architecture() != Architecture::X86_64 {
+        return Ok(vec![]);
+    }
+    let pdata = match exe.section_by_name(PDATA_SECTION) {
+        Some(section) => section.data()?,
+        None => return Ok(vec![]),
+    };
+    let image_base = image_base(exe);
+    let mut functions: Vec<_> = pdata
+        .chunks_exact(RUNTIME_FUNCTION_SIZE)
+        .map(read_runtime_function)
+        .filter(|(begin, end, _)| begin < end)
+        .map(|(begin, end, unwind_info)| FunctionRange {
+            begin: begin.into(),
+            end: end.into(),
+            function_start: chain_start(exe, image_base, begin, unwind_info).into(),
+        })
+        .collect();
+    functions.sort_by_key(|range| range.begin);
+    Ok(functions)
+}
+
+/// Reads the ranges of the FDEs in the `.eh_frame` section of ELF files.
+fn read_eh_frame(exe: &object::read::File) -> Result<Vec<FunctionRange>> {
+    let section = match exe.section_by_name(EH_FRAME_SECTION) {
+        Some(section) => section,
+        None => return Ok(vec![]),
+    };
+    let endian = if exe.is_little_endian() {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    };
+    let mut eh_frame = gimli::EhFrame::new(section.data()?, endian);
+    eh_frame.set_address_size(if exe.is_64() { 8 } else { 4 });
+    let mut bases = gimli::BaseAddresses::default().set_eh_frame(section.address());
+    if let Some(text) = find_section(exe, TEXT_SECTIONS) {
+        bases = bases.set_text(text.address());
+    }
+
+    let image_base = image_base(exe);
+    let mut functions = vec![];
+    let mut entries = eh_frame.entries(&bases);
+    while let Some(entry) = entries.next()? {
+        if let gimli::CieOrFde::Fde(partial) = entry {
+            let fde = partial.parse(|section, bases, offset| section.cie_from_offset(bases, offset))?;
+            match fde.initial_address().checked_sub(image_base) {
+                Some(begin) if fde.len() > 0 => functions.push(FunctionRange {
+                    begin,
+                    end: begin + fde.len(),
+                    function_start: begin,
+                }),
+                _ => {}
+            }
+        }
+    }
+    functions.sort_by_key(|range| range.begin);
+    Ok(functions)
+}
+
+/// Follows the chained unwind info of an entry back to the start of the function it belongs to.
+fn chain_start(exe: &object::read::File, image_base: u64, mut begin: u32, mut unwind_info: u32) -> u32 {
+    for _ in 0..MAX_UNWIND_CHAIN {
+        let parent = if unwind_info & 1 != 0 {
+            // the lowest bit marks a reference to the entry of the parent instead of unwind info
+            read_at_rva(exe, image_base, unwind_info & !1, RUNTIME_FUNCTION_SIZE)
+        } else {
+            let info = read_at_rva(exe, image_base, unwind_info, 4);
+            match info {
+                Some(info) if (info[0] >> 3) & UNW_FLAG_CHAININFO != 0 => {
+                    // unwind codes are 2 bytes each and their count is rounded up to an even number
+                    let codes = (info[2] as usize + 1) & !1;
+                    let offset = 4 + codes as u32 * 2;
+                    read_at_rva(exe, image_base, unwind_info + offset, RUNTIME_FUNCTION_SIZE)
+                }
+                _ => None,
+            }
+        };
+        match parent.map(read_runtime_function) {
+            Some((parent_begin, _, parent_unwind_info)) => {
+                begin = parent_begin;
+                unwind_info = parent_unwind_info;
+            }
+            None => break,
+        }
+    }
+    begin
+}
+
+fn read_runtime_function(bytes: &[u8]) -> (u32, u32, u32) {
+    let read = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    (read(0), read(4), read(8))
+}
+
+fn read_at_rva<'data>(
+    exe: &object::read::File<'data>,
+    image_base: u64,
+    rva: u32,
+    len: usize,
+) -> Option<&'data [u8]> {
+    let va = image_base + rva as u64;
+    let section = exe
+        .sections()
+        .find(|section| (section.address()..section.address() + section.size()).contains(&va))?;
+    let start = (va - section.address()) as usize;
+    section.data().ok()?.get(start..start + len)
+}
+
+fn find_section<'data, 'file>(
+    exe: &'file object::read::File<'data>,
+    names: SectionNames,
+) -> Option<object::read::Section<'data, 'file>> {
+    names.iter().find_map(|(segment, name)| {
+        exe.sections().find(|section| {
+            section.name().ok() == Some(*name)
+                && segment.map_or(true, |segment| {
+                    section.segment_name().ok().flatten() == Some(segment)
+                })
+        })
+    })
+}
+
+/// Returns the address that RVAs are relative to. Mach-O files don't define one,
+/// so the address of the `__TEXT` segment is used like in most tools.
+fn image_base(exe: &object::read::File) -> u64 {
+    if exe.format() == BinaryFormat::MachO {
+        let text = exe
+            .segments()
+            .find(|segment| segment.name().ok().flatten() == Some(MACHO_TEXT_SEGMENT));
+        if let Some(text) = text {
+            return text.address();
+        }
+    }
+    exe.relative_address_base()
+}
+
+/// Identifies a build of an executable by what it records about itself, so that it's recognized
+/// under any path and no matter how it was copied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryIdentity {
+    /// The file version from the `VS_VERSIONINFO` resource of a PE executable.
+    pub file_version: Option<Version>,
+    /// The GUID and the age of the PDB from the CodeView entry in the debug directory of a PE executable.
+    pub pdb: Option<([u8; 16], u32)>,
+    /// The contents of the `NT_GNU_BUILD_ID` note of an ELF executable.
+    pub build_id: Option<Vec<u8>>,
+}
+
+impl BinaryIdentity {
+    pub fn from_object(exe: &object::read::File) -> Self {
+        Self {
+            file_version: file_version(exe),
+            pdb: exe
+                .pdb_info()
+                .ok()
+                .flatten()
+                .map(|info| (info.guid(), info.age())),
+            build_id: exe.build_id().ok().flatten().map(<[u8]>::to_vec),
+        }
+    }
+
+    /// Returns the identifier of the PDB in the form used by symbol servers, the GUID followed by the age.
+    pub fn pdb_id(&self) -> Option<String> {
+        let (guid, age) = self.pdb?;
+        // the first three fields of the GUID are little-endian integers
+        let data1 = LittleEndian.read_u32_bytes(guid[0..4].try_into().unwrap());
+        let data2 = LittleEndian.read_u16_bytes(guid[4..6].try_into().unwrap());
+        let data3 = LittleEndian.read_u16_bytes(guid[6..8].try_into().unwrap());
+        let data4: String = guid[8..].iter().map(|byte| format!("{byte:02X}")).collect();
+        Some(format!("{data1:08X}{data2:04X}{data3:04X}{data4}{age:X}"))
+    }
+
+    pub fn build_id_hex(&self) -> Option<String> {
+        let build_id = self.build_id.as_ref()?;
+        Some(build_id.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+
+    /// Returns a key that's unique to the build, when the executable has a PDB identifier or
+    /// a build ID. The file version alone isn't enough, since it's often the same between builds.
+    pub fn key(&self) -> Option<String> {
+        match (self.pdb_id(), self.build_id_hex()) {
+            (Some(pdb), _) => Some(format!("pdb:{pdb}")),
+            (None, Some(build_id)) => Some(format!("build-id:{build_id}")),
+            (None, None) => None,
+        }
+    }
+}
+
+impl fmt::Display for BinaryIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<_> = [
+            self.file_version
+                .as_ref()
+                .map(|version| format!("version {version}")),
+            self.pdb_id().map(|id| format!("PDB {id}")),
+            self.build_id_hex().map(|id| format!("build ID {id}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if parts.is_empty() {
+            write!(f, "unknown build")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
 }
 
-#[derive(Debug)]
+/// Returns the file version from the `VS_FIXEDFILEINFO` in the version resource of a PE executable.
+/// The structure is found by its signature, which is always 4-byte aligned in the resource section.
+fn file_version(exe: &object::read::File) -> Option<Version> {
+    if exe.format() != BinaryFormat::Pe {
+        return None;
+    }
+    let data = exe.section_by_name(RSRC_SECTION)?.data().ok()?;
+    let start = (0..data.len().saturating_sub(VS_FIXEDFILEINFO_SIZE))
+        .step_by(4)
+        .find(|&i| LittleEndian.read_u32_bytes(data[i..i + 4].try_into().unwrap()) == VS_FFI_SIGNATURE)?;
+    // dwFileVersionMS and dwFileVersionLS follow the signature and the version of the structure
+    let ms = LittleEndian.read_u32_bytes(data[start + 8..start + 12].try_into().unwrap());
+    let ls = LittleEndian.read_u32_bytes(data[start + 12..start + 16].try_into().unwrap());
+    let components = [ms >> 16, ms & 0xFFFF, ls >> 16, ls & 0xFFFF];
+    Some(Version::new(components.into_iter().map(u64::from).collect()))
+}
+
+/// Describes how relative operands captured by patterns are turned into absolute addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingModel {
+    /// A signed 32-bit displacement relative to the end of the displacement field (x86 `CALL`/`JMP`/RIP).
+    X86Rel32,
+}
+
+impl AddressingModel {
+    pub fn for_architecture(architecture: Architecture) -> Result<Self> {
+        match architecture {
+            Architecture::I386 | Architecture::X86_64 | Architecture::X86_64_X32 => Ok(Self::X86Rel32),
+            other => Err(Error::UnsupportedArchitecture(other)),
+        }
+    }
+
+    /// Size in bytes of a relative operand.
+    pub fn rel_size(&self) -> usize {
+        match self {
+            AddressingModel::X86Rel32 => std::mem::size_of::<i32>(),
+        }
+    }
+
+    /// Resolves a relative operand located at `field_addr` into an absolute address.
+    pub fn resolve_rel(&self, field_addr: u64, bytes: &[u8], endianness: Endianness) -> u64 {
+        match self {
+            AddressingModel::X86Rel32 => {
+                let rel = endianness.read_i32_bytes(bytes[..4].try_into().unwrap());
+                (field_addr as i64 + self.rel_size() as i64 + rel as i64) as u64
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ExeProperties {
     architecture: Architecture,
     endianess: Endianness,
+    format: BinaryFormat,
     image_base: u64,
+    object_config: ObjectConfig,
+}
+
+/// Overrides of the objects written with [`ExeProperties::replicate_object`], for loaders that
+/// only accept some values of the header fields or expect other section names.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectConfig {
+    /// `e_machine` of ELF objects, derived from the architecture by default.
+    pub elf_machine: Option<u16>,
+    /// `EI_OSABI` of ELF objects, `ELFOSABI_NONE` by default.
+    pub elf_os_abi: Option<u8>,
+    /// Standard section names along with the names to write the sections under instead.
+    pub section_names: Vec<(String, String)>,
 }
 
 impl ExeProperties {
-    pub fn from_object<'a: 'b, 'b, O: Object<'a, 'b>>(obj: &'b O) -> Self {
+    pub fn from_object(obj: &object::read::File) -> Self {
         Self {
             architecture: obj.architecture(),
             endianess: obj.endianness(),
-            image_base: obj.relative_address_base(),
+            format: obj.format(),
+            image_base: image_base(obj),
+            object_config: ObjectConfig::default(),
         }
     }
 
+    /// Creates an empty object for the architecture of the executable, it should be written with
+    /// [`ExeProperties::write_object`] to apply the [`ObjectConfig`].
     pub fn replicate_object<'a>(&self, format: BinaryFormat) -> object::write::Object<'a> {
         object::write::Object::new(format, self.architecture, self.endianess)
     }
 
-    pub fn is64bit(&self) -> bool {
-        match self.architecture {
-            Architecture::X86_64 => true,
-            Architecture::X86_64_X32 => false,
-            _ => unimplemented!(),
+    /// Writes an object created with [`ExeProperties::replicate_object`]. The ELF header fields
+    /// of the [`ObjectConfig`] can't be set through the object writer, so they're patched afterwards.
+    pub fn write_object<W: io::Write>(&self, obj: &object::write::Object, mut output: W) -> Result<()> {
+        let mut data = vec![];
+        obj.write_stream(&mut data)?;
+        if data.starts_with(&elf::ELFMAG) {
+            if let Some(os_abi) = self.object_config.elf_os_abi {
+                data[EI_OSABI] = os_abi;
+            }
+            if let Some(machine) = self.object_config.elf_machine {
+                // e_machine follows e_ident and e_type in both ELF classes
+                data[18..20].copy_from_slice(&self.endianess.write_u16_bytes(machine));
+            }
         }
+        output.write_all(&data)?;
+        Ok(())
     }
 
-    pub fn address_size(&self) -> u8 {
-        match self.architecture {
-            Architecture::X86_64 => 8,
-            Architecture::X86_64_X32 => 4,
-            _ => unimplemented!(),
-        }
+    /// Returns the name to write a section under, which is its standard name unless the
+    /// [`ObjectConfig`] renames it.
+    pub fn object_section_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.object_config
+            .section_names
+            .iter()
+            .find(|(from, _)| from == name)
+            .map_or(name, |(_, to)| to)
+    }
+
+    pub fn with_object_config(mut self, config: ObjectConfig) -> Self {
+        self.object_config = config;
+        self
+    }
+
+    pub fn is64bit(&self) -> Result<bool> {
+        Ok(self.address_size()? == 8)
     }
 
+    pub fn address_size(&self) -> Result<u8> {
+        self.architecture
+            .address_size()
+            .map(|size| size.bytes())
+            .ok_or(Error::UnsupportedArchitecture(self.architecture))
+    }
+
+    /// The address the executable is assumed to be loaded at. This is the preferred load address,
+    /// unless it has been overriden with [`ExeProperties::with_image_base`].
     pub fn image_base(&self) -> u64 {
         self.image_base
     }
+
+    /// Converts an address relative to the image base into a virtual address at the assumed load address.
+    pub fn rva_to_va(&self, rva: u64) -> u64 {
+        self.image_base + rva
+    }
+
+    pub fn from_layout(layout: &Layout) -> Self {
+        Self {
+            architecture: layout.architecture.into(),
+            endianess: Endianness::Little,
+            format: layout.format.into(),
+            image_base: layout.image_base,
+            object_config: ObjectConfig::default(),
+        }
+    }
+
+    pub fn with_image_base(mut self, image_base: u64) -> Self {
+        self.image_base = image_base;
+        self
+    }
+
+    pub fn architecture(&self) -> Architecture {
+        self.architecture
+    }
+
+    pub fn format(&self) -> BinaryFormat {
+        self.format
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianess
+    }
+
+    /// Returns the data model of the platform the executable targets, based on its architecture
+    /// and format.
+    pub fn data_model(&self) -> DataModel {
+        match (self.architecture, self.format) {
+            (Architecture::I386 | Architecture::X86_64_X32, _) => DataModel::Ilp32,
+            (_, BinaryFormat::Pe | BinaryFormat::Coff) => DataModel::Llp64,
+            _ => DataModel::Lp64,
+        }
+    }
+
+    pub fn addressing_model(&self) -> Result<AddressingModel> {
+        AddressingModel::for_architecture(self.architecture)
+    }
+
+    /// Returns the target triple of the platform the executable targets, so that the sources can
+    /// be compiled for the same ABI. PE files are assumed to be built with MSVC.
+    pub fn target_triple(&self) -> Option<&'static str> {
+        match (self.architecture, self.format) {
+            (Architecture::X86_64, BinaryFormat::Pe | BinaryFormat::Coff) => Some("x86_64-pc-windows-msvc"),
+            (Architecture::I386, BinaryFormat::Pe | BinaryFormat::Coff) => Some("i686-pc-windows-msvc"),
+            (Architecture::X86_64, BinaryFormat::Elf) => Some("x86_64-unknown-linux-gnu"),
+            (Architecture::X86_64_X32, BinaryFormat::Elf) => Some("x86_64-unknown-linux-gnux32"),
+            (Architecture::I386, BinaryFormat::Elf) => Some("i686-unknown-linux-gnu"),
+            (Architecture::X86_64, BinaryFormat::MachO) => Some("x86_64-apple-macosx"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(architecture: &str, format: &str) -> ExeProperties {
+        let layout: Layout = toml::from_str(&format!(
+            "architecture = \"{architecture}\"\nformat = \"{format}\"\nimage_base = 0x400000\n\
+             [text]\nfile_offset = 0x400\naddress = 0x401000\nsize = 0x1000"
+        ))
+        .unwrap();
+        ExeProperties::from_layout(&layout)
+    }
+
+    #[test]
+    fn data_model_matches_address_size() {
+        for (architecture, format) in [("x86", "pe"), ("x86", "elf"), ("x86_64", "pe"), ("x86_64", "elf")] {
+            let props = props(architecture, format);
+            let pointer_size = props.data_model().pointer_size();
+            assert_eq!(pointer_size, props.address_size().unwrap() as usize);
+            assert_eq!(props.is64bit().unwrap(), pointer_size == 8);
+        }
+        assert_eq!(props("x86", "pe").data_model(), DataModel::Ilp32);
+        assert_eq!(props("x86", "elf").data_model(), DataModel::Ilp32);
+    }
+
+    #[test]
+    fn load_unsupported_architecture() {
+        let mut obj =
+            object::write::Object::new(BinaryFormat::Elf, Architecture::Aarch64, Endianness::Little);
+        let text = obj.add_section(vec![], b".text".to_vec(), object::SectionKind::Text);
+        obj.append_section_data(text, &[0; 16], 4);
+        let rdata = obj.add_section(vec![], b".rdata".to_vec(), object::SectionKind::ReadOnlyData);
+        obj.append_section_data(rdata, &0x1234u64.to_le_bytes(), 8);
+        let bytes = obj.write().unwrap();
+
+        let exe = object::read::File::parse(&*bytes).unwrap();
+        let data = ExecutableData::new(&exe).unwrap();
+        assert_eq!(data.resolve_rel_rdata(0).unwrap(), 0x1234);
+        assert!(matches!(
+            data.resolve_rel_text(0),
+            Err(Error::UnsupportedArchitecture(Architecture::Aarch64))
+        ));
+        assert!(matches!(ExeProperties::from_object(&exe).address_size(), Ok(8)));
+    }
+
+    #[test]
+    fn target_triple_matches_address_size() {
+        assert_eq!(props("x86", "pe").target_triple(), Some("i686-pc-windows-msvc"));
+        assert_eq!(props("x86", "elf").target_triple(), Some("i686-unknown-linux-gnu"));
+        for (architecture, format) in [("x86", "pe"), ("x86", "elf"), ("x86_64", "pe"), ("x86_64", "elf")] {
+            let props = props(architecture, format);
+            let triple = props.target_triple().unwrap();
+            assert_eq!(triple.starts_with("i686"), !props.is64bit().unwrap());
+        }
+    }
 }
@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use crate::error::SymbolError;
+use crate::exe::ExecutableData;
+use crate::patterns::{self, Pattern};
+use crate::spec::Warning;
+
+/// Number of bytes shown before and after each of the places of interest.
+const CONTEXT_SIZE: usize = 32;
+const BYTES_PER_LINE: usize = 16;
+/// Ambiguous patterns can have thousands of matches, only the first few are shown.
+const MAX_SHOWN_MATCHES: usize = 8;
+
+/// Describes the bytes around the places a failed spec came close to matching at, or around
+/// each of its matches when it's ambiguous. `section` is the section of a spec of data.
+/// Returns `None` for errors that can't be explained by looking at the bytes.
+pub fn explain_failure(
+    data: &ExecutableData,
+    patterns: &[Pattern],
+    section: Option<&str>,
+    err: &SymbolError,
+) -> Option<String> {
+    let (base, bytes) = match section {
+        Some(name) => data.section(name)?,
+        None => (data.text_offset_from_base(), data.text()),
+    };
+    let mut res = String::new();
+    match err {
+        SymbolError::NoMatches(_) => {
+            let (offset, len) = patterns
+                .iter()
+                .filter_map(|pattern| pattern.longest_partial_match(bytes))
+                .max_by_key(|(offset, len)| (*len, std::cmp::Reverse(*offset)))?;
+            let va = data.rva_to_va(base + offset as u64);
+            writeln!(res, "closest partial match at 0x{va:X}, mismatch after {len} byte(s):").unwrap();
+            write_hex_dump(&mut res, data, base, bytes, offset + len);
+        }
+        _ if err.warning() == Some(Warning::Ambiguous) => {
+            let matches = patterns::multi_search(patterns, bytes);
+            let mut offsets: Vec<_> = matches.iter().map(|mat| mat.rva as usize).collect();
+            offsets.dedup();
+            for offset in offsets.iter().take(MAX_SHOWN_MATCHES) {
+                let va = data.rva_to_va(base + *offset as u64);
+                writeln!(res, "match at 0x{va:X}:").unwrap();
+                write_hex_dump(&mut res, data, base, bytes, *offset);
+            }
+            if offsets.len() > MAX_SHOWN_MATCHES {
+                writeln!(res, "and {} more", offsets.len() - MAX_SHOWN_MATCHES).unwrap();
+            }
+        }
+        _ => return None,
+    }
+    (!res.is_empty()).then_some(res)
+}
+
+/// Writes the bytes around an offset in a section as lines of hex with their virtual addresses,
+/// the byte at the offset is enclosed in brackets.
+fn write_hex_dump(output: &mut String, data: &ExecutableData, base: u64, bytes: &[u8], offset: usize) {
+    let start = offset.saturating_sub(CONTEXT_SIZE) / BYTES_PER_LINE * BYTES_PER_LINE;
+    let end = (offset + CONTEXT_SIZE).min(bytes.len());
+    for line_start in (start..end).step_by(BYTES_PER_LINE) {
+        let va = data.rva_to_va(base + line_start as u64);
+        write!(output, "  {va:016X} ").unwrap();
+        for (pos, byte) in bytes[line_start..end.min(line_start + BYTES_PER_LINE)]
+            .iter()
+            .enumerate()
+        {
+            if line_start + pos == offset {
+                write!(output, "[{byte:02X}]").unwrap();
+            } else if line_start + pos == offset + 1 {
+                write!(output, "{byte:02X}").unwrap();
+            } else {
+                write!(output, " {byte:02X}").unwrap();
+            }
+        }
+        output.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Layout;
+
+    #[test]
+    fn dump_bytes_around_match() {
+        let layout =
+            Layout::parse("image_base = 0x140000000\n[text]\nfile_offset = 0\naddress = 0x140001000\nsize = 0x40")
+                .unwrap();
+        let bytes: Vec<u8> = (0..0x40).collect();
+        let data = ExecutableData::from_layout(&bytes, &layout).unwrap();
+
+        let mut output = String::new();
+        write_hex_dump(&mut output, &data, data.text_offset_from_base(), data.text(), 0x21);
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[2],
+            "  0000000140001020  20[21]22 23 24 25 26 27 28 29 2A 2B 2C 2D 2E 2F"
+        );
+    }
+}
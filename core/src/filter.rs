@@ -0,0 +1,90 @@
+/// A pattern for type names where `*` matches any sequence of characters and `?` matches a single
+/// character, like `std::_Tree*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob(Vec<char>);
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        Self(pattern.chars().collect())
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<_> = name.chars().collect();
+        let (mut pat_pos, mut name_pos) = (0, 0);
+        // the position after the last star and the position in the name it has been matched up to
+        let mut star = None;
+        while name_pos < name.len() {
+            match self.0.get(pat_pos) {
+                Some('*') => {
+                    pat_pos += 1;
+                    star = Some((pat_pos, name_pos));
+                }
+                Some(&expected) if expected == '?' || expected == name[name_pos] => {
+                    pat_pos += 1;
+                    name_pos += 1;
+                }
+                // on a mismatch the last star takes one more character
+                _ => match star {
+                    Some((star_pat_pos, star_name_pos)) => {
+                        pat_pos = star_pat_pos;
+                        name_pos = star_name_pos + 1;
+                        star = Some((star_pat_pos, name_pos));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        self.0[pat_pos..].iter().all(|&rest| rest == '*')
+    }
+}
+
+/// Decides which named types are exported with `--export-type` and `--skip-type`. All of the types
+/// are exported when there are no `--export-type` patterns, the skipped ones are left out either way.
+#[derive(Debug, Clone, Default)]
+pub struct TypeFilter {
+    export: Vec<Glob>,
+    skip: Vec<Glob>,
+}
+
+impl TypeFilter {
+    pub fn new(export: Vec<Glob>, skip: Vec<Glob>) -> Self {
+        Self { export, skip }
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        (self.export.is_empty() || self.export.iter().any(|glob| glob.matches(name)))
+            && !self.skip.iter().any(|glob| glob.matches(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_globs() {
+        let glob = Glob::new("std::_Tree*");
+        assert!(glob.matches("std::_Tree<int>"));
+        assert!(glob.matches("std::_Tree"));
+        assert!(!glob.matches("std::map<int, int>"));
+
+        let glob = Glob::new("*::Node<?>");
+        assert!(glob.matches("Game::List::Node<T>"));
+        assert!(!glob.matches("Game::List::Node<int>"));
+        assert!(Glob::new("*a*b").matches("xaybab"));
+        assert!(!Glob::new("*a*b").matches("xayba"));
+    }
+
+    #[test]
+    fn filter_types() {
+        let filter = TypeFilter::new(vec![Glob::new("Game::*")], vec![Glob::new("*Internal*")]);
+        assert!(filter.is_exported("Game::Player"));
+        assert!(!filter.is_exported("Game::Internal::Pool"));
+        assert!(!filter.is_exported("std::string"));
+
+        let filter = TypeFilter::new(vec![], vec![Glob::new("std::*")]);
+        assert!(filter.is_exported("Game::Player"));
+        assert!(!filter.is_exported("std::string"));
+        assert!(TypeFilter::default().is_exported("std::string"));
+    }
+}
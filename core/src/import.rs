@@ -0,0 +1,749 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use gimli::{
+    AttributeValue, DebuggingInformationEntry, EndianSlice, EntriesTreeNode, RunTimeEndian, Unit, UnitOffset
+};
+use object::{Object, ObjectSection};
+use ustr::Ustr;
+
+use crate::error::{Error, Result};
+use crate::exe::ExeProperties;
+use crate::spec::SourceLocation;
+use crate::symbols::{Confidence, FunctionSymbol};
+use crate::types::{
+    DataMember, EnumMember, EnumType, EnumValue, FunctionType, NameAllocator, StructType, Type, TypeInfo, UnionType
+};
+
+type Slice<'a> = EndianSlice<'a, RunTimeEndian>;
+type Entry<'abbrev, 'unit, 'a> = DebuggingInformationEntry<'abbrev, 'unit, Slice<'a>>;
+
+/// How deep the types referring to other unnamed types can be nested, deeper types become `void`.
+const MAX_DEPTH: usize = 64;
+
+/// The symbols and types read from a DWARF file written with `--dwarf-output`.
+#[derive(Debug, Default)]
+pub struct ImportedSymbols {
+    pub symbols: Vec<FunctionSymbol>,
+    pub types: TypeInfo,
+}
+
+/// Reads the structs, unions and enums defined in the DWARF sections of an object file, like
+/// a related binary built with debug information or a file written with `--dwarf-output`.
+/// Types without a name get generated names. Compressed debug sections are not supported.
+pub fn import_dwarf_types(data: &[u8]) -> Result<TypeInfo> {
+    let dwarf = load_dwarf(data)?;
+    let imported = import(&dwarf, None).map_err(Error::TypeImportError)?;
+    Ok(imported.types)
+}
+
+/// Reads the functions and the data symbols defined at the top level of a DWARF file written
+/// with `--dwarf-output`, along with all of its types. The addresses are converted to RVAs with
+/// the image base of the executable, so the file should have been written for the same image base.
+/// The confidence of the symbols isn't stored in DWARF, they're all treated as unique matches.
+pub fn import_dwarf_symbols(data: &[u8], props: &ExeProperties) -> Result<ImportedSymbols> {
+    let dwarf = load_dwarf(data)?;
+    import(&dwarf, Some(props.image_base())).map_err(Error::TypeImportError)
+}
+
+fn load_dwarf(data: &[u8]) -> Result<gimli::Dwarf<Slice>> {
+    let obj = object::read::File::parse(data)?;
+    let endian = if obj.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+    let load_section = |id: gimli::SectionId| -> Result<Slice> {
+        // Mach-O sections are named like `__debug_info`
+        let section = obj
+            .section_by_name(id.name())
+            .or_else(|| obj.section_by_name(&format!("__{}", &id.name()[1..])));
+        let data = match section {
+            Some(section) => section.data()?,
+            None => &[],
+        };
+        Ok(EndianSlice::new(data, endian))
+    };
+    gimli::Dwarf::load(load_section)
+}
+
+/// Imports the types of all units and, when the image base is given, the symbols too.
+fn import(dwarf: &gimli::Dwarf<Slice>, image_base: Option<u64>) -> gimli::Result<ImportedSymbols> {
+    let mut imported = ImportedSymbols::default();
+    let mut names = NameAllocator::default();
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut importer = UnitImporter {
+            dwarf,
+            unit: &unit,
+            types: &mut imported.types,
+            anonymous_names: &mut names,
+            names: HashMap::new(),
+            defined: HashSet::new(),
+        };
+        importer.import()?;
+        if let Some(image_base) = image_base {
+            importer.import_symbols(image_base, &mut imported.symbols)?;
+        }
+    }
+    Ok(imported)
+}
+
+struct UnitImporter<'a, 'u> {
+    dwarf: &'a gimli::Dwarf<Slice<'a>>,
+    unit: &'u Unit<Slice<'a>>,
+    types: &'u mut TypeInfo,
+    anonymous_names: &'u mut NameAllocator,
+    /// The qualified names of the named types in the unit.
+    names: HashMap<UnitOffset, Ustr>,
+    defined: HashSet<UnitOffset>,
+}
+
+impl<'a, 'u> UnitImporter<'a, 'u> {
+    fn import(&mut self) -> gimli::Result<()> {
+        let unit = self.unit;
+        let mut tree = unit.entries_tree(None)?;
+        let mut definitions = vec![];
+        self.collect_names(tree.root()?, "", &mut definitions)?;
+        for offset in definitions {
+            self.define(offset, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Collects the functions with an address and the variables with a fixed location defined
+    /// at the top level of the unit.
+    fn import_symbols(&mut self, image_base: u64, symbols: &mut Vec<FunctionSymbol>) -> gimli::Result<()> {
+        let unit = self.unit;
+        let mut tree = unit.entries_tree(None)?;
+        let root = tree.root()?;
+        let mut children = root.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            let name = match self.entry_name(entry)? {
+                Some(name) => Ustr::from(name.as_str()),
+                None => continue,
+            };
+            let location = self.decl_location(entry)?;
+
+            match entry.tag() {
+                gimli::DW_TAG_subprogram => {
+                    let low_pc = match entry.attr_value(gimli::DW_AT_low_pc)? {
+                        Some(AttributeValue::Addr(addr)) => addr,
+                        _ => continue,
+                    };
+                    let size = match entry.attr_value(gimli::DW_AT_high_pc)? {
+                        Some(AttributeValue::Addr(high_pc)) => Some(high_pc.saturating_sub(low_pc)),
+                        Some(value) => value.udata_value(),
+                        None => None,
+                    };
+                    let return_type = self.member_type(entry, 0)?;
+                    let mut params = vec![];
+                    let mut params_tree = unit.entries_tree(Some(entry.offset()))?;
+                    let mut param_nodes = params_tree.root()?.children();
+                    while let Some(param) = param_nodes.next()? {
+                        if param.entry().tag() == gimli::DW_TAG_formal_parameter {
+                            params.push(self.member_type(param.entry(), 0)?);
+                        }
+                    }
+                    let typ = FunctionType::new(params, return_type).into();
+                    let rva = low_pc.wrapping_sub(image_base);
+                    let sym = FunctionSymbol::new(name, typ, rva, Confidence::Unique, location);
+                    symbols.push(sym.with_size(size));
+                }
+                gimli::DW_TAG_variable => {
+                    let addr = match entry.attr_value(gimli::DW_AT_location)? {
+                        Some(AttributeValue::Exprloc(expr)) => {
+                            match expr.operations(unit.encoding()).next()? {
+                                Some(gimli::Operation::Address { address }) => address,
+                                _ => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+                    let data_type = self.member_type(entry, 0)?;
+                    let typ = Arc::new(FunctionType::new(vec![], Type::Void));
+                    let rva = addr.wrapping_sub(image_base);
+                    let sym = FunctionSymbol::new(name, typ, rva, Confidence::Unique, location);
+                    symbols.push(sym.with_data_type(Some(data_type)));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the file and the line an entry was declared at, based on the line program of the unit.
+    fn decl_location(&self, entry: &Entry<'_, '_, 'a>) -> gimli::Result<Option<SourceLocation>> {
+        let (file, line) = match (
+            entry.attr_value(gimli::DW_AT_decl_file)?,
+            entry.attr_value(gimli::DW_AT_decl_line)?,
+        ) {
+            (Some(AttributeValue::FileIndex(file)), Some(line)) => (file, line.udata_value()),
+            _ => return Ok(None),
+        };
+        let header = match &self.unit.line_program {
+            Some(program) => program.header(),
+            None => return Ok(None),
+        };
+        let (file, line) = match (header.file(file), line) {
+            (Some(file), Some(line)) => (file, line),
+            _ => return Ok(None),
+        };
+
+        let mut path = PathBuf::new();
+        if let Some(dir) = file.directory(header) {
+            let dir = self.dwarf.attr_string(self.unit, dir)?;
+            let dir = dir.to_string_lossy();
+            // files in the compilation directory are written with `.` as their directory
+            if dir != "." {
+                path.push(dir.as_ref());
+            }
+        }
+        let name = self.dwarf.attr_string(self.unit, file.path_name())?;
+        path.push(name.to_string_lossy().as_ref());
+        Ok(Some(SourceLocation { file: path, line }))
+    }
+
+    /// Assigns qualified names to the named types and collects the ones that are defined in the unit.
+    fn collect_names(
+        &mut self,
+        node: EntriesTreeNode<Slice<'a>>,
+        prefix: &str,
+        definitions: &mut Vec<UnitOffset>,
+    ) -> gimli::Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            let tag = entry.tag();
+            let offset = entry.offset();
+            let is_declaration = entry.attr_value(gimli::DW_AT_declaration)?.is_some();
+            let name = self.entry_name(entry)?.map(|name| qualify(prefix, &name));
+
+            match tag {
+                gimli::DW_TAG_namespace => {
+                    // members of anonymous namespaces are named as if they were in the enclosing one
+                    let prefix = name.as_deref().unwrap_or(prefix).to_owned();
+                    self.collect_names(child, &prefix, definitions)?;
+                }
+                gimli::DW_TAG_structure_type
+                | gimli::DW_TAG_class_type
+                | gimli::DW_TAG_union_type
+                | gimli::DW_TAG_enumeration_type => {
+                    if let Some(name) = &name {
+                        self.names.insert(offset, name.as_str().into());
+                        if !is_declaration {
+                            definitions.push(offset);
+                        }
+                        self.collect_names(child, name, definitions)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the definition of a struct, union or enum at an offset to the types, unless a type
+    /// with the same name has already been defined, and returns the type.
+    fn define(&mut self, offset: UnitOffset, depth: usize) -> gimli::Result<Type> {
+        let unit = self.unit;
+        let mut tree = unit.entries_tree(Some(offset))?;
+        let node = tree.root()?;
+        let entry = node.entry();
+        let tag = entry.tag();
+        let is_declaration = entry.attr_value(gimli::DW_AT_declaration)?.is_some();
+        let size = entry
+            .attr_value(gimli::DW_AT_byte_size)?
+            .and_then(|value| value.udata_value())
+            .map(|size| size as usize);
+        let name = match self.names.get(&offset) {
+            Some(name) => *name,
+            None => {
+                let name: Ustr = self.anonymous_names.allocate().into();
+                self.names.insert(offset, name);
+                name
+            }
+        };
+
+        let typ = match tag {
+            gimli::DW_TAG_enumeration_type => Type::Enum(name.into()),
+            gimli::DW_TAG_union_type => Type::Union(name.into()),
+            _ => Type::Struct(name.into()),
+        };
+        let exists = match &typ {
+            Type::Struct(id) => self.types.structs.contains_key(id),
+            Type::Union(id) => self.types.unions.contains_key(id),
+            Type::Enum(id) => self.types.enums.contains_key(id),
+            _ => false,
+        };
+        if is_declaration || exists || !self.defined.insert(offset) {
+            return Ok(typ);
+        }
+
+        if tag == gimli::DW_TAG_enumeration_type {
+            // producers that don't write the underlying type use int
+            let underlying_type = match self.member_type(entry, depth)? {
+                Type::Void => Type::Int(true),
+                typ => typ,
+            };
+            let mut enum_ = EnumType {
+                name,
+                members: vec![],
+                size,
+                underlying_type,
+                is_scoped: entry.attr_value(gimli::DW_AT_enum_class)?.is_some(),
+            };
+            let is_signed = enum_.is_signed();
+            let mut children = node.children();
+            while let Some(child) = children.next()? {
+                let entry = child.entry();
+                if entry.tag() != gimli::DW_TAG_enumerator {
+                    continue;
+                }
+                let value = match entry.attr_value(gimli::DW_AT_const_value)? {
+                    Some(AttributeValue::Sdata(value)) => value,
+                    // fixed size constants of signed enums have to be sign extended
+                    Some(AttributeValue::Data1(value)) if is_signed => value as i8 as i64,
+                    Some(AttributeValue::Data2(value)) if is_signed => value as i16 as i64,
+                    Some(AttributeValue::Data4(value)) if is_signed => value as i32 as i64,
+                    Some(value) => value.udata_value().unwrap_or_default() as i64,
+                    None => continue,
+                };
+                let value = if is_signed {
+                    EnumValue::Signed(value)
+                } else {
+                    EnumValue::Unsigned(value as u64)
+                };
+                let name = self.entry_name(entry)?.unwrap_or_default();
+                enum_.members.push(EnumMember::new(name.into(), value));
+            }
+            self.types.enums.insert(name.into(), enum_);
+            return Ok(typ);
+        }
+
+        let mut base = None;
+        let mut members = vec![];
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                gimli::DW_TAG_inheritance if base.is_none() => {
+                    if let Type::Struct(id) = self.member_type(entry, depth)? {
+                        base = Some(id);
+                    }
+                }
+                gimli::DW_TAG_member => {
+                    // the virtual table pointer and other members added by the compiler are skipped
+                    let is_artificial = entry.attr_value(gimli::DW_AT_artificial)?.is_some();
+                    let is_static = entry.attr_value(gimli::DW_AT_external)?.is_some();
+                    if !is_artificial && !is_static {
+                        members.push(self.data_member(entry, depth)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match tag {
+            gimli::DW_TAG_union_type => {
+                let union = UnionType { name, members, size };
+                self.types.unions.insert(name.into(), union);
+            }
+            _ => {
+                let struct_ = StructType {
+                    name,
+                    base,
+                    members,
+                    virtual_methods: vec![],
+                    size,
+                };
+                self.types.structs.insert(name.into(), struct_);
+            }
+        }
+        Ok(typ)
+    }
+
+    fn data_member(&mut self, entry: &Entry<'_, '_, 'a>, depth: usize) -> gimli::Result<DataMember> {
+        let name = self.entry_name(entry)?;
+        let typ = self.member_type(entry, depth)?;
+        let bit_width = entry
+            .attr_value(gimli::DW_AT_bit_size)?
+            .and_then(|value| value.udata_value())
+            .map(|width| width as usize);
+        let location = self
+            .member_location(entry)?
+            .map(|offset| offset as usize * u8::BITS as usize);
+        let bit_offset = match entry.attr_value(gimli::DW_AT_data_bit_offset)? {
+            Some(value) => value.udata_value().map(|offset| offset as usize),
+            // the older form counts from the most significant bit of the storage unit
+            None => match (
+                entry
+                    .attr_value(gimli::DW_AT_bit_offset)?
+                    .and_then(|value| value.udata_value()),
+                entry
+                    .attr_value(gimli::DW_AT_byte_size)?
+                    .and_then(|value| value.udata_value()),
+                bit_width,
+            ) {
+                (Some(offset), Some(size), Some(width)) => (size * u8::BITS as u64)
+                    .checked_sub(offset + width as u64)
+                    .map(|start| location.unwrap_or_default() + start as usize),
+                _ => location,
+            },
+        };
+        Ok(DataMember {
+            is_anonymous: name.is_none(),
+            name: name.unwrap_or_default().into(),
+            typ,
+            bit_offset,
+            bit_width,
+        })
+    }
+
+    /// Returns the byte offset of a member, which is either a constant or, in older versions
+    /// of DWARF, an expression that adds the offset to the address of the struct.
+    fn member_location(&self, entry: &Entry<'_, '_, 'a>) -> gimli::Result<Option<u64>> {
+        match entry.attr_value(gimli::DW_AT_data_member_location)? {
+            Some(AttributeValue::Exprloc(expr)) => match expr.operations(self.unit.encoding()).next()? {
+                Some(gimli::Operation::PlusConstant { value }) => Ok(Some(value)),
+                _ => Ok(None),
+            },
+            Some(value) => Ok(value.udata_value()),
+            None => Ok(None),
+        }
+    }
+
+    fn member_type(&mut self, entry: &Entry<'_, '_, 'a>, depth: usize) -> gimli::Result<Type> {
+        match entry.attr_value(gimli::DW_AT_type)? {
+            Some(AttributeValue::UnitRef(offset)) => self.resolve_type(offset, depth + 1),
+            _ => Ok(Type::Void),
+        }
+    }
+
+    fn resolve_type(&mut self, offset: UnitOffset, depth: usize) -> gimli::Result<Type> {
+        if depth > MAX_DEPTH {
+            return Ok(Type::Void);
+        }
+        let unit = self.unit;
+        let entry = unit.entry(offset)?;
+        let inner = |this: &mut Self| this.member_type(&entry, depth);
+
+        let typ = match entry.tag() {
+            gimli::DW_TAG_base_type => self.base_type(&entry)?,
+            gimli::DW_TAG_pointer_type => Type::Pointer(inner(self)?.into()),
+            gimli::DW_TAG_reference_type | gimli::DW_TAG_rvalue_reference_type => {
+                Type::Reference(inner(self)?.into())
+            }
+            gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_restrict_type
+            | gimli::DW_TAG_atomic_type
+            | gimli::DW_TAG_typedef => inner(self)?,
+            gimli::DW_TAG_ptr_to_member_type => {
+                let class = match entry.attr_value(gimli::DW_AT_containing_type)? {
+                    Some(AttributeValue::UnitRef(class)) => self.resolve_type(class, depth + 1)?,
+                    _ => Type::Void,
+                };
+                match class {
+                    Type::Struct(class) => Type::MemberPointer(inner(self)?.into(), class),
+                    _ => Type::Void,
+                }
+            }
+            gimli::DW_TAG_array_type => self.array_type(&entry, depth)?,
+            gimli::DW_TAG_subroutine_type => {
+                let return_type = inner(self)?;
+                let mut params = vec![];
+                let mut tree = unit.entries_tree(Some(offset))?;
+                let mut children = tree.root()?.children();
+                while let Some(child) = children.next()? {
+                    if child.entry().tag() == gimli::DW_TAG_formal_parameter {
+                        params.push(self.member_type(child.entry(), depth)?);
+                    }
+                }
+                Type::Function(FunctionType::new(params, return_type).into())
+            }
+            gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_class_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_enumeration_type => self.define(offset, depth)?,
+            _ => Type::Void,
+        };
+        Ok(typ)
+    }
+
+    fn base_type(&self, entry: &Entry<'_, '_, 'a>) -> gimli::Result<Type> {
+        let name = self.entry_name(entry)?.unwrap_or_default();
+        let size = entry
+            .attr_value(gimli::DW_AT_byte_size)?
+            .and_then(|value| value.udata_value())
+            .unwrap_or_default();
+        let encoding = match entry.attr_value(gimli::DW_AT_encoding)? {
+            Some(AttributeValue::Encoding(encoding)) => encoding,
+            _ => gimli::DW_ATE_unsigned,
+        };
+        let is_long = name.contains("long") && !name.contains("long long");
+
+        let typ = match (encoding, size) {
+            (_, 0) => Type::Void,
+            (gimli::DW_ATE_boolean, _) => Type::Bool,
+            (gimli::DW_ATE_float, 4) => Type::Float,
+            (gimli::DW_ATE_float, 8) => Type::Double,
+            (gimli::DW_ATE_float, _) => Type::LongDouble,
+            _ if name == "wchar_t" => Type::WChar,
+            (gimli::DW_ATE_signed_char, _) => Type::Char(true),
+            (gimli::DW_ATE_unsigned_char, _) => Type::Char(false),
+            (encoding, size) => {
+                let is_signed = encoding == gimli::DW_ATE_signed;
+                match size {
+                    1 => Type::Char(is_signed),
+                    2 => Type::Short(is_signed),
+                    4 if is_long => Type::Long(is_signed),
+                    4 => Type::Int(is_signed),
+                    8 if is_long => Type::Long(is_signed),
+                    8 => Type::LongLong(is_signed),
+                    _ => Type::Int128(is_signed),
+                }
+            }
+        };
+        Ok(typ)
+    }
+
+    fn array_type(&mut self, entry: &Entry<'_, '_, 'a>, depth: usize) -> gimli::Result<Type> {
+        let element = self.member_type(entry, depth)?;
+        let is_vector = entry.attr_value(gimli::DW_AT_GNU_vector)?.is_some();
+
+        let mut counts = vec![];
+        let mut tree = self.unit.entries_tree(Some(entry.offset()))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            if entry.tag() != gimli::DW_TAG_subrange_type {
+                continue;
+            }
+            let count = match entry.attr_value(gimli::DW_AT_count)? {
+                Some(count) => count.udata_value(),
+                None => entry
+                    .attr_value(gimli::DW_AT_upper_bound)?
+                    .and_then(|bound| bound.udata_value())
+                    .map(|bound| bound + 1),
+            };
+            counts.push(count.map(|count| count as usize));
+        }
+
+        let typ = match counts.as_slice() {
+            [Some(count)] if is_vector => Type::Vector(element.into(), *count),
+            [] => Type::Array(element.into()),
+            // the first subrange is the outermost dimension
+            dimensions => dimensions.iter().rev().fold(element, |inner, count| match count {
+                Some(count) => Type::FixedArray(inner.into(), *count),
+                None => Type::Array(inner.into()),
+            }),
+        };
+        Ok(typ)
+    }
+
+    fn entry_name(&self, entry: &Entry<'_, '_, 'a>) -> gimli::Result<Option<String>> {
+        match entry.attr_value(gimli::DW_AT_name)? {
+            Some(value) => {
+                let name = self.dwarf.attr_string(self.unit, value)?;
+                Ok(Some(name.to_string_lossy().into_owned()))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+fn qualify(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{prefix}::{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dwarf::{self, DwarfOptions, DwarfVersion, UnitInfo};
+    use crate::filter::TypeFilter;
+    use crate::layout::Layout;
+    use crate::names::NameStyle;
+    use crate::symbols;
+    use crate::types::{EnumId, StructId, UnionId};
+
+    fn sample_types() -> TypeInfo {
+        let mut types = TypeInfo::default();
+        let [vector, player, state] = ["Game::Vector", "Game::Player", "Game::State"].map(Ustr::from);
+        types.structs.insert(
+            vector.into(),
+            StructType {
+                name: vector,
+                base: None,
+                members: vec![
+                    DataMember::basic("x".into(), Type::Float),
+                    DataMember::basic("y".into(), Type::Float),
+                ],
+                virtual_methods: vec![],
+                size: Some(8),
+            },
+        );
+        types.structs.insert(
+            player.into(),
+            StructType {
+                name: player,
+                base: None,
+                members: vec![
+                    DataMember::basic("position".into(), Type::Struct(vector.into())),
+                    DataMember::basic("name".into(), Type::Pointer(Type::Char(true).into())),
+                    DataMember::basic("state".into(), Type::Enum(state.into())),
+                ],
+                virtual_methods: vec![],
+                size: Some(24),
+            },
+        );
+        types.enums.insert(
+            state.into(),
+            EnumType {
+                name: state,
+                members: vec![
+                    EnumMember::new("Alive".into(), EnumValue::Signed(0)),
+                    EnumMember::new("Dead".into(), EnumValue::Signed(-1)),
+                ],
+                size: Some(4),
+                underlying_type: Type::Int(true),
+                is_scoped: false,
+            },
+        );
+        let flags = Ustr::from("Game::Flags");
+        types.enums.insert(
+            flags.into(),
+            EnumType {
+                name: flags,
+                members: vec![EnumMember::new("All".into(), EnumValue::Unsigned(u64::MAX))],
+                size: Some(8),
+                underlying_type: Type::LongLong(false),
+                is_scoped: true,
+            },
+        );
+        types
+    }
+
+    fn properties() -> ExeProperties {
+        let layout: Layout = toml::from_str(
+            "image_base = 0x140000000\n[text]\nfile_offset = 0x400\naddress = 0x140001000\nsize = 0x1000",
+        )
+        .unwrap();
+        ExeProperties::from_layout(&layout)
+    }
+
+    fn write_sample(symbols: &[FunctionSymbol]) -> Vec<u8> {
+        let options = DwarfOptions {
+            eager_type_export: true,
+            type_filter: TypeFilter::default(),
+            name_style: NameStyle::Identifier,
+            compression: None,
+            version: DwarfVersion::V5,
+            unit: UnitInfo::default(),
+        };
+        let mut output = vec![];
+        dwarf::write_symbol_file(&mut output, symbols, &sample_types(), properties(), options).unwrap();
+        output
+    }
+
+    #[test]
+    fn import_written_types() {
+        let types = import_dwarf_types(&write_sample(&[])).unwrap();
+        let player = &types.structs[&StructId::from(Ustr::from("Game::Player"))];
+        assert_eq!(player.size, Some(24));
+        let offsets: Vec<_> = player.members.iter().map(|member| member.bit_offset).collect();
+        assert_eq!(offsets, [Some(0), Some(64), Some(128)]);
+        assert_eq!(
+            player.members[0].typ,
+            Type::Struct(Ustr::from("Game::Vector").into())
+        );
+        assert_eq!(player.members[1].typ, Type::Pointer(Type::Char(true).into()));
+
+        let state = &types.enums[&EnumId::from(Ustr::from("Game::State"))];
+        assert_eq!(state.underlying_type, Type::Int(true));
+        assert!(!state.is_scoped);
+        assert_eq!(state.members[1].value, EnumValue::Signed(-1));
+        let flags = &types.enums[&EnumId::from(Ustr::from("Game::Flags"))];
+        assert_eq!(flags.underlying_type, Type::LongLong(false));
+        assert!(flags.is_scoped);
+        assert_eq!(flags.members[0].value, EnumValue::Unsigned(u64::MAX));
+        assert!(types
+            .unions
+            .get(&UnionId::from(Ustr::from("Game::State")))
+            .is_none());
+    }
+
+    #[test]
+    fn import_and_merge_written_symbols() {
+        let player = Type::Struct(Ustr::from("Game::Player").into());
+        let location = SourceLocation {
+            file: "specs/game.hpp".into(),
+            line: 12,
+        };
+        let get_player = FunctionSymbol::new(
+            "Game::get_player".into(),
+            FunctionType::new(vec![Type::Int(true)], Type::Pointer(player.clone().into())).into(),
+            0x1000,
+            Confidence::Scored,
+            Some(location.clone()),
+        )
+        .with_size(Some(0x20));
+        let world = FunctionSymbol::new(
+            "Game::world".into(),
+            FunctionType::new(vec![], Type::Void).into(),
+            0x2000,
+            Confidence::Unique,
+            None,
+        )
+        .with_data_type(Some(player.clone()));
+
+        let previous = import_dwarf_symbols(&write_sample(&[get_player, world]), &properties()).unwrap();
+        assert_eq!(previous.symbols.len(), 2);
+        let get_player = &previous.symbols[0];
+        assert_eq!(get_player.rva(), 0x1000);
+        assert_eq!(get_player.size(), Some(0x20));
+        assert_eq!(get_player.function_type().params, [Type::Int(true)]);
+        assert_eq!(get_player.location().map(|loc| loc.line), Some(12));
+        assert_eq!(
+            get_player.location().map(|loc| loc.file.as_path()),
+            Some(location.file.as_path())
+        );
+        assert_eq!(previous.symbols[1].data_type(), Some(&player));
+        assert!(previous
+            .types
+            .structs
+            .contains_key(&StructId::from(Ustr::from("Game::Vector"))));
+
+        let mut symbols = vec![FunctionSymbol::new(
+            "Game::get_player".into(),
+            FunctionType::new(vec![], Type::Void).into(),
+            0x1010,
+            Confidence::Unique,
+            None,
+        )];
+        assert_eq!(symbols::merge_symbols(&mut symbols, previous.symbols), 1);
+        assert_eq!(symbols[0].rva(), 0x1010);
+        assert_eq!(symbols[1].name(), "Game::world");
+    }
+
+    #[test]
+    fn merge_replaces_only_stubs() {
+        let mut types = TypeInfo::default();
+        let [vector, player] = ["Game::Vector", "Game::Player"].map(Ustr::from);
+        types.structs.insert(vector.into(), StructType::stub(vector));
+        let mut own_player = StructType::stub(player);
+        own_player.size = Some(32);
+        types.structs.insert(player.into(), own_player);
+
+        assert_eq!(types.merge(sample_types()), 3);
+        assert_eq!(types.structs[&vector.into()].size, Some(8));
+        assert_eq!(types.structs[&player.into()].size, Some(32));
+    }
+}
@@ -0,0 +1,323 @@
+use object::{Endian, Endianness, FileKind};
+
+use crate::error::{Error, Result};
+
+/// Name of the section that links an executable to a separate debug info file.
+pub const DEBUGLINK_SECTION: &str = ".gnu_debuglink";
+
+const SHT_PROGBITS: u32 = 1;
+const SHN_LORESERVE: usize = 0xff00;
+const PE_SECTION_HEADER_SIZE: usize = 40;
+const PE_SYMBOL_SIZE: usize = 18;
+/// Initialized, discardable and readable data, like the debug sections written by MinGW.
+const PE_DEBUG_SECTION_CHARACTERISTICS: u32 = 0x4200_0040;
+
+/// Returns a copy of an ELF or PE executable with the sections appended to it.
+/// The existing contents of the executable are not moved, so the code stays the same byte for byte.
+pub fn append_sections(exe: &[u8], sections: &[(&str, Vec<u8>)]) -> Result<Vec<u8>> {
+    let sections: Vec<_> = sections.iter().filter(|(_, data)| !data.is_empty()).collect();
+    match FileKind::parse(exe)? {
+        FileKind::Elf32 => append_elf_sections(exe, &sections, false),
+        FileKind::Elf64 => append_elf_sections(exe, &sections, true),
+        FileKind::Pe32 | FileKind::Pe64 => append_pe_sections(exe, &sections),
+        _ => Err(Error::InjectionError("only ELF and PE executables are supported")),
+    }
+}
+
+/// Returns the contents of a `.gnu_debuglink` section pointing to a debug info file.
+pub fn debuglink(file_name: &str, debug_file: &[u8], endian: Endianness) -> Vec<u8> {
+    let mut res = file_name.as_bytes().to_vec();
+    res.push(0);
+    res.resize(align(res.len(), 4), 0);
+    res.extend(endian.write_u32_bytes(crc32(debug_file)));
+    res
+}
+
+fn append_elf_sections(exe: &[u8], sections: &[&(&str, Vec<u8>)], is64: bool) -> Result<Vec<u8>> {
+    // EI_DATA is 2 for big-endian files
+    let endian = if exe.get(5) == Some(&2) {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    };
+    let mut file = Patcher::new(exe.to_vec(), endian);
+    let (shoff_pos, shnum_pos, header_size) = if is64 { (0x28, 0x3C, 64) } else { (0x20, 0x30, 40) };
+    let (offset_pos, size_pos) = if is64 { (0x18, 0x20) } else { (0x10, 0x14) };
+
+    let shoff = file.read_word(shoff_pos, is64)? as usize;
+    let shentsize = file.read_u16(shnum_pos - 2)? as usize;
+    let shnum = file.read_u16(shnum_pos)? as usize;
+    let shstrndx = file.read_u16(shnum_pos + 2)? as usize;
+    if shnum == 0 || shstrndx >= shnum || shentsize != header_size {
+        return Err(Error::InjectionError(
+            "the section table is missing or unsupported",
+        ));
+    }
+    if shnum + sections.len() >= SHN_LORESERVE {
+        return Err(Error::InjectionError("too many sections"));
+    }
+
+    let mut headers = Patcher::new(file.slice(shoff, shnum * shentsize)?.to_vec(), endian);
+    let strtab_header = shstrndx * shentsize;
+    let strtab_offset = headers.read_word(strtab_header + offset_pos, is64)? as usize;
+    let strtab_size = headers.read_word(strtab_header + size_pos, is64)? as usize;
+    let mut strtab = file.slice(strtab_offset, strtab_size)?.to_vec();
+
+    let mut added = Vec::with_capacity(sections.len());
+    for (name, data) in sections {
+        let name_offset = strtab.len();
+        strtab.extend(name.as_bytes());
+        strtab.push(0);
+        file.align(8);
+        added.push((name_offset, file.data.len(), data.len()));
+        file.data.extend(data);
+    }
+
+    // the section names are moved to the end of the file, since they can't grow in place
+    let strtab_offset = file.data.len();
+    file.data.extend(&strtab);
+    headers.write_word(strtab_header + offset_pos, strtab_offset as u64, is64);
+    headers.write_word(strtab_header + size_pos, strtab.len() as u64, is64);
+
+    for (name, offset, size) in added {
+        headers.push_u32(name as u32);
+        headers.push_u32(SHT_PROGBITS);
+        headers.push_word(0, is64); // sh_flags
+        headers.push_word(0, is64); // sh_addr
+        headers.push_word(offset as u64, is64);
+        headers.push_word(size as u64, is64);
+        headers.push_u32(0); // sh_link
+        headers.push_u32(0); // sh_info
+        headers.push_word(1, is64); // sh_addralign
+        headers.push_word(0, is64); // sh_entsize
+    }
+    file.align(8);
+    let shoff = file.data.len();
+    file.data.extend(&headers.data);
+    file.write_word(shoff_pos, shoff as u64, is64);
+    file.write_u16(shnum_pos, (shnum + sections.len()) as u16);
+
+    Ok(file.data)
+}
+
+fn append_pe_sections(exe: &[u8], sections: &[&(&str, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut file = Patcher::new(exe.to_vec(), Endianness::Little);
+    // e_lfanew points at the PE signature, which is followed by the COFF header
+    let coff = file.read_u32(0x3C)? as usize + 4;
+    let num_sections = file.read_u16(coff + 2)? as usize;
+    let symtab_offset = file.read_u32(coff + 8)? as usize;
+    let num_symbols = file.read_u32(coff + 12)? as usize;
+    let opt_size = file.read_u16(coff + 16)? as usize;
+    let opt = coff + 20;
+    let section_alignment = file.read_u32(opt + 32)? as usize;
+    let file_alignment = file.read_u32(opt + 36)? as usize;
+    let size_of_headers = file.read_u32(opt + 60)? as usize;
+
+    let table = opt + opt_size;
+    let table_end = table + num_sections * PE_SECTION_HEADER_SIZE;
+    let mut first_data = size_of_headers;
+    let mut image_end = 0;
+    for i in 0..num_sections {
+        let header = table + i * PE_SECTION_HEADER_SIZE;
+        let virtual_size = file.read_u32(header + 8)? as usize;
+        let address = file.read_u32(header + 12)? as usize;
+        let raw_size = file.read_u32(header + 16)? as usize;
+        let raw_offset = file.read_u32(header + 20)? as usize;
+        if raw_size != 0 && raw_offset != 0 {
+            first_data = first_data.min(raw_offset);
+        }
+        image_end = image_end.max(address + virtual_size.max(raw_size));
+    }
+    if table_end + sections.len() * PE_SECTION_HEADER_SIZE > first_data {
+        return Err(Error::InjectionError("no space left for new section headers"));
+    }
+
+    // long section names are stored in the string table that follows the symbols,
+    // both get moved to the end of the file
+    let (symbols, mut strings) = if symtab_offset != 0 {
+        let strings_offset = symtab_offset + num_symbols * PE_SYMBOL_SIZE;
+        let strings_size = file.read_u32(strings_offset)? as usize;
+        (
+            file.slice(symtab_offset, num_symbols * PE_SYMBOL_SIZE)?.to_vec(),
+            file.slice(strings_offset, strings_size)?.to_vec(),
+        )
+    } else {
+        (vec![], vec![0; 4])
+    };
+
+    let mut headers = Patcher::new(vec![], Endianness::Little);
+    let mut address = align(image_end, section_alignment);
+    for (name, data) in sections {
+        file.align(file_alignment);
+        let raw_offset = file.data.len();
+        file.data.extend(data);
+        file.align(file_alignment);
+
+        headers.data.extend(pe_section_name(name, &mut strings));
+        headers.push_u32(data.len() as u32);
+        headers.push_u32(address as u32);
+        headers.push_u32((file.data.len() - raw_offset) as u32);
+        headers.push_u32(raw_offset as u32);
+        headers.data.extend([0; 12]); // relocations and line numbers
+        headers.push_u32(PE_DEBUG_SECTION_CHARACTERISTICS);
+        address = align(address + data.len(), section_alignment);
+    }
+
+    if symtab_offset != 0 || strings.len() > 4 {
+        let strings_size = strings.len() as u32;
+        strings[..4].copy_from_slice(&strings_size.to_le_bytes());
+        let symtab_offset = file.data.len();
+        file.data.extend(symbols);
+        file.data.extend(strings);
+        file.write_u32(coff + 8, symtab_offset as u32);
+    }
+    file.data[table_end..table_end + headers.data.len()].copy_from_slice(&headers.data);
+    file.write_u16(coff + 2, (num_sections + sections.len()) as u16);
+    file.write_u32(opt + 56, address as u32);
+
+    Ok(file.data)
+}
+
+/// Section names longer than 8 bytes are replaced with an offset into the string table.
+fn pe_section_name(name: &str, strings: &mut Vec<u8>) -> [u8; 8] {
+    let name = if name.len() <= 8 {
+        name.as_bytes().to_vec()
+    } else {
+        let offset = strings.len();
+        strings.extend(name.as_bytes());
+        strings.push(0);
+        format!("/{offset}").into_bytes()
+    };
+    let mut res = [0; 8];
+    res[..name.len()].copy_from_slice(&name);
+    res
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+/// The CRC-32 used in `.gnu_debuglink` sections (the same as in zlib).
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct Patcher {
+    data: Vec<u8>,
+    endian: Endianness,
+}
+
+impl Patcher {
+    fn new(data: Vec<u8>, endian: Endianness) -> Self {
+        Self { data, endian }
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        self.data
+            .get(offset..offset + len)
+            .ok_or(Error::InvalidAccess(offset))
+    }
+
+    fn read_u16(&self, offset: usize) -> Result<u16> {
+        let bytes = self.slice(offset, 2)?.try_into().unwrap();
+        Ok(self.endian.read_u16_bytes(bytes))
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32> {
+        let bytes = self.slice(offset, 4)?.try_into().unwrap();
+        Ok(self.endian.read_u32_bytes(bytes))
+    }
+
+    fn read_word(&self, offset: usize, is64: bool) -> Result<u64> {
+        if is64 {
+            let bytes = self.slice(offset, 8)?.try_into().unwrap();
+            Ok(self.endian.read_u64_bytes(bytes))
+        } else {
+            self.read_u32(offset).map(u64::from)
+        }
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        self.data[offset..offset + 2].copy_from_slice(&self.endian.write_u16_bytes(value));
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.data[offset..offset + 4].copy_from_slice(&self.endian.write_u32_bytes(value));
+    }
+
+    fn write_word(&mut self, offset: usize, value: u64, is64: bool) {
+        if is64 {
+            self.data[offset..offset + 8].copy_from_slice(&self.endian.write_u64_bytes(value));
+        } else {
+            self.write_u32(offset, value as u32);
+        }
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.data.extend(self.endian.write_u32_bytes(value));
+    }
+
+    fn push_word(&mut self, value: u64, is64: bool) {
+        if is64 {
+            self.data.extend(self.endian.write_u64_bytes(value));
+        } else {
+            self.push_u32(value as u32);
+        }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        self.data.resize(align(self.data.len(), alignment), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{Architecture, BinaryFormat, Object, ObjectSection, SectionKind};
+
+    use super::*;
+
+    #[test]
+    fn compute_debuglink_checksum() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let link = debuglink("app.debug", b"123456789", Endianness::Little);
+        assert_eq!(link, b"app.debug\0\0\0\x26\x39\xF4\xCB");
+    }
+
+    #[test]
+    fn append_sections_to_elf() {
+        let mut obj =
+            object::write::Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        obj.set_section_data(text, vec![0xC3; 16], 16);
+        let exe = obj.write().unwrap();
+
+        let sections = [
+            (".debug_info", b"info".to_vec()),
+            (".debug_abbrev", vec![]),
+            (DEBUGLINK_SECTION, b"link".to_vec()),
+        ];
+        let res = append_sections(&exe, &sections).unwrap();
+        let file = object::read::File::parse(&*res).unwrap();
+        let data = |name| {
+            file.section_by_name(name)
+                .map(|section| section.data().unwrap().to_vec())
+        };
+
+        assert_eq!(data(".text"), Some(vec![0xC3; 16]));
+        assert_eq!(data(".debug_info"), Some(b"info".to_vec()));
+        assert_eq!(data(".debug_abbrev"), None);
+        assert_eq!(data(DEBUGLINK_SECTION), Some(b"link".to_vec()));
+    }
+}
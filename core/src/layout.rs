@@ -0,0 +1,95 @@
+use object::{Architecture, BinaryFormat};
+use serde::Deserialize;
+
+use crate::error::Result;
+
+/// Describes the sections of an executable that has no usable section table (e.g. a memory dump).
+///
+/// ```toml
+/// architecture = "x86_64"
+/// image_base = 0x140000000
+///
+/// [text]
+/// file_offset = 0x1000
+/// address = 0x140001000
+/// size = 0x2000000
+///
+/// [rdata]
+/// file_offset = 0x2001000
+/// address = 0x142001000
+/// size = 0x800000
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Layout {
+    #[serde(default)]
+    pub architecture: LayoutArchitecture,
+    #[serde(default)]
+    pub format: LayoutFormat,
+    pub image_base: u64,
+    pub text: SectionLayout,
+    pub rdata: Option<SectionLayout>,
+}
+
+impl Layout {
+    #[cfg(feature = "native")]
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(str: &str) -> Result<Self> {
+        Ok(toml::from_str(str)?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SectionLayout {
+    /// Offset of the section in the file.
+    pub file_offset: u64,
+    /// Virtual address of the section.
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutArchitecture {
+    X86_64,
+    X86,
+}
+
+impl Default for LayoutArchitecture {
+    fn default() -> Self {
+        Self::X86_64
+    }
+}
+
+impl From<LayoutArchitecture> for Architecture {
+    fn from(arch: LayoutArchitecture) -> Self {
+        match arch {
+            LayoutArchitecture::X86_64 => Architecture::X86_64,
+            LayoutArchitecture::X86 => Architecture::I386,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutFormat {
+    Pe,
+    Elf,
+}
+
+impl Default for LayoutFormat {
+    fn default() -> Self {
+        Self::Pe
+    }
+}
+
+impl From<LayoutFormat> for BinaryFormat {
+    fn from(format: LayoutFormat) -> Self {
+        match format {
+            LayoutFormat::Pe => BinaryFormat::Pe,
+            LayoutFormat::Elf => BinaryFormat::Elf,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::io;
+use std::str::FromStr;
+
+use flexi_logger::{DeferredNow, FlexiLoggerError, LogSpecification, Logger};
+use log::{LevelFilter, Record};
+
+use crate::opts::Opts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line with the level, the target and the message of each record.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown log format '{other}'")),
+        }
+    }
+}
+
+/// Starts logging to stderr with the level and the format picked in the options.
+pub fn init(opts: &Opts) -> Result<(), FlexiLoggerError> {
+    let logger = Logger::with(LogSpecification::builder().default(opts.log_level()).build());
+    let logger = match opts.log_format {
+        LogFormat::Text => logger,
+        LogFormat::Json => logger.format(json_format),
+    };
+    logger.start()?;
+    Ok(())
+}
+
+/// Returns the level that is `verbose` steps above and `quiet` steps below the info level.
+pub fn level(verbose: usize, quiet: usize) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let index = (3 + verbose).saturating_sub(quiet).min(LEVELS.len() - 1);
+    LEVELS[index]
+}
+
+fn json_format(output: &mut dyn io::Write, _now: &mut DeferredNow, record: &Record) -> io::Result<()> {
+    let entry = serde_json::json!({
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    write!(output, "{entry}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjust_log_level() {
+        assert_eq!(level(0, 0), LevelFilter::Info);
+        assert_eq!(level(1, 0), LevelFilter::Debug);
+        assert_eq!(level(5, 0), LevelFilter::Trace);
+        assert_eq!(level(0, 1), LevelFilter::Warn);
+        assert_eq!(level(0, 5), LevelFilter::Off);
+        assert_eq!(level(1, 1), LevelFilter::Info);
+    }
+}
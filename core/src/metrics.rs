@@ -0,0 +1,212 @@
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::schema::{MetricsReport, PatternCostRecord, StatsReport, SCHEMA_VERSION};
+use crate::symbols::PatternStats;
+
+/// The number of the slowest patterns listed in the statistics.
+const SLOWEST_PATTERN_COUNT: usize = 10;
+
+/// Statistics of a single run, meant to be collected by automated pipelines.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    specs: usize,
+    resolved: usize,
+    failed: usize,
+    suppressed: usize,
+    phases: Vec<(&'static str, Duration)>,
+    outputs: Vec<(String, u64)>,
+    stats: Option<Stats>,
+}
+
+/// The details collected with `--stats`.
+#[derive(Debug)]
+struct Stats {
+    ambiguous: usize,
+    slowest_patterns: Vec<PatternStats>,
+}
+
+impl Metrics {
+    pub fn set_resolution(&mut self, specs: usize, resolved: usize, failed: usize) {
+        self.specs = specs;
+        self.resolved = resolved;
+        self.failed = failed;
+    }
+
+    /// Records the number of failures that were not reported because of `@allow` parameters.
+    pub fn set_suppressed(&mut self, suppressed: usize) {
+        self.suppressed = suppressed;
+    }
+
+    /// Records the details of the resolution reported with `--stats`, `ambiguous` is the number
+    /// of failures caused by multiple matches. Only the slowest of the patterns are kept.
+    pub fn set_stats(&mut self, ambiguous: usize, patterns: &[PatternStats]) {
+        let mut slowest_patterns = patterns.to_vec();
+        slowest_patterns.sort_by_key(|pat| Reverse(pat.candidates));
+        slowest_patterns.truncate(SLOWEST_PATTERN_COUNT);
+        self.stats = Some(Stats {
+            ambiguous,
+            slowest_patterns,
+        });
+    }
+
+    /// Records the time elapsed since `start` as the duration of a phase.
+    pub fn add_phase(&mut self, name: &'static str, start: Instant) {
+        self.phases.push((name, start.elapsed()));
+    }
+
+    /// Records the size of an output file that has already been written.
+    #[cfg(feature = "native")]
+    pub fn add_output(&mut self, name: impl Into<String>, path: &Path) -> Result<()> {
+        self.outputs.push((name.into(), path.metadata()?.len()));
+        Ok(())
+    }
+
+    pub fn resolve_rate(&self) -> f64 {
+        if self.specs == 0 {
+            1.
+        } else {
+            self.resolved as f64 / self.specs as f64
+        }
+    }
+
+    /// Formats the statistics as a summary for the log, `None` unless they were recorded.
+    pub fn summary(&self) -> Option<String> {
+        let stats = self.stats.as_ref()?;
+        let mut summary = format!(
+            "Specs: {}, matched: {}, ambiguous: {}, missed: {}",
+            self.specs,
+            self.resolved,
+            stats.ambiguous,
+            self.failed - stats.ambiguous
+        );
+        if !stats.slowest_patterns.is_empty() {
+            summary.push_str("\nSlowest patterns:");
+            for pat in &stats.slowest_patterns {
+                let (name, candidates, len) = (pat.name, pat.candidates, pat.anchor_len);
+                write!(
+                    summary,
+                    "\n  {name}: {candidates} candidate(s) for a {len}-byte anchor"
+                )
+                .ok();
+            }
+        }
+        summary.push_str("\nPhases:");
+        for (name, duration) in &self.phases {
+            write!(summary, "\n  {name}: {:.3}s", duration.as_secs_f64()).ok();
+        }
+        Some(summary)
+    }
+
+    pub fn write<W: io::Write>(&self, output: W, format: MetricsFormat) -> Result<()> {
+        match format {
+            MetricsFormat::Json => self.write_json(output),
+            MetricsFormat::Prometheus => self.write_prometheus(output),
+        }
+    }
+
+    fn write_json<W: io::Write>(&self, output: W) -> Result<()> {
+        let report = MetricsReport {
+            schema_version: SCHEMA_VERSION,
+            specs: self.specs,
+            resolved: self.resolved,
+            failed: self.failed,
+            suppressed: self.suppressed,
+            resolve_rate: self.resolve_rate(),
+            phase_durations: self
+                .phases
+                .iter()
+                .map(|(name, duration)| (name.to_string(), duration.as_secs_f64()))
+                .collect(),
+            output_sizes: self
+                .outputs
+                .iter()
+                .map(|(name, size)| (name.clone(), *size))
+                .collect(),
+            stats: self.stats.as_ref().map(|stats| StatsReport {
+                ambiguous: stats.ambiguous,
+                missed: self.failed - stats.ambiguous,
+                slowest_patterns: stats
+                    .slowest_patterns
+                    .iter()
+                    .map(|pat| PatternCostRecord {
+                        name: pat.name.to_string(),
+                        anchor_length: pat.anchor_len,
+                        candidates: pat.candidates,
+                    })
+                    .collect(),
+            }),
+        };
+        serde_json::to_writer_pretty(output, &report)?;
+        Ok(())
+    }
+
+    fn write_prometheus<W: io::Write>(&self, mut output: W) -> Result<()> {
+        let gauges = [
+            ("zoltan_specs", "Number of function specs", self.specs as f64),
+            (
+                "zoltan_resolved_symbols",
+                "Number of resolved symbols",
+                self.resolved as f64,
+            ),
+            (
+                "zoltan_failed_specs",
+                "Number of specs that failed to resolve",
+                self.failed as f64,
+            ),
+            (
+                "zoltan_suppressed_failures",
+                "Number of failures suppressed with @allow",
+                self.suppressed as f64,
+            ),
+            (
+                "zoltan_resolve_rate",
+                "Share of specs that were resolved",
+                self.resolve_rate(),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            writeln!(output, "# HELP {name} {help}")?;
+            writeln!(output, "# TYPE {name} gauge")?;
+            writeln!(output, "{name} {value}")?;
+        }
+
+        writeln!(
+            output,
+            "# HELP zoltan_phase_duration_seconds Duration of each phase"
+        )?;
+        writeln!(output, "# TYPE zoltan_phase_duration_seconds gauge")?;
+        for (name, duration) in &self.phases {
+            let secs = duration.as_secs_f64();
+            writeln!(output, "zoltan_phase_duration_seconds{{phase=\"{name}\"}} {secs}")?;
+        }
+
+        writeln!(output, "# HELP zoltan_output_size_bytes Size of each output file")?;
+        writeln!(output, "# TYPE zoltan_output_size_bytes gauge")?;
+        for (name, size) in &self.outputs {
+            writeln!(output, "zoltan_output_size_bytes{{output=\"{name}\"}} {size}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    Json,
+    /// The Prometheus text exposition format.
+    Prometheus,
+}
+
+impl MetricsFormat {
+    /// Picks the Prometheus format for files with the `.prom` extension and JSON otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "prom" => Self::Prometheus,
+            _ => Self::Json,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// Identifier-friendly names of C++ operators, the longer operators come first so that
+/// they're not mistaken for their prefixes.
+const OPERATORS: &[(&str, &str)] = &[
+    ("delete[]", "delete_array"),
+    ("new[]", "new_array"),
+    ("delete", "delete"),
+    ("new", "new"),
+    ("<=>", "cmp"),
+    ("<<=", "shl_assign"),
+    (">>=", "shr_assign"),
+    ("->*", "arrow_star"),
+    ("==", "eq"),
+    ("!=", "ne"),
+    ("<=", "le"),
+    (">=", "ge"),
+    ("+=", "add_assign"),
+    ("-=", "sub_assign"),
+    ("*=", "mul_assign"),
+    ("/=", "div_assign"),
+    ("%=", "rem_assign"),
+    ("&=", "and_assign"),
+    ("|=", "or_assign"),
+    ("^=", "xor_assign"),
+    ("<<", "shl"),
+    (">>", "shr"),
+    ("&&", "logical_and"),
+    ("||", "logical_or"),
+    ("++", "inc"),
+    ("--", "dec"),
+    ("->", "arrow"),
+    ("()", "call"),
+    ("[]", "index"),
+    ("<", "lt"),
+    (">", "gt"),
+    ("+", "add"),
+    ("-", "sub"),
+    ("*", "mul"),
+    ("/", "div"),
+    ("%", "rem"),
+    ("&", "and"),
+    ("|", "or"),
+    ("^", "xor"),
+    ("~", "not"),
+    ("!", "logical_not"),
+    ("=", "assign"),
+    (",", "comma"),
+];
+
+/// Describes how names of operators and destructors are written to the outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameStyle {
+    /// Keep the names as they appear in the sources, like `operator==` or `~Object`.
+    Verbatim,
+    /// Turn the names into valid identifiers, like `operator_eq` or `dtor_Object`.
+    Identifier,
+}
+
+impl NameStyle {
+    pub fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            NameStyle::Verbatim => name.into(),
+            NameStyle::Identifier => sanitize_identifier(name),
+        }
+    }
+}
+
+impl FromStr for NameStyle {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "verbatim" => Ok(Self::Verbatim),
+            "identifier" => Ok(Self::Identifier),
+            other => Err(format!("unknown name style '{other}'")),
+        }
+    }
+}
+
+/// Converts a name into a valid C identifier, operators and destructors get descriptive names
+/// and any other disallowed characters are replaced with underscores.
+pub fn sanitize_identifier(name: &str) -> Cow<'_, str> {
+    let is_valid = name.chars().enumerate().all(|(i, c)| match c {
+        '0'..='9' => i > 0,
+        c => c.is_ascii_alphabetic() || c == '_',
+    });
+    if is_valid && !name.is_empty() {
+        return name.into();
+    }
+
+    let readable = if let Some(class) = name.strip_prefix('~') {
+        format!("dtor_{}", class.trim())
+    } else if let Some(op) = operator_name(name) {
+        format!("operator_{op}")
+    } else {
+        name.to_owned()
+    };
+    let mut res: String = readable
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !res.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        res.insert(0, '_');
+    }
+    res.into()
+}
+
+fn operator_name(name: &str) -> Option<Cow<'_, str>> {
+    let rest = name.strip_prefix("operator")?;
+    let symbol = rest.trim();
+    if let Some((_, ident)) = OPERATORS.iter().find(|(op, _)| *op == symbol) {
+        Some((*ident).into())
+    } else if rest.starts_with(char::is_whitespace) {
+        // a conversion operator like 'operator int'
+        Some(symbol.into())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_special_names() {
+        assert_eq!(sanitize_identifier("get_player"), "get_player");
+        assert_eq!(sanitize_identifier("operator=="), "operator_eq");
+        assert_eq!(sanitize_identifier("operator <<="), "operator_shl_assign");
+        assert_eq!(sanitize_identifier("operator[]"), "operator_index");
+        assert_eq!(sanitize_identifier("operator delete[]"), "operator_delete_array");
+        assert_eq!(
+            sanitize_identifier("operator unsigned int"),
+            "operator_unsigned_int"
+        );
+        assert_eq!(sanitize_identifier("~Object"), "dtor_Object");
+        assert_eq!(sanitize_identifier("Vector<int>::size"), "Vector_int___size");
+        assert_eq!(sanitize_identifier("3d"), "_3d");
+    }
+}
@@ -1,29 +1,189 @@
-use std::path::PathBuf;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use log::LevelFilter;
+use object::elf;
+
+use crate::codegen::ListOrder;
+use crate::dwarf::{DebugCompression, DwarfVersion};
+use crate::filter::{Glob, TypeFilter};
+use crate::logging::{self, LogFormat};
+use crate::names::NameStyle;
+use crate::process::ProcessRef;
+use crate::symbols::{AmbiguityStrategy, Confidence};
+use crate::types::DataModel;
+use crate::version::Version;
+use crate::{args, diff};
+
+/// The first argument that runs the `diff` command instead of processing specs.
+const DIFF_COMMAND: &str = "diff";
 
 #[derive(Clone, Debug)]
 pub struct Opts {
     pub source_path: PathBuf,
     pub exe_path: PathBuf,
+    pub layout_path: Option<PathBuf>,
     pub dwarf_output_path: Option<PathBuf>,
+    pub dwarf_split: bool,
+    pub dwarf_version: DwarfVersion,
+    pub compress_debug_sections: Option<DebugCompression>,
+    pub merge_with_path: Option<PathBuf>,
+    pub source_hash: bool,
+    pub elf_machine: Option<u16>,
+    pub elf_os_abi: Option<u8>,
+    /// Standard names of the sections in the DWARF outputs along with the names to use instead.
+    pub section_names: Vec<(String, String)>,
+    pub inject_output_path: Option<PathBuf>,
+    pub debuglink_output_path: Option<PathBuf>,
     pub c_output_path: Option<PathBuf>,
     pub rust_output_path: Option<PathBuf>,
+    pub x64dbg_output_path: Option<PathBuf>,
+    pub sdk_output_path: Option<PathBuf>,
+    pub breakpad_output_path: Option<PathBuf>,
+    pub json_output_path: Option<PathBuf>,
+    /// Commands of external backends along with the files to write their outputs to.
+    pub backend_outputs: Vec<(String, PathBuf)>,
+    pub template_path: Option<PathBuf>,
+    pub template_output_path: Option<PathBuf>,
+    pub report_path: Option<PathBuf>,
+    pub list: bool,
+    pub list_order: ListOrder,
+    pub sdk_namespace: String,
     pub strip_namespaces: bool,
+    pub strict_types: bool,
+    pub data_model: Option<DataModel>,
+    pub import_types_paths: Vec<PathBuf>,
     pub eager_type_export: bool,
+    pub eager_include_paths: Vec<PathBuf>,
+    pub reachable_types: bool,
+    pub keep_types: Vec<String>,
+    pub export_types: Vec<Glob>,
+    pub skip_types: Vec<Glob>,
+    pub struct_definitions: bool,
+    pub map_containers: bool,
+    pub container_map_path: Option<PathBuf>,
+    pub name_style: NameStyle,
+    pub ambiguity_strategy: AmbiguityStrategy,
+    pub min_confidence: Confidence,
+    pub export_all_functions: bool,
+    pub only_groups: Vec<String>,
+    pub exclude_groups: Vec<String>,
+    /// The version of the executable to select the specs with, read from the executable by default.
+    pub binary_version: Option<Version>,
+    pub base_process: Option<ProcessRef>,
+    pub absolute_addresses: bool,
+    pub metrics_path: Option<PathBuf>,
+    pub pattern_cache_path: Option<PathBuf>,
+    pub watch: bool,
+    pub verify: bool,
+    pub stats: bool,
+    pub explain_failures: bool,
+    pub preview: Option<usize>,
+    pub reproducible: bool,
+    pub verbose: usize,
+    pub quiet: usize,
+    pub log_format: LogFormat,
+    pub compile_commands_path: Option<PathBuf>,
+    pub target: Option<String>,
+    pub cflags: Vec<String>,
     pub compiler_flags: Vec<String>,
 }
 
 impl Opts {
+    /// Parses the command line, arguments of the form `@file` are replaced with the arguments
+    /// read from response files.
     pub fn load(header: &'static str) -> Self {
+        let args = match args::expand_response_files(std::env::args_os().skip(1)) {
+            Ok(args) => args,
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        };
+        if args.first().map_or(false, |arg| arg == DIFF_COMMAND) {
+            let opts = run_parser(DiffOpts::parser(), DiffOpts::DESCRIPTION, &args[1..]);
+            match diff::run(&opts) {
+                Ok(()) => std::process::exit(0),
+                Err(err) => {
+                    eprintln!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        run_parser(Self::parser(), header, &args)
+    }
+
+    pub(crate) fn parser() -> bpaf::Parser<Self> {
         use bpaf::*;
 
         let source_path = positional_os("SOURCE").map(PathBuf::from);
         let exe_path = positional_os("EXE").map(PathBuf::from);
+        let layout_path = long("layout")
+            .help("TOML file describing the sections of an executable without a section table")
+            .argument_os("LAYOUT")
+            .map(PathBuf::from)
+            .optional();
         let dwarf_output_path = long("dwarf-output")
             .short('o')
             .help("DWARF file to write")
             .argument_os("DWARF")
             .map(PathBuf::from)
             .optional();
+        let dwarf_split = long("dwarf-split")
+            .help("Write most of the DWARF to a separate .dwo file next to the DWARF file")
+            .switch();
+        let dwarf_version = long("dwarf-version")
+            .help("DWARF version to write (4, 5)")
+            .argument("VERSION")
+            .parse(|str| str.parse())
+            .fallback(DwarfVersion::V5);
+        let compress_debug_sections = long("compress-debug-sections")
+            .help("Compress the DWARF sections in ELF files (zlib, zstd)")
+            .argument("FORMAT")
+            .parse(|str| str.parse())
+            .optional();
+        let merge_with_path = long("merge-with")
+            .help(
+                "DWARF file written before to keep the symbols and types that weren't resolved again from",
+            )
+            .argument_os("DWARF")
+            .map(PathBuf::from)
+            .optional();
+        let source_hash = long("source-hash")
+            .help("Add a section with a hash of the source file to the DWARF output")
+            .switch();
+        let elf_machine = long("elf-machine")
+            .help("e_machine to write to the ELF header of the DWARF output instead of the one of the architecture")
+            .argument("MACHINE")
+            .parse(|str| {
+                u16::try_from(parse_int(&str)?).map_err(|_| "e_machine must fit in 16 bits".to_owned())
+            })
+            .optional();
+        let elf_os_abi = long("elf-os-abi")
+            .help("OS ABI to write to the ELF header of the DWARF output (none, sysv, gnu, freebsd or a number)")
+            .argument("ABI")
+            .parse(|str| parse_os_abi(&str))
+            .optional();
+        let section_names = long("section-name")
+            .help("Rename a section in the DWARF output, like .debug_info=.zdebug_info, can be repeated")
+            .argument("NAME=NEW_NAME")
+            .parse(|str| {
+                str.split_once('=')
+                    .map(|(name, new_name)| (name.to_owned(), new_name.to_owned()))
+                    .ok_or("expected a section name and a new name separated by =")
+            })
+            .many();
+        let inject_output_path = long("inject-output")
+            .help("Copy of the executable with the DWARF sections added to write")
+            .argument_os("EXE")
+            .map(PathBuf::from)
+            .optional();
+        let debuglink_output_path = long("debuglink-output")
+            .help("Copy of the executable with a link to the DWARF file to write")
+            .argument_os("EXE")
+            .map(PathBuf::from)
+            .optional();
         let c_output_path = long("c-output")
             .help("C header with offsets to write")
             .argument_os("C")
@@ -34,12 +194,219 @@ impl Opts {
             .argument_os("RUST")
             .map(PathBuf::from)
             .optional();
+        let x64dbg_output_path = long("x64dbg-output")
+            .help("x64dbg database with labels to write")
+            .argument_os("X64DBG")
+            .map(PathBuf::from)
+            .optional();
+        let sdk_output_path = long("sdk-output")
+            .help("C++ plugin SDK header with addresses to write")
+            .argument_os("SDK")
+            .map(PathBuf::from)
+            .optional();
+        let sdk_namespace = long("sdk-namespace")
+            .help("Namespace to use in the plugin SDK header")
+            .argument("NAMESPACE")
+            .fallback("zoltan".to_owned());
+        let breakpad_output_path = long("breakpad-output")
+            .help("Breakpad symbol file to write")
+            .argument_os("BREAKPAD")
+            .map(PathBuf::from)
+            .optional();
+        let json_output_path = long("json-output")
+            .help("JSON file with symbols to write")
+            .argument_os("JSON")
+            .map(PathBuf::from)
+            .optional();
+        let backend_outputs = long("backend-output")
+            .help("Run a command that gets the symbols as JSON on stdin and write its stdout to a file, can be repeated")
+            .argument("COMMAND=FILE")
+            .parse(|str| {
+                str.rsplit_once('=')
+                    .map(|(command, path)| (command.to_owned(), PathBuf::from(path)))
+                    .ok_or("expected a command and a file separated by =")
+            })
+            .many();
+        let template_path = long("template")
+            .help("Template to render with the resolved symbols and types")
+            .argument_os("TEMPLATE")
+            .map(PathBuf::from)
+            .optional();
+        let template_output_path = long("template-output")
+            .help("File to write the rendered template to")
+            .argument_os("FILE")
+            .map(PathBuf::from)
+            .optional();
+        let report_path = long("report")
+            .help("Markdown or HTML (.html) report of the symbols and types to write")
+            .argument_os("REPORT")
+            .map(PathBuf::from)
+            .optional();
+        let list = long("list")
+            .help("Print a listing of the resolved symbols")
+            .switch();
+        let list_order = long("sort-by")
+            .help("Order of the symbol listing (address, name)")
+            .argument("ORDER")
+            .parse(|str| str.parse())
+            .fallback(ListOrder::Address);
         let strip_namespaces = long("strip-namespaces")
             .help("Strip namespaces from type names")
             .switch();
+        let strict_types = long("strict-types")
+            .help("Fail on types and typedefs that can't be resolved instead of skipping them")
+            .switch();
+        let data_model = long("data-model")
+            .help("Data model used for type sizes (llp64, lp64, ilp32), derived from the executable by default")
+            .argument("MODEL")
+            .parse(|str| str.parse())
+            .optional();
+        let import_types_paths = long("import-types")
+            .help(
+                "File with DWARF debug info to take the definitions of missing types from, can be repeated",
+            )
+            .argument_os("FILE")
+            .map(PathBuf::from)
+            .many();
         let eager_type_export = long("eager-type-export")
             .help("Export all types found in the sources")
             .switch();
+        let eager_include_paths = long("eager-include-path")
+            .help("Directory with headers to include in the eager type export")
+            .argument_os("DIR")
+            .map(PathBuf::from)
+            .many();
+        let reachable_types = long("reachable-types")
+            .help("Only export the types reachable from the symbols and the --keep-type types")
+            .switch();
+        let keep_types = long("keep-type")
+            .help("Type to export along with the types reachable from the symbols")
+            .argument("NAME")
+            .many();
+        let export_types = long("export-type")
+            .help("Only export the types with names matching this pattern, can be repeated")
+            .argument("GLOB")
+            .map(|str| Glob::new(&str))
+            .many();
+        let skip_types = long("skip-type")
+            .help("Skip the types with names matching this pattern, can be repeated")
+            .argument("GLOB")
+            .map(|str| Glob::new(&str))
+            .many();
+        let struct_definitions = long("struct-definitions")
+            .help("Add struct definitions with explicit padding to the C and Rust outputs")
+            .switch();
+        let map_containers = long("map-containers")
+            .help(
+                "Replace the internals of std::vector, std::string and smart pointers with simple structs",
+            )
+            .switch();
+        let container_map_path = long("container-map")
+            .help("TOML file with the layouts of additional containers to map, implies --map-containers")
+            .argument_os("FILE")
+            .map(PathBuf::from)
+            .optional();
+        let name_style = long("name-style")
+            .help("How to write operator and destructor names in DWARF (identifier, verbatim)")
+            .argument("STYLE")
+            .parse(|str| str.parse())
+            .fallback(NameStyle::Identifier);
+        let ambiguity_strategy = long("ambiguity-strategy")
+            .help("How to handle patterns with multiple matches (fail, score)")
+            .argument("STRATEGY")
+            .parse(|str| str.parse())
+            .fallback(AmbiguityStrategy::Fail);
+        let min_confidence = long("min-confidence")
+            .help("Minimum confidence of symbols written to DWARF (fallback, scored, nth, unique)")
+            .argument("CONFIDENCE")
+            .parse(|str| str.parse())
+            .fallback(Confidence::Fallback);
+        let export_all_functions = long("export-all-functions")
+            .help("Export every function in the unwind tables, as fn_<RVA> when no spec resolved it")
+            .switch();
+        let only_groups = long("only-group")
+            .help("Only process the specs with this @group, can be repeated")
+            .argument("GROUP")
+            .many();
+        let exclude_groups = long("exclude-group")
+            .help("Skip the specs with this @group, can be repeated")
+            .argument("GROUP")
+            .many();
+        let binary_version = long("binary-version")
+            .help("Version of the executable to match @version against, read from the PE version resource by default")
+            .argument("VERSION")
+            .parse(|str| str.parse())
+            .optional();
+        let base_process = long("base-from-running-process")
+            .help("Use the image base of the executable loaded in a running process (name or PID)")
+            .argument("PROCESS")
+            .parse(|str| str.parse())
+            .optional();
+        let absolute_addresses = long("absolute-addresses")
+            .help("Write virtual addresses instead of RVAs to the C, Rust, SDK and JSON outputs")
+            .switch();
+        let metrics_path = long("emit-metrics")
+            .help("Metrics file to write (JSON, or Prometheus for .prom files)")
+            .argument_os("METRICS")
+            .map(PathBuf::from)
+            .optional();
+        let pattern_cache_path = long("pattern-cache")
+            .help("File to keep pattern matches in between runs against the same executable")
+            .argument_os("CACHE")
+            .map(PathBuf::from)
+            .optional();
+        let watch = long("watch")
+            .help("Keep running and process the sources again every time they change")
+            .switch();
+        let verify = long("verify")
+            .help("Check the resolved addresses against the ones in @expect parameters")
+            .switch();
+        let stats = long("stats")
+            .help("Log a summary with the number of failures, the slowest patterns and the duration of each phase")
+            .switch();
+        let explain_failures = long("explain-failures")
+            .help("Log a hex dump around the closest partial match or the matches of each failed pattern")
+            .switch();
+        let preview = long("preview")
+            .help("Disassemble the first N instructions of each symbol into the log and the JSON output")
+            .argument("N")
+            .parse(|str| str.parse())
+            .optional();
+        let reproducible = long("reproducible")
+            .help("Write the same bytes for the same inputs, with the symbols sorted and paths relative to the current directory")
+            .switch();
+        let verbose = short('v')
+            .long("verbose")
+            .help("Log more details, can be repeated")
+            .req_flag(())
+            .many()
+            .map(|flags| flags.len());
+        let quiet = short('q')
+            .long("quiet")
+            .help("Log only warnings, or only errors when repeated")
+            .req_flag(())
+            .many()
+            .map(|flags| flags.len());
+        let log_format = long("log-format")
+            .help("Format of the log written to stderr (text, json)")
+            .argument("FORMAT")
+            .parse(|str| str.parse())
+            .fallback(LogFormat::Text);
+        let compile_commands_path = long("compile-commands")
+            .help("Compilation database to take the compiler flags for the source file from")
+            .argument_os("FILE")
+            .map(PathBuf::from)
+            .optional();
+        let target = long("target")
+            .help("Target triple to compile the sources for, derived from the executable by default")
+            .argument("TRIPLE")
+            .optional();
+        let cflags = long("cflags")
+            .help("Flags to pass to the compiler as they are, split into arguments like in a shell")
+            .argument("FLAGS")
+            .parse(|str| args::split(&str))
+            .many()
+            .map(|flags| flags.concat());
         let compiler_flags = long("compiler-flag")
             .short('f')
             .help("Flags to pass to the compiler")
@@ -47,17 +414,155 @@ impl Opts {
             .map(|flag| format!("-{}", flag))
             .many();
 
-        let parser = construct!(Opts {
+        construct!(Opts {
             source_path,
             exe_path,
+            layout_path,
             dwarf_output_path,
+            dwarf_split,
+            dwarf_version,
+            compress_debug_sections,
+            merge_with_path,
+            source_hash,
+            elf_machine,
+            elf_os_abi,
+            section_names,
+            inject_output_path,
+            debuglink_output_path,
             c_output_path,
             rust_output_path,
+            x64dbg_output_path,
+            sdk_output_path,
+            sdk_namespace,
+            breakpad_output_path,
+            json_output_path,
+            backend_outputs,
+            template_path,
+            template_output_path,
+            report_path,
+            list,
+            list_order,
             strip_namespaces,
-            eager_type_export
+            strict_types,
+            data_model,
+            import_types_paths,
+            eager_type_export,
+            eager_include_paths,
+            reachable_types,
+            keep_types,
+            export_types,
+            skip_types,
+            struct_definitions,
+            map_containers,
+            container_map_path,
+            name_style,
+            ambiguity_strategy,
+            min_confidence,
+            export_all_functions,
+            only_groups,
+            exclude_groups,
+            binary_version,
+            base_process,
+            absolute_addresses,
+            metrics_path,
+            pattern_cache_path,
+            watch,
+            verify,
+            stats,
+            explain_failures,
+            preview,
+            reproducible,
+            verbose,
+            quiet,
+            log_format,
+            compile_commands_path,
+            target,
+            cflags,
             compiler_flags,
-        });
+        })
+    }
+
+    pub fn log_level(&self) -> LevelFilter {
+        logging::level(self.verbose, self.quiet)
+    }
+
+    /// Returns whether the types declared in a file should be included in the eager type export,
+    /// this is the case for the source file and the files in the `--eager-include-path` directories.
+    pub fn is_eager_export_path(&self, path: &Path) -> bool {
+        path == self.source_path || self.eager_include_paths.iter().any(|dir| path.starts_with(dir))
+    }
+
+    pub fn type_filter(&self) -> TypeFilter {
+        TypeFilter::new(self.export_types.clone(), self.skip_types.clone())
+    }
+}
+
+/// Options of the `diff` command, which compares two symbol maps written with `--json-output`.
+#[derive(Clone, Debug)]
+pub struct DiffOpts {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+    pub json_output_path: Option<PathBuf>,
+}
+
+impl DiffOpts {
+    const DESCRIPTION: &'static str =
+        "Print the symbols and type layouts that changed between two symbol maps written with --json-output";
+
+    fn parser() -> bpaf::Parser<Self> {
+        use bpaf::*;
+
+        let old_path = positional_os("OLD").map(PathBuf::from);
+        let new_path = positional_os("NEW").map(PathBuf::from);
+        let json_output_path = long("json-output")
+            .help("JSON file with the differences to write")
+            .argument_os("JSON")
+            .map(PathBuf::from)
+            .optional();
+        construct!(DiffOpts {
+            old_path,
+            new_path,
+            json_output_path,
+        })
+    }
+}
+
+/// Parses a decimal or a `0x` prefixed hexadecimal number.
+fn parse_int(str: &str) -> Result<u64, String> {
+    let res = match str.strip_prefix("0x").or_else(|| str.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => str.parse(),
+    };
+    res.map_err(|err| format!("invalid number '{str}': {err}"))
+}
+
+fn parse_os_abi(str: &str) -> Result<u8, String> {
+    match str {
+        "none" | "sysv" => Ok(elf::ELFOSABI_SYSV),
+        "gnu" | "linux" => Ok(elf::ELFOSABI_GNU),
+        "freebsd" => Ok(elf::ELFOSABI_FREEBSD),
+        other => u8::try_from(parse_int(other)?).map_err(|_| format!("unknown OS ABI '{other}'")),
+    }
+}
+
+/// Runs a parser on the arguments, exits with the help or the error message when it fails.
+fn run_parser<T>(parser: bpaf::Parser<T>, header: &'static str, args: &[OsString]) -> T
+where
+    T: Clone + fmt::Debug + 'static,
+{
+    use bpaf::*;
 
-        Info::default().descr(header).for_parser(parser).run()
+    let args: Vec<&OsStr> = args.iter().map(OsString::as_os_str).collect();
+    let info = Info::default().descr(header);
+    match info.for_parser(parser).run_inner(Args::from(args.as_slice())) {
+        Ok(opts) => opts,
+        Err(ParseFailure::Stdout(msg)) => {
+            println!("{msg}");
+            std::process::exit(0);
+        }
+        Err(ParseFailure::Stderr(msg)) => {
+            eprintln!("{msg}");
+            std::process::exit(1);
+        }
     }
 }
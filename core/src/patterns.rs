@@ -1,33 +1,60 @@
+use std::io::{self, Read};
+
 use aho_corasick::AhoCorasick;
 use enum_as_inner::EnumAsInner;
+use memchr::memmem::Finder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Anchors at least this long are searched for with memmem, which outperforms the automaton
+/// for a handful of long byte sequences.
+const MEMMEM_MIN_ANCHOR: usize = 8;
+/// Each anchor searched for with memmem takes a separate pass over the haystack, so memmem is
+/// only used when there are few of them.
+const MEMMEM_MAX_ANCHORS: usize = 4;
+/// Each combination of alternatives becomes a separate pattern, this keeps their number
+/// from exploding.
+const MAX_PATTERN_VARIANTS: usize = 64;
 
-#[derive(Debug, EnumAsInner)]
+#[derive(Debug, Clone, Hash, EnumAsInner)]
 pub enum PatItem {
     Byte(u8),
     Any,
     Group(String, VarType),
+    Gap(usize, usize),
 }
 
 impl PatItem {
+    /// Returns the minimum number of bytes matched by this item.
     #[inline]
     fn size(&self) -> usize {
         match self {
             PatItem::Byte(_) => 1,
             PatItem::Any => 1,
             PatItem::Group(_, VarType::Rel) => 4,
+            PatItem::Gap(min, _) => *min,
+        }
+    }
+
+    #[inline]
+    fn max_size(&self) -> usize {
+        match self {
+            PatItem::Gap(_, max) => *max,
+            other => other.size(),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum VarType {
     Rel,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Hash)]
 pub struct Pattern {
     parts: Vec<PatItem>,
     size: usize,
+    max_size: usize,
 }
 
 impl Pattern {
@@ -35,14 +62,22 @@ impl Pattern {
     fn new(parts: Vec<PatItem>) -> Self {
         Self {
             size: parts.iter().map(PatItem::size).sum(),
+            max_size: parts.iter().map(PatItem::max_size).sum(),
             parts,
         }
     }
 
+    /// Parses a pattern without alternatives.
     pub fn parse(str: &str) -> Result<Self, peg::error::ParseError<peg::str::LineCol>> {
         pattern::pattern(str)
     }
 
+    /// Parses a pattern that can have alternatives like `[48 | 4C] 8B`, it's expanded into
+    /// a concrete pattern for each combination of them.
+    pub fn parse_variants(str: &str) -> Result<Vec<Self>, peg::error::ParseError<peg::str::LineCol>> {
+        pattern::variants(str)
+    }
+
     #[inline]
     fn parts(&self) -> &[PatItem] {
         &self.parts
@@ -53,6 +88,13 @@ impl Pattern {
         self.size
     }
 
+    #[inline]
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Returns the groups with their offsets, assuming all gaps have their minimum length.
+    /// Use [`Pattern::groups_at`] to get the offsets of an actual match.
     pub fn groups(&self) -> impl Iterator<Item = (&str, VarType, usize)> {
         self.parts
             .iter()
@@ -64,36 +106,182 @@ impl Pattern {
             .filter_map(|(it, offset)| it.as_group().map(|(key, typ)| (key.as_str(), *typ, offset)))
     }
 
-    fn does_match(&self, bytes: &[u8]) -> bool {
-        let mut bytes = bytes.iter();
-        for pat in self.parts() {
-            match pat {
-                PatItem::Byte(expected) => {
-                    if bytes.next() != Some(expected) {
-                        return false;
-                    }
-                }
-                PatItem::Group(_, _) => {
-                    if bytes.advance_by(pat.size()).is_err() {
-                        return false;
-                    }
-                }
-                PatItem::Any => {
-                    bytes.next();
-                }
-            }
+    /// Matches the pattern against the start of `bytes` and returns the groups with their offsets.
+    pub fn groups_at(&self, bytes: &[u8]) -> Option<Vec<(&str, VarType, usize)>> {
+        let mut groups = vec![];
+        if match_parts(self.parts(), bytes, 0, &mut groups) {
+            Some(groups)
+        } else {
+            None
         }
-        true
     }
 
+    /// Finds the place where the longest part of the pattern preceding the first gap matches,
+    /// to show how close the pattern came to matching. Returns the offset and the number
+    /// of matched bytes, or `None` when not even the first byte matches anywhere.
+    pub fn longest_partial_match(&self, haystack: &[u8]) -> Option<(usize, usize)> {
+        let prefix = self.prefix();
+        let matched_len = |offset: usize| (offset, matched_prefix_len(prefix, &haystack[offset..]));
+        let best = match prefix.first() {
+            Some(PatItem::Byte(first)) => memchr::memchr_iter(*first, haystack)
+                .map(matched_len)
+                .max_by_key(|(offset, len)| (*len, std::cmp::Reverse(*offset))),
+            _ => (0..haystack.len())
+                .map(matched_len)
+                .max_by_key(|(offset, len)| (*len, std::cmp::Reverse(*offset))),
+        };
+        best.filter(|(_, len)| *len > 0)
+    }
+
+    fn does_match(&self, bytes: &[u8]) -> bool {
+        match_parts(self.parts(), bytes, 0, &mut vec![])
+    }
+
+    /// Returns the longest sequence of bytes that precedes the first gap, the offset of
+    /// anything after a gap is not known until the pattern is matched.
     fn longest_byte_sequence(&self) -> &[PatItem] {
-        self.parts()
+        let prefix = self.prefix();
+        prefix
             .group_by(|a, b| a.as_byte().is_some() && b.as_byte().is_some())
             .max_by_key(|parts| parts.len())
+            .unwrap_or(&prefix[..0])
+    }
+
+    /// Returns the sequence of bytes preceding the first gap that is expected to occur the least
+    /// often in the haystack the histogram was built from. Longer sequences win the ties.
+    fn rarest_byte_sequence(&self, histogram: &BigramHistogram) -> &[PatItem] {
+        self.byte_runs()
+            .map(|run| (run, histogram.estimate(run.iter().filter_map(PatItem::as_byte))))
+            .min_by(|(a, a_count), (b, b_count)| a_count.total_cmp(b_count).then(b.len().cmp(&a.len())))
+            .map(|(run, _)| run)
+            .unwrap_or_else(|| self.longest_byte_sequence())
+    }
+
+    /// Returns the runs of bytes that precede the first gap.
+    fn byte_runs(&self) -> impl Iterator<Item = &[PatItem]> {
+        self.prefix()
+            .group_by(|a, b| a.as_byte().is_some() && b.as_byte().is_some())
+            .filter(|parts| parts[0].as_byte().is_some())
+    }
+
+    /// Returns the offset of a sequence of the parts preceding the first gap from the start
+    /// of a match.
+    fn offset_of(&self, parts: &[PatItem]) -> usize {
+        let start = offset_from(self.parts(), parts);
+        self.parts[0..start].iter().map(PatItem::size).sum()
+    }
+
+    fn prefix(&self) -> &[PatItem] {
+        self.parts()
+            .split(|part| matches!(part, PatItem::Gap(..)))
+            .next()
             .unwrap_or_default()
     }
 }
 
+/// Counts of the bytes and pairs of bytes in a haystack, used to pick the anchors that produce
+/// the fewest candidates.
+struct BigramHistogram {
+    bytes: Vec<u32>,
+    pairs: Vec<u32>,
+}
+
+impl BigramHistogram {
+    fn new(haystack: &[u8]) -> Self {
+        let mut bytes = vec![0; 0x100];
+        let mut pairs = vec![0; 0x10000];
+        for byte in haystack {
+            bytes[*byte as usize] += 1;
+        }
+        for pair in haystack.windows(2) {
+            pairs[Self::pair_index(pair[0], pair[1])] += 1;
+        }
+        Self { bytes, pairs }
+    }
+
+    /// Estimates how many times a sequence of bytes occurs, assuming that each byte only
+    /// depends on the one before it.
+    fn estimate<'a>(&self, seq: impl Iterator<Item = &'a u8>) -> f64 {
+        let seq: Vec<u8> = seq.copied().collect();
+        match &seq[..] {
+            [] => f64::INFINITY,
+            [byte] => self.bytes[*byte as usize].into(),
+            [first, second, ..] => seq[1..].windows(2).fold(
+                self.pairs[Self::pair_index(*first, *second)].into(),
+                |count: f64, pair| {
+                    let total = self.bytes[pair[0] as usize];
+                    if total == 0 {
+                        0.
+                    } else {
+                        count * f64::from(self.pairs[Self::pair_index(pair[0], pair[1])]) / f64::from(total)
+                    }
+                },
+            ),
+        }
+    }
+
+    #[inline]
+    fn pair_index(first: u8, second: u8) -> usize {
+        (first as usize) << 8 | second as usize
+    }
+}
+
+/// The cost of searching for a pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchCost {
+    /// The number of bytes of the anchor the pattern was searched for with.
+    pub anchor_len: usize,
+    /// How many times the anchor was found and the rest of the pattern had to be checked.
+    pub candidates: usize,
+}
+
+fn match_parts<'a>(
+    parts: &'a [PatItem],
+    bytes: &[u8],
+    pos: usize,
+    groups: &mut Vec<(&'a str, VarType, usize)>,
+) -> bool {
+    match parts.split_first() {
+        None => true,
+        Some((PatItem::Byte(expected), rest)) => {
+            bytes.get(pos) == Some(expected) && match_parts(rest, bytes, pos + 1, groups)
+        }
+        Some((PatItem::Any, rest)) => pos < bytes.len() && match_parts(rest, bytes, pos + 1, groups),
+        Some((part @ PatItem::Group(key, typ), rest)) => {
+            if pos + part.size() > bytes.len() {
+                return false;
+            }
+            groups.push((key.as_str(), *typ, pos));
+            if match_parts(rest, bytes, pos + part.size(), groups) {
+                true
+            } else {
+                groups.pop();
+                false
+            }
+        }
+        Some((PatItem::Gap(min, max), rest)) => (*min..=*max)
+            .take_while(|len| pos + len <= bytes.len())
+            .any(|len| match_parts(rest, bytes, pos + len, groups)),
+    }
+}
+
+/// Returns the number of bytes matched by the parts before the first mismatch.
+fn matched_prefix_len(parts: &[PatItem], bytes: &[u8]) -> usize {
+    let mut pos = 0;
+    for part in parts {
+        let is_match = match part {
+            PatItem::Byte(expected) => bytes.get(pos) == Some(expected),
+            PatItem::Any | PatItem::Group(_, _) => pos + part.size() <= bytes.len(),
+            PatItem::Gap(_, _) => false,
+        };
+        if !is_match {
+            break;
+        }
+        pos += part.size();
+    }
+    pos
+}
+
 peg::parser! {
     grammar pattern() for str {
         rule _() =
@@ -104,57 +292,344 @@ peg::parser! {
             = "?"
         rule ident() -> String
             = id:$(['a'..='z' | 'A'..='Z' | '_']+) { id.to_owned() }
+        rule number() -> usize
+            = n:$(['0'..='9']+) {? n.parse().or(Err("usize")) }
         rule var_type() -> VarType
             = "rel" { VarType::Rel }
-        rule item() -> PatItem
-            = n:byte() { PatItem::Byte(n) }
-            / any() { PatItem::Any }
-            / "(" _ id:ident() _ ":" _ typ:var_type() _ ")" { PatItem::Group(id, typ) }
+        rule gap() -> PatItem
+            = "?{" _ min:number() _ "," _ max:number() _ "}" {?
+                if min <= max { Ok(PatItem::Gap(min, max)) } else { Err("gap bounds") }
+            }
+        // repetitions and alternatives are expanded while parsing, each rule returns all of
+        // the variants of the items it matched, so the rest of the code only sees plain items
+        rule item() -> Vec<Vec<PatItem>>
+            = n:byte() { vec![vec![PatItem::Byte(n)]] }
+            / gap:gap() { vec![vec![gap]] }
+            / "?{" _ n:number() _ "}" { vec![vec![PatItem::Any; n]] }
+            / any() { vec![vec![PatItem::Any]] }
+            / "(" _ id:ident() _ ":" _ typ:var_type() _ ")" { vec![vec![PatItem::Group(id, typ)]] }
+            / "(" _ items:items() _ ")" _ "{" _ n:number() _ "}" {?
+                (0..n).try_fold(vec![vec![]], |acc, _| combine_variants(acc, &items))
+            }
+            / "[" _ alts:(items() ++ (_ "|" _)) _ "]" { alts.concat() }
+        rule items() -> Vec<Vec<PatItem>>
+            = items:item() ** _ {? items.iter().try_fold(vec![vec![]], |acc, it| combine_variants(acc, it)) }
+        pub rule variants() -> Vec<Pattern>
+            = items:items() { items.into_iter().map(Pattern::new).collect() }
         pub rule pattern() -> Pattern
-            = items:item() ** _ { Pattern::new(items) }
+            = variants:variants() {?
+                let mut variants = variants;
+                match variants.len() {
+                    1 => Ok(variants.remove(0)),
+                    _ => Err("a pattern without alternatives"),
+                }
+            }
     }
 }
 
+/// Appends each of the variants of the next items to each of the variants so far.
+fn combine_variants(
+    variants: Vec<Vec<PatItem>>,
+    next: &[Vec<PatItem>],
+) -> Result<Vec<Vec<PatItem>>, &'static str> {
+    if variants.len() * next.len() > MAX_PATTERN_VARIANTS {
+        return Err("fewer alternatives");
+    }
+    let res = variants
+        .iter()
+        .flat_map(|variant| next.iter().map(move |items| [variant.as_slice(), items].concat()))
+        .collect();
+    Ok(res)
+}
+
+/// Searches for all of the patterns at once. The matches are sorted by their RVA
+/// and each pattern is reported at most once per RVA.
 pub fn multi_search<'a, I>(patterns: I, haystack: &[u8]) -> Vec<Match>
 where
     I: IntoIterator<Item = &'a Pattern>,
 {
-    let mut items = vec![];
-    let mut sequences: Vec<Vec<u8>> = vec![];
+    multi_search_with_costs(patterns, haystack).0
+}
 
-    for pat in patterns {
-        let seq = pat.longest_byte_sequence();
-        let start = offset_from(pat.parts(), seq);
-        let offset: usize = pat.parts[0..start].iter().map(PatItem::size).sum();
-        items.push((pat, offset));
-        sequences.push(seq.iter().filter_map(PatItem::as_byte).cloned().collect());
-    }
+/// Same as [`multi_search`], but also returns the cost of each pattern. Patterns with anchors
+/// that occur often have a lot of candidates and they are what slows the search down.
+pub fn multi_search_with_costs<'a, I>(patterns: I, haystack: &[u8]) -> (Vec<Match>, Vec<SearchCost>)
+where
+    I: IntoIterator<Item = &'a Pattern>,
+{
+    let searcher = Searcher::new(patterns, Some(haystack));
+    let mut matches = vec![];
+    let mut candidates = vec![0; searcher.items.len()];
+    searcher.search(haystack, 0, haystack.len(), &mut matches, &mut candidates);
+    let costs = searcher
+        .anchor_lens
+        .iter()
+        .zip(candidates)
+        .map(|(anchor_len, candidates)| SearchCost {
+            anchor_len: *anchor_len,
+            candidates,
+        })
+        .collect();
+    (sort_matches(matches), costs)
+}
 
-    let ac = AhoCorasick::new(&sequences);
+/// Same as [`multi_search`], but reads the haystack in chunks of `chunk_size` bytes, so that
+/// it never has to be loaded into memory in full. Consecutive chunks overlap by the size
+/// of the longest pattern, the results are identical to [`multi_search`]. The haystack isn't
+/// known upfront, so the anchors are always the longest runs of bytes.
+pub fn multi_search_chunked<'a, I, R>(
+    patterns: I,
+    mut reader: R,
+    chunk_size: usize,
+) -> io::Result<Vec<Match>>
+where
+    I: IntoIterator<Item = &'a Pattern>,
+    R: Read,
+{
+    let searcher = Searcher::new(patterns, None);
+    let mut candidates = vec![0; searcher.items.len()];
+    let overlap = searcher.max_size;
+    let chunk_size = chunk_size.max(overlap + 1);
+    let mut buffer = Vec::with_capacity(chunk_size + overlap);
+    let mut base = 0;
     let mut matches = vec![];
 
-    for mat in ac.find_overlapping_iter(haystack) {
-        let (pat, offset) = items[mat.pattern()];
-        let start = mat.start() - offset;
-        let slice = &haystack[start..start + pat.size()];
+    loop {
+        let read = reader.by_ref().take(chunk_size as u64).read_to_end(&mut buffer)?;
+        if read < chunk_size {
+            searcher.search(&buffer, base, buffer.len(), &mut matches, &mut candidates);
+            break;
+        }
+        // matches starting in the overlap are only verified once the next chunk is read
+        let limit = buffer.len() - overlap;
+        searcher.search(&buffer, base, limit, &mut matches, &mut candidates);
+        buffer.drain(..limit);
+        base += limit as u64;
+    }
+    Ok(sort_matches(matches))
+}
+
+fn sort_matches(mut matches: Vec<Match>) -> Vec<Match> {
+    matches.sort_by_key(|mat| (mat.rva, mat.pattern));
+    matches.dedup_by_key(|mat| (mat.rva, mat.pattern));
+    matches
+}
+
+struct SearchItem<'a> {
+    pattern: &'a Pattern,
+    /// Offset of the anchor from the start of the pattern.
+    anchor_offset: usize,
+    /// Another run of bytes along with its offset, it's compared before the whole pattern
+    /// is walked to discard most of the false candidates cheaply.
+    filter: Option<(usize, Vec<u8>)>,
+}
+
+struct Searcher<'a> {
+    items: Vec<SearchItem<'a>>,
+    anchor_lens: Vec<usize>,
+    ac: AhoCorasick,
+    /// Indices of the items searched for with the automaton.
+    ac_items: Vec<usize>,
+    /// Indices of the items searched for with memmem along with their finders.
+    finders: Vec<(usize, Finder<'static>)>,
+    max_size: usize,
+}
+
+impl<'a> Searcher<'a> {
+    /// Picks the anchors of the patterns, the rarest ones in the haystack when it's given.
+    fn new<I>(patterns: I, haystack: Option<&[u8]>) -> Self
+    where
+        I: IntoIterator<Item = &'a Pattern>,
+    {
+        let patterns: Vec<_> = patterns.into_iter().collect();
+        // the histogram is only worth building when some of the patterns have a choice of anchors
+        let histogram = haystack
+            .filter(|_| patterns.iter().any(|pat| pat.byte_runs().nth(1).is_some()))
+            .map(BigramHistogram::new);
+        let mut items = vec![];
+        let mut sequences: Vec<Vec<u8>> = vec![];
 
-        if pat.does_match(slice) {
-            let mat = Match {
-                pattern: mat.pattern(),
-                rva: start as u64,
+        for pat in patterns {
+            let seq = match &histogram {
+                Some(histogram) => pat.rarest_byte_sequence(histogram),
+                None => pat.longest_byte_sequence(),
             };
-            matches.push(mat);
+            let filter = pat
+                .byte_runs()
+                .filter(|run| run.as_ptr() != seq.as_ptr())
+                .max_by_key(|run| run.len())
+                .map(|run| {
+                    (
+                        pat.offset_of(run),
+                        run.iter().filter_map(PatItem::as_byte).cloned().collect(),
+                    )
+                });
+            items.push(SearchItem {
+                pattern: pat,
+                anchor_offset: pat.offset_of(seq),
+                filter,
+            });
+            sequences.push(seq.iter().filter_map(PatItem::as_byte).cloned().collect());
+        }
+        let max_size = items
+            .iter()
+            .map(|item| item.pattern.max_size())
+            .max()
+            .unwrap_or(0);
+        let anchor_lens = sequences.iter().map(Vec::len).collect();
+
+        let long_anchors = sequences
+            .iter()
+            .filter(|seq| seq.len() >= MEMMEM_MIN_ANCHOR)
+            .count();
+        let mut ac_items = vec![];
+        let mut ac_sequences = vec![];
+        let mut finders = vec![];
+        for (i, seq) in sequences.into_iter().enumerate() {
+            if long_anchors <= MEMMEM_MAX_ANCHORS && seq.len() >= MEMMEM_MIN_ANCHOR {
+                finders.push((i, Finder::new(&seq).into_owned()));
+            } else {
+                ac_items.push(i);
+                ac_sequences.push(seq);
+            }
+        }
+
+        Self {
+            items,
+            anchor_lens,
+            ac: AhoCorasick::new(&ac_sequences),
+            ac_items,
+            finders,
+            max_size,
+        }
+    }
+
+    /// Collects the matches that start before `limit`, `base` is the RVA of the haystack.
+    /// The candidates checked are counted for each pattern.
+    fn search(
+        &self,
+        haystack: &[u8],
+        base: u64,
+        limit: usize,
+        matches: &mut Vec<Match>,
+        candidates: &mut [usize],
+    ) {
+        let mut check = |index: usize, anchor: usize| {
+            let item = &self.items[index];
+            // the bytes preceding the anchor would start before the haystack
+            let start = match anchor.checked_sub(item.anchor_offset) {
+                Some(start) if start < limit => start,
+                _ => return,
+            };
+            candidates[index] += 1;
+            if let Some((offset, bytes)) = &item.filter {
+                let pos = start + offset;
+                if haystack.get(pos..pos + bytes.len()) != Some(bytes.as_slice()) {
+                    return;
+                }
+            }
+            let pat = item.pattern;
+            if start + pat.size() > haystack.len() {
+                return;
+            }
+            let slice = &haystack[start..(start + pat.max_size()).min(haystack.len())];
+
+            if pat.does_match(slice) {
+                let mat = Match {
+                    pattern: index,
+                    rva: base + start as u64,
+                };
+                matches.push(mat);
+            }
+        };
+
+        for mat in self.ac.find_overlapping_iter(haystack) {
+            check(self.ac_items[mat.pattern()], mat.start());
+        }
+        for (item, finder) in &self.finders {
+            // the anchors can overlap, so the search resumes right after the start of each one
+            let mut pos = 0;
+            while let Some(found) = finder.find(&haystack[pos..]) {
+                check(*item, pos + found);
+                pos += found + 1;
+            }
         }
     }
-    matches
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct Match {
     pub pattern: usize,
     pub rva: u64,
 }
 
+/// A regular expression evaluated against the hex-encoded haystack. It's much slower than
+/// the byte patterns, but it can express things like variable-length gaps.
+#[derive(Debug)]
+pub struct HexRegex {
+    regex: Regex,
+}
+
+impl HexRegex {
+    pub fn parse(str: &str) -> Result<Self, regex::Error> {
+        // whitespace is insignificant and hex digits are case-insensitive
+        let regex = Regex::new(&format!("(?xi){}", str))?;
+        Ok(Self { regex })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Searches a haystack encoded with [`encode_hex`], matches that don't start on a byte
+    /// boundary are discarded. Named capture groups are interpreted as relative operands.
+    pub fn search(&self, hex: &str) -> Vec<RegexMatch> {
+        let mut locs = self.regex.capture_locations();
+        let mut matches = vec![];
+        let mut pos = 0;
+
+        while let Some(mat) = self.regex.captures_read_at(&mut locs, hex, pos) {
+            if mat.start() % 2 != 0 {
+                pos = mat.start() + 1;
+                continue;
+            }
+            let groups = self
+                .regex
+                .capture_names()
+                .enumerate()
+                .filter_map(|(i, name)| {
+                    let (start, _) = locs.get(i)?;
+                    Some((name?.to_owned(), VarType::Rel, (start - mat.start()) / 2))
+                })
+                .collect();
+            matches.push(RegexMatch {
+                rva: (mat.start() / 2) as u64,
+                groups,
+            });
+            pos = mat.start() + 2;
+        }
+        matches
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegexMatch {
+    pub rva: u64,
+    pub groups: Vec<(String, VarType, usize)>,
+}
+
+/// Encodes bytes as uppercase hex digits with no separators.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    let mut str = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        str.push(DIGITS[(byte >> 4) as usize] as char);
+        str.push(DIGITS[(byte & 0xF) as usize] as char);
+    }
+    str
+}
+
 /// Returns the offset of `other` into `slice`.
 #[inline]
 fn offset_from<T>(slice: &[T], other: &[T]) -> usize {
@@ -189,6 +664,48 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn expand_repeated_items() {
+        let pat = Pattern::parse("E8 ?{3} (AA (fn:rel)){2} BB").unwrap();
+        assert_matches!(pat.parts(), &[
+            PatItem::Byte(0xE8),
+            PatItem::Any,
+            PatItem::Any,
+            PatItem::Any,
+            PatItem::Byte(0xAA),
+            PatItem::Group(_, VarType::Rel),
+            PatItem::Byte(0xAA),
+            PatItem::Group(_, VarType::Rel),
+            PatItem::Byte(0xBB),
+        ]);
+        assert_eq!(pat.groups().collect::<Vec<_>>(), vec![
+            ("fn", VarType::Rel, 5),
+            ("fn", VarType::Rel, 10)
+        ]);
+        assert_eq!(pat.size(), 15);
+    }
+
+    #[test]
+    fn expand_alternatives() {
+        let pats = Pattern::parse_variants("[48 | 4C 8B] ? [E8 | ] (fn:rel)").unwrap();
+        let parts: Vec<_> = pats.iter().map(|pat| format!("{:?}", pat.parts())).collect();
+        assert_eq!(parts, vec![
+            r#"[Byte(72), Any, Byte(232), Group("fn", Rel)]"#,
+            r#"[Byte(72), Any, Group("fn", Rel)]"#,
+            r#"[Byte(76), Byte(139), Any, Byte(232), Group("fn", Rel)]"#,
+            r#"[Byte(76), Byte(139), Any, Group("fn", Rel)]"#,
+        ]);
+        assert_eq!(pats[2].groups().collect::<Vec<_>>(), vec![(
+            "fn",
+            VarType::Rel,
+            4
+        )]);
+
+        assert!(Pattern::parse("[48 | 4C] 8B").is_err());
+        assert!(Pattern::parse_variants("[48 | 4C]{7}").is_err());
+        assert!(Pattern::parse_variants("([48 | 4C | 4D | 49]){4}").is_err());
+    }
+
     #[test]
     fn return_correct_longest_seq() {
         let pat = Pattern::parse("8B ? 0D ? F9 5F 48 B8 ? BA 10").unwrap();
@@ -200,6 +717,14 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn find_longest_partial_match() {
+        let pat = Pattern::parse("48 8B ? 11 22 33").unwrap();
+        let haystack = [0x48, 0x8B, 0x00, 0x11, 0x99, 0x48, 0x8B, 0x00, 0x11, 0x22, 0x44];
+        assert_eq!(pat.longest_partial_match(&haystack), Some((5, 5)));
+        assert_eq!(pat.longest_partial_match(&[0x8B, 0x11]), None);
+    }
+
     #[test]
     fn match_valid_patterns() {
         let pat1 = Pattern::parse("FD 98 07 ? ? 49 C5").unwrap();
@@ -216,6 +741,123 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn return_matches_in_ascending_order() {
+        let pat1 = Pattern::parse("? ? ? ? 11 22").unwrap();
+        let pat2 = Pattern::parse("33 44").unwrap();
+        let haystack = [0x00, 0x33, 0x44, 0x00, 0x11, 0x22];
+        assert_matches!(multi_search([&pat1, &pat2], &haystack).as_slice(), &[
+            Match { pattern: 0, rva: 0 },
+            Match { pattern: 1, rva: 1 },
+        ]);
+    }
+
+    #[test]
+    fn report_overlapping_matches_once() {
+        let pat = Pattern::parse("AA AA ? AA").unwrap();
+        let haystack = [0xAA; 6];
+        assert_matches!(multi_search([&pat], &haystack).as_slice(), &[
+            Match { pattern: 0, rva: 0 },
+            Match { pattern: 0, rva: 1 },
+            Match { pattern: 0, rva: 2 },
+        ]);
+    }
+
+    #[test]
+    fn count_anchor_candidates() {
+        let pat = Pattern::parse("BB CC ? DD").unwrap();
+        let haystack = [
+            0xBB, 0xCC, 0x00, 0xDD, 0xDD, 0xDD, 0xDD, 0xBB, 0xCC, 0x00, 0x00, 0xBB, 0xCC,
+        ];
+        let (matches, costs) = multi_search_with_costs([&pat], &haystack);
+        assert_matches!(matches.as_slice(), &[Match { pattern: 0, rva: 0 }]);
+        assert_eq!(costs, [SearchCost {
+            anchor_len: 2,
+            candidates: 3
+        }]);
+    }
+
+    #[test]
+    fn pick_rarest_anchors() {
+        let pat = Pattern::parse("48 89 5C 24 ? E8 ? ? ? ? 9A 7B").unwrap();
+        let mut haystack = [
+            0x48, 0x89, 0x5C, 0x24, 0x00, 0xE8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]
+        .repeat(10);
+        haystack.extend([
+            0x48, 0x89, 0x5C, 0x24, 0x10, 0xE8, 0x01, 0x02, 0x03, 0x04, 0x9A, 0x7B,
+        ]);
+        let (matches, costs) = multi_search_with_costs([&pat], &haystack);
+        assert_matches!(matches.as_slice(), &[Match { pattern: 0, rva: 120 }]);
+        assert_eq!(costs, [SearchCost {
+            anchor_len: 2,
+            candidates: 1
+        }]);
+    }
+
+    #[test]
+    fn match_long_anchors_like_short_ones() {
+        let long = Pattern::parse("AA ? 11 22 33 44 55 66 77 88").unwrap();
+        let short = Pattern::parse("33 44 55").unwrap();
+        let haystack = [
+            0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88,
+        ];
+        let expected = [Match { pattern: 0, rva: 4 }, Match { pattern: 1, rva: 8 }];
+        let (matches, costs) = multi_search_with_costs([&long, &short], &haystack);
+        assert_eq!(matches, expected);
+        assert_eq!(costs, [
+            SearchCost {
+                anchor_len: 8,
+                candidates: 1
+            },
+            SearchCost {
+                anchor_len: 3,
+                candidates: 1
+            }
+        ]);
+
+        // too many long anchors for memmem, all of them go through the automaton
+        let pats = [&long, &long, &long, &long, &long, &short];
+        let matches = multi_search(pats, &haystack);
+        assert_eq!(matches.len(), 6);
+    }
+
+    #[test]
+    fn skip_anchors_preceded_by_missing_bytes() {
+        let pat = Pattern::parse("? ? AA BB CC").unwrap();
+        let haystack = [0xAA, 0xBB, 0xCC, 0x00, 0xAA, 0xBB, 0xCC];
+        assert_matches!(multi_search([&pat], &haystack).as_slice(), &[Match {
+            pattern: 0,
+            rva: 2
+        }]);
+
+        let pat = Pattern::parse("AA ? BB BB").unwrap();
+        let haystack = [0xBB, 0xBB, 0xAA, 0x00, 0xBB, 0xBB];
+        assert_matches!(multi_search([&pat], &haystack).as_slice(), &[Match {
+            pattern: 0,
+            rva: 2
+        }]);
+    }
+
+    #[test]
+    fn match_chunks_like_whole_haystack() {
+        let pat1 = Pattern::parse("? ? AA BB ?{0,2} CC").unwrap();
+        let pat2 = Pattern::parse("DD (fn:rel) EE").unwrap();
+        let haystack: Vec<u8> = [0x00, 0xAA, 0xBB, 0x11, 0xCC, 0xDD, 0x01, 0x02, 0x03, 0x04, 0xEE]
+            .into_iter()
+            .cycle()
+            .take(200)
+            .collect();
+
+        let expected = multi_search([&pat1, &pat2], &haystack);
+        assert!(!expected.is_empty());
+        for chunk_size in [1, 7, 16, 64, 1000] {
+            let matches = multi_search_chunked([&pat1, &pat2], &haystack[..], chunk_size).unwrap();
+            let pairs = |matches: &[Match]| matches.iter().map(|m| (m.pattern, m.rva)).collect::<Vec<_>>();
+            assert_eq!(pairs(&matches), pairs(&expected));
+        }
+    }
+
     #[test]
     fn return_correct_groups() {
         let pat = Pattern::parse("BA CC (one:rel) FF 89 BF (two:rel) (three:rel) 56").unwrap();
@@ -225,4 +867,46 @@ mod tests {
             ("three", VarType::Rel, 13)
         ]);
     }
+
+    #[test]
+    fn match_patterns_with_gaps() {
+        let pat = Pattern::parse("8B 0D ?{0,2} (fn:rel) BA").unwrap();
+        assert_matches!(pat.parts(), &[
+            PatItem::Byte(0x8B),
+            PatItem::Byte(0x0D),
+            PatItem::Gap(0, 2),
+            PatItem::Group(_, VarType::Rel),
+            PatItem::Byte(0xBA),
+        ]);
+
+        let haystack = [
+            0x8B, 0x0D, 0x01, 0x02, 0x03, 0x04, 0xBA, 0x8B, 0x0D, 0xFF, 0xFF, 0x01, 0x02, 0x03, 0x04, 0xBA,
+            0x8B, 0x0D, 0xFF, 0xFF, 0xFF, 0x01, 0x02, 0x03, 0x04, 0xBA,
+        ];
+        assert_matches!(multi_search([&pat], &haystack).as_slice(), &[
+            Match { pattern: 0, rva: 0 },
+            Match { pattern: 0, rva: 7 },
+        ]);
+        assert_eq!(pat.groups_at(&haystack[7..]), Some(vec![("fn", VarType::Rel, 4)]));
+    }
+
+    #[test]
+    fn reject_invalid_gaps() {
+        assert!(Pattern::parse("8B ?{4,2} 0D").is_err());
+    }
+
+    #[test]
+    fn match_hex_regex() {
+        let regex = HexRegex::parse("E8 (?P<fn>.{8}) 45 (33|8B)").unwrap();
+        let haystack = [0x11, 0xE8, 0x10, 0x00, 0x00, 0x00, 0x45, 0x8B, 0x1E, 0x8E, 0x80];
+        let matches = regex.search(&encode_hex(&haystack));
+        assert_matches!(matches.as_slice(), [RegexMatch { rva: 1, .. }]);
+        assert_eq!(matches[0].groups, vec![("fn".to_owned(), VarType::Rel, 1)]);
+    }
+
+    #[test]
+    fn skip_unaligned_hex_regex_matches() {
+        let regex = HexRegex::parse("E8").unwrap();
+        assert!(regex.search(&encode_hex(&[0x1E, 0x8E, 0x80])).is_empty());
+    }
 }
@@ -0,0 +1,138 @@
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// Identifies a running process either by its id or by its executable name.
+#[derive(Debug, Clone)]
+pub enum ProcessRef {
+    Pid(u32),
+    Name(String),
+}
+
+impl FromStr for ProcessRef {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str.parse() {
+            Ok(pid) => Ok(Self::Pid(pid)),
+            Err(_) if !str.is_empty() => Ok(Self::Name(str.to_owned())),
+            Err(_) => Err("empty process name".to_owned()),
+        }
+    }
+}
+
+/// Returns the address at which `module` is loaded in a running process.
+pub fn find_module_base(process: &ProcessRef, module: &str) -> Result<u64> {
+    let pid = match process {
+        ProcessRef::Pid(pid) => *pid,
+        ProcessRef::Name(name) => {
+            imp::find_pid(name).ok_or_else(|| Error::ProcessNotFound(name.clone()))?
+        }
+    };
+    imp::find_module_base(pid, module).ok_or_else(|| Error::ModuleNotFound(module.to_owned(), pid))
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::mem;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, Process32FirstW, Process32NextW, MODULEENTRY32W, PROCESSENTRY32W, TH32CS_SNAPMODULE, TH32CS_SNAPMODULE32, TH32CS_SNAPPROCESS
+    };
+
+    pub fn find_pid(name: &str) -> Option<u32> {
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return None;
+            }
+            let mut entry: PROCESSENTRY32W = mem::zeroed();
+            entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+
+            let mut res = None;
+            let mut has_next = Process32FirstW(snapshot, &mut entry);
+            while has_next != 0 {
+                if from_wide(&entry.szExeFile).eq_ignore_ascii_case(name) {
+                    res = Some(entry.th32ProcessID);
+                    break;
+                }
+                has_next = Process32NextW(snapshot, &mut entry);
+            }
+            CloseHandle(snapshot);
+            res
+        }
+    }
+
+    pub fn find_module_base(pid: u32, module: &str) -> Option<u64> {
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, pid);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return None;
+            }
+            let mut entry: MODULEENTRY32W = mem::zeroed();
+            entry.dwSize = mem::size_of::<MODULEENTRY32W>() as u32;
+
+            let mut res = None;
+            let mut has_next = Module32FirstW(snapshot, &mut entry);
+            while has_next != 0 {
+                if from_wide(&entry.szModule).eq_ignore_ascii_case(module) {
+                    res = Some(entry.modBaseAddr as u64);
+                    break;
+                }
+                has_next = Module32NextW(snapshot, &mut entry);
+            }
+            CloseHandle(snapshot);
+            res
+        }
+    }
+
+    fn from_wide(str: &[u16]) -> String {
+        let len = str.iter().position(|c| *c == 0).unwrap_or(str.len());
+        String::from_utf16_lossy(&str[..len])
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs;
+    use std::path::Path;
+
+    pub fn find_pid(name: &str) -> Option<u32> {
+        fs::read_dir("/proc")
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .find(|pid: &u32| {
+                fs::read_link(format!("/proc/{pid}/exe"))
+                    .ok()
+                    .and_then(|path| Some(path.file_name()? == name))
+                    .unwrap_or(false)
+            })
+    }
+
+    pub fn find_module_base(pid: u32, module: &str) -> Option<u64> {
+        let maps = fs::read_to_string(format!("/proc/{pid}/maps")).ok()?;
+        maps.lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let range = parts.next()?;
+                let path = parts.nth(4)?;
+                if Path::new(path).file_name()? != module {
+                    return None;
+                }
+                u64::from_str_radix(range.split('-').next()?, 16).ok()
+            })
+            .min()
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod imp {
+    pub fn find_pid(_name: &str) -> Option<u32> {
+        None
+    }
+
+    pub fn find_module_base(_pid: u32, _module: &str) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::codegen;
+use crate::error::Result;
+use crate::symbols::FunctionSymbol;
+use crate::types::{Type, TypeInfo};
+
+/// The namespace of the symbols with names that aren't qualified.
+const GLOBAL_NAMESPACE: &str = "(global)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// Picks HTML for files with the `.html` or `.htm` extension and Markdown otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "html" || ext == "htm" => Self::Html,
+            _ => Self::Markdown,
+        }
+    }
+}
+
+/// Writes a human-readable report with a table of the symbols for each namespace, with their
+/// signatures, addresses and the locations of their specs, followed by an index of the types.
+pub fn write_report<W: Write>(
+    output: W,
+    symbols: &[FunctionSymbol],
+    types: &TypeInfo,
+    image_base: Option<u64>,
+    format: ReportFormat,
+) -> Result<()> {
+    let mut namespaces: BTreeMap<&str, Vec<&FunctionSymbol>> = BTreeMap::new();
+    for symbol in symbols {
+        let namespace = symbol
+            .name()
+            .rsplit_once("::")
+            .map_or(GLOBAL_NAMESPACE, |(namespace, _)| namespace);
+        namespaces.entry(namespace).or_default().push(symbol);
+    }
+
+    let mut writer = ReportWriter { output, format };
+    writer.begin()?;
+    writer.heading(1, "Symbols")?;
+    for (namespace, mut symbols) in namespaces {
+        symbols.sort_by(|a, b| a.name().cmp(b.name()));
+        let rows = symbols.iter().map(|symbol| {
+            let signature = match symbol.data_type() {
+                Some(typ) => typ.name().into_owned(),
+                None => codegen::signature(symbol.function_type()),
+            };
+            let address = image_base.unwrap_or_default() + symbol.rva();
+            vec![
+                Cell::Text(symbol.name().to_owned()),
+                Cell::Code(format!("0x{address:X}")),
+                Cell::Code(signature),
+                Cell::Text(symbol.location().map(ToString::to_string).unwrap_or_default()),
+            ]
+        });
+        writer.heading(2, namespace)?;
+        writer.table(&["Name", "Address", "Signature", "Source"], rows)?;
+    }
+
+    writer.heading(1, "Types")?;
+    let mut structs = vec![];
+    let mut unions = vec![];
+    let mut enums = vec![];
+    for typ in types.sorted_types() {
+        match typ {
+            Type::Struct(id) => {
+                let struct_ = &types.structs[&id];
+                structs.push(vec![
+                    Cell::Text(struct_.name.to_string()),
+                    Cell::Code(size(struct_.size)),
+                    Cell::Text(struct_.base.map(|base| base.to_string()).unwrap_or_default()),
+                    Cell::Text(struct_.members.len().to_string()),
+                ]);
+            }
+            Type::Union(id) => {
+                let union = &types.unions[&id];
+                unions.push(vec![
+                    Cell::Text(union.name.to_string()),
+                    Cell::Code(size(union.size)),
+                    Cell::Text(union.members.len().to_string()),
+                ]);
+            }
+            Type::Enum(id) => {
+                let enum_ = &types.enums[&id];
+                enums.push(vec![
+                    Cell::Text(enum_.name.to_string()),
+                    Cell::Code(size(enum_.size)),
+                    Cell::Text(enum_.members.len().to_string()),
+                ]);
+            }
+            _ => {}
+        }
+    }
+    if !structs.is_empty() {
+        writer.heading(2, "Structs")?;
+        writer.table(&["Name", "Size", "Base", "Members"], structs)?;
+    }
+    if !unions.is_empty() {
+        writer.heading(2, "Unions")?;
+        writer.table(&["Name", "Size", "Members"], unions)?;
+    }
+    if !enums.is_empty() {
+        writer.heading(2, "Enums")?;
+        writer.table(&["Name", "Size", "Members"], enums)?;
+    }
+    writer.end()
+}
+
+fn size(size: Option<usize>) -> String {
+    size.map(|size| format!("0x{size:X}")).unwrap_or_default()
+}
+
+enum Cell {
+    Text(String),
+    Code(String),
+}
+
+struct ReportWriter<W> {
+    output: W,
+    format: ReportFormat,
+}
+
+impl<W: Write> ReportWriter<W> {
+    fn begin(&mut self) -> Result<()> {
+        if self.format == ReportFormat::Html {
+            writeln!(self.output, "<!DOCTYPE html>")?;
+            writeln!(self.output, "<html>")?;
+            writeln!(
+                self.output,
+                "<head><meta charset=\"utf-8\"><title>Symbols</title></head>"
+            )?;
+            writeln!(self.output, "<body>")?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.format == ReportFormat::Html {
+            writeln!(self.output, "</body>")?;
+            writeln!(self.output, "</html>")?;
+        }
+        Ok(())
+    }
+
+    fn heading(&mut self, level: usize, text: &str) -> Result<()> {
+        match self.format {
+            ReportFormat::Markdown => {
+                writeln!(self.output, "{} {}", "#".repeat(level), text)?;
+                writeln!(self.output)?;
+            }
+            ReportFormat::Html => writeln!(self.output, "<h{level}>{}</h{level}>", escape_html(text))?,
+        }
+        Ok(())
+    }
+
+    fn table(&mut self, headers: &[&str], rows: impl IntoIterator<Item = Vec<Cell>>) -> Result<()> {
+        match self.format {
+            ReportFormat::Markdown => {
+                writeln!(self.output, "| {} |", headers.join(" | "))?;
+                writeln!(self.output, "|{}", "---|".repeat(headers.len()))?;
+                for row in rows {
+                    let cells: Vec<_> = row
+                        .iter()
+                        .map(|cell| match cell {
+                            Cell::Text(text) => text.replace('|', "\\|"),
+                            Cell::Code(code) => format!("`{}`", code.replace('|', "\\|")),
+                        })
+                        .collect();
+                    writeln!(self.output, "| {} |", cells.join(" | "))?;
+                }
+                writeln!(self.output)?;
+            }
+            ReportFormat::Html => {
+                writeln!(self.output, "<table>")?;
+                let headers: Vec<_> = headers
+                    .iter()
+                    .map(|header| format!("<th>{header}</th>"))
+                    .collect();
+                writeln!(self.output, "<tr>{}</tr>", headers.concat())?;
+                for row in rows {
+                    let cells: Vec<_> = row
+                        .iter()
+                        .map(|cell| match cell {
+                            Cell::Text(text) => format!("<td>{}</td>", escape_html(text)),
+                            Cell::Code(code) => format!("<td><code>{}</code></td>", escape_html(code)),
+                        })
+                        .collect();
+                    writeln!(self.output, "<tr>{}</tr>", cells.concat())?;
+                }
+                writeln!(self.output, "</table>")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::symbols::Confidence;
+    use crate::types::FunctionType;
+
+    fn symbols() -> Vec<FunctionSymbol> {
+        let typ = FunctionType::new(vec![Type::Int(true)], Type::Bool).into();
+        ["Game::Player::is_alive", "Game::Map<int>::get", "get_world"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                FunctionSymbol::new(
+                    name.into(),
+                    Arc::clone(&typ),
+                    0x1000 * (i as u64 + 1),
+                    Confidence::Unique,
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn write_markdown_report() {
+        let mut output = vec![];
+        write_report(
+            &mut output,
+            &symbols(),
+            &TypeInfo::default(),
+            None,
+            ReportFormat::Markdown,
+        )
+        .unwrap();
+        let report = String::from_utf8(output).unwrap();
+        assert!(report
+            .contains("## Game::Player\n\n| Name | Address | Signature | Source |\n|---|---|---|---|\n"));
+        assert!(report.contains("| Game::Player::is_alive | `0x1000` | `bool (int)` |  |\n"));
+        assert!(report.contains("## (global)\n"));
+    }
+
+    #[test]
+    fn write_html_report() {
+        let mut output = vec![];
+        write_report(
+            &mut output,
+            &symbols(),
+            &TypeInfo::default(),
+            None,
+            ReportFormat::Html,
+        )
+        .unwrap();
+        let report = String::from_utf8(output).unwrap();
+        assert!(report.contains("<h2>Game::Player</h2>"));
+        assert!(report.contains("<h2>Game::Map&lt;int&gt;</h2>"));
+        assert!(report.contains("<td><code>bool (int)</code></td>"));
+        assert!(report.ends_with("</html>\n"));
+    }
+}
@@ -0,0 +1,371 @@
+//! Types of the JSON outputs meant to be consumed by other tools, the JSON schemas describing them
+//! are in the `schema` directory of the crate. The outputs only change in a backward compatible way,
+//! new fields can be added, but existing fields are never removed or changed without bumping
+//! [`SCHEMA_VERSION`], so consumers should ignore fields they don't know about.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Version of the output schemas, written to every output in the `schema_version` field.
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub const SYMBOLS_SCHEMA: &str = include_str!("../schema/symbols.schema.json");
+pub const METRICS_SCHEMA: &str = include_str!("../schema/metrics.schema.json");
+pub const BACKEND_SCHEMA: &str = include_str!("../schema/backend.schema.json");
+pub const DIFF_SCHEMA: &str = include_str!("../schema/diff.schema.json");
+
+/// The symbol map written with `--json-output`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SymbolMap {
+    pub schema_version: u32,
+    /// Identifies the build of the executable, missing when it has none of the identifiers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<IdentityRecord>,
+    pub symbols: Vec<SymbolRecord>,
+    /// The layouts of the structs and unions, missing in files written by older versions.
+    #[serde(default)]
+    pub types: Vec<TypeRecord>,
+    /// The byte offset and the length of the record of each symbol in the file.
+    pub index: BTreeMap<String, (usize, usize)>,
+}
+
+/// What the executable records about its build, see [`crate::exe::BinaryIdentity`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityRecord {
+    /// The file version from the version resource of a PE executable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_version: Option<String>,
+    /// The GUID and the age of the PDB in the form used by symbol servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdb: Option<String>,
+    /// The GNU build ID of an ELF executable in hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolRecord {
+    pub name: String,
+    pub confidence: String,
+    /// The address relative to the image base, present unless absolute addresses were requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rva: Option<u64>,
+    /// The virtual address, present when absolute addresses were requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub va: Option<u64>,
+    /// The first instructions at the address, present when requested with `--preview`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<Vec<String>>,
+}
+
+impl SymbolRecord {
+    /// The address written to the record, either an RVA or a virtual address.
+    pub fn address(&self) -> Option<u64> {
+        self.rva.or(self.va)
+    }
+}
+
+/// The layout of a struct or a union.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeRecord {
+    pub name: String,
+    /// Either `struct` or `union`.
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    pub members: Vec<MemberRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    /// The offset in bytes, missing when the frontend doesn't report it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+/// The input written to the standard input of an external backend given with `--backend-output`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackendInput {
+    pub schema_version: u32,
+    /// The architecture of the executable, e.g. `x86_64`.
+    pub architecture: String,
+    /// The format of the executable, e.g. `pe` or `elf`.
+    pub format: String,
+    pub image_base: u64,
+    pub symbols: Vec<SymbolRecord>,
+}
+
+/// The differences between two symbol maps written by the `diff` command.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub schema_version: u32,
+    pub added: Vec<SymbolRecord>,
+    pub removed: Vec<SymbolRecord>,
+    pub moved: Vec<MovedSymbolRecord>,
+    pub type_changes: Vec<TypeChangeRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MovedSymbolRecord {
+    pub name: String,
+    pub old_address: u64,
+    pub new_address: u64,
+}
+
+/// A type that was added, removed or had its layout changed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TypeChangeRecord {
+    pub name: String,
+    /// The layout in the old map, missing for added types.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<TypeRecord>,
+    /// The layout in the new map, missing for removed types.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<TypeRecord>,
+}
+
+/// The metrics written with `--emit-metrics` in the JSON format.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricsReport {
+    pub schema_version: u32,
+    pub specs: usize,
+    pub resolved: usize,
+    pub failed: usize,
+    pub suppressed: usize,
+    pub resolve_rate: f64,
+    /// Duration of each phase in seconds.
+    pub phase_durations: BTreeMap<String, f64>,
+    /// Size of each output file in bytes.
+    pub output_sizes: BTreeMap<String, u64>,
+    /// Present when the statistics were requested with `--stats`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<StatsReport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsReport {
+    /// Number of failures caused by multiple matches.
+    pub ambiguous: usize,
+    /// Number of the other failures.
+    pub missed: usize,
+    /// The patterns with the most candidates, in descending order.
+    pub slowest_patterns: Vec<PatternCostRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PatternCostRecord {
+    pub name: String,
+    /// Length of the anchor the pattern was searched for with in bytes.
+    pub anchor_length: usize,
+    /// How many times the anchor was found and the rest of the pattern had to be checked.
+    pub candidates: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::codegen;
+    use crate::exe::BinaryIdentity;
+    use crate::symbols::{Confidence, FunctionSymbol};
+    use crate::types::{FunctionType, Type, TypeInfo};
+
+    /// Checks that the schema lists the fields of the value as its properties and that it requires
+    /// all of the fields except for the optional ones.
+    fn assert_schema_matches<T: Serialize>(schema: &Value, value: &T, optional: &[&str]) {
+        let value = serde_json::to_value(value).unwrap();
+
+        let mut properties: Vec<_> = schema["properties"].as_object().unwrap().keys().collect();
+        let mut fields: Vec<_> = value.as_object().unwrap().keys().collect();
+        properties.sort();
+        fields.sort();
+        assert_eq!(properties, fields);
+
+        let mut required: Vec<_> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field.as_str().unwrap())
+            .collect();
+        let mut expected: Vec<_> = fields
+            .into_iter()
+            .map(String::as_str)
+            .filter(|field| !optional.contains(field))
+            .collect();
+        required.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(required, expected);
+    }
+
+    #[test]
+    fn symbols_schema_is_up_to_date() {
+        let identity = IdentityRecord {
+            file_version: Some("1.52.0.3401".to_owned()),
+            pdb: Some("3844DBB920174967BE7AA4A2C20430FA2".to_owned()),
+            build_id: Some("8f3c2a".to_owned()),
+        };
+        let map = SymbolMap {
+            schema_version: SCHEMA_VERSION,
+            identity: Some(identity.clone()),
+            symbols: vec![],
+            types: vec![],
+            index: BTreeMap::new(),
+        };
+        let schema: Value = serde_json::from_str(SYMBOLS_SCHEMA).unwrap();
+        assert_schema_matches(&schema, &map, &["identity", "types"]);
+        assert_schema_matches(
+            &schema["$defs"]["identity"],
+            &identity,
+            &["file_version", "pdb", "build_id"],
+        );
+
+        let record = SymbolRecord {
+            name: "get_player".to_owned(),
+            confidence: "unique".to_owned(),
+            rva: Some(0x1000),
+            va: Some(0x140001000),
+            preview: Some(vec!["140001000  ret".to_owned()]),
+        };
+        assert_schema_matches(&schema["$defs"]["symbol"], &record, &["rva", "va", "preview"]);
+
+        let member = MemberRecord {
+            name: "health".to_owned(),
+            typ: "int".to_owned(),
+            offset: Some(8),
+        };
+        let record = TypeRecord {
+            name: "Player".to_owned(),
+            kind: "struct".to_owned(),
+            size: Some(16),
+            members: vec![member.clone()],
+        };
+        assert_schema_matches(&schema["$defs"]["type"], &record, &["size"]);
+        assert_schema_matches(&schema["$defs"]["member"], &member, &["offset"]);
+    }
+
+    #[test]
+    fn diff_schema_is_up_to_date() {
+        let report = DiffReport {
+            schema_version: SCHEMA_VERSION,
+            added: vec![],
+            removed: vec![],
+            moved: vec![],
+            type_changes: vec![],
+        };
+        let schema: Value = serde_json::from_str(DIFF_SCHEMA).unwrap();
+        assert_schema_matches(&schema, &report, &[]);
+
+        let moved = MovedSymbolRecord {
+            name: "get_player".to_owned(),
+            old_address: 0x1000,
+            new_address: 0x1010,
+        };
+        assert_schema_matches(&schema["$defs"]["moved_symbol"], &moved, &[]);
+        let change = TypeChangeRecord {
+            name: "Player".to_owned(),
+            old: Some(TypeRecord {
+                name: "Player".to_owned(),
+                kind: "struct".to_owned(),
+                size: None,
+                members: vec![],
+            }),
+            new: None,
+        };
+        let mut value = serde_json::to_value(&change).unwrap();
+        value["new"] = value["old"].clone();
+        assert_schema_matches(&schema["$defs"]["type_change"], &value, &["old", "new"]);
+    }
+
+    #[test]
+    fn metrics_schema_is_up_to_date() {
+        let report = MetricsReport {
+            schema_version: SCHEMA_VERSION,
+            specs: 0,
+            resolved: 0,
+            failed: 0,
+            suppressed: 0,
+            resolve_rate: 1.,
+            phase_durations: BTreeMap::new(),
+            output_sizes: BTreeMap::new(),
+            stats: Some(StatsReport {
+                ambiguous: 0,
+                missed: 0,
+                slowest_patterns: vec![],
+            }),
+        };
+        let schema: Value = serde_json::from_str(METRICS_SCHEMA).unwrap();
+        assert_schema_matches(&schema, &report, &["stats"]);
+
+        let stats = report.stats.unwrap();
+        assert_schema_matches(&schema["$defs"]["stats"], &stats, &[]);
+        let pattern = PatternCostRecord {
+            name: "get_player".to_owned(),
+            anchor_length: 4,
+            candidates: 1,
+        };
+        assert_schema_matches(&schema["$defs"]["pattern_cost"], &pattern, &[]);
+    }
+
+    #[test]
+    fn backend_schema_is_up_to_date() {
+        let input = BackendInput {
+            schema_version: SCHEMA_VERSION,
+            architecture: "x86_64".to_owned(),
+            format: "pe".to_owned(),
+            image_base: 0x140000000,
+            symbols: vec![],
+        };
+        let schema: Value = serde_json::from_str(BACKEND_SCHEMA).unwrap();
+        assert_schema_matches(&schema, &input, &[]);
+    }
+
+    #[test]
+    fn read_written_symbol_map() {
+        let typ = FunctionType::new(vec![], Type::Void).into();
+        let symbols = [FunctionSymbol::new(
+            "get_player".into(),
+            typ,
+            0x1000,
+            Confidence::Unique,
+            None,
+        )];
+        let mut output = vec![];
+        let identity = BinaryIdentity {
+            build_id: Some(vec![0x8F, 0x3C, 0x2A]),
+            ..BinaryIdentity::default()
+        };
+        codegen::write_json(
+            &mut output,
+            &symbols,
+            &TypeInfo::default(),
+            None,
+            &HashMap::new(),
+            &identity,
+        )
+        .unwrap();
+
+        let map: SymbolMap = serde_json::from_slice(&output).unwrap();
+        assert_eq!(map.schema_version, SCHEMA_VERSION);
+        assert_eq!(map.identity.unwrap().build_id.as_deref(), Some("8f3c2a"));
+        assert_eq!(map.symbols[0].name, "get_player");
+        assert_eq!(map.symbols[0].rva, Some(0x1000));
+        let (offset, len) = map.index["get_player"];
+        let record: SymbolRecord = serde_json::from_slice(&output[offset..offset + len]).unwrap();
+        assert_eq!(record.confidence, "unique");
+    }
+
+    #[test]
+    fn schemas_have_current_version() {
+        for schema in [SYMBOLS_SCHEMA, METRICS_SCHEMA, BACKEND_SCHEMA, DIFF_SCHEMA] {
+            let schema: Value = serde_json::from_str(schema).unwrap();
+            let version = &schema["properties"]["schema_version"]["maximum"];
+            assert_eq!(version.as_u64(), Some(SCHEMA_VERSION.into()));
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+
+use ustr::Ustr;
+
+use crate::cache::PatternCache;
+use crate::error::{Result, SymbolError};
+use crate::exe::{ExeProperties, ExecutableData};
+use crate::layout::Layout;
+use crate::spec::FunctionSpec;
+use crate::symbols::{self, AmbiguityStrategy, FunctionSymbol, PatternStats};
+
+/// An executable that is loaded once and then queried repeatedly, for long-lived tools like GUIs.
+/// Specs can be resolved in multiple batches, `@xref-to` can refer to symbols from the earlier ones
+/// and the matches of patterns are cached for the lifetime of the session.
+///
+/// A session is `Send` and `Sync`, so it can be shared between threads behind a lock.
+pub struct Session<'a> {
+    data: ExecutableData<'a>,
+    props: ExeProperties,
+    strategy: AmbiguityStrategy,
+    cache: PatternCache,
+    /// In the order they were resolved in.
+    symbols: Vec<FunctionSymbol>,
+    by_name: HashMap<Ustr, usize>,
+    /// Indices of the symbols sorted by their RVA.
+    by_address: Vec<usize>,
+    pattern_stats: Vec<PatternStats>,
+}
+
+impl<'a> Session<'a> {
+    /// Loads an executable, the layout describes its sections when it doesn't have a section table.
+    pub fn new(exe_bytes: &'a [u8], layout: Option<&Layout>) -> Result<Self> {
+        let (data, props) = match layout {
+            Some(layout) => (
+                ExecutableData::from_layout(exe_bytes, layout)?,
+                ExeProperties::from_layout(layout),
+            ),
+            None => {
+                let exe = object::read::File::parse(exe_bytes)?;
+                (ExecutableData::new(&exe)?, ExeProperties::from_object(&exe))
+            }
+        };
+        let res = Self {
+            data,
+            props,
+            strategy: AmbiguityStrategy::Fail,
+            cache: PatternCache::default(),
+            symbols: vec![],
+            by_name: HashMap::new(),
+            by_address: vec![],
+            pattern_stats: vec![],
+        };
+        Ok(res)
+    }
+
+    pub fn with_ambiguity_strategy(self, strategy: AmbiguityStrategy) -> Self {
+        Self { strategy, ..self }
+    }
+
+    /// Replaces the pattern cache, for instance with one loaded from a file.
+    pub fn with_cache(self, cache: PatternCache) -> Self {
+        Self { cache, ..self }
+    }
+
+    /// Takes the pattern cache out of the session, leaving an empty one in its place.
+    pub fn take_cache(&mut self) -> PatternCache {
+        std::mem::take(&mut self.cache)
+    }
+
+    pub fn data(&self) -> &ExecutableData<'a> {
+        &self.data
+    }
+
+    pub fn properties(&self) -> &ExeProperties {
+        &self.props
+    }
+
+    /// Resolves another batch of specs and returns the errors of the batch. Symbols resolved
+    /// before are replaced by the specs with the same name, even when they fail to resolve.
+    pub fn resolve(&mut self, specs: Vec<FunctionSpec>) -> Result<Vec<SymbolError>> {
+        let names: HashSet<Ustr> = specs.iter().map(|spec| spec.name).collect();
+        self.symbols
+            .retain(|sym| !names.contains(&Ustr::from(sym.name())));
+
+        self.pattern_stats.clear();
+        let (syms, errors) = symbols::resolve_in_exe(
+            specs,
+            &self.data,
+            self.strategy,
+            Some(&mut self.cache),
+            &self.symbols,
+            Some(&mut self.pattern_stats),
+        )?;
+        self.symbols.extend(syms);
+        self.reindex();
+        Ok(errors)
+    }
+
+    /// Returns the cost of the patterns of the last batch, patterns with matches taken from
+    /// the cache are not included.
+    pub fn pattern_stats(&self) -> &[PatternStats] {
+        &self.pattern_stats
+    }
+
+    /// Returns all of the symbols in the order they were resolved in.
+    pub fn symbols(&self) -> &[FunctionSymbol] {
+        &self.symbols
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&FunctionSymbol> {
+        self.by_name.get(&Ustr::from(name)).map(|i| &self.symbols[*i])
+    }
+
+    /// Returns the symbol at an RVA, or the symbol of the function containing it when the function
+    /// bounds are known from the unwind tables.
+    pub fn symbol_at(&self, rva: u64) -> Option<&FunctionSymbol> {
+        self.symbol_starting_at(rva).or_else(|| {
+            let start = self.data.function_containing(rva)?;
+            self.symbol_starting_at(start)
+        })
+    }
+
+    fn symbol_starting_at(&self, rva: u64) -> Option<&FunctionSymbol> {
+        let pos = self.by_address.partition_point(|i| self.symbols[*i].rva() < rva);
+        let i = self.by_address.get(pos)?;
+        let sym = &self.symbols[*i];
+        (sym.rva() == rva).then_some(sym)
+    }
+
+    fn reindex(&mut self) {
+        self.by_name = self
+            .symbols
+            .iter()
+            .enumerate()
+            .map(|(i, sym)| (Ustr::from(sym.name()), i))
+            .collect();
+        self.by_address = (0..self.symbols.len()).collect();
+        self.by_address.sort_by_key(|i| self.symbols[*i].rva());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Session<'static>>();
+    }
+}
@@ -1,62 +1,222 @@
 use std::collections::HashMap;
-use std::ops::Deref;
-use std::rc::Rc;
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use ustr::Ustr;
 
 use crate::error::{Error, ParamError, Result};
 use crate::eval::Expr;
-use crate::patterns::Pattern;
-use crate::types::FunctionType;
+use crate::patterns::{HexRegex, Pattern};
+use crate::types::{FunctionType, Type};
+use crate::version::VersionReq;
+use crate::x86::InsnPattern;
+use crate::xrefs;
 
 #[derive(Debug)]
 pub struct FunctionSpec {
     pub name: Ustr,
-    pub function_type: Rc<FunctionType>,
-    pub pattern: Pattern,
-    pub offset: Option<i64>,
+    pub function_type: Arc<FunctionType>,
+    /// The type of the data at the match, set for specs of typedefs of anything other than functions.
+    pub data_type: Option<Type>,
+    /// The section searched instead of the code section, only for specs of data.
+    pub section: Option<String>,
+    /// The concrete patterns of `@pattern`, there's one for each combination of its alternatives
+    /// and none when it's not given.
+    pub patterns: Vec<Pattern>,
+    pub regex: Option<HexRegex>,
+    /// A string referenced by the function.
+    pub string: Option<String>,
+    /// A symbol called by the function.
+    pub xref_to: Option<Ustr>,
+    /// Instructions expected at each of the matches.
+    pub x86: Option<InsnPattern>,
+    /// Whether the matches are moved back to the start of the function containing them.
+    pub align_to_prologue: bool,
+    /// The only offset from the start of the code section a match can be at.
+    pub section_offset: Option<u64>,
+    /// Whether the matches have to be at the start of a function.
+    pub function_start: bool,
+    /// The virtual addresses the matches have to be in.
+    pub range: Option<Range<u64>>,
+    pub offset: Option<Offset>,
     pub eval: Option<Expr>,
-    pub nth_entry_of: Option<(usize, usize)>,
+    /// The size of the function, evaluated like an `@offset` expression.
+    pub size: Option<Expr>,
+    pub nth_entry_of: Option<NthEntry>,
+    pub expect: Option<u64>,
+    /// Warnings that are not reported for this spec.
+    pub allow: Vec<Warning>,
+    /// Additional names of the symbol.
+    pub aliases: Vec<Ustr>,
+    /// Names of the groups the spec belongs to, used to select a subset of the specs.
+    pub groups: Vec<String>,
+    /// The versions of the executable the spec applies to, it's skipped for the others.
+    pub version: Option<VersionReq>,
+    pub location: Option<SourceLocation>,
 }
 
 impl FunctionSpec {
-    pub fn new<'a, I>(name: Ustr, function_type: Rc<FunctionType>, comments: I) -> Option<Result<Self>>
+    /// Creates a spec from the comments of a typedef, the location of the typedef is included in errors.
+    pub fn new<'a, I>(
+        name: Ustr,
+        function_type: Arc<FunctionType>,
+        comments: I,
+        location: Option<SourceLocation>,
+    ) -> Option<Result<Self>>
     where
         I: IntoIterator<Item = &'a str>,
     {
-        let mut params = HashMap::new();
-        for comment in comments {
-            if let Some((key, val)) = parse_typedef_comment(comment) {
-                params.insert(key, val);
-            }
-        }
+        let params = parse_typedef_comments(comments);
         if params.is_empty() {
             None
         } else {
-            let spec = Self::from_params(name, function_type, params)
-                .map_err(|err| Error::TypedefParamError(name, err));
+            let spec = match Self::from_params(name, function_type, params) {
+                Ok(spec) if spec.section.is_some() => Err(ParamError::InvalidParam(
+                    "section",
+                    "only supported for typedefs of data".to_owned(),
+                )),
+                res => res,
+            };
+            let spec = match spec {
+                Ok(spec) => Ok(Self { location, ..spec }),
+                Err(err) => Err(Error::TypedefParamError(name, location, err)),
+            };
             Some(spec)
         }
     }
 
+    /// Creates a spec for the data in one of the sections of the executable from the comments
+    /// of a typedef of a non-function type. Only the typedefs with a `@section` parameter are specs.
+    pub fn new_data<'a, I>(
+        name: Ustr,
+        data_type: Type,
+        comments: I,
+        location: Option<SourceLocation>,
+    ) -> Option<Result<Self>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let params = parse_typedef_comments(comments);
+        if !params.contains_key("section") {
+            return None;
+        }
+        // data has no signature, the function type is only a placeholder
+        let function_type = Arc::new(FunctionType::new(vec![], Type::Void));
+        let spec = match Self::from_params(name, function_type, params) {
+            Ok(spec) => Ok(Self {
+                data_type: Some(data_type),
+                location,
+                ..spec
+            }),
+            Err(err) => Err(Error::TypedefParamError(name, location, err)),
+        };
+        Some(spec)
+    }
+
     fn from_params(
         name: Ustr,
-        function_type: Rc<FunctionType>,
+        function_type: Arc<FunctionType>,
         mut params: HashMap<&str, &str>,
     ) -> Result<Self, ParamError> {
-        let pattern = Pattern::parse(params.remove("pattern").ok_or(ParamError::MissingPattern)?)
+        let patterns = params
+            .remove("pattern")
+            .map(Pattern::parse_variants)
+            .transpose()
             .map_err(|err| ParamError::ParseError("pattern", err))?;
-        let offset = params
-            .remove("offset")
-            .map(|str| parse_from_str(str, "offset"))
-            .transpose()?;
+        let regex = params
+            .remove("regex")
+            .map(HexRegex::parse)
+            .transpose()
+            .map_err(|err| ParamError::InvalidParam("regex", err.to_string()))?;
+        let string = params.remove("string").map(parse_string).transpose()?;
+        let xref_to = params.remove("xref-to").map(Ustr::from);
+        let x86 = params
+            .remove("x86")
+            .map(InsnPattern::parse)
+            .transpose()
+            .map_err(|err| ParamError::ParseError("x86", err))?;
+        match (&patterns, &regex) {
+            (None, None) if string.is_none() && xref_to.is_none() => {
+                return Err(ParamError::MissingPattern)
+            }
+            (Some(_), Some(_)) => {
+                return Err(ParamError::InvalidParam(
+                    "regex",
+                    "cannot be combined with 'pattern'".to_owned(),
+                ))
+            }
+            _ => {}
+        }
+        let offset = params.remove("offset").map(Offset::parse).transpose()?;
         let eval = params
             .remove("eval")
             .map(Expr::parse)
             .transpose()
             .map_err(|err| ParamError::ParseError("eval", err))?;
+        let size = params
+            .remove("size")
+            .map(Expr::parse)
+            .transpose()
+            .map_err(|err| ParamError::ParseError("size", err))?;
+        let align_to_prologue = params
+            .remove("align-to-prologue")
+            .map(|str| parse_flag(str, "align-to-prologue"))
+            .transpose()?
+            .is_some();
+        let section_offset = params
+            .remove("section-offset")
+            .map(|str| parse_address(str, "section-offset"))
+            .transpose()?;
+        let function_start = params
+            .remove("function-start")
+            .map(|str| parse_flag(str, "function-start"))
+            .transpose()?
+            .is_some();
+        let range = params.remove("range").map(parse_range).transpose()?;
+        let section = params.remove("section").map(parse_section).transpose()?;
+        if align_to_prologue && (offset.is_some() || eval.is_some()) {
+            return Err(ParamError::InvalidParam(
+                "align-to-prologue",
+                "cannot be combined with 'offset' or 'eval'".to_owned(),
+            ));
+        }
+        if section.is_some() {
+            let code_only = [
+                ("regex", regex.is_some()),
+                ("string", string.is_some()),
+                ("xref-to", xref_to.is_some()),
+                ("x86", x86.is_some()),
+                ("align-to-prologue", align_to_prologue),
+                ("function-start", function_start),
+                ("eval", eval.is_some()),
+                ("size", size.is_some()),
+                ("offset", matches!(offset, Some(Offset::Expr(_)))),
+            ];
+            if let Some((field, _)) = code_only.iter().find(|(_, is_set)| *is_set) {
+                return Err(ParamError::InvalidParam(
+                    *field,
+                    "cannot be combined with 'section'".to_owned(),
+                ));
+            }
+        }
         let nth_entry_of = params.remove("nth").map(parse_index_specifier).transpose()?;
+        let expect = params
+            .remove("expect")
+            .map(|str| parse_address(str, "expect"))
+            .transpose()?;
+        let allow = params.remove("allow").map(parse_warnings).transpose()?;
+        let aliases = params.remove("alias").map(parse_aliases);
+        let groups = params.remove("group").map(parse_groups).transpose()?;
+        let version = params
+            .remove("version")
+            .map(|str| {
+                str.parse()
+                    .map_err(|err| ParamError::InvalidParam("version", err))
+            })
+            .transpose()?;
         if let Some(str) = params.keys().next() {
             return Err(ParamError::UnknownParam(str.deref().to_owned()));
         }
@@ -64,33 +224,214 @@ impl FunctionSpec {
         Ok(Self {
             name,
             function_type,
-            pattern,
+            data_type: None,
+            section,
+            patterns: patterns.unwrap_or_default(),
+            regex,
+            string,
+            xref_to,
+            x86,
+            align_to_prologue,
+            section_offset,
+            function_start,
+            range,
             offset,
             eval,
+            size,
             nth_entry_of,
+            expect,
+            allow: allow.unwrap_or_default(),
+            aliases: aliases.unwrap_or_default(),
+            groups: groups.unwrap_or_default(),
+            version,
+            location: None,
         })
     }
 }
 
+impl FunctionSpec {
+    /// Whether the spec passes the `--only-group` and `--exclude-group` filters. Specs without
+    /// a group are only left out when some groups are selected.
+    pub fn in_groups(&self, only: &[String], exclude: &[String]) -> bool {
+        (only.is_empty() || self.groups.iter().any(|group| only.contains(group)))
+            && !self.groups.iter().any(|group| exclude.contains(group))
+    }
+}
+
+/// The file and the 1-based line of a declaration in the sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+#[derive(Debug)]
+pub enum Offset {
+    /// A number of bytes between the symbol and the start of the match.
+    Fixed(i64),
+    /// An expression evaluated in the same way as `@eval`, except that integers are byte counts.
+    Expr(Expr),
+}
+
+impl Offset {
+    fn parse(str: &str) -> Result<Self, ParamError> {
+        match str.parse() {
+            Ok(offset) => Ok(Offset::Fixed(offset)),
+            Err(_) => Expr::parse(str)
+                .map(Offset::Expr)
+                .map_err(|err| ParamError::ParseError("offset", err)),
+        }
+    }
+}
+
+/// Selects one of multiple matches of a pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NthEntry {
+    /// The n-th match out of an exact total number of matches.
+    Exact(usize, usize),
+    /// The n-th match regardless of the total number of matches.
+    Index(usize),
+    First,
+    Last,
+    /// The only match whose bytes differ from all the others.
+    UniqueDifferent,
+}
+
+/// A kind of resolution failure that can be suppressed with `@allow`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning {
+    /// The pattern has multiple matches and none of them could be selected.
+    Ambiguous,
+    /// The pattern has no matches.
+    NoMatch,
+    /// The symbol has the same address as another one.
+    Collision,
+}
+
+impl FromStr for Warning {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "ambiguous" => Ok(Self::Ambiguous),
+            "no-match" => Ok(Self::NoMatch),
+            "collision" => Ok(Self::Collision),
+            other => Err(format!("unknown warning '{other}'")),
+        }
+    }
+}
+
+fn parse_typedef_comments<'a, I>(comments: I) -> HashMap<&'a str, &'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    comments.into_iter().filter_map(parse_typedef_comment).collect()
+}
+
 fn parse_typedef_comment(line: &str) -> Option<(&str, &str)> {
-    let (key, val) = line
+    let param = line
         .trim_start()
         .strip_prefix("///")?
         .trim_start()
-        .strip_prefix('@')?
-        .split_once(' ')?;
+        .strip_prefix('@')?;
+    // flags have no value
+    let (key, val) = param.split_once(' ').unwrap_or((param.trim_end(), ""));
 
     Some((key, val.trim()))
 }
 
-fn parse_index_specifier(str: &str) -> Result<(usize, usize), ParamError> {
-    let (n, max) = str
-        .split_once('/')
-        .ok_or_else(|| ParamError::InvalidParam("nth", "invalid format".to_string()))?;
-    Ok((
-        parse_from_str(n.trim(), "nth")?,
-        parse_from_str(max.trim(), "nth")?,
-    ))
+fn parse_index_specifier(str: &str) -> Result<NthEntry, ParamError> {
+    match str.split_once('/') {
+        Some((n, max)) => Ok(NthEntry::Exact(
+            parse_from_str(n.trim(), "nth")?,
+            parse_from_str(max.trim(), "nth")?,
+        )),
+        None if str == "first" => Ok(NthEntry::First),
+        None if str == "last" => Ok(NthEntry::Last),
+        None if str == "unique-different" => Ok(NthEntry::UniqueDifferent),
+        None => Ok(NthEntry::Index(parse_from_str(str, "nth")?)),
+    }
+}
+
+fn parse_address(str: &str, field: &'static str) -> Result<u64, ParamError> {
+    match str.strip_prefix("0x") {
+        Some(hex) => {
+            u64::from_str_radix(hex, 16).map_err(|err| ParamError::InvalidParam(field, err.to_string()))
+        }
+        None => parse_from_str(str, field),
+    }
+}
+
+fn parse_range(str: &str) -> Result<Range<u64>, ParamError> {
+    let (start, end) = str.split_once('-').ok_or_else(|| {
+        ParamError::InvalidParam("range", "expected two addresses like 'start-end'".to_owned())
+    })?;
+    let range = parse_address(start.trim(), "range")?..parse_address(end.trim(), "range")?;
+    if range.is_empty() {
+        return Err(ParamError::InvalidParam("range", "the range is empty".to_owned()));
+    }
+    Ok(range)
+}
+
+fn parse_section(str: &str) -> Result<String, ParamError> {
+    match str {
+        "" => Err(ParamError::InvalidParam(
+            "section",
+            "expected a section name".to_owned(),
+        )),
+        name => Ok(name.to_owned()),
+    }
+}
+
+fn parse_warnings(str: &str) -> Result<Vec<Warning>, ParamError> {
+    str.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|str| !str.is_empty())
+        .map(|str| str.parse().map_err(|err| ParamError::InvalidParam("allow", err)))
+        .collect()
+}
+
+fn parse_string(str: &str) -> Result<String, ParamError> {
+    str.strip_prefix('"')
+        .and_then(|str| str.strip_suffix('"'))
+        .filter(|str| !str.is_empty())
+        .map(xrefs::unescape)
+        .ok_or_else(|| ParamError::InvalidParam("string", "expected a non-empty quoted string".to_owned()))
+}
+
+fn parse_flag(str: &str, field: &'static str) -> Result<(), ParamError> {
+    if str.is_empty() {
+        Ok(())
+    } else {
+        Err(ParamError::InvalidParam(field, "expected no value".to_owned()))
+    }
+}
+
+fn parse_aliases(str: &str) -> Vec<Ustr> {
+    str.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|str| !str.is_empty())
+        .map(Ustr::from)
+        .collect()
+}
+
+fn parse_groups(str: &str) -> Result<Vec<String>, ParamError> {
+    let groups: Vec<_> = str
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|str| !str.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if groups.is_empty() {
+        return Err(ParamError::InvalidParam(
+            "group",
+            "expected a group name".to_owned(),
+        ));
+    }
+    Ok(groups)
 }
 
 fn parse_from_str<F: FromStr>(str: &str, field: &'static str) -> Result<F, ParamError>
@@ -118,16 +459,205 @@ mod tests {
             "/// @offset 13",
             "/// @eval fn",
         ];
-        let spec = FunctionSpec::new("test".into(), function_type.into(), comment.into_iter());
+        let spec = FunctionSpec::new("test".into(), function_type.into(), comment.into_iter(), None);
 
         assert_matches!(
             spec,
             Some(Ok(FunctionSpec {
-                nth_entry_of: Some((5, 24)),
-                offset: Some(13),
+                nth_entry_of: Some(NthEntry::Exact(5, 24)),
+                offset: Some(Offset::Fixed(13)),
                 eval: Some(Expr::Ident(_)),
                 ..
             }))
         )
     }
+
+    #[test]
+    fn report_spec_location() {
+        let function_type = FunctionType::new(vec![], Type::Void);
+        let comment = ["/// @pattern E8 ?? ?? ?? ??", "/// @nth fifth"];
+        let location = SourceLocation {
+            file: "types.hpp".into(),
+            line: 42,
+        };
+        let spec = FunctionSpec::new(
+            "test".into(),
+            function_type.into(),
+            comment.into_iter(),
+            Some(location),
+        );
+
+        let err = spec.unwrap().unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("types.hpp:42: invalid parameter in 'test'"));
+    }
+
+    #[test]
+    fn parse_flag_params() {
+        let function_type = Arc::new(FunctionType::new(vec![], Type::Void));
+        let comment = ["/// @pattern 48 8B 0D", "/// @align-to-prologue"];
+        let spec = FunctionSpec::new("test".into(), function_type.clone(), comment.into_iter(), None);
+        assert_matches!(
+            spec,
+            Some(Ok(FunctionSpec {
+                align_to_prologue: true,
+                ..
+            }))
+        );
+
+        let comment = ["/// @pattern 48 8B 0D", "/// @align-to-prologue yes"];
+        let spec = FunctionSpec::new("test".into(), function_type.clone(), comment.into_iter(), None);
+        assert_matches!(spec, Some(Err(_)));
+
+        let comment = ["/// @pattern 48 8B 0D", "/// @align-to-prologue", "/// @offset 4"];
+        let spec = FunctionSpec::new("test".into(), function_type, comment.into_iter(), None);
+        assert_matches!(spec, Some(Err(_)));
+    }
+
+    #[test]
+    fn parse_nth_forms() {
+        assert_matches!(parse_index_specifier("5 / 24"), Ok(NthEntry::Exact(5, 24)));
+        assert_matches!(parse_index_specifier("5"), Ok(NthEntry::Index(5)));
+        assert_matches!(parse_index_specifier("first"), Ok(NthEntry::First));
+        assert_matches!(parse_index_specifier("last"), Ok(NthEntry::Last));
+        assert_matches!(
+            parse_index_specifier("unique-different"),
+            Ok(NthEntry::UniqueDifferent)
+        );
+        assert_matches!(
+            parse_index_specifier("fifth"),
+            Err(ParamError::InvalidParam("nth", _))
+        );
+    }
+
+    #[test]
+    fn parse_expected_address() {
+        assert_matches!(parse_address("0x40B820", "expect"), Ok(0x40B820));
+        assert_matches!(parse_address("4241440", "expect"), Ok(0x40B820));
+        assert_matches!(
+            parse_address("0xZZ", "expect"),
+            Err(ParamError::InvalidParam("expect", _))
+        );
+    }
+
+    #[test]
+    fn parse_address_ranges() {
+        assert_eq!(
+            parse_range("0x140100000-0x140200000").unwrap(),
+            0x140100000..0x140200000
+        );
+        assert_eq!(parse_range("0x1000 - 8192").unwrap(), 0x1000..0x2000);
+        assert_matches!(
+            parse_range("0x2000-0x1000"),
+            Err(ParamError::InvalidParam("range", _))
+        );
+        assert_matches!(parse_range("0x2000"), Err(ParamError::InvalidParam("range", _)));
+    }
+
+    #[test]
+    fn parse_allowed_warnings() {
+        assert_matches!(parse_warnings("ambiguous"), Ok(warnings) if warnings == [Warning::Ambiguous]);
+        assert_matches!(
+            parse_warnings("ambiguous, no-match"),
+            Ok(warnings) if warnings == [Warning::Ambiguous, Warning::NoMatch]
+        );
+        assert_matches!(parse_warnings("slow"), Err(ParamError::InvalidParam("allow", _)));
+    }
+
+    #[test]
+    fn parse_spec_aliases() {
+        let function_type = FunctionType::new(vec![], Type::Void);
+        let comment = [
+            "/// @pattern E8 ?? ?? ?? ??",
+            "/// @alias GetPlayer, get_local_player",
+        ];
+        let spec = FunctionSpec::new("test".into(), function_type.into(), comment.into_iter(), None);
+
+        assert_matches!(
+            spec,
+            Some(Ok(FunctionSpec { aliases, .. })) if aliases == [Ustr::from("GetPlayer"), Ustr::from("get_local_player")]
+        )
+    }
+
+    #[test]
+    fn select_spec_groups() {
+        let function_type = Arc::new(FunctionType::new(vec![], Type::Void));
+        let comment = ["/// @pattern E8 ?? ?? ?? ??", "/// @group scripting, ui"];
+        let spec = FunctionSpec::new("test".into(), function_type.clone(), comment.into_iter(), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(spec.groups, ["scripting", "ui"]);
+
+        let groups = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert!(spec.in_groups(&[], &[]));
+        assert!(spec.in_groups(&groups(&["ui"]), &[]));
+        assert!(!spec.in_groups(&groups(&["physics"]), &[]));
+        assert!(!spec.in_groups(&[], &groups(&["scripting"])));
+
+        let comment = ["/// @pattern E8 ?? ?? ?? ??", "/// @group"];
+        let spec = FunctionSpec::new("test".into(), function_type, comment.into_iter(), None);
+        assert_matches!(spec, Some(Err(_)));
+    }
+
+    #[test]
+    fn parse_version_requirement() {
+        let function_type = Arc::new(FunctionType::new(vec![], Type::Void));
+        let comment = ["/// @pattern E8 ?? ?? ?? ??", "/// @version >=1.52 <2.0"];
+        let spec = FunctionSpec::new("test".into(), function_type.clone(), comment.into_iter(), None);
+        assert_matches!(
+            spec,
+            Some(Ok(FunctionSpec { version: Some(req), .. })) if req.to_string() == ">=1.52 <2.0"
+        );
+
+        let comment = ["/// @pattern E8 ?? ?? ?? ??", "/// @version >=next"];
+        let spec = FunctionSpec::new("test".into(), function_type, comment.into_iter(), None);
+        assert_matches!(spec, Some(Err(_)));
+    }
+
+    #[test]
+    fn parse_data_spec() {
+        let comment = [
+            "/// @pattern 1F 8B 08 00",
+            "/// @section .rdata",
+            "/// @offset -4",
+        ];
+        let spec = FunctionSpec::new_data("GZIP_MAGIC".into(), Type::Void, comment.into_iter(), None);
+        assert_matches!(
+            spec,
+            Some(Ok(FunctionSpec {
+                data_type: Some(Type::Void),
+                section: Some(section),
+                ..
+            })) if section == ".rdata"
+        );
+
+        let comment = ["/// @pattern 1F 8B 08 00"];
+        let spec = FunctionSpec::new_data("GZIP_MAGIC".into(), Type::Void, comment.into_iter(), None);
+        assert_matches!(spec, None);
+
+        let comment = ["/// @pattern 1F 8B 08 00", "/// @section .rdata", "/// @x86 mov"];
+        let spec = FunctionSpec::new_data("GZIP_MAGIC".into(), Type::Void, comment.into_iter(), None);
+        assert_matches!(spec, Some(Err(_)));
+
+        let function_type = FunctionType::new(vec![], Type::Void);
+        let comment = ["/// @pattern 1F 8B 08 00", "/// @section .rdata"];
+        let spec = FunctionSpec::new("test".into(), function_type.into(), comment.into_iter(), None);
+        assert_matches!(spec, Some(Err(_)));
+    }
+
+    #[test]
+    fn parse_offset_expr() {
+        let function_type = FunctionType::new(vec![], Type::Void);
+        let comment = ["/// @pattern E8 (fn:rel) 45 8B 86", "/// @offset fn + 0x10"];
+        let spec = FunctionSpec::new("test".into(), function_type.into(), comment.into_iter(), None);
+
+        assert_matches!(
+            spec,
+            Some(Ok(FunctionSpec {
+                offset: Some(Offset::Expr(Expr::Add(_, _))),
+                ..
+            }))
+        )
+    }
 }
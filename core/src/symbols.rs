@@ -1,74 +1,772 @@
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use ustr::Ustr;
 
+use crate::cache::PatternCache;
 use crate::error::{Result, SymbolError};
 use crate::eval::EvalContext;
 use crate::exe::ExecutableData;
-use crate::patterns;
-use crate::spec::FunctionSpec;
-use crate::types::FunctionType;
+use crate::patterns::{self, VarType};
+use crate::spec::{FunctionSpec, NthEntry, Offset, SourceLocation};
+use crate::types::{FunctionType, Type};
+use crate::xrefs;
 
+/// Byte sequences that commonly start x86-64 functions.
+const PROLOGUES: &[&[u8]] = &[
+    &[0x48, 0x89, 0x5C, 0x24],
+    &[0x48, 0x89, 0x4C, 0x24],
+    &[0x48, 0x89, 0x54, 0x24],
+    &[0x48, 0x83, 0xEC],
+    &[0x48, 0x81, 0xEC],
+    &[0x40, 0x53],
+    &[0x40, 0x55],
+    &[0x40, 0x56],
+    &[0x40, 0x57],
+    &[0x55, 0x48, 0x8B, 0xEC],
+    &[0x55, 0x48, 0x89, 0xE5],
+    &[0x4C, 0x8B, 0xDC],
+];
+/// Bytes that typically precede a function start (alignment padding or a return).
+const FUNCTION_PADDING: &[u8] = &[0xCC, 0x90, 0xC3];
+const FUNCTION_ALIGNMENT: u64 = 16;
+const MAX_RELATED_DISTANCE: u64 = 0x100000;
+/// Maximum distance searched for the start of a function containing an instruction.
+const MAX_FUNCTION_SIZE: usize = 0x10000;
+/// Number of bytes compared to detect identical copies of the same code.
+const CLUSTER_WINDOW: usize = 128;
+/// Scored matches with a lower share of the total score are considered a fallback.
+const MIN_SCORED_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmbiguityStrategy {
+    /// Report an error when a pattern has more than one match.
+    Fail,
+    /// Rank the matches using a set of heuristics and pick the best one.
+    Score,
+}
+
+impl FromStr for AmbiguityStrategy {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "fail" => Ok(Self::Fail),
+            "score" => Ok(Self::Score),
+            other => Err(format!("unknown ambiguity strategy '{other}'")),
+        }
+    }
+}
+
+/// A callback invoked on each resolved symbol, it can modify the symbol or drop it by returning `false`.
+pub type SymbolHook = dyn Fn(&mut FunctionSymbol, &ResolutionContext) -> bool;
+
+pub struct ResolutionContext<'a> {
+    pub data: &'a ExecutableData<'a>,
+    pub errors: &'a [SymbolError],
+}
+
+/// How costly it was to search for a pattern.
+#[derive(Debug, Clone)]
+pub struct PatternStats {
+    pub name: Ustr,
+    /// The number of bytes of the anchor the pattern was searched for with.
+    pub anchor_len: usize,
+    /// How many times the anchor was found and the rest of the pattern had to be checked.
+    pub candidates: usize,
+}
+
+/// Resolves the specs, `known` are symbols resolved earlier that can be referenced with `@xref-to`.
+/// The cost of each pattern that was searched for, rather than taken from the cache, is added
+/// to `stats`.
 pub fn resolve_in_exe(
     specs: Vec<FunctionSpec>,
     exe: &ExecutableData,
+    strategy: AmbiguityStrategy,
+    mut cache: Option<&mut PatternCache>,
+    known: &[FunctionSymbol],
+    stats: Option<&mut Vec<PatternStats>>,
 ) -> Result<(Vec<FunctionSymbol>, Vec<SymbolError>)> {
-    let mut match_map: HashMap<usize, Vec<u64>> = HashMap::new();
-    for mat in patterns::multi_search(specs.iter().map(|spec| &spec.pattern), exe.text()) {
-        match_map.entry(mat.pattern).or_default().push(mat.rva);
+    // indexed by the position of the spec, avoids hashing on every match of large spec sets
+    let mut match_map: Vec<Vec<u64>> = vec![vec![]; specs.len()];
+    let mut captures: HashMap<(usize, u64), Vec<_>> = HashMap::new();
+
+    let mut indices = vec![];
+    let mut pats = vec![];
+    for (i, spec) in specs
+        .iter()
+        .enumerate()
+        .filter(|(_, spec)| spec.section.is_none())
+    {
+        for pat in &spec.patterns {
+            match cache.as_mut().and_then(|cache| cache.get(pat)) {
+                Some(rvas) => match_map[i].extend_from_slice(rvas),
+                None => {
+                    indices.push(i);
+                    pats.push(pat);
+                }
+            }
+        }
+    }
+    if !pats.is_empty() {
+        let (matches, costs) = patterns::multi_search_with_costs(pats.iter().copied(), exe.text());
+        let mut found = vec![vec![]; pats.len()];
+        for mat in matches {
+            found[mat.pattern].push(mat.rva);
+        }
+        if let Some(stats) = stats {
+            stats.extend(indices.iter().zip(costs).map(|(i, cost)| PatternStats {
+                name: specs[*i].name,
+                anchor_len: cost.anchor_len,
+                candidates: cost.candidates,
+            }));
+        }
+        for ((i, pat), rvas) in indices.iter().zip(pats).zip(found) {
+            match_map[*i].extend_from_slice(&rvas);
+            if let Some(cache) = cache.as_mut() {
+                cache.insert(pat, rvas);
+            }
+        }
     }
 
-    let mut syms = vec![];
     let mut errs = vec![];
-    for (i, fun) in specs.into_iter().enumerate() {
-        match match_map.get(&i).map(|vec| &vec[..]) {
-            Some([addr]) => syms.push(resolve_symbol(fun, exe, *addr)?),
-            Some(addrs) => {
-                if let Some((n, max)) = fun.nth_entry_of {
-                    match addrs.get(n) {
-                        Some(rva) if max == addrs.len() => syms.push(resolve_symbol(fun, exe, *rva)?),
-                        Some(_) => errs.push(SymbolError::CountMismatch(fun.name, addrs.len())),
-                        None => errs.push(SymbolError::NotEnoughMatches(fun.name, addrs.len())),
+    // the cache is only for the code section, so the specs of data are always searched for
+    let mut data_specs: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, spec) in specs.iter().enumerate() {
+        if let Some(section) = &spec.section {
+            data_specs.entry(section.as_str()).or_default().push(i);
+        }
+    }
+    let mut missing_sections = HashSet::new();
+    for (section, indices) in data_specs {
+        match exe.section(section) {
+            Some((_, bytes)) => {
+                let pats: Vec<_> = indices
+                    .iter()
+                    .flat_map(|i| specs[*i].patterns.iter().map(move |pat| (*i, pat)))
+                    .collect();
+                for mat in patterns::multi_search(pats.iter().map(|(_, pat)| *pat), bytes) {
+                    match_map[pats[mat.pattern].0].push(mat.rva);
+                }
+            }
+            None => {
+                for i in indices {
+                    errs.push(SymbolError::MissingSection(specs[i].name, section.to_owned()));
+                    missing_sections.insert(i);
+                }
+            }
+        }
+    }
+    // the variants of a pattern with alternatives can match at the same place
+    for (i, spec) in specs.iter().enumerate() {
+        if spec.patterns.len() > 1 {
+            match_map[i].sort_unstable();
+            match_map[i].dedup();
+        }
+    }
+
+    // the hex encoding is only needed when some of the regexes aren't cached
+    let mut hex = None;
+    for (i, spec) in specs.iter().enumerate() {
+        let regex = match &spec.regex {
+            Some(regex) => regex,
+            None => continue,
+        };
+        let matches = match cache.as_mut().and_then(|cache| cache.get_regex(regex)) {
+            Some(matches) => matches.to_vec(),
+            None => {
+                let hex = hex.get_or_insert_with(|| patterns::encode_hex(exe.text()));
+                let matches = regex.search(hex);
+                if let Some(cache) = cache.as_mut() {
+                    cache.insert_regex(regex, matches.clone());
+                }
+                matches
+            }
+        };
+        for mat in matches {
+            match_map[i].push(mat.rva);
+            captures.insert((i, mat.rva), mat.groups);
+        }
+    }
+
+    let mut string_functions = vec![];
+    let mut strings = vec![];
+    for (i, spec) in specs.iter().enumerate() {
+        if let Some(str) = &spec.string {
+            match cache.as_mut().and_then(|cache| cache.get_string(str)) {
+                Some(functions) => string_functions.push((i, functions.to_vec())),
+                None => strings.push((i, str, xrefs::find_strings(exe, str.as_bytes()))),
+            }
+        }
+    }
+    if !strings.is_empty() {
+        let targets = strings
+            .iter()
+            .flat_map(|(_, _, addrs)| addrs.iter().copied())
+            .collect();
+        let refs = xrefs::find_rip_relative_refs(exe, &targets);
+        for (i, str, addrs) in strings {
+            let offsets = addrs.iter().filter_map(|addr| refs.get(addr)).flatten().copied();
+            let functions = function_starts(exe, offsets);
+            if let Some(cache) = cache.as_mut() {
+                cache.insert_string(str, functions.clone());
+            }
+            string_functions.push((i, functions));
+        }
+    }
+    for (i, functions) in string_functions {
+        let spec = &specs[i];
+        let replace = spec.patterns.is_empty() && spec.regex.is_none();
+        filter_by_functions(exe, &mut match_map[i], functions, replace);
+    }
+
+    let mut syms = Vec::with_capacity(specs.len());
+    let mut pending: Vec<_> = specs
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !missing_sections.contains(i))
+        .collect();
+    // specs located by references to other symbols are resolved in rounds, after their dependencies
+    while !pending.is_empty() {
+        let resolved: HashMap<Ustr, u64> = known
+            .iter()
+            .chain(&syms)
+            .map(|sym: &FunctionSymbol| (sym.name, sym.rva))
+            .collect();
+        let (ready, waiting): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(_, spec)| spec.xref_to.map_or(true, |dep| resolved.contains_key(&dep)));
+        pending = waiting;
+        if ready.is_empty() {
+            // whatever is left depends on a symbol that failed or on itself
+            for (_, spec) in pending.drain(..) {
+                if let Some(dep) = spec.xref_to {
+                    errs.push(SymbolError::UnresolvedDependency(spec.name, dep));
+                }
+            }
+            break;
+        }
+        let deps: HashSet<u64> = ready
+            .iter()
+            .filter_map(|(_, spec)| Some(exe.rva_to_va(resolved[&spec.xref_to?])))
+            .collect();
+        if !deps.is_empty() {
+            let calls = xrefs::find_calls(exe, &deps);
+            for (i, spec) in &ready {
+                if let Some(dep) = spec.xref_to {
+                    let target = exe.rva_to_va(resolved[&dep]);
+                    let callers = function_starts(exe, calls.get(&target).into_iter().flatten().copied());
+                    let replace = spec.patterns.is_empty() && spec.regex.is_none() && spec.string.is_none();
+                    filter_by_functions(exe, &mut match_map[*i], callers, replace);
+                }
+            }
+        }
+
+        for (i, spec) in &ready {
+            match_map[*i].retain(|rva| is_allowed_position(exe, spec, *rva));
+            if let Some(x86) = &spec.x86 {
+                match_map[*i].retain(|rva| x86.does_match(&exe.text()[*rva as usize..]));
+            }
+            if spec.align_to_prologue {
+                let mut starts: Vec<_> = match_map[*i]
+                    .iter()
+                    .filter_map(|rva| prologue_start(exe, *rva as usize))
+                    .map(|offset| offset as u64)
+                    .collect();
+                starts.sort_unstable();
+                starts.dedup();
+                match_map[*i] = starts;
+            }
+        }
+
+        let mut ambiguous = vec![];
+        for (i, fun) in ready {
+            let resolve = |fun: &FunctionSpec, rva: u64, confidence| {
+                resolve_symbol(fun, exe, rva, captures.get(&(i, rva)), confidence)
+            };
+            if let (Some(NthEntry::Index(n)), Some(cache)) = (fun.nth_entry_of, cache.as_mut()) {
+                let count = match_map[i].len();
+                match cache.replace_match_count(&fun.name, count) {
+                    Some(previous) if previous != count => log::warn!(
+                        "{} has {count} match(es) instead of {previous}, @nth {n} might pick a different one",
+                        fun.name
+                    ),
+                    _ => {}
+                }
+            }
+            match &match_map[i][..] {
+                [] => errs.push(SymbolError::NoMatches(fun.name)),
+                [addr] => syms.push(resolve(&fun, *addr, Confidence::Unique)?),
+                addrs => {
+                    if let Some(nth) = fun.nth_entry_of {
+                        let selected = match nth {
+                            NthEntry::Exact(n, max) => addrs.get(n).filter(|_| max == addrs.len()),
+                            NthEntry::Index(n) => addrs.get(n),
+                            NthEntry::First => addrs.first(),
+                            NthEntry::Last => addrs.last(),
+                            NthEntry::UniqueDifferent => {
+                                let clusters = cluster_candidates(search_section(exe, &fun).1, addrs);
+                                let singles: Vec<_> =
+                                    clusters.iter().filter(|cluster| cluster.len() == 1).collect();
+                                match &singles[..] {
+                                    [single] if clusters.len() > 1 => Some(single[0]),
+                                    _ => {
+                                        let clusters = MatchClusters::new(&clusters);
+                                        errs.push(SymbolError::NoUniqueMatch(fun.name, clusters));
+                                        continue;
+                                    }
+                                }
+                            }
+                        };
+                        match (selected, nth) {
+                            (Some(rva), _) => syms.push(resolve(&fun, *rva, Confidence::Nth)?),
+                            (None, NthEntry::Exact(n, _)) if n < addrs.len() => {
+                                errs.push(SymbolError::CountMismatch(fun.name, addrs.len()))
+                            }
+                            (None, _) => errs.push(SymbolError::NotEnoughMatches(fun.name, addrs.len())),
+                        }
+                    } else if strategy == AmbiguityStrategy::Score && fun.section.is_none() {
+                        ambiguous.push((i, fun));
+                    } else {
+                        let clusters = cluster_candidates(search_section(exe, &fun).1, addrs);
+                        errs.push(SymbolError::MoreThanOneMatch(
+                            fun.name,
+                            addrs.len(),
+                            MatchClusters::new(&clusters),
+                        ));
                     }
-                } else {
-                    errs.push(SymbolError::MoreThanOneMatch(fun.name, addrs.len()));
                 }
             }
-            None => errs.push(SymbolError::NoMatches(fun.name)),
+        }
+
+        // symbols resolved unambiguously are used as reference points for scoring
+        let mut related: Vec<u64> = syms.iter().map(FunctionSymbol::rva).collect();
+        related.sort_unstable();
+        for (i, fun) in ambiguous {
+            let addrs = &match_map[i];
+            let mut candidates = vec![];
+            for rva in addrs {
+                let sym = resolve_symbol(&fun, exe, *rva, captures.get(&(i, *rva)), Confidence::Scored)?;
+                let score = score_candidate(exe, sym.rva(), &related);
+                candidates.push((sym, score));
+            }
+            candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+            match &candidates[..] {
+                [(_, best), (_, second), ..] if best <= second => errs.push(SymbolError::MoreThanOneMatch(
+                    fun.name,
+                    addrs.len(),
+                    MatchClusters::new(&cluster_candidates(exe.text(), addrs)),
+                )),
+                _ => {
+                    let total: f64 = candidates.iter().map(|(_, score)| score).sum();
+                    let (mut sym, best) = candidates.swap_remove(0);
+                    let ratio = best / total;
+                    if ratio < MIN_SCORED_RATIO {
+                        sym.confidence = Confidence::Fallback;
+                    }
+                    log::info!(
+                        "Picked 0x{:X} for {} out of {} candidates (confidence {:.2})",
+                        sym.rva(),
+                        sym.name(),
+                        addrs.len(),
+                        ratio
+                    );
+                    syms.push(sym);
+                }
+            }
         }
     }
+
     Ok((syms, errs))
 }
 
-fn resolve_symbol(spec: FunctionSpec, data: &ExecutableData, rva: u64) -> Result<FunctionSymbol> {
-    let res = match &spec.eval {
-        Some(expr) => expr.eval(&EvalContext::new(&spec.pattern, data, rva)?)? - data.image_base(),
-        None => (rva as i64 - spec.offset.unwrap_or(0) as i64) as u64 + data.text_offset_from_base(),
-    };
-    Ok(FunctionSymbol::new(spec.name, spec.function_type, res))
+/// Compares the resolved symbols with the addresses they're expected at.
+pub fn verify(symbols: &[FunctionSymbol], expected: &[(Ustr, u64)]) -> Vec<SymbolError> {
+    let resolved: HashMap<Ustr, u64> = symbols.iter().map(|sym| (sym.name, sym.rva)).collect();
+    expected
+        .iter()
+        .filter_map(|(name, rva)| match resolved.get(name) {
+            Some(actual) if actual == rva => None,
+            Some(actual) => Some(SymbolError::UnexpectedAddress(*name, *actual, *rva)),
+            None => Some(SymbolError::MissingExpected(*name, *rva)),
+        })
+        .collect()
 }
 
+/// Finds symbols resolved to the same address as one of the preceding symbols, which usually
+/// means that one of the patterns is too generic.
+pub fn find_collisions(symbols: &[FunctionSymbol]) -> Vec<SymbolError> {
+    let mut first: HashMap<u64, Ustr> = HashMap::new();
+    let mut errors = vec![];
+    for sym in symbols {
+        match first.entry(sym.rva) {
+            Entry::Occupied(entry) => {
+                errors.push(SymbolError::AddressCollision(sym.name, *entry.get(), sym.rva))
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(sym.name);
+            }
+        }
+    }
+    errors
+}
+
+/// Adds a copy of each symbol with aliases under each of the alias names.
+pub fn add_aliases(symbols: &mut Vec<FunctionSymbol>, aliases: &HashMap<Ustr, Vec<Ustr>>) {
+    let mut copies = vec![];
+    for sym in symbols.iter() {
+        for alias in aliases.get(&sym.name).into_iter().flatten() {
+            let mut copy = sym.clone();
+            copy.set_name(*alias);
+            copies.push(copy);
+        }
+    }
+    symbols.extend(copies);
+}
+
+/// Adds the symbols of a previous run that weren't resolved again, the new ones take precedence
+/// over the previous ones with the same name. Returns the number of symbols added.
+pub fn merge_symbols(symbols: &mut Vec<FunctionSymbol>, previous: Vec<FunctionSymbol>) -> usize {
+    let names: HashSet<_> = symbols.iter().map(|sym| sym.name).collect();
+    let count = symbols.len();
+    symbols.extend(previous.into_iter().filter(|sym| !names.contains(&sym.name)));
+    symbols.len() - count
+}
+
+/// Adds an `fn_<RVA>` symbol for each of the functions in the unwind tables that no other symbol
+/// points at, so that the debug symbols cover every function.
+pub fn add_unnamed_functions(symbols: &mut Vec<FunctionSymbol>, data: &ExecutableData) -> usize {
+    let named: HashSet<u64> = symbols.iter().map(FunctionSymbol::rva).collect();
+    let function_type = Arc::new(FunctionType::new(vec![], Type::Void));
+    let count = symbols.len();
+    for (start, end) in data.functions() {
+        if !named.contains(&start) {
+            let name = format!("fn_{start:X}").into();
+            let sym = FunctionSymbol::new(name, function_type.clone(), start, Confidence::Unique, None);
+            symbols.push(sym.with_size(Some(end - start)));
+        }
+    }
+    symbols.len() - count
+}
+
+/// Groups candidates with identical bytes, the groups are sorted from the largest to the smallest.
+fn cluster_candidates<'a>(section: &[u8], addrs: &'a [u64]) -> Vec<Vec<&'a u64>> {
+    let mut clusters: Vec<(&[u8], Vec<&u64>)> = vec![];
+    for rva in addrs {
+        let start = *rva as usize;
+        let end = (start + CLUSTER_WINDOW).min(section.len());
+        let bytes = &section[start..end];
+        match clusters.iter_mut().find(|(other, _)| *other == bytes) {
+            Some((_, cluster)) => cluster.push(rva),
+            None => clusters.push((bytes, vec![rva])),
+        }
+    }
+    let mut clusters: Vec<_> = clusters.into_iter().map(|(_, cluster)| cluster).collect();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.len()));
+    clusters
+}
+
+/// Sizes of groups of identical matches, used to explain ambiguous results.
 #[derive(Debug)]
+pub struct MatchClusters(Vec<usize>);
+
+impl MatchClusters {
+    fn new<A>(clusters: &[Vec<A>]) -> Self {
+        Self(clusters.iter().map(Vec::len).collect())
+    }
+}
+
+impl fmt::Display for MatchClusters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let different = self.0.iter().filter(|size| **size == 1).count();
+        let mut parts: Vec<_> = self
+            .0
+            .iter()
+            .filter(|size| **size > 1)
+            .map(|size| format!("{size} identical copies"))
+            .collect();
+        if different > 0 {
+            parts.push(format!("{different} different"));
+        }
+        f.write_str(&parts.join(" + "))
+    }
+}
+
+/// Returns the sorted and deduplicated starts of the functions containing the offsets.
+fn function_starts(exe: &ExecutableData, offsets: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut starts: Vec<_> = offsets.filter_map(|offset| function_start(exe, offset)).collect();
+    starts.sort_unstable();
+    starts.dedup();
+    starts
+}
+
+/// Makes the function starts the matches of a spec, or when `replace` is false, keeps only the
+/// existing matches inside one of the functions.
+fn filter_by_functions(exe: &ExecutableData, matches: &mut Vec<u64>, functions: Vec<usize>, replace: bool) {
+    if replace {
+        *matches = functions.into_iter().map(|offset| offset as u64).collect();
+    } else {
+        matches.retain(|rva| {
+            function_start(exe, *rva as usize)
+                .map_or(false, |start| functions.binary_search(&start).is_ok())
+        });
+    }
+}
+
+/// Walks back from an offset in the text section to the closest aligned function start preceded
+/// by padding, `nop` padding only counts when followed by a common prologue since it's also used
+/// to align loops. Returns `None` when there's no function start within `MAX_FUNCTION_SIZE` bytes.
+fn function_start(exe: &ExecutableData, offset: usize) -> Option<usize> {
+    if exe.has_function_table() {
+        return table_function_start(exe, offset);
+    }
+    (offset.saturating_sub(MAX_FUNCTION_SIZE).max(1)..=offset.min(exe.text().len()))
+        .rev()
+        .find(|pos| is_function_start(exe, *pos))
+}
+
+/// Returns the RVA and the contents of the section a spec is searched for in, which is the code
+/// section unless the spec is for data in another section.
+fn search_section<'a>(exe: &'a ExecutableData<'a>, spec: &FunctionSpec) -> (u64, &'a [u8]) {
+    spec.section
+        .as_deref()
+        .and_then(|name| exe.section(name))
+        .unwrap_or((exe.text_offset_from_base(), exe.text()))
+}
+
+/// Checks the restrictions of a spec on where its matches can be.
+fn is_allowed_position(exe: &ExecutableData, spec: &FunctionSpec, rva: u64) -> bool {
+    let va = exe.rva_to_va(rva + search_section(exe, spec).0);
+    spec.section_offset.map_or(true, |offset| offset == rva)
+        && spec.range.as_ref().map_or(true, |range| range.contains(&va))
+        && (!spec.function_start || prologue_start(exe, rva as usize) == Some(rva as usize))
+}
+
+/// Walks back from a match to the closest recognized function start, unlike [`function_start`]
+/// an aligned prologue is accepted even when it isn't preceded by padding.
+fn prologue_start(exe: &ExecutableData, offset: usize) -> Option<usize> {
+    if exe.has_function_table() {
+        return table_function_start(exe, offset);
+    }
+    let text = exe.text();
+    let base = exe.text_offset_from_base();
+    (offset.saturating_sub(MAX_FUNCTION_SIZE)..=offset.min(text.len()))
+        .rev()
+        .find(|pos| {
+            let is_prologue = PROLOGUES
+                .iter()
+                .any(|prologue| text[*pos..].starts_with(prologue));
+            (is_prologue && (base + *pos as u64) % FUNCTION_ALIGNMENT == 0)
+                || (*pos > 0 && is_function_start(exe, *pos))
+        })
+}
+
+/// Looks up the start of the function containing an offset in the unwind tables, which are
+/// authoritative, so there's no fallback to the heuristics.
+fn table_function_start(exe: &ExecutableData, offset: usize) -> Option<usize> {
+    let base = exe.text_offset_from_base();
+    let start = exe.function_containing(base + offset as u64)?;
+    start.checked_sub(base).map(|start| start as usize)
+}
+
+fn is_function_start(exe: &ExecutableData, pos: usize) -> bool {
+    let text = exe.text();
+    let bytes = &text[pos..];
+    (exe.text_offset_from_base() + pos as u64) % FUNCTION_ALIGNMENT == 0
+        && match text[pos - 1] {
+            0x90 => PROLOGUES.iter().any(|prologue| bytes.starts_with(prologue)),
+            other => FUNCTION_PADDING.contains(&other),
+        }
+}
+
+/// Scores a candidate function address, higher scores indicate a more plausible function start.
+/// The `related` addresses have to be sorted.
+fn score_candidate(exe: &ExecutableData, rva: u64, related: &[u64]) -> f64 {
+    let mut score = 0.;
+    if let Some(offset) = rva.checked_sub(exe.text_offset_from_base()) {
+        let offset = offset as usize;
+        let bytes = exe.text().get(offset..).unwrap_or_default();
+        if PROLOGUES.iter().any(|prologue| bytes.starts_with(prologue)) {
+            score += 2.;
+        }
+        if offset > 0
+            && exe
+                .text()
+                .get(offset - 1)
+                .map_or(false, |b| FUNCTION_PADDING.contains(b))
+        {
+            score += 1.;
+        }
+    }
+    // an entry of the exception directory is as reliable as it gets
+    if exe.function_containing(rva) == Some(rva) {
+        score += 2.;
+    }
+    if rva % FUNCTION_ALIGNMENT == 0 {
+        score += 1.;
+    }
+    let closest = related.partition_point(|other| *other < rva);
+    let neighbours = related[closest.saturating_sub(1)..].iter().take(2);
+    if let Some(distance) = neighbours.map(|other| rva.abs_diff(*other)).min() {
+        score += 1. - distance.min(MAX_RELATED_DISTANCE) as f64 / MAX_RELATED_DISTANCE as f64;
+    }
+    score
+}
+
+fn resolve_symbol(
+    spec: &FunctionSpec,
+    data: &ExecutableData,
+    rva: u64,
+    captures: Option<&Vec<(String, VarType, usize)>>,
+    confidence: Confidence,
+) -> Result<FunctionSymbol> {
+    let base = search_section(data, spec).0;
+    let match_va = data.rva_to_va(rva + base);
+    let make_ctx = || -> Result<EvalContext> {
+        let ctx = match captures {
+            Some(groups) => {
+                let groups = groups
+                    .iter()
+                    .map(|(key, typ, offset)| (key.as_str(), *typ, *offset));
+                EvalContext::new(groups, data, rva)
+            }
+            None => {
+                // the groups come from whichever variant of the pattern matched here
+                let groups = spec
+                    .patterns
+                    .iter()
+                    .find_map(|pattern| pattern.groups_at(&data.text()[rva as usize..]));
+                EvalContext::new(groups.unwrap_or_default(), data, rva)
+            }
+        };
+        Ok(ctx?.with_builtin("match", match_va))
+    };
+    let res = match (&spec.eval, &spec.offset) {
+        (Some(expr), _) => data.va_to_rva(expr.eval(&make_ctx()?)?),
+        (None, Some(Offset::Expr(expr))) => data.va_to_rva(expr.eval(&make_ctx()?.with_byte_ints())?),
+        (None, Some(Offset::Fixed(offset))) => (rva as i64 - offset) as u64 + base,
+        (None, None) => rva + base,
+    };
+    let size = match &spec.size {
+        Some(expr) => {
+            let ctx = make_ctx()?
+                .with_byte_ints()
+                .with_builtin("symbol", data.rva_to_va(res));
+            Some(expr.eval(&ctx)?)
+        }
+        None if spec.data_type.is_some() => None,
+        None => data.function_end(res).map(|end| end - res),
+    };
+    let sym = FunctionSymbol::new(
+        spec.name,
+        spec.function_type.clone(),
+        res,
+        confidence,
+        spec.location.clone(),
+    );
+    Ok(sym.with_size(size).with_data_type(spec.data_type.clone()))
+}
+
+/// Describes how a symbol address was selected, ordered from the least to the most reliable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// Picked by scoring, but without a clear winner.
+    Fallback,
+    /// Picked by scoring.
+    Scored,
+    /// Selected from multiple matches with `@nth`.
+    Nth,
+    /// The only match of a pattern.
+    Unique,
+}
+
+impl Confidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Confidence::Fallback => "fallback",
+            Confidence::Scored => "scored",
+            Confidence::Nth => "nth",
+            Confidence::Unique => "unique",
+        }
+    }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Confidence {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "fallback" => Ok(Self::Fallback),
+            "scored" => Ok(Self::Scored),
+            "nth" => Ok(Self::Nth),
+            "unique" => Ok(Self::Unique),
+            other => Err(format!("unknown confidence level '{other}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FunctionSymbol {
     name: Ustr,
-    function_type: Rc<FunctionType>,
+    function_type: Arc<FunctionType>,
     rva: u64,
+    confidence: Confidence,
+    location: Option<SourceLocation>,
+    /// Size in bytes, known when the executable has an exception directory.
+    size: Option<u64>,
+    /// The type of the data the symbol points at, symbols of functions don't have one.
+    data_type: Option<Type>,
 }
 
 impl FunctionSymbol {
-    fn new(name: Ustr, function_type: Rc<FunctionType>, rva: u64) -> Self {
+    pub(crate) fn new(
+        name: Ustr,
+        function_type: Arc<FunctionType>,
+        rva: u64,
+        confidence: Confidence,
+        location: Option<SourceLocation>,
+    ) -> Self {
         Self {
             name,
             function_type,
             rva,
+            confidence,
+            location,
+            size: None,
+            data_type: None,
         }
     }
 
+    pub(crate) fn with_size(self, size: Option<u64>) -> Self {
+        Self { size, ..self }
+    }
+
+    pub(crate) fn with_data_type(self, data_type: Option<Type>) -> Self {
+        Self { data_type, ..self }
+    }
+
+    pub(crate) fn with_location(self, location: Option<SourceLocation>) -> Self {
+        Self { location, ..self }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn set_name(&mut self, name: Ustr) {
+        self.name = name;
+    }
+
     pub fn function_type(&self) -> &FunctionType {
         &self.function_type
     }
@@ -76,4 +774,91 @@ impl FunctionSymbol {
     pub fn rva(&self) -> u64 {
         self.rva
     }
+
+    /// Moves the symbol, the size is dropped since it was known for the previous address.
+    pub fn set_rva(&mut self, rva: u64) {
+        self.rva = rva;
+        self.size = None;
+    }
+
+    pub fn confidence(&self) -> Confidence {
+        self.confidence
+    }
+
+    /// Returns the location of the typedef the symbol was resolved from.
+    pub fn location(&self) -> Option<&SourceLocation> {
+        self.location.as_ref()
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Returns the type of the data at the symbol, or `None` for functions.
+    pub fn data_type(&self) -> Option<&Type> {
+        self.data_type.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::write::Object;
+    use object::{Architecture, BinaryFormat, Endianness, SectionKind};
+
+    use super::*;
+    use crate::types::Type;
+
+    /// Writes an executable with two padded and aligned copies of `33 C0 C3` at 0x10 and 0x20.
+    fn exe_with_two_matches() -> Vec<u8> {
+        let mut code = vec![0xCC; 0x40];
+        code[0x10..0x13].copy_from_slice(&[0x33, 0xC0, 0xC3]);
+        code[0x20..0x23].copy_from_slice(&[0x33, 0xC0, 0xC3]);
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        obj.append_section_data(text, &code, 16);
+        let rdata = obj.add_section(vec![], b".rdata".to_vec(), SectionKind::ReadOnlyData);
+        obj.append_section_data(rdata, &[0; 8], 8);
+        obj.write().unwrap()
+    }
+
+    fn spec(comment: &[&str]) -> FunctionSpec {
+        let typ = FunctionType::new(vec![], Type::Void).into();
+        FunctionSpec::new("zero".into(), typ, comment.iter().copied(), None)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn score_exception_directory_entries() {
+        let bytes = exe_with_two_matches();
+        let exe = object::read::File::parse(&*bytes).unwrap();
+        let spec = || spec(&["/// @pattern 33 C0 C3"]);
+
+        // both of the matches are aligned and preceded by padding
+        let data = ExecutableData::new(&exe).unwrap();
+        let (syms, errs) =
+            resolve_in_exe(vec![spec()], &data, AmbiguityStrategy::Score, None, &[], None).unwrap();
+        assert!(syms.is_empty());
+        assert!(matches!(errs[..], [SymbolError::MoreThanOneMatch(_, 2, _)]));
+
+        let data = data.with_functions(&[(0x20, 0x23)]);
+        let (syms, errs) =
+            resolve_in_exe(vec![spec()], &data, AmbiguityStrategy::Score, None, &[], None).unwrap();
+        assert!(errs.is_empty());
+        assert_eq!(syms[0].rva(), 0x20);
+    }
+
+    #[test]
+    fn record_nth_match_counts() {
+        let bytes = exe_with_two_matches();
+        let exe = object::read::File::parse(&*bytes).unwrap();
+        let spec = spec(&["/// @pattern 33 C0 C3", "/// @nth 1"]);
+
+        let data = ExecutableData::new(&exe).unwrap();
+        let mut cache = PatternCache::new(&bytes);
+        let strategy = AmbiguityStrategy::Fail;
+        let (syms, _) = resolve_in_exe(vec![spec], &data, strategy, Some(&mut cache), &[], None).unwrap();
+        assert_eq!(syms[0].rva(), 0x20);
+        assert_eq!(cache.replace_match_count("zero", 3), Some(2));
+    }
 }
@@ -0,0 +1,319 @@
+//! Templates rendered with `--template`, written in a small subset of the Handlebars syntax:
+//! - `{{path}}` writes a value, where the path is a dot-separated list of keys like `symbol.name`,
+//!   `this` refers to the current item and `@index` to its index in a loop
+//! - `{{#each path}}...{{/each}}` repeats the block for each item of an array
+//! - `{{#if path}}...{{else}}...{{/if}}` writes the first block when the value is not empty,
+//!   `false`, `0` or missing, and the optional second block otherwise
+//! - `{{! comment}}` is skipped
+//!
+//! Names are looked up in the current item first and then in the enclosing ones. Values are written
+//! as they are, without any escaping.
+use std::io::Write;
+
+use serde_json::{json, Value};
+
+use crate::error::{Error, Result};
+use crate::exe::ExeProperties;
+use crate::symbols::FunctionSymbol;
+use crate::types::{EnumValue, Type, TypeInfo};
+
+#[derive(Debug)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Value(String),
+    Each(String, Vec<Node>),
+    If(String, Vec<Node>, Vec<Node>),
+}
+
+/// A block that hasn't been closed yet while parsing.
+enum Block {
+    Each(String),
+    If(String, Option<Vec<Node>>),
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut stack: Vec<(Block, Vec<Node>)> = vec![];
+        let mut nodes = vec![];
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                nodes.push(Node::Text(rest[..start].to_owned()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| template_error("unclosed tag"))?;
+            let tag = rest[start + 2..start + end].trim();
+            rest = &rest[start + end + 2..];
+
+            if tag.starts_with('!') {
+                continue;
+            } else if let Some(path) = tag.strip_prefix("#each ") {
+                stack.push((Block::Each(path.trim().to_owned()), nodes));
+                nodes = vec![];
+            } else if let Some(path) = tag.strip_prefix("#if ") {
+                stack.push((Block::If(path.trim().to_owned(), None), nodes));
+                nodes = vec![];
+            } else if tag == "else" {
+                match stack.last_mut() {
+                    Some((Block::If(_, then @ None), _)) => *then = Some(std::mem::take(&mut nodes)),
+                    _ => return Err(template_error("else outside of an if block")),
+                }
+            } else if let Some(name) = tag.strip_prefix('/') {
+                let (block, parent) = stack
+                    .pop()
+                    .ok_or_else(|| template_error(format!("unexpected closing tag {tag}")))?;
+                let node = match (block, name.trim()) {
+                    (Block::Each(path), "each") => Node::Each(path, nodes),
+                    (Block::If(path, None), "if") => Node::If(path, nodes, vec![]),
+                    (Block::If(path, Some(then)), "if") => Node::If(path, then, nodes),
+                    _ => return Err(template_error(format!("mismatched closing tag {tag}"))),
+                };
+                nodes = parent;
+                nodes.push(node);
+            } else if tag.starts_with('#') {
+                return Err(template_error(format!("unsupported block {tag}")));
+            } else {
+                nodes.push(Node::Value(tag.to_owned()));
+            }
+        }
+        if !stack.is_empty() {
+            return Err(template_error("unclosed block"));
+        }
+        if !rest.is_empty() {
+            nodes.push(Node::Text(rest.to_owned()));
+        }
+        Ok(Self { nodes })
+    }
+
+    pub fn render<W: Write>(&self, context: &Value, mut output: W) -> Result<()> {
+        let mut scopes = vec![Scope {
+            value: context,
+            index: None,
+        }];
+        render_nodes(&self.nodes, &mut scopes, &mut output)
+    }
+}
+
+struct Scope<'a> {
+    value: &'a Value,
+    index: Option<usize>,
+}
+
+fn render_nodes<'a, W: Write>(
+    nodes: &'a [Node],
+    scopes: &mut Vec<Scope<'a>>,
+    output: &mut W,
+) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.write_all(text.as_bytes())?,
+            Node::Value(path) if path == "@index" => {
+                if let Some(index) = scopes.last().and_then(|scope| scope.index) {
+                    write!(output, "{index}")?;
+                }
+            }
+            Node::Value(path) => match lookup(scopes, path) {
+                Some(Value::String(str)) => output.write_all(str.as_bytes())?,
+                Some(Value::Null) | None => {}
+                Some(other) => write!(output, "{other}")?,
+            },
+            Node::Each(path, body) => {
+                if let Some(Value::Array(items)) = lookup(scopes, path) {
+                    for (index, value) in items.iter().enumerate() {
+                        scopes.push(Scope {
+                            value,
+                            index: Some(index),
+                        });
+                        render_nodes(body, scopes, output)?;
+                        scopes.pop();
+                    }
+                }
+            }
+            Node::If(path, then, otherwise) => {
+                let block = if lookup(scopes, path).map_or(false, is_truthy) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(block, scopes, output)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'a>(scopes: &[Scope<'a>], path: &str) -> Option<&'a Value> {
+    let current = scopes.last()?;
+    if path == "this" || path == "." {
+        return Some(current.value);
+    }
+    let mut keys = path.strip_prefix("this.").unwrap_or(path).split('.');
+    let first = keys.next()?;
+    let value = scopes.iter().rev().find_map(|scope| scope.value.get(first))?;
+    keys.try_fold(value, |value, key| value.get(key))
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(bool) => *bool,
+        Value::Number(num) => num.as_f64() != Some(0.),
+        Value::String(str) => !str.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+fn template_error(message: impl Into<String>) -> Error {
+    Error::TemplateError(message.into())
+}
+
+/// Builds the value templates are rendered with, the fields are described in the README.
+pub fn template_context(
+    symbols: &[FunctionSymbol],
+    types: &TypeInfo,
+    props: &ExeProperties,
+    image_base: Option<u64>,
+) -> Value {
+    let symbols: Vec<_> = symbols
+        .iter()
+        .map(|sym| {
+            let signature = match sym.data_type() {
+                Some(typ) => format!("{} {}", typ.name(), sym.name()),
+                None => {
+                    let typ = sym.function_type();
+                    let params: Vec<_> = typ.params.iter().map(Type::name).collect();
+                    format!("{} {}({})", typ.return_type.name(), sym.name(), params.join(", "))
+                }
+            };
+            let address = image_base.map_or(sym.rva(), |base| base + sym.rva());
+            json!({
+                "name": sym.name(),
+                "rva": sym.rva(),
+                "va": props.rva_to_va(sym.rva()),
+                "address": format!("0x{address:X}"),
+                "confidence": sym.confidence().as_str(),
+                "signature": signature,
+                "is_data": sym.data_type().is_some(),
+                "size": sym.size(),
+                "file": sym.location().map(|loc| loc.file.display().to_string()),
+                "line": sym.location().map(|loc| loc.line),
+            })
+        })
+        .collect();
+
+    let mut structs = vec![];
+    let mut unions = vec![];
+    let mut enums = vec![];
+    for typ in types.sorted_types() {
+        match typ {
+            Type::Struct(id) => {
+                let struct_ = &types.structs[&id];
+                let members: Vec<_> = struct_
+                    .members
+                    .iter()
+                    .map(|member| {
+                        json!({
+                            "name": member.name.as_str(),
+                            "type": member.typ.name(),
+                            "offset": member.bit_offset.map(|offset| offset / 8),
+                        })
+                    })
+                    .collect();
+                structs.push(json!({
+                    "name": struct_.name.as_str(),
+                    "base": struct_.base.map(|base| base.to_string()),
+                    "size": struct_.size,
+                    "members": members
+                }));
+            }
+            Type::Union(id) => {
+                let union = &types.unions[&id];
+                let members: Vec<_> = union
+                    .members
+                    .iter()
+                    .map(|member| json!({ "name": member.name.as_str(), "type": member.typ.name() }))
+                    .collect();
+                unions.push(json!({ "name": union.name.as_str(), "size": union.size, "members": members }));
+            }
+            Type::Enum(id) => {
+                let enum_ = &types.enums[&id];
+                let members: Vec<_> = enum_
+                    .members
+                    .iter()
+                    .map(|member| {
+                        let value = match member.value {
+                            EnumValue::Signed(value) => json!(value),
+                            EnumValue::Unsigned(value) => json!(value),
+                        };
+                        json!({ "name": member.name.as_str(), "value": value })
+                    })
+                    .collect();
+                enums.push(json!({
+                    "name": enum_.name.as_str(),
+                    "size": enum_.size,
+                    "underlying_type": enum_.underlying_type.name(),
+                    "is_scoped": enum_.is_scoped,
+                    "members": members
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    json!({
+        "image_base": props.image_base(),
+        "architecture": format!("{:?}", props.architecture()).to_lowercase(),
+        "format": format!("{:?}", props.format()).to_lowercase(),
+        "symbols": symbols,
+        "structs": structs,
+        "unions": unions,
+        "enums": enums,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, context: Value) -> String {
+        let mut output = vec![];
+        Template::parse(source)
+            .unwrap()
+            .render(&context, &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn render_loops_and_conditions() {
+        let context = json!({
+            "module": "game",
+            "symbols": [
+                { "name": "get_player", "address": "0x1000", "file": "game.hpp" },
+                { "name": "get_world", "address": "0x2000", "file": null },
+            ],
+        });
+        let source = "{{! symbols }}{{#each symbols}}{{@index}} {{module}}!{{name}} {{address}}\
+            {{#if file}} ({{file}}){{else}} (unknown){{/if}}\n{{/each}}";
+        assert_eq!(
+            render(source, context),
+            "0 game!get_player 0x1000 (game.hpp)\n1 game!get_world 0x2000 (unknown)\n"
+        );
+    }
+
+    #[test]
+    fn reject_unbalanced_blocks() {
+        assert!(Template::parse("{{#each symbols}}{{name}}").is_err());
+        assert!(Template::parse("{{#if name}}{{/each}}").is_err());
+        assert!(Template::parse("{{name").is_err());
+        assert!(Template::parse("{{else}}").is_err());
+    }
+}
@@ -1,7 +1,10 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::hash::BuildHasherDefault;
-use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use auto_enums::auto_enum;
 use derive_more::{AsRef, Display, From};
@@ -20,13 +23,20 @@ pub enum Type {
     Short(bool),
     Int(bool),
     Long(bool),
+    LongLong(bool),
+    Int128(bool),
     Float,
     Double,
-    Pointer(Rc<Type>),
-    Reference(Rc<Type>),
-    Array(Rc<Type>),
-    FixedArray(Rc<Type>, usize),
-    Function(Rc<FunctionType>),
+    LongDouble,
+    Pointer(Arc<Type>),
+    Reference(Arc<Type>),
+    /// A pointer to a data member or a member function of a class, like `int Class::*`.
+    MemberPointer(Arc<Type>, StructId),
+    Array(Arc<Type>),
+    FixedArray(Arc<Type>, usize),
+    /// A SIMD vector with a number of elements, like `__m128`.
+    Vector(Arc<Type>, usize),
+    Function(Arc<FunctionType>),
     Union(UnionId),
     Struct(StructId),
     Enum(EnumId),
@@ -38,26 +48,43 @@ impl Type {
             Type::Void => Some(0),
             Type::Bool => Some(1),
             Type::Char(_) => Some(1),
-            #[cfg(windows)]
-            Type::WChar => Some(2),
-            #[cfg(unix)]
-            Type::WChar => Some(4),
+            Type::WChar => Some(info.data_model.wchar_size()),
             Type::Short(_) => Some(2),
             Type::Int(_) => Some(4),
-            Type::Long(_) => Some(8),
+            Type::Long(_) => Some(info.data_model.long_size()),
+            Type::LongLong(_) => Some(8),
+            Type::Int128(_) => Some(16),
             Type::Float => Some(4),
             Type::Double => Some(8),
-            Type::Pointer(_) => Some(POINTER_SIZE),
-            Type::Reference(_) => Some(POINTER_SIZE),
+            Type::LongDouble => Some(info.data_model.long_double_size()),
+            Type::Pointer(_) => Some(info.data_model.pointer_size()),
+            Type::Reference(_) => Some(info.data_model.pointer_size()),
+            Type::MemberPointer(inner, _) => {
+                Some(info.data_model.member_pointer_size(matches!(**inner, Type::Function(_))))
+            }
             Type::Array(_) => None,
             Type::FixedArray(ty, size) => ty.size(info).map(|v| v * size),
-            Type::Function(_) => Some(POINTER_SIZE),
+            Type::Vector(ty, count) => ty.size(info).map(|v| v * count),
+            Type::Function(_) => Some(info.data_model.pointer_size()),
             Type::Union(u) => info.unions.get(u).and_then(|u| u.size),
             Type::Struct(s) => info.structs.get(s).and_then(|s| s.size),
             Type::Enum(e) => info.enums.get(e).and_then(|e| e.size),
         }
     }
 
+    pub fn alignment(&self, info: &TypeInfo) -> Option<usize> {
+        match self {
+            Type::Array(ty) | Type::FixedArray(ty, _) => ty.alignment(info),
+            Type::Vector(_, _) | Type::Int128(_) | Type::LongDouble => self.size(info),
+            Type::Struct(s) => info.structs.get(s).and_then(|s| s.alignment(info)),
+            Type::Union(u) => {
+                let union = info.unions.get(u)?;
+                max_alignment(union.members.iter().map(|member| member.typ.alignment(info)))
+            }
+            other => other.size(info).map(|size| size.clamp(1, MAX_ALIGN)),
+        }
+    }
+
     pub fn name(&self) -> Cow<'static, str> {
         match self {
             Type::Void => "void".into(),
@@ -71,15 +98,22 @@ impl Type {
             Type::Int(false) => "unsigned int".into(),
             Type::Long(true) => "long".into(),
             Type::Long(false) => "unsigned long".into(),
+            Type::LongLong(true) => "long long".into(),
+            Type::LongLong(false) => "unsigned long long".into(),
+            Type::Int128(true) => "__int128".into(),
+            Type::Int128(false) => "unsigned __int128".into(),
             Type::Float => "float".into(),
             Type::Double => "double".into(),
+            Type::LongDouble => "long double".into(),
             Type::Union(id) => id.as_ref().as_str().into(),
             Type::Struct(id) => id.as_ref().as_str().into(),
             Type::Enum(id) => id.as_ref().as_str().into(),
             Type::Pointer(inner) => format!("{}*", inner.name()).into(),
             Type::Reference(inner) => format!("{}&", inner.name()).into(),
+            Type::MemberPointer(inner, class) => format!("{} {}::*", inner.name(), class).into(),
             Type::Array(inner) => format!("{}[]", inner.name()).into(),
             Type::FixedArray(inner, size) => format!("{}[{}]", inner.name(), size).into(),
+            Type::Vector(inner, count) => format!("vector<{}, {}>", inner.name(), count).into(),
             Type::Function(fun) => {
                 let ret = fun.return_type.name();
                 let mut params = String::new();
@@ -121,7 +155,11 @@ pub struct DataMember {
     pub name: Ustr,
     pub typ: Type,
     pub bit_offset: Option<usize>,
-    pub is_bitfield: bool,
+    /// Width in bits of a bitfield.
+    pub bit_width: Option<usize>,
+    /// Whether the member is an anonymous struct or union, its members are accessed as if
+    /// they were members of the enclosing type.
+    pub is_anonymous: bool,
 }
 
 impl DataMember {
@@ -130,9 +168,14 @@ impl DataMember {
             name,
             typ,
             bit_offset: None,
-            is_bitfield: false,
+            bit_width: None,
+            is_anonymous: false,
         }
     }
+
+    pub fn is_bitfield(&self) -> bool {
+        self.bit_width.is_some()
+    }
 }
 
 #[derive(Debug)]
@@ -164,6 +207,31 @@ impl StructType {
                 .any(|typ| typ.has_virtual_methods(types))
     }
 
+    pub fn alignment(&self, types: &TypeInfo) -> Option<usize> {
+        let vft = self
+            .has_virtual_methods(types)
+            .then(|| Some(types.data_model.pointer_size()));
+        let members = self.all_members(types).map(|member| member.typ.alignment(types));
+        max_alignment(members.chain(vft))
+    }
+
+    /// Computes the offsets of all members (including the inherited ones) and the size of the struct
+    /// from the sizes and alignments of the member types. Returns `None` when some of the sizes are unknown.
+    pub fn compute_layout(&self, types: &TypeInfo) -> Option<(Vec<usize>, usize)> {
+        let mut offset = if self.has_virtual_methods(types) {
+            types.data_model.pointer_size()
+        } else {
+            0
+        };
+        let mut offsets = vec![];
+        for member in self.all_members(types) {
+            offset = align_up(offset, member.typ.alignment(types)?);
+            offsets.push(offset);
+            offset += member.typ.size(types)?;
+        }
+        Some((offsets, align_up(offset, self.alignment(types)?)))
+    }
+
     #[auto_enum(Iterator)]
     pub fn all_members<'a>(&'a self, types: &'a TypeInfo) -> impl Iterator<Item = &'a DataMember> {
         match self.base.and_then(|id| types.structs.get(&id)) {
@@ -187,7 +255,7 @@ impl StructType {
 #[derive(Debug)]
 pub struct Method {
     pub name: Ustr,
-    pub typ: Rc<FunctionType>,
+    pub typ: Arc<FunctionType>,
 }
 
 #[derive(Debug)]
@@ -197,30 +265,313 @@ pub struct UnionType {
     pub size: Option<usize>,
 }
 
+impl UnionType {
+    pub fn stub(name: Ustr) -> Self {
+        Self {
+            name,
+            members: vec![],
+            size: None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct EnumType {
     pub name: Ustr,
     pub members: Vec<EnumMember>,
     pub size: Option<usize>,
+    /// The integer type the values are stored as, like `unsigned long long` for `enum E : uint64_t`.
+    pub underlying_type: Type,
+    /// Whether it's a C++ `enum class`, which keeps its enumerators in its own scope.
+    pub is_scoped: bool,
+}
+
+impl EnumType {
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self.underlying_type,
+            Type::Char(true)
+                | Type::Short(true)
+                | Type::Int(true)
+                | Type::Long(true)
+                | Type::LongLong(true)
+                | Type::Int128(true)
+        )
+    }
 }
 
 #[derive(Debug)]
 pub struct EnumMember {
     pub name: Ustr,
-    pub value: i64,
+    pub value: EnumValue,
 }
 
 impl EnumMember {
-    pub fn new(name: Ustr, value: i64) -> Self {
+    pub fn new(name: Ustr, value: EnumValue) -> Self {
         Self { name, value }
     }
 }
 
-#[derive(Debug)]
+/// Value of an enumerator, the values of unsigned enums are kept apart so that the ones above
+/// `i64::MAX` don't turn negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumValue {
+    Signed(i64),
+    Unsigned(u64),
+}
+
+#[derive(Debug, Default)]
 pub struct TypeInfo {
     pub structs: TypeMap<StructId, StructType>,
     pub unions: TypeMap<UnionId, UnionType>,
     pub enums: TypeMap<EnumId, EnumType>,
+    pub data_model: DataModel,
+}
+
+impl TypeInfo {
+    /// Returns all of the named types, structs first, then unions and enums, each sorted by name.
+    /// The outputs use this order instead of the one of the maps to be the same in every run.
+    pub fn sorted_types(&self) -> Vec<Type> {
+        let structs = sorted_ids(self.structs.keys()).into_iter().map(Type::Struct);
+        let unions = sorted_ids(self.unions.keys()).into_iter().map(Type::Union);
+        let enums = sorted_ids(self.enums.keys()).into_iter().map(Type::Enum);
+        structs.chain(unions).chain(enums).collect()
+    }
+
+    /// Adds the types from another type info that are missing or only declared in this one,
+    /// returns the number of types that were added.
+    pub fn merge(&mut self, other: TypeInfo) -> usize {
+        let mut count = 0;
+        for (id, struct_) in other.structs {
+            let is_stub = self
+                .structs
+                .get(&id)
+                .map_or(true, |cur| cur.size.is_none() && cur.members.is_empty());
+            if is_stub {
+                self.structs.insert(id, struct_);
+                count += 1;
+            }
+        }
+        for (id, union) in other.unions {
+            let is_stub = self
+                .unions
+                .get(&id)
+                .map_or(true, |cur| cur.size.is_none() && cur.members.is_empty());
+            if is_stub {
+                self.unions.insert(id, union);
+                count += 1;
+            }
+        }
+        for (id, enum_) in other.enums {
+            if let Entry::Vacant(entry) = self.enums.entry(id) {
+                entry.insert(enum_);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Removes the named types that can't be reached from the roots through the members, the bases
+    /// and the function signatures of other types, returns the number of types that were removed.
+    pub fn retain_reachable(&mut self, roots: impl IntoIterator<Item = Type>) -> usize {
+        let mut structs = HashSet::new();
+        let mut unions = HashSet::new();
+        let mut enums = HashSet::new();
+        let mut pending: Vec<_> = roots.into_iter().collect();
+        while let Some(typ) = pending.pop() {
+            match typ {
+                Type::Pointer(inner)
+                | Type::Reference(inner)
+                | Type::Array(inner)
+                | Type::FixedArray(inner, _)
+                | Type::Vector(inner, _) => pending.push((*inner).clone()),
+                Type::MemberPointer(inner, id) => {
+                    pending.push((*inner).clone());
+                    pending.push(Type::Struct(id));
+                }
+                Type::Function(typ) => {
+                    pending.extend(typ.params.iter().cloned());
+                    pending.push(typ.return_type.clone());
+                }
+                Type::Struct(id) if structs.insert(id) => {
+                    if let Some(struct_) = self.structs.get(&id) {
+                        pending.extend(struct_.base.map(Type::Struct));
+                        pending.extend(struct_.members.iter().map(|member| member.typ.clone()));
+                        pending.extend(
+                            struct_
+                                .virtual_methods
+                                .iter()
+                                .map(|method| Type::Function(method.typ.clone())),
+                        );
+                    }
+                }
+                Type::Union(id) if unions.insert(id) => {
+                    if let Some(union) = self.unions.get(&id) {
+                        pending.extend(union.members.iter().map(|member| member.typ.clone()));
+                    }
+                }
+                Type::Enum(id) => {
+                    enums.insert(id);
+                }
+                _ => {}
+            }
+        }
+
+        let count = self.structs.len() + self.unions.len() + self.enums.len();
+        self.structs.retain(|id, _| structs.contains(id));
+        self.unions.retain(|id, _| unions.contains(id));
+        self.enums.retain(|id, _| enums.contains(id));
+        count - self.structs.len() - self.unions.len() - self.enums.len()
+    }
+
+    /// Compares the struct layouts computed by [`StructType::compute_layout`] with the sizes
+    /// and offsets reported by the frontend.
+    pub fn verify_layouts(&self) -> Vec<LayoutMismatch> {
+        let mut mismatches = vec![];
+        for struct_ in self.structs.values() {
+            let members: Vec<_> = struct_.all_members(self).collect();
+            if members.iter().any(|member| member.is_bitfield()) {
+                continue;
+            }
+            let (expected_size, (offsets, size)) = match (struct_.size, struct_.compute_layout(self)) {
+                (Some(expected), Some(layout)) => (expected, layout),
+                _ => continue,
+            };
+            let member = members.iter().zip(offsets).find_map(|(member, offset)| {
+                let expected = member.bit_offset? / u8::BITS as usize;
+                (expected != offset).then(|| (member.name, expected, offset))
+            });
+            if size != expected_size || member.is_some() {
+                mismatches.push(LayoutMismatch {
+                    name: struct_.name,
+                    expected_size,
+                    size,
+                    member,
+                });
+            }
+        }
+        mismatches.sort_by(|a, b| a.name.cmp(&b.name));
+        mismatches
+    }
+}
+
+/// Sizes of the C types that differ between platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataModel {
+    /// 64-bit Windows, where `long` is 4 bytes.
+    Llp64,
+    /// 64-bit Unix-like systems, where `long` is 8 bytes.
+    Lp64,
+    /// 32-bit x86, where `int`, `long` and pointers are 4 bytes.
+    Ilp32,
+}
+
+impl DataModel {
+    pub fn pointer_size(self) -> usize {
+        match self {
+            DataModel::Llp64 | DataModel::Lp64 => 8,
+            DataModel::Ilp32 => 4,
+        }
+    }
+
+    pub fn long_size(self) -> usize {
+        match self {
+            DataModel::Llp64 | DataModel::Ilp32 => 4,
+            DataModel::Lp64 => 8,
+        }
+    }
+
+    /// Size of a member pointer in the simplest case of a class with single inheritance. MSVC uses a
+    /// 32-bit offset for data members and a plain pointer for member functions, while the Itanium ABI
+    /// uses an offset and a pointer with an adjustment of `this` respectively.
+    pub fn member_pointer_size(self, is_function: bool) -> usize {
+        match (self, is_function) {
+            (DataModel::Llp64, true) => self.pointer_size(),
+            (DataModel::Llp64, false) => 4,
+            (_, true) => self.pointer_size() * 2,
+            (_, false) => self.pointer_size(),
+        }
+    }
+
+    /// `wchar_t` is UTF-16 on Windows and UTF-32 everywhere else.
+    pub fn wchar_size(self) -> usize {
+        match self {
+            DataModel::Llp64 => 2,
+            DataModel::Lp64 | DataModel::Ilp32 => 4,
+        }
+    }
+
+    /// MSVC treats `long double` as `double`, elsewhere it's the x87 extended type padded
+    /// to the alignment of the platform.
+    pub fn long_double_size(self) -> usize {
+        match self {
+            DataModel::Llp64 => 8,
+            DataModel::Lp64 => 16,
+            DataModel::Ilp32 => 12,
+        }
+    }
+}
+
+impl FromStr for DataModel {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "llp64" => Ok(Self::Llp64),
+            "lp64" => Ok(Self::Lp64),
+            "ilp32" => Ok(Self::Ilp32),
+            other => Err(format!("unknown data model '{other}'")),
+        }
+    }
+}
+
+impl Default for DataModel {
+    fn default() -> Self {
+        DataModel::Lp64
+    }
+}
+
+/// A struct with a layout that differs from the one reported by the frontend.
+#[derive(Debug)]
+pub struct LayoutMismatch {
+    pub name: Ustr,
+    pub expected_size: usize,
+    pub size: usize,
+    /// The first member with a different offset, with the expected and the computed offset.
+    pub member: Option<(Ustr, usize, usize)>,
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has a size of {} instead of {}",
+            self.name, self.size, self.expected_size
+        )?;
+        if let Some((name, expected, offset)) = self.member {
+            write!(
+                f,
+                ", first diverging member is {name} at {offset} instead of {expected}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn align_up(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+fn sorted_ids<'a, K: AsRef<Ustr> + Copy + 'a>(ids: impl Iterator<Item = &'a K>) -> Vec<K> {
+    let mut ids: Vec<_> = ids.copied().collect();
+    ids.sort_by(|a, b| a.as_ref().as_str().cmp(b.as_ref().as_str()));
+    ids
+}
+
+fn max_alignment<I: IntoIterator<Item = Option<usize>>>(alignments: I) -> Option<usize> {
+    alignments
+        .into_iter()
+        .try_fold(1, |acc, align| Some(acc.max(align?)))
 }
 
 #[derive(Debug, Default)]
@@ -235,3 +586,66 @@ impl NameAllocator {
         format!("__anonymous{}", i)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retain_reachable_types() {
+        let vector = Ustr::from("Vector");
+        let entity = Ustr::from("Entity");
+        let player = Ustr::from("Player");
+        let unused = Ustr::from("Unused");
+        let handle = Ustr::from("Handle");
+        let team = Ustr::from("Team");
+
+        let mut types = TypeInfo::default();
+        let members = vec![DataMember::basic("x".into(), Type::Float)];
+        let struct_ = StructType {
+            members,
+            ..StructType::stub(vector)
+        };
+        types.structs.insert(vector.into(), struct_);
+        let typ = Type::FixedArray(Type::Struct(vector.into()).into(), 2);
+        let members = vec![DataMember::basic("position".into(), typ)];
+        let struct_ = StructType {
+            members,
+            ..StructType::stub(entity)
+        };
+        types.structs.insert(entity.into(), struct_);
+        let virtual_methods = vec![Method {
+            name: "team".into(),
+            typ: FunctionType::new(vec![], Type::Enum(team.into())).into(),
+        }];
+        let struct_ = StructType {
+            base: Some(entity.into()),
+            virtual_methods,
+            ..StructType::stub(player)
+        };
+        types.structs.insert(player.into(), struct_);
+        types.structs.insert(unused.into(), StructType::stub(unused));
+        types.unions.insert(handle.into(), UnionType::stub(handle));
+        let enum_ = EnumType {
+            name: team,
+            members: vec![],
+            size: Some(4),
+            underlying_type: Type::Int(true),
+            is_scoped: true,
+        };
+        types.enums.insert(team.into(), enum_);
+
+        let roots = [
+            Type::Pointer(Type::Struct(player.into()).into()),
+            Type::Union(handle.into()),
+        ];
+        assert_eq!(types.retain_reachable(roots), 1);
+        assert_eq!(types.sorted_types(), [
+            Type::Struct(entity.into()),
+            Type::Struct(player.into()),
+            Type::Struct(vector.into()),
+            Type::Union(handle.into()),
+            Type::Enum(team.into())
+        ]);
+    }
+}
@@ -0,0 +1,160 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A version of an executable made of dot-separated numbers, like `1.52.0.3401`.
+/// Missing trailing components are treated as zeros, so `1.52` equals `1.52.0.0`.
+#[derive(Debug, Clone)]
+pub struct Version(Vec<u64>);
+
+impl Version {
+    pub fn new(components: Vec<u64>) -> Self {
+        Self(components)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.0.len().max(other.0.len());
+        let component = |version: &Self, i: usize| version.0.get(i).copied().unwrap_or_default();
+        (0..len)
+            .map(|i| component(self, i).cmp(&component(other, i)))
+            .find(|ord| ord.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        str.split('.')
+            .map(|component| component.parse().map_err(|_| format!("invalid version '{str}'")))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let components: Vec<_> = self.0.iter().map(u64::to_string).collect();
+        write!(f, "{}", components.join("."))
+    }
+}
+
+/// Comparisons a version has to pass, written like `>=1.52 <2.0`. A version without an operator
+/// has to be equal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq(Vec<(Comparison, Version)>);
+
+impl VersionReq {
+    pub fn matches(&self, version: &Version) -> bool {
+        self.0.iter().all(|(cmp, expected)| {
+            let ord = version.cmp(expected);
+            match cmp {
+                Comparison::Eq => ord.is_eq(),
+                Comparison::Lt => ord.is_lt(),
+                Comparison::Le => ord.is_le(),
+                Comparison::Gt => ord.is_gt(),
+                Comparison::Ge => ord.is_ge(),
+            }
+        })
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let comparisons = str
+            .split_whitespace()
+            .map(|str| {
+                // the longer operators come first so that they're not mistaken for their prefixes
+                let (cmp, version) = [
+                    (">=", Comparison::Ge),
+                    ("<=", Comparison::Le),
+                    (">", Comparison::Gt),
+                    ("<", Comparison::Lt),
+                    ("=", Comparison::Eq),
+                ]
+                .into_iter()
+                .find_map(|(op, cmp)| Some((cmp, str.strip_prefix(op)?)))
+                .unwrap_or((Comparison::Eq, str));
+                Ok((cmp, version.parse()?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if comparisons.is_empty() {
+            return Err("expected a version".to_owned());
+        }
+        Ok(Self(comparisons))
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let comparisons: Vec<_> = self
+            .0
+            .iter()
+            .map(|(cmp, version)| format!("{}{version}", cmp.as_str()))
+            .collect();
+        write!(f, "{}", comparisons.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_version_ranges() {
+        let req: VersionReq = ">=1.52 <2.0".parse().unwrap();
+        assert!(req.matches(&"1.52".parse().unwrap()));
+        assert!(req.matches(&"1.52.0.3401".parse().unwrap()));
+        assert!(req.matches(&"1.61".parse().unwrap()));
+        assert!(!req.matches(&"1.51.9".parse().unwrap()));
+        assert!(!req.matches(&"2".parse().unwrap()));
+        assert_eq!(req.to_string(), ">=1.52 <2.0");
+
+        let req: VersionReq = "1.6".parse().unwrap();
+        assert!(req.matches(&"1.6.0.0".parse().unwrap()));
+        assert!(!req.matches(&"1.61".parse().unwrap()));
+
+        assert!("".parse::<VersionReq>().is_err());
+        assert!(">=1.x".parse::<VersionReq>().is_err());
+    }
+}
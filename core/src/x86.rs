@@ -0,0 +1,841 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A sequence of instructions with wildcard operands like `mov r??, [rip+?]; call ?`. It's matched
+/// by decoding the instructions at a candidate, so that signatures survive changes in register
+/// allocation and in the encoding of operands between builds.
+///
+/// Only the common general-purpose instructions of 64-bit mode are understood, anything else never
+/// matches.
+#[derive(Debug, Hash)]
+pub struct InsnPattern {
+    insns: Vec<InsnMatcher>,
+}
+
+impl InsnPattern {
+    pub fn parse(str: &str) -> Result<Self, peg::error::ParseError<peg::str::LineCol>> {
+        insn_pattern::pattern(str)
+    }
+
+    /// Decodes the instructions at the start of `bytes` and checks them against the pattern.
+    pub fn does_match(&self, bytes: &[u8]) -> bool {
+        let mut pos = 0;
+        for matcher in &self.insns {
+            match decode(&bytes[pos..]) {
+                Some(insn) if matcher.does_match(&insn) => pos += insn.len,
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Hash)]
+struct InsnMatcher {
+    /// `None` matches any instruction.
+    mnemonic: Option<Mnemonic>,
+    /// `None` matches any operands.
+    operands: Option<Vec<OperandPattern>>,
+}
+
+impl InsnMatcher {
+    fn does_match(&self, insn: &Insn) -> bool {
+        self.mnemonic.map_or(true, |mnemonic| mnemonic == insn.mnemonic)
+            && self.operands.as_ref().map_or(true, |operands| {
+                operands.len() == insn.operands.len()
+                    && operands
+                        .iter()
+                        .zip(&insn.operands)
+                        .all(|(pat, op)| pat.does_match(op))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum OperandPattern {
+    /// `?`
+    Any,
+    /// `r??`
+    AnyReg,
+    /// `reg64`, `reg32`, `reg16` or `reg8`
+    RegOfSize(u8),
+    /// A register name like `rcx` or `r8d`.
+    Reg(Reg),
+    /// `[rip+?]`
+    RipRelative,
+    /// `[?]`
+    Mem,
+    /// `imm` or a number.
+    Imm(Option<i64>),
+    /// `rel`, the target of a call or a jump.
+    Rel,
+}
+
+impl OperandPattern {
+    fn does_match(&self, op: &Operand) -> bool {
+        match (self, op) {
+            (OperandPattern::Any, _) => true,
+            (OperandPattern::AnyReg, Operand::Reg(_)) => true,
+            (OperandPattern::RegOfSize(size), Operand::Reg(reg)) => reg.size == *size,
+            (OperandPattern::Reg(expected), Operand::Reg(reg)) => expected == reg,
+            (OperandPattern::RipRelative, Operand::Mem(mem)) => mem.is_rip_relative,
+            (OperandPattern::Mem, Operand::Mem(_)) => true,
+            (OperandPattern::Imm(expected), Operand::Imm(val)) => expected.map_or(true, |exp| exp == *val),
+            (OperandPattern::Rel, Operand::Rel(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn from_ident(str: &str) -> Option<Self> {
+        match str {
+            "imm" => Some(OperandPattern::Imm(None)),
+            "rel" => Some(OperandPattern::Rel),
+            "reg64" => Some(OperandPattern::RegOfSize(8)),
+            "reg32" => Some(OperandPattern::RegOfSize(4)),
+            "reg16" => Some(OperandPattern::RegOfSize(2)),
+            "reg8" => Some(OperandPattern::RegOfSize(1)),
+            other => Reg::from_name(other).map(OperandPattern::Reg),
+        }
+    }
+}
+
+peg::parser! {
+    grammar insn_pattern() for str {
+        rule _() =
+            quiet!{[' ' | '\t']*}
+        rule ident() -> &'input str
+            = $(['a'..='z' | '0'..='9']+)
+        rule number() -> i64
+            = "0x" n:$(['0'..='9' | 'a'..='f' | 'A'..='F']+) {? i64::from_str_radix(n, 16).or(Err("hex number")) }
+            / n:$("-"? ['0'..='9']+) {? n.parse().or(Err("number")) }
+        rule mnemonic() -> Option<Mnemonic>
+            = "?" { None }
+            / id:ident() {? id.parse().map(Some).or(Err("mnemonic")) }
+        rule operand() -> OperandPattern
+            = "[" _ "rip" _ "+" _ "?" _ "]" { OperandPattern::RipRelative }
+            / "[" _ "?" _ "]" { OperandPattern::Mem }
+            / "r??" { OperandPattern::AnyReg }
+            / "?" { OperandPattern::Any }
+            / n:number() { OperandPattern::Imm(Some(n)) }
+            / id:ident() {? OperandPattern::from_ident(id).ok_or("operand") }
+        rule operands() -> Vec<OperandPattern>
+            = [' ' | '\t']+ ops:(operand() ++ (_ "," _)) { ops }
+        rule insn() -> InsnMatcher
+            = mnemonic:mnemonic() operands:operands()? { InsnMatcher { mnemonic, operands } }
+        pub rule pattern() -> InsnPattern
+            = _ insns:(insn() ++ (_ ";" _)) _ { InsnPattern { insns } }
+    }
+}
+
+/// Classes of instructions, the condition codes of jumps and conditional moves are not distinguished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mnemonic {
+    Mov,
+    Movzx,
+    Movsx,
+    Movsxd,
+    Lea,
+    Call,
+    Jmp,
+    Jcc,
+    Push,
+    Pop,
+    Add,
+    Or,
+    Adc,
+    Sbb,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+    Test,
+    Imul,
+    Shl,
+    Shr,
+    Sar,
+    Inc,
+    Dec,
+    Setcc,
+    Cmovcc,
+    Ret,
+    Nop,
+    Int3,
+}
+
+const ALU_MNEMONICS: [Mnemonic; 8] = [
+    Mnemonic::Add,
+    Mnemonic::Or,
+    Mnemonic::Adc,
+    Mnemonic::Sbb,
+    Mnemonic::And,
+    Mnemonic::Sub,
+    Mnemonic::Xor,
+    Mnemonic::Cmp,
+];
+
+impl Mnemonic {
+    const ALL: [Mnemonic; 30] = [
+        Mnemonic::Mov,
+        Mnemonic::Movzx,
+        Mnemonic::Movsx,
+        Mnemonic::Movsxd,
+        Mnemonic::Lea,
+        Mnemonic::Call,
+        Mnemonic::Jmp,
+        Mnemonic::Jcc,
+        Mnemonic::Push,
+        Mnemonic::Pop,
+        Mnemonic::Add,
+        Mnemonic::Or,
+        Mnemonic::Adc,
+        Mnemonic::Sbb,
+        Mnemonic::And,
+        Mnemonic::Sub,
+        Mnemonic::Xor,
+        Mnemonic::Cmp,
+        Mnemonic::Test,
+        Mnemonic::Imul,
+        Mnemonic::Shl,
+        Mnemonic::Shr,
+        Mnemonic::Sar,
+        Mnemonic::Inc,
+        Mnemonic::Dec,
+        Mnemonic::Setcc,
+        Mnemonic::Cmovcc,
+        Mnemonic::Ret,
+        Mnemonic::Nop,
+        Mnemonic::Int3,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mnemonic::Mov => "mov",
+            Mnemonic::Movzx => "movzx",
+            Mnemonic::Movsx => "movsx",
+            Mnemonic::Movsxd => "movsxd",
+            Mnemonic::Lea => "lea",
+            Mnemonic::Call => "call",
+            Mnemonic::Jmp => "jmp",
+            Mnemonic::Jcc => "jcc",
+            Mnemonic::Push => "push",
+            Mnemonic::Pop => "pop",
+            Mnemonic::Add => "add",
+            Mnemonic::Or => "or",
+            Mnemonic::Adc => "adc",
+            Mnemonic::Sbb => "sbb",
+            Mnemonic::And => "and",
+            Mnemonic::Sub => "sub",
+            Mnemonic::Xor => "xor",
+            Mnemonic::Cmp => "cmp",
+            Mnemonic::Test => "test",
+            Mnemonic::Imul => "imul",
+            Mnemonic::Shl => "shl",
+            Mnemonic::Shr => "shr",
+            Mnemonic::Sar => "sar",
+            Mnemonic::Inc => "inc",
+            Mnemonic::Dec => "dec",
+            Mnemonic::Setcc => "setcc",
+            Mnemonic::Cmovcc => "cmovcc",
+            Mnemonic::Ret => "ret",
+            Mnemonic::Nop => "nop",
+            Mnemonic::Int3 => "int3",
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mnemonic| mnemonic.as_str() == str)
+            .ok_or_else(|| format!("unknown mnemonic '{str}'"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Reg {
+    num: u8,
+    /// Size in bytes.
+    size: u8,
+}
+
+impl Reg {
+    const NAMES_64: [&'static str; 8] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi"];
+    const NAMES_32: [&'static str; 8] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+    const NAMES_16: [&'static str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+    const NAMES_8: [&'static str; 8] = ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil"];
+
+    fn from_name(name: &str) -> Option<Self> {
+        if let Some(num) = Self::NAMES_64.iter().position(|str| *str == name) {
+            return Some(Reg {
+                num: num as u8,
+                size: 8,
+            });
+        }
+        if let Some(num) = Self::NAMES_32.iter().position(|str| *str == name) {
+            return Some(Reg {
+                num: num as u8,
+                size: 4,
+            });
+        }
+        // r8 to r15 with an optional size suffix
+        let rest = name.strip_prefix('r')?;
+        let (num, size) = match rest.find(|c: char| !c.is_ascii_digit()) {
+            Some(pos) => match &rest[pos..] {
+                "d" => (&rest[..pos], 4),
+                "w" => (&rest[..pos], 2),
+                "b" => (&rest[..pos], 1),
+                _ => return None,
+            },
+            None => (rest, 8),
+        };
+        let num: u8 = num.parse().ok()?;
+        (8..16).contains(&num).then_some(Reg { num, size })
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = match self.size {
+            8 => &Self::NAMES_64,
+            4 => &Self::NAMES_32,
+            2 => &Self::NAMES_16,
+            _ => &Self::NAMES_8,
+        };
+        match names.get(self.num as usize) {
+            Some(name) => f.write_str(name),
+            None => {
+                let suffix = match self.size {
+                    8 => "",
+                    4 => "d",
+                    2 => "w",
+                    _ => "b",
+                };
+                write!(f, "r{}{suffix}", self.num)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mem {
+    pub is_rip_relative: bool,
+    pub base: Option<Reg>,
+    /// The index register and its scale.
+    pub index: Option<(Reg, u8)>,
+    pub disp: i64,
+}
+
+impl fmt::Display for Mem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if self.is_rip_relative {
+            parts.push("rip".to_owned());
+        }
+        if let Some(base) = self.base {
+            parts.push(base.to_string());
+        }
+        match self.index {
+            Some((index, 1)) => parts.push(index.to_string()),
+            Some((index, scale)) => parts.push(format!("{index}*{scale}")),
+            None => {}
+        }
+        write!(f, "[{}", parts.join("+"))?;
+        match self.disp {
+            0 if !parts.is_empty() => {}
+            disp if disp < 0 => write!(f, "-0x{:X}", disp.unsigned_abs())?,
+            disp if parts.is_empty() => write!(f, "0x{disp:X}")?,
+            disp => write!(f, "+0x{disp:X}")?,
+        }
+        write!(f, "]")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg(Reg),
+    Mem(Mem),
+    Imm(i64),
+    /// The displacement of a call or a jump.
+    Rel(i64),
+}
+
+#[derive(Debug)]
+pub struct Insn {
+    pub mnemonic: Mnemonic,
+    pub operands: Vec<Operand>,
+    /// Length of the encoded instruction in bytes.
+    pub len: usize,
+}
+
+impl Insn {
+    /// Formats the instruction in the syntax of `@x86` patterns, the targets of calls and jumps
+    /// are resolved against the address of the instruction.
+    pub fn to_string_at(&self, va: u64) -> String {
+        let operands: Vec<_> = self
+            .operands
+            .iter()
+            .map(|op| match op {
+                Operand::Reg(reg) => reg.to_string(),
+                Operand::Mem(mem) => mem.to_string(),
+                Operand::Imm(imm) if *imm < 0 => format!("-0x{:X}", imm.unsigned_abs()),
+                Operand::Imm(imm) => format!("0x{imm:X}"),
+                Operand::Rel(rel) => {
+                    let target = va.wrapping_add(self.len as u64).wrapping_add(*rel as u64);
+                    format!("0x{target:X}")
+                }
+            })
+            .collect();
+        if operands.is_empty() {
+            self.mnemonic.to_string()
+        } else {
+            format!("{} {}", self.mnemonic, operands.join(", "))
+        }
+    }
+}
+
+/// Decodes up to `count` instructions at the start of `bytes`, located at the virtual address `va`,
+/// into lines with the address and the instruction. Decoding stops at the first instruction
+/// that isn't understood, it's shown as a byte.
+pub fn disassemble(bytes: &[u8], va: u64, count: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut pos = 0;
+    while lines.len() < count && pos < bytes.len() {
+        let insn_va = va + pos as u64;
+        match decode(&bytes[pos..]) {
+            Some(insn) => {
+                lines.push(format!("{insn_va:X}  {}", insn.to_string_at(insn_va)));
+                pos += insn.len;
+            }
+            None => {
+                lines.push(format!("{insn_va:X}  db 0x{:02X}", bytes[pos]));
+                break;
+            }
+        }
+    }
+    lines
+}
+
+/// Decodes a single 64-bit mode instruction at the start of `bytes`.
+pub fn decode(bytes: &[u8]) -> Option<Insn> {
+    let mut decoder = Decoder {
+        bytes,
+        pos: 0,
+        rex: 0,
+        operand_size_override: false,
+    };
+    let (mnemonic, operands) = decoder.decode()?;
+    Some(Insn {
+        mnemonic,
+        operands,
+        len: decoder.pos,
+    })
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    rex: u8,
+    operand_size_override: bool,
+}
+
+impl<'a> Decoder<'a> {
+    const REX_W: u8 = 0b1000;
+    const REX_R: u8 = 0b0100;
+    const REX_X: u8 = 0b0010;
+    const REX_B: u8 = 0b0001;
+
+    fn decode(&mut self) -> Option<(Mnemonic, Vec<Operand>)> {
+        let mut opcode = self.u8()?;
+        // legacy prefixes
+        while matches!(
+            opcode,
+            0x66 | 0x67 | 0xF0 | 0xF2 | 0xF3 | 0x2E | 0x36 | 0x3E | 0x26 | 0x64 | 0x65
+        ) {
+            self.operand_size_override |= opcode == 0x66;
+            opcode = self.u8()?;
+        }
+        if opcode & 0xF0 == 0x40 {
+            self.rex = opcode;
+            opcode = self.u8()?;
+        }
+        let size = self.operand_size();
+
+        let res = match opcode {
+            0x0F => return self.decode_two_byte(),
+            0x00..=0x3F if opcode & 0x7 < 6 => {
+                let mnemonic = ALU_MNEMONICS[(opcode >> 3) as usize];
+                let operands = match opcode & 0x7 {
+                    0 => self.rm_reg(1)?,
+                    1 => self.rm_reg(size)?,
+                    2 => self.reg_rm(1, 1)?,
+                    3 => self.reg_rm(size, size)?,
+                    4 => vec![Operand::Reg(Reg { num: 0, size: 1 }), self.imm8()?],
+                    _ => vec![Operand::Reg(Reg { num: 0, size }), self.imm_z()?],
+                };
+                (mnemonic, operands)
+            }
+            0x50..=0x57 => (Mnemonic::Push, vec![self.opcode_reg(opcode, 8)]),
+            0x58..=0x5F => (Mnemonic::Pop, vec![self.opcode_reg(opcode, 8)]),
+            0x63 => (Mnemonic::Movsxd, self.reg_rm(size, 4)?),
+            0x68 => (Mnemonic::Push, vec![self.imm_z()?]),
+            0x6A => (Mnemonic::Push, vec![self.imm8()?]),
+            0x69 | 0x6B => {
+                let mut operands = self.reg_rm(size, size)?;
+                operands.push(if opcode == 0x69 {
+                    self.imm_z()?
+                } else {
+                    self.imm8()?
+                });
+                (Mnemonic::Imul, operands)
+            }
+            0x70..=0x7F => (Mnemonic::Jcc, vec![self.rel8()?]),
+            0x80 | 0x81 | 0x83 => {
+                let (ext, rm) = self.modrm(if opcode == 0x80 { 1 } else { size })?;
+                let imm = if opcode == 0x81 {
+                    self.imm_z()?
+                } else {
+                    self.imm8()?
+                };
+                (ALU_MNEMONICS[ext as usize & 0x7], vec![rm, imm])
+            }
+            0x84 => (Mnemonic::Test, self.rm_reg(1)?),
+            0x85 => (Mnemonic::Test, self.rm_reg(size)?),
+            0x88 => (Mnemonic::Mov, self.rm_reg(1)?),
+            0x89 => (Mnemonic::Mov, self.rm_reg(size)?),
+            0x8A => (Mnemonic::Mov, self.reg_rm(1, 1)?),
+            0x8B => (Mnemonic::Mov, self.reg_rm(size, size)?),
+            0x8D => (Mnemonic::Lea, self.reg_rm(size, size)?),
+            0x90 => (Mnemonic::Nop, vec![]),
+            0xA8 => (Mnemonic::Test, vec![
+                Operand::Reg(Reg { num: 0, size: 1 }),
+                self.imm8()?,
+            ]),
+            0xA9 => (Mnemonic::Test, vec![
+                Operand::Reg(Reg { num: 0, size }),
+                self.imm_z()?,
+            ]),
+            0xB0..=0xB7 => (Mnemonic::Mov, vec![self.opcode_reg(opcode, 1), self.imm8()?]),
+            0xB8..=0xBF => {
+                let reg = self.opcode_reg(opcode, size);
+                let imm = if size == 8 {
+                    Operand::Imm(self.i64()?)
+                } else {
+                    self.imm_z()?
+                };
+                (Mnemonic::Mov, vec![reg, imm])
+            }
+            0xC0 | 0xC1 | 0xD0 | 0xD1 | 0xD2 | 0xD3 => {
+                let (ext, rm) = self.modrm(if opcode & 1 == 0 { 1 } else { size })?;
+                let mnemonic = match ext & 0x7 {
+                    4 => Mnemonic::Shl,
+                    5 => Mnemonic::Shr,
+                    7 => Mnemonic::Sar,
+                    _ => return None,
+                };
+                let operands = match opcode {
+                    0xC0 | 0xC1 => vec![rm, self.imm8()?],
+                    0xD0 | 0xD1 => vec![rm, Operand::Imm(1)],
+                    _ => vec![rm, Operand::Reg(Reg { num: 1, size: 1 })],
+                };
+                (mnemonic, operands)
+            }
+            0xC2 => (Mnemonic::Ret, vec![Operand::Imm(self.u16()? as i64)]),
+            0xC3 => (Mnemonic::Ret, vec![]),
+            0xC6 | 0xC7 => {
+                let (ext, rm) = self.modrm(if opcode == 0xC6 { 1 } else { size })?;
+                if ext & 0x7 != 0 {
+                    return None;
+                }
+                let imm = if opcode == 0xC6 {
+                    self.imm8()?
+                } else {
+                    self.imm_z()?
+                };
+                (Mnemonic::Mov, vec![rm, imm])
+            }
+            0xCC => (Mnemonic::Int3, vec![]),
+            0xE8 => (Mnemonic::Call, vec![self.rel32()?]),
+            0xE9 => (Mnemonic::Jmp, vec![self.rel32()?]),
+            0xEB => (Mnemonic::Jmp, vec![self.rel8()?]),
+            0xF6 | 0xF7 => {
+                let (ext, rm) = self.modrm(if opcode == 0xF6 { 1 } else { size })?;
+                if ext & 0x7 != 0 {
+                    return None;
+                }
+                let imm = if opcode == 0xF6 {
+                    self.imm8()?
+                } else {
+                    self.imm_z()?
+                };
+                (Mnemonic::Test, vec![rm, imm])
+            }
+            0xFE | 0xFF => {
+                let (ext, _) = self.peek_modrm()?;
+                let (mnemonic, size) = match (opcode, ext & 0x7) {
+                    (_, 0) => (Mnemonic::Inc, if opcode == 0xFE { 1 } else { size }),
+                    (_, 1) => (Mnemonic::Dec, if opcode == 0xFE { 1 } else { size }),
+                    (0xFF, 2) => (Mnemonic::Call, 8),
+                    (0xFF, 4) => (Mnemonic::Jmp, 8),
+                    (0xFF, 6) => (Mnemonic::Push, 8),
+                    _ => return None,
+                };
+                let (_, rm) = self.modrm(size)?;
+                (mnemonic, vec![rm])
+            }
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    fn decode_two_byte(&mut self) -> Option<(Mnemonic, Vec<Operand>)> {
+        let size = self.operand_size();
+        let res = match self.u8()? {
+            0x1F => {
+                let (_, rm) = self.modrm(size)?;
+                (Mnemonic::Nop, vec![rm])
+            }
+            0x40..=0x4F => (Mnemonic::Cmovcc, self.reg_rm(size, size)?),
+            0x80..=0x8F => (Mnemonic::Jcc, vec![self.rel32()?]),
+            0x90..=0x9F => {
+                let (_, rm) = self.modrm(1)?;
+                (Mnemonic::Setcc, vec![rm])
+            }
+            0xAF => (Mnemonic::Imul, self.reg_rm(size, size)?),
+            0xB6 => (Mnemonic::Movzx, self.reg_rm(size, 1)?),
+            0xB7 => (Mnemonic::Movzx, self.reg_rm(size, 2)?),
+            0xBE => (Mnemonic::Movsx, self.reg_rm(size, 1)?),
+            0xBF => (Mnemonic::Movsx, self.reg_rm(size, 2)?),
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    fn operand_size(&self) -> u8 {
+        if self.rex & Self::REX_W != 0 {
+            8
+        } else if self.operand_size_override {
+            2
+        } else {
+            4
+        }
+    }
+
+    fn opcode_reg(&self, opcode: u8, size: u8) -> Operand {
+        let num = (opcode & 0x7) | if self.rex & Self::REX_B != 0 { 8 } else { 0 };
+        Operand::Reg(Reg { num, size })
+    }
+
+    /// Operands encoded as `r/m, reg`.
+    fn rm_reg(&mut self, size: u8) -> Option<Vec<Operand>> {
+        let (reg, rm) = self.modrm(size)?;
+        Some(vec![rm, Operand::Reg(Reg { num: reg, size })])
+    }
+
+    /// Operands encoded as `reg, r/m`, the sizes can differ for extending moves.
+    fn reg_rm(&mut self, reg_size: u8, rm_size: u8) -> Option<Vec<Operand>> {
+        let (reg, rm) = self.modrm(rm_size)?;
+        Some(vec![
+            Operand::Reg(Reg {
+                num: reg,
+                size: reg_size,
+            }),
+            rm,
+        ])
+    }
+
+    fn peek_modrm(&self) -> Option<(u8, u8)> {
+        let modrm = *self.bytes.get(self.pos)?;
+        Some(((modrm >> 3) & 0x7, modrm & 0x7))
+    }
+
+    /// Decodes the ModRM byte with the SIB byte and the displacement that follow it. Returns the
+    /// `reg` field and the `r/m` operand.
+    fn modrm(&mut self, size: u8) -> Option<(u8, Operand)> {
+        let modrm = self.u8()?;
+        let mode = modrm >> 6;
+        let reg = ((modrm >> 3) & 0x7) | if self.rex & Self::REX_R != 0 { 8 } else { 0 };
+        let rm = modrm & 0x7;
+
+        let rex_b = if self.rex & Self::REX_B != 0 { 8 } else { 0 };
+        if mode == 0b11 {
+            let num = rm | rex_b;
+            return Some((reg, Operand::Reg(Reg { num, size })));
+        }
+        let mut mem = Mem {
+            is_rip_relative: false,
+            base: None,
+            index: None,
+            disp: 0,
+        };
+        if rm == 0b100 {
+            let sib = self.u8()?;
+            let index = ((sib >> 3) & 0x7) | if self.rex & Self::REX_X != 0 { 8 } else { 0 };
+            // rsp can't be an index, it stands for no index
+            if index != 0b100 {
+                mem.index = Some((Reg { num: index, size: 8 }, 1 << (sib >> 6)));
+            }
+            // no base register, a 32-bit displacement follows
+            if mode == 0 && sib & 0x7 == 0b101 {
+                mem.disp = self.i32()?.into();
+            } else {
+                mem.base = Some(Reg {
+                    num: (sib & 0x7) | rex_b,
+                    size: 8,
+                });
+            }
+        } else if mode == 0 && rm == 0b101 {
+            mem.is_rip_relative = true;
+            mem.disp = self.i32()?.into();
+        } else {
+            mem.base = Some(Reg {
+                num: rm | rex_b,
+                size: 8,
+            });
+        }
+        match mode {
+            0b01 => mem.disp = (self.u8()? as i8).into(),
+            0b10 => mem.disp = self.i32()?.into(),
+            _ => {}
+        }
+        Some((reg, Operand::Mem(mem)))
+    }
+
+    fn imm8(&mut self) -> Option<Operand> {
+        Some(Operand::Imm(self.u8()? as i8 as i64))
+    }
+
+    /// An immediate of the operand size, limited to 32 bits and sign-extended.
+    fn imm_z(&mut self) -> Option<Operand> {
+        let imm = if self.operand_size() == 2 {
+            self.u16()? as i16 as i64
+        } else {
+            self.i32()? as i64
+        };
+        Some(Operand::Imm(imm))
+    }
+
+    fn rel8(&mut self) -> Option<Operand> {
+        Some(Operand::Rel(self.u8()? as i8 as i64))
+    }
+
+    fn rel32(&mut self) -> Option<Operand> {
+        Some(Operand::Rel(self.i32()? as i64))
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.take()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_le_bytes(self.take()?))
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.bytes.get(self.pos..self.pos + N)?.try_into().ok()?;
+        self.pos += N;
+        Some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_common_instructions() {
+        // mov rcx, [rip+0x10]
+        let insn = decode(&[0x48, 0x8B, 0x0D, 0x10, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(insn.mnemonic, Mnemonic::Mov);
+        assert_eq!(insn.len, 7);
+        assert_eq!(insn.operands, [
+            Operand::Reg(Reg { num: 1, size: 8 }),
+            Operand::Mem(Mem {
+                is_rip_relative: true,
+                base: None,
+                index: None,
+                disp: 0x10,
+            })
+        ]);
+
+        // sub rsp, 0x28
+        let insn = decode(&[0x48, 0x83, 0xEC, 0x28]).unwrap();
+        assert_eq!(insn.mnemonic, Mnemonic::Sub);
+        assert_eq!(insn.operands, [
+            Operand::Reg(Reg { num: 4, size: 8 }),
+            Operand::Imm(0x28)
+        ]);
+
+        // mov [rsp+rax*8+0x10], r9d
+        let insn = decode(&[0x44, 0x89, 0x4C, 0xC4, 0x10]).unwrap();
+        assert_eq!(insn.len, 5);
+        assert_eq!(insn.operands[1], Operand::Reg(Reg { num: 9, size: 4 }));
+
+        assert!(decode(&[0x0F, 0x0B]).is_none());
+        assert!(decode(&[0x48, 0x8B]).is_none());
+    }
+
+    #[test]
+    fn disassemble_instructions() {
+        // sub rsp, 0x28; mov [rsp+rax*8+0x10], r9d; call -5; ud2
+        let bytes = [
+            0x48, 0x83, 0xEC, 0x28, 0x44, 0x89, 0x4C, 0xC4, 0x10, 0xE8, 0xFB, 0xFF, 0xFF, 0xFF, 0x0F, 0x0B,
+        ];
+        assert_eq!(disassemble(&bytes, 0x140001000, 8), [
+            "140001000  sub rsp, 0x28",
+            "140001004  mov [rsp+rax*8+0x10], r9d",
+            "140001009  call 0x140001009",
+            "14000100E  db 0x0F",
+        ]);
+        assert_eq!(disassemble(&bytes, 0x140001000, 1), ["140001000  sub rsp, 0x28"]);
+    }
+
+    #[test]
+    fn match_instruction_patterns() {
+        let pat = InsnPattern::parse("mov r??, [rip+?]; test reg64, reg64; jcc; call ?").unwrap();
+        // mov rcx, [rip+0x10]; test rcx, rcx; je +5; call +0
+        let bytes = [
+            0x48, 0x8B, 0x0D, 0x10, 0x00, 0x00, 0x00, 0x48, 0x85, 0xC9, 0x74, 0x05, 0xE8, 0x00, 0x00, 0x00,
+            0x00,
+        ];
+        assert!(pat.does_match(&bytes));
+        // same, but loading into r8 with a different encoding of the jump
+        let bytes = [
+            0x4C, 0x8B, 0x05, 0x10, 0x00, 0x00, 0x00, 0x4D, 0x85, 0xC0, 0x0F, 0x84, 0x05, 0x00, 0x00, 0x00,
+            0xE8, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert!(pat.does_match(&bytes));
+
+        let pat = InsnPattern::parse("mov rcx, [?]; ?").unwrap();
+        assert!(!pat.does_match(&bytes));
+        let pat = InsnPattern::parse("mov r8, [?]; ? reg64, reg64").unwrap();
+        assert!(pat.does_match(&bytes));
+        let pat = InsnPattern::parse("sub rsp, 0x28").unwrap();
+        assert!(pat.does_match(&[0x48, 0x83, 0xEC, 0x28]));
+    }
+
+    #[test]
+    fn reject_invalid_instruction_patterns() {
+        assert!(InsnPattern::parse("mov r??,").is_err());
+        assert!(InsnPattern::parse("frobnicate rax").is_err());
+        assert!(InsnPattern::parse("mov r16d, rax").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::exe::ExecutableData;
+
+/// Opcodes of `LEA` and `MOV` that load an address or a value from memory.
+const LOAD_OPCODES: &[u8] = &[0x8D, 0x8B];
+const CALL_OPCODE: u8 = 0xE8;
+
+/// Returns the virtual addresses of all NUL-terminated occurrences of a string in the read-only data.
+pub fn find_strings(data: &ExecutableData, str: &[u8]) -> Vec<u64> {
+    let mut needle = str.to_vec();
+    needle.push(0);
+    data.rdata()
+        .windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| *window == needle)
+        .map(|(offset, _)| data.rdata_offset() + offset as u64)
+        .collect()
+}
+
+/// Finds the x86-64 instructions that load one of the targets with RIP-relative addressing,
+/// like `lea rcx, [rip+disp]` or `mov rax, [rip+disp]`. The result maps each target to the
+/// offsets of the instructions in the text section.
+pub fn find_rip_relative_refs(data: &ExecutableData, targets: &HashSet<u64>) -> HashMap<u64, Vec<usize>> {
+    let text = data.text();
+    let mut refs: HashMap<u64, Vec<usize>> = HashMap::new();
+    // the displacement is preceded by at least an opcode and a ModRM byte
+    for offset in 2..text.len().saturating_sub(3) {
+        let modrm = text[offset - 1];
+        // mod = 00 and r/m = 101 is RIP-relative in 64-bit mode
+        if modrm & 0xC7 != 0x05 || !LOAD_OPCODES.contains(&text[offset - 2]) {
+            continue;
+        }
+        let disp = i32::from_le_bytes(text[offset..offset + 4].try_into().unwrap());
+        let next = data.text_offset() + offset as u64 + 4;
+        let target = next.wrapping_add(disp as i64 as u64);
+        if targets.contains(&target) {
+            // include the REX prefix if there's one
+            let start = match text.get(offset.wrapping_sub(3)) {
+                Some(rex) if rex & 0xF0 == 0x40 => offset - 3,
+                _ => offset - 2,
+            };
+            refs.entry(target).or_default().push(start);
+        }
+    }
+    refs
+}
+
+/// Finds the relative `CALL` instructions targeting one of the addresses. The result maps each target
+/// to the offsets of the calls in the text section.
+pub fn find_calls(data: &ExecutableData, targets: &HashSet<u64>) -> HashMap<u64, Vec<usize>> {
+    let text = data.text();
+    let mut calls: HashMap<u64, Vec<usize>> = HashMap::new();
+    for offset in 0..text.len().saturating_sub(4) {
+        if text[offset] != CALL_OPCODE {
+            continue;
+        }
+        let disp = i32::from_le_bytes(text[offset + 1..offset + 5].try_into().unwrap());
+        let next = data.text_offset() + offset as u64 + 5;
+        let target = next.wrapping_add(disp as i64 as u64);
+        if targets.contains(&target) {
+            calls.entry(target).or_default().push(offset);
+        }
+    }
+    calls
+}
+
+/// Decodes the escape sequences (`\"`, `\\`, `\n`, `\t` and `\0`) of a string literal in a spec.
+pub fn unescape(str: &str) -> String {
+    let mut res = String::with_capacity(str.len());
+    let mut chars = str.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(next @ ('"' | '\\'))) => res.push(next),
+            ('\\', Some('n')) => res.push('\n'),
+            ('\\', Some('t')) => res.push('\t'),
+            ('\\', Some('0')) => res.push('\0'),
+            _ => {
+                res.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_string_literal() {
+        assert_eq!(unescape(r#"Failed to load \"%s\"\n"#), "Failed to load \"%s\"\n");
+        assert_eq!(unescape(r#"C:\\dir"#), r#"C:\dir"#);
+        assert_eq!(unescape(r#"trailing\"#), r#"trailing\"#);
+    }
+}
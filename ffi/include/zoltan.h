@@ -0,0 +1,118 @@
+#ifndef ZOLTAN_H
+#define ZOLTAN_H
+
+/* This file has been generated by cbindgen, don't edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A session over a copy of an executable, with the specs waiting to be resolved.
+ */
+typedef struct ZoltanSession ZoltanSession;
+
+/**
+ * A resolved symbol.
+ */
+typedef struct ZoltanSymbol {
+  /**
+   * A NUL-terminated name, valid until the process exits.
+   */
+  const char *name;
+  /**
+   * The address relative to the image base.
+   */
+  uint64_t rva;
+  /**
+   * The size of the function in bytes, or 0 when it's not known.
+   */
+  uint64_t size;
+} ZoltanSymbol;
+
+/**
+ * Creates a session from the bytes of an executable, the bytes are copied. Returns null when
+ * the executable cannot be loaded.
+ *
+ * # Safety
+ * `bytes` has to point to `len` readable bytes.
+ */
+struct ZoltanSession *zoltan_session_new(const uint8_t *bytes, uintptr_t len);
+
+/**
+ * Frees a session, the pointer cannot be used afterwards.
+ *
+ * # Safety
+ * `session` has to be null or a pointer returned by [`zoltan_session_new`] that wasn't freed yet.
+ */
+void zoltan_session_free(struct ZoltanSession *session);
+
+/**
+ * Adds a spec to be resolved with the next call to [`zoltan_session_resolve`]. The parameters are
+ * given one per line in the same format as in the headers, like `@pattern 48 8B 0D` or
+ * `/// @nth first`. Returns false when they're invalid, the reason is available through
+ * [`zoltan_session_last_error`].
+ *
+ * # Safety
+ * `session` has to be a valid session, `name` and `params` have to be NUL-terminated strings.
+ */
+bool zoltan_session_add_spec(struct ZoltanSession *session, const char *name, const char *params);
+
+/**
+ * Resolves the specs added since the last call. Returns the number of specs that failed, which
+ * can be inspected with [`zoltan_session_error`], or -1 when the resolution couldn't be completed.
+ *
+ * # Safety
+ * `session` has to be a valid session.
+ */
+intptr_t zoltan_session_resolve(struct ZoltanSession *session);
+
+/**
+ * Returns the number of symbols resolved so far.
+ *
+ * # Safety
+ * `session` has to be a valid session.
+ */
+uintptr_t zoltan_session_symbol_count(const struct ZoltanSession *session);
+
+/**
+ * Writes the symbol at an index to `out`, returns false when the index is out of bounds.
+ *
+ * # Safety
+ * `session` has to be a valid session and `out` has to be writable.
+ */
+bool zoltan_session_symbol(const struct ZoltanSession *session,
+                           uintptr_t index,
+                           struct ZoltanSymbol *out);
+
+/**
+ * Looks up a symbol by name and writes it to `out`, returns false when it wasn't resolved.
+ *
+ * # Safety
+ * `session` has to be a valid session, `name` has to be a NUL-terminated string and `out` has
+ * to be writable.
+ */
+bool zoltan_session_find_symbol(const struct ZoltanSession *session,
+                                const char *name,
+                                struct ZoltanSymbol *out);
+
+/**
+ * Returns the message of a failure from the last call to [`zoltan_session_resolve`], or null
+ * when the index is out of bounds. The string is valid until the next resolution.
+ *
+ * # Safety
+ * `session` has to be a valid session.
+ */
+const char *zoltan_session_error(const struct ZoltanSession *session, uintptr_t index);
+
+/**
+ * Returns the reason of the last call that failed, or null when there's none. The string is
+ * valid until the next failure.
+ *
+ * # Safety
+ * `session` has to be a valid session.
+ */
+const char *zoltan_session_last_error(const struct ZoltanSession *session);
+
+#endif /* ZOLTAN_H */
@@ -0,0 +1,253 @@
+//! A C API for embedding the resolver in other tools without going through the CLI. The header
+//! in `include/zoltan.h` is generated with `cbindgen --config cbindgen.toml --output include/zoltan.h`.
+use std::ffi::{CStr, CString};
+use std::mem::ManuallyDrop;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use zoltan::session::Session;
+use zoltan::spec::FunctionSpec;
+use zoltan::symbols::FunctionSymbol;
+use zoltan::types::{FunctionType, Type};
+use zoltan::ustr::Ustr;
+
+/// A session over a copy of an executable, with the specs waiting to be resolved.
+pub struct ZoltanSession {
+    session: ManuallyDrop<Session<'static>>,
+    /// Owned by the session, it's freed after the session is dropped.
+    bytes: *mut [u8],
+    specs: Vec<FunctionSpec>,
+    errors: Vec<CString>,
+    last_error: Option<CString>,
+}
+
+impl ZoltanSession {
+    fn set_error(&mut self, message: impl ToString) {
+        self.last_error = CString::new(message.to_string()).ok();
+    }
+}
+
+impl Drop for ZoltanSession {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.session);
+            drop(Box::from_raw(self.bytes));
+        }
+    }
+}
+
+/// A resolved symbol.
+#[repr(C)]
+pub struct ZoltanSymbol {
+    /// A NUL-terminated name, valid until the process exits.
+    pub name: *const c_char,
+    /// The address relative to the image base.
+    pub rva: u64,
+    /// The size of the function in bytes, or 0 when it's not known.
+    pub size: u64,
+}
+
+impl From<&FunctionSymbol> for ZoltanSymbol {
+    fn from(sym: &FunctionSymbol) -> Self {
+        Self {
+            // interned strings are never freed and they're always NUL-terminated
+            name: Ustr::from(sym.name()).as_char_ptr(),
+            rva: sym.rva(),
+            size: sym.size().unwrap_or(0),
+        }
+    }
+}
+
+/// Creates a session from the bytes of an executable, the bytes are copied. Returns null when
+/// the executable cannot be loaded.
+///
+/// # Safety
+/// `bytes` has to point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_new(bytes: *const u8, len: usize) -> *mut ZoltanSession {
+    let bytes = Box::into_raw(Box::<[u8]>::from(std::slice::from_raw_parts(bytes, len)));
+    // the bytes are only freed when the session is dropped, so they outlive it
+    match Session::new(&*bytes, None) {
+        Ok(session) => Box::into_raw(Box::new(ZoltanSession {
+            session: ManuallyDrop::new(session),
+            bytes,
+            specs: vec![],
+            errors: vec![],
+            last_error: None,
+        })),
+        Err(_) => {
+            drop(Box::from_raw(bytes));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a session, the pointer cannot be used afterwards.
+///
+/// # Safety
+/// `session` has to be null or a pointer returned by [`zoltan_session_new`] that wasn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_free(session: *mut ZoltanSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Adds a spec to be resolved with the next call to [`zoltan_session_resolve`]. The parameters are
+/// given one per line in the same format as in the headers, like `@pattern 48 8B 0D` or
+/// `/// @nth first`. Returns false when they're invalid, the reason is available through
+/// [`zoltan_session_last_error`].
+///
+/// # Safety
+/// `session` has to be a valid session, `name` and `params` have to be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_add_spec(
+    session: *mut ZoltanSession,
+    name: *const c_char,
+    params: *const c_char,
+) -> bool {
+    let session = &mut *session;
+    let (name, params) = match (CStr::from_ptr(name).to_str(), CStr::from_ptr(params).to_str()) {
+        (Ok(name), Ok(params)) => (name, params),
+        _ => {
+            session.set_error("the name and the parameters have to be valid UTF-8");
+            return false;
+        }
+    };
+    let comments: Vec<_> = params
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.strip_prefix('@') {
+            Some(_) => format!("/// {line}"),
+            None => line.to_owned(),
+        })
+        .collect();
+    // the function types are irrelevant without headers
+    let function_type = Arc::new(FunctionType::new(vec![], Type::Void));
+    match FunctionSpec::new(
+        name.into(),
+        function_type,
+        comments.iter().map(String::as_str),
+        None,
+    ) {
+        Some(Ok(spec)) => {
+            session.specs.push(spec);
+            true
+        }
+        Some(Err(err)) => {
+            session.set_error(err);
+            false
+        }
+        None => {
+            session.set_error(format!("no parameters for {name}"));
+            false
+        }
+    }
+}
+
+/// Resolves the specs added since the last call. Returns the number of specs that failed, which
+/// can be inspected with [`zoltan_session_error`], or -1 when the resolution couldn't be completed.
+///
+/// # Safety
+/// `session` has to be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_resolve(session: *mut ZoltanSession) -> isize {
+    let session = &mut *session;
+    let specs = std::mem::take(&mut session.specs);
+    match session.session.resolve(specs) {
+        Ok(errors) => {
+            session.errors = errors
+                .iter()
+                .filter_map(|err| CString::new(err.to_string()).ok())
+                .collect();
+            session.errors.len() as isize
+        }
+        Err(err) => {
+            session.set_error(err);
+            -1
+        }
+    }
+}
+
+/// Returns the number of symbols resolved so far.
+///
+/// # Safety
+/// `session` has to be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_symbol_count(session: *const ZoltanSession) -> usize {
+    (*session).session.symbols().len()
+}
+
+/// Writes the symbol at an index to `out`, returns false when the index is out of bounds.
+///
+/// # Safety
+/// `session` has to be a valid session and `out` has to be writable.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_symbol(
+    session: *const ZoltanSession,
+    index: usize,
+    out: *mut ZoltanSymbol,
+) -> bool {
+    match (*session).session.symbols().get(index) {
+        Some(sym) => {
+            out.write(sym.into());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Looks up a symbol by name and writes it to `out`, returns false when it wasn't resolved.
+///
+/// # Safety
+/// `session` has to be a valid session, `name` has to be a NUL-terminated string and `out` has
+/// to be writable.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_find_symbol(
+    session: *const ZoltanSession,
+    name: *const c_char,
+    out: *mut ZoltanSymbol,
+) -> bool {
+    let sym = CStr::from_ptr(name)
+        .to_str()
+        .ok()
+        .and_then(|name| (*session).session.symbol(name));
+    match sym {
+        Some(sym) => {
+            out.write(sym.into());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the message of a failure from the last call to [`zoltan_session_resolve`], or null
+/// when the index is out of bounds. The string is valid until the next resolution.
+///
+/// # Safety
+/// `session` has to be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_error(
+    session: *const ZoltanSession,
+    index: usize,
+) -> *const c_char {
+    (*session)
+        .errors
+        .get(index)
+        .map_or(ptr::null(), |err| err.as_ptr())
+}
+
+/// Returns the reason of the last call that failed, or null when there's none. The string is
+/// valid until the next failure.
+///
+/// # Safety
+/// `session` has to be a valid session.
+#[no_mangle]
+pub unsafe extern "C" fn zoltan_session_last_error(session: *const ZoltanSession) -> *const c_char {
+    (*session)
+        .last_error
+        .as_ref()
+        .map_or(ptr::null(), |err| err.as_ptr())
+}
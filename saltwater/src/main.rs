@@ -1,20 +1,20 @@
+use std::path::Path;
+
 use error::{Error, Result};
-use flexi_logger::{LogSpecification, Logger};
 use resolver::TypeResolver;
 use saltwater::codespan::LineIndex;
 use saltwater::hir::Variable;
 use saltwater::{check_semantics, get_str, Opt, StorageClass};
 use zoltan::opts::Opts;
-use zoltan::spec::FunctionSpec;
-use zoltan::types::Type;
+use zoltan::spec::{FunctionSpec, SourceLocation};
+use zoltan::types::{Type, TypeInfo};
 
 mod error;
 mod resolver;
 
 fn main() {
-    Logger::with(LogSpecification::info()).start().unwrap();
-
     let opts = Opts::load("Zoltan Saltwater frontend for C");
+    zoltan::logging::init(&opts).unwrap();
     match run(&opts) {
         Ok(()) => log::info!("Finished!"),
         Err(err) => {
@@ -25,10 +25,25 @@ fn main() {
 }
 
 fn run(opts: &Opts) -> Result<()> {
+    if opts.watch {
+        zoltan::watch(opts, || parse(opts))?;
+    } else {
+        let (specs, types) = parse(opts)?;
+        zoltan::process_specs(specs, types, opts)?;
+    }
+
+    Ok(())
+}
+
+fn parse(opts: &Opts) -> Result<(Vec<FunctionSpec>, TypeInfo)> {
     let source = std::fs::read_to_string(&opts.source_path)?;
-    let program = check_semantics(source.as_ref(), Opt::default());
+    let opt = Opt {
+        filename: opts.source_path.clone(),
+        ..Opt::default()
+    };
+    let program = check_semantics(source.as_ref(), opt);
 
-    let mut resolver = TypeResolver::default();
+    let mut resolver = TypeResolver::default().with_type_filter(opts.type_filter());
     let mut specs = vec![];
 
     for decl in program
@@ -37,7 +52,7 @@ fn run(opts: &Opts) -> Result<()> {
     {
         let var = decl.data.symbol.get();
         if let Variable {
-            ctype: function_type,
+            ctype,
             storage_class: StorageClass::Typedef,
             ..
         } = &*var
@@ -52,17 +67,24 @@ fn run(opts: &Opts) -> Result<()> {
                 })
                 .take_while(|str| str.starts_with("///"));
 
-            if let Type::Function(fn_type) = resolver.resolve_type(function_type)? {
-                if let Some(spec) = FunctionSpec::new(get_str!(var.id).into(), fn_type, comments) {
-                    specs.push(spec?);
-                }
+            let location = SourceLocation {
+                file: program.files.name(file).into(),
+                line: line.0 as u64 + 1,
+            };
+            let name = get_str!(var.id).into();
+            let spec = match resolver.resolve_type(ctype)? {
+                Type::Function(fn_type) => FunctionSpec::new(name, fn_type, comments, Some(location)),
+                typ => FunctionSpec::new_data(name, typ, comments, Some(location)),
+            };
+            if let Some(spec) = spec {
+                specs.push(spec?);
             }
-        } else if opts.eager_type_export {
+        } else if opts.eager_type_export
+            && opts.is_eager_export_path(Path::new(program.files.name(decl.location.file)))
+        {
             resolver.resolve_type(&var.ctype)?;
         }
     }
 
-    zoltan::process_specs(specs, &resolver.into_types(), opts)?;
-
-    Ok(())
+    Ok((specs, resolver.into_types()))
 }
@@ -1,5 +1,6 @@
 use saltwater::types::ArrayType;
 use saltwater::{get_str, InternedStr};
+use zoltan::filter::TypeFilter;
 use zoltan::types::*;
 use zoltan::ustr::Ustr;
 
@@ -11,14 +12,21 @@ pub struct TypeResolver {
     unions: TypeMap<UnionId, UnionType>,
     enums: TypeMap<EnumId, EnumType>,
     name_allocator: NameAllocator,
+    type_filter: TypeFilter,
 }
 
 impl TypeResolver {
+    /// Types rejected by the filter are resolved without their members.
+    pub fn with_type_filter(self, type_filter: TypeFilter) -> Self {
+        Self { type_filter, ..self }
+    }
+
     pub fn into_types(self) -> TypeInfo {
         TypeInfo {
             structs: self.structs,
             unions: self.unions,
             enums: self.enums,
+            ..TypeInfo::default()
         }
     }
 
@@ -87,12 +95,18 @@ impl TypeResolver {
             .map(Into::into)
             .unwrap_or_else(|| self.name_allocator.allocate().into());
 
+        // incomplete types are left out of the type info
+        if vars.is_empty() && size.is_none() {
+            return Ok(name.into());
+        }
         if !self.unions.contains_key(&name.into()) {
-            let mut members = vec![];
-            for var in vars {
-                let typ = self.resolve_type(&var.ctype)?;
-                members.push(DataMember::basic(name, typ));
-            }
+            self.unions.insert(name.into(), UnionType::stub(name));
+
+            let members = if self.type_filter.is_exported(&name) {
+                self.resolve_members(vars)?
+            } else {
+                vec![]
+            };
             let union = UnionType {
                 name,
                 members,
@@ -112,14 +126,17 @@ impl TypeResolver {
         let name: Ustr = name
             .map(Into::into)
             .unwrap_or_else(|| self.name_allocator.allocate().into());
+        if vars.is_empty() && size.is_none() {
+            return Ok(name.into());
+        }
         if !self.structs.contains_key(&name.into()) {
             self.structs.insert(name.into(), StructType::stub(name));
 
-            let mut members = vec![];
-            for var in vars {
-                let typ = self.resolve_type(&var.ctype)?;
-                members.push(DataMember::basic(name, typ));
-            }
+            let members = if self.type_filter.is_exported(&name) {
+                self.resolve_members(vars)?
+            } else {
+                vec![]
+            };
             let struct_ = StructType {
                 name,
                 base: None,
@@ -132,6 +149,31 @@ impl TypeResolver {
         Ok(name.into())
     }
 
+    fn resolve_members(&mut self, vars: &[saltwater::hir::Variable]) -> Result<Vec<DataMember>> {
+        let mut members = vec![];
+        for var in vars {
+            let name = get_str!(var.id);
+            let typ = self.resolve_type(&var.ctype)?;
+            // a nameless struct or union member is an anonymous aggregate, its members belong to the parent
+            let is_anonymous = name.is_empty()
+                && matches!(
+                    var.ctype,
+                    saltwater::Type::Struct(saltwater::StructType::Anonymous(_))
+                        | saltwater::Type::Union(saltwater::StructType::Anonymous(_))
+                );
+            let name = if name.is_empty() {
+                self.name_allocator.allocate().into()
+            } else {
+                name.into()
+            };
+            members.push(DataMember {
+                is_anonymous,
+                ..DataMember::basic(name, typ)
+            });
+        }
+        Ok(members)
+    }
+
     fn resolve_enum(
         &mut self,
         name: Option<&str>,
@@ -142,14 +184,29 @@ impl TypeResolver {
             .map(Into::into)
             .unwrap_or_else(|| self.name_allocator.allocate().into());
         if !self.enums.contains_key(&name.into()) {
+            // like in GCC, enums without negative values are unsigned
+            let is_signed = vars.iter().any(|(_, val)| *val < 0);
             let mut members = vec![];
-            for (str, val) in vars {
-                members.push(EnumMember::new(get_str!(str).into(), *val));
+            if self.type_filter.is_exported(&name) {
+                for (str, val) in vars {
+                    let value = if is_signed {
+                        EnumValue::Signed(*val)
+                    } else {
+                        EnumValue::Unsigned(*val as u64)
+                    };
+                    members.push(EnumMember::new(get_str!(str).into(), value));
+                }
             }
+            let underlying_type = match size {
+                Some(8) => Type::LongLong(is_signed),
+                _ => Type::Int(is_signed),
+            };
             let enum_ = EnumType {
                 name,
                 members,
                 size: size.map(|s| s as usize),
+                underlying_type,
+                is_scoped: false,
             };
             self.enums.insert(name.into(), enum_);
         }